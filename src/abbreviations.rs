@@ -0,0 +1,131 @@
+use crate::traps::quote;
+use regex::Regex;
+
+/// Where in a command line an abbreviation is expanded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    /// Only as the command name (the first word of a command)
+    Command,
+    /// As any word
+    Anywhere,
+}
+
+/**
+    A fish-style abbreviation: a word that the line editor replaces with its expansion
+    as soon as space or Enter is pressed after it, so the full command ends up in the history.
+*/
+#[derive(Debug, Clone)]
+pub struct Abbreviation {
+    pub name: String,
+    pub expansion: String,
+    pub position: Position,
+    /// Words matching this pattern are expanded instead of the name (and the compiled pattern)
+    regex: Option<(String, Regex)>,
+}
+
+impl Abbreviation {
+    pub fn new(name: &str, expansion: &str) -> Abbreviation {
+        Abbreviation {
+            name: name.to_string(),
+            expansion: expansion.to_string(),
+            position: Position::Command,
+            regex: None,
+        }
+    }
+
+    /// Makes the abbreviation apply to words matching `pattern` entirely
+    pub fn set_regex(&mut self, pattern: &str) -> Result<(), regex::Error> {
+        let regex = Regex::new(&format!("^(?:{})$", pattern))?;
+        self.regex = Some((pattern.to_string(), regex));
+        Ok(())
+    }
+
+    pub fn regex(&self) -> Option<&str> {
+        self.regex.as_ref().map(|(pattern, _)| pattern.as_str())
+    }
+
+    /// Whether `word` at this place of a command line is expanded by this abbreviation
+    pub fn matches(&self, word: &str, command_position: bool) -> bool {
+        if self.position == Position::Command && !command_position {
+            return false;
+        }
+        match &self.regex {
+            Some((_, regex)) => regex.is_match(word),
+            None => word == self.name,
+        }
+    }
+
+    /// The `abbr` command that defines this abbreviation again
+    pub fn definition(&self) -> String {
+        let mut definition = String::from("abbr -a");
+        if self.position == Position::Anywhere {
+            definition.push_str(" --position anywhere");
+        }
+        if let Some(pattern) = self.regex() {
+            definition.push_str(&format!(" --regex {}", quote(pattern)));
+        }
+        definition.push_str(&format!(" -- {} {}", self.name, quote(&self.expansion)));
+        definition
+    }
+}
+
+/// The abbreviations defined with `abbr`, in the order they were added
+#[derive(Debug, Clone, Default)]
+pub struct Abbreviations {
+    list: Vec<Abbreviation>,
+}
+
+impl Abbreviations {
+    /// Adds an abbreviation, replacing one with the same name
+    pub fn add(&mut self, abbreviation: Abbreviation) {
+        match self
+            .list
+            .iter_mut()
+            .find(|other| other.name == abbreviation.name)
+        {
+            Some(other) => *other = abbreviation,
+            None => self.list.push(abbreviation),
+        }
+    }
+
+    /// Removes an abbreviation; returns whether it existed
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.list.len();
+        self.list.retain(|abbreviation| abbreviation.name != name);
+        self.list.len() != len
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Abbreviation> {
+        self.list
+            .iter()
+            .find(|abbreviation| abbreviation.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Abbreviation> {
+        self.list.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /**
+        The expansion of a word, if an abbreviation applies to it.
+        Names are tried before regexes; a later regex wins over an earlier one.
+    */
+    pub fn expand(&self, word: &str, command_position: bool) -> Option<&str> {
+        let plain = self
+            .list
+            .iter()
+            .filter(|abbreviation| abbreviation.regex.is_none());
+        let regexes = self
+            .list
+            .iter()
+            .rev()
+            .filter(|abbreviation| abbreviation.regex.is_some());
+        plain
+            .chain(regexes)
+            .find(|abbreviation| abbreviation.matches(word, command_position))
+            .map(|abbreviation| abbreviation.expansion.as_str())
+    }
+}
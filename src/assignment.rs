@@ -0,0 +1,399 @@
+// Variable assignments: `name=value`, `name[subscript]=value` and `name=(elements)`
+use crate::expand;
+use crate::parser;
+use crate::shell::{self, Shell, Value};
+use crate::traps;
+use std::collections::BTreeMap;
+
+/// An assignment as written, with `+=` appending instead of replacing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assignment {
+    pub name: String,
+    /// The subscript of `name[subscript]=value`, unexpanded
+    pub subscript: Option<String>,
+    pub append: bool,
+    pub value: AssignedValue,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssignedValue {
+    /// The value, unexpanded
+    Scalar(String),
+    /// The words between the parentheses, unexpanded: values or `[subscript]=value`
+    Compound(Vec<String>),
+}
+
+/// An assigned value after expansion; subscripts are still as written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expanded {
+    Scalar(String),
+    Compound(Vec<(Option<String>, String)>),
+}
+
+/// Reads a word as an assignment; `None` if it is none (e.g. quoted, or not a valid name)
+pub fn parse(word: &str) -> Option<Assignment> {
+    let name_end = word.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))?;
+    let name = &word[..name_end];
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        return None;
+    }
+
+    let mut rest = &word[name_end..];
+    let subscript: Option<&str> = match rest.strip_prefix('[') {
+        Some(inside) => {
+            let end = closing_bracket(inside)?;
+            rest = &inside[end + 1..];
+            Some(&inside[..end])
+        }
+        None => None,
+    };
+    let (append, value) = match rest.strip_prefix("+=") {
+        Some(value) => (true, value),
+        None => (false, rest.strip_prefix('=')?),
+    };
+
+    let value = match value
+        .strip_prefix('(')
+        .and_then(|value| value.strip_suffix(')'))
+    {
+        Some(inside) if subscript.is_none() => {
+            AssignedValue::Compound(parser::split_words(inside).ok()?)
+        }
+        _ => AssignedValue::Scalar(value.to_string()),
+    };
+    Some(Assignment {
+        name: name.to_string(),
+        subscript: subscript.map(str::to_string),
+        append,
+        value,
+    })
+}
+
+/// The position of the `]` ending a subscript, skipping quoted parts
+fn closing_bracket(text: &str) -> Option<usize> {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (index, ch) in text.char_indices() {
+        match (quote, ch) {
+            _ if escaped => escaped = false,
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(ch),
+            (None, ']') => return Some(index),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Splits an element of `name=(...)` like `[key]=value` into its subscript and value
+fn keyed_element(word: &str) -> Option<(&str, &str)> {
+    let inside = word.strip_prefix('[')?;
+    let end = closing_bracket(inside)?;
+    let value = inside[end + 1..].strip_prefix('=')?;
+    Some((&inside[..end], value))
+}
+
+/// Assigns a variable, expanding the value first
+pub fn assign(assignment: &Assignment, shell: &mut Shell) -> Result<(), String> {
+    let value = expand_value(&assignment.value, shell)?;
+    store(assignment, value, shell)
+}
+
+/**
+    Expands an assigned value: a scalar and keyed elements become a single field each,
+    while other elements of `name=(...)` are split into fields and globbed like arguments.
+*/
+pub fn expand_value(value: &AssignedValue, shell: &Shell) -> Result<Expanded, String> {
+    match value {
+        AssignedValue::Scalar(value) => Ok(Expanded::Scalar(expand::expand_single(value, shell)?)),
+        AssignedValue::Compound(words) => {
+            let mut elements: Vec<(Option<String>, String)> = Vec::new();
+            for word in words {
+                match keyed_element(word) {
+                    Some((key, value)) => {
+                        let value = expand::expand_single(value, shell)?;
+                        elements.push((Some(key.to_string()), value));
+                    }
+                    None => {
+                        let fields = expand::expand_word(word, shell)?;
+                        elements.extend(fields.into_iter().map(|field| (None, field)));
+                    }
+                }
+            }
+            Ok(Expanded::Compound(elements))
+        }
+    }
+}
+
+/**
+    The assignment as a single argument for `declare`, with the value already expanded:
+    `name=value`, or `name=([key]='value' ...)` with the elements quoted.
+*/
+pub fn expand_argument(assignment: &Assignment, shell: &Shell) -> Result<String, String> {
+    let mut argument = assignment.name.clone();
+    if let Some(subscript) = &assignment.subscript {
+        argument.push_str(&format!("[{}]", subscript));
+    }
+    argument.push_str(if assignment.append { "+=" } else { "=" });
+    match expand_value(&assignment.value, shell)? {
+        Expanded::Scalar(value) => argument.push_str(&value),
+        Expanded::Compound(elements) => {
+            let elements: Vec<String> = elements
+                .iter()
+                .map(|(key, value)| match key {
+                    Some(key) => format!("[{}]={}", key, traps::quote(value)),
+                    None => traps::quote(value),
+                })
+                .collect();
+            argument.push_str(&format!("({})", elements.join(" ")));
+        }
+    }
+    Ok(argument)
+}
+
+/// Stores an expanded value into the variable (or element) the assignment names
+pub fn store(assignment: &Assignment, value: Expanded, shell: &mut Shell) -> Result<(), String> {
+    let name = assignment.name.as_str();
+    match (value, &assignment.subscript) {
+        (Expanded::Scalar(value), Some(subscript)) => {
+            let key = subscript_key(name, subscript, shell)?;
+            let value = match (assignment.append, shell.get_element(name, &key)) {
+                (true, Some(old)) => format!("{}{}", old, value),
+                _ => value,
+            };
+            shell.set_element(name, &key, &value)
+        }
+        (Expanded::Scalar(value), None) => {
+            let value = match (assignment.append, shell.get_var(name)) {
+                (true, Some(old)) => format!("{}{}", old, value),
+                _ => value,
+            };
+            shell.set_var(name, &value);
+            Ok(())
+        }
+        (Expanded::Compound(_), Some(subscript)) => Err(format!(
+            "{}[{}]: cannot assign list to array member",
+            name, subscript
+        )),
+        (Expanded::Compound(elements), None) => {
+            let old: Option<&Value> = shell.vars.get(name).map(|variable| &variable.value);
+            let value = match old {
+                Some(Value::Associative(old)) => {
+                    let mut map = if assignment.append {
+                        old.clone()
+                    } else {
+                        BTreeMap::new()
+                    };
+                    for (key, value) in elements {
+                        let Some(key) = key else {
+                            return Err(format!(
+                                "{}: {}: must use subscript when assigning associative array",
+                                name, value
+                            ));
+                        };
+                        map.insert(expand::expand_single(&key, shell)?, value);
+                    }
+                    Value::Associative(map)
+                }
+                _ => {
+                    let mut map: BTreeMap<usize, String> = match old {
+                        Some(Value::Indexed(old)) if assignment.append => old.clone(),
+                        Some(Value::Scalar(old)) if assignment.append => {
+                            BTreeMap::from([(0, old.clone())])
+                        }
+                        _ => BTreeMap::new(),
+                    };
+                    let mut next = map.keys().next_back().map_or(0, |last| last + 1);
+                    for (key, value) in elements {
+                        let index = match key {
+                            Some(key) => {
+                                let index = evaluate_subscript(&key, shell)?;
+                                let length = map.keys().next_back().map_or(0, |last| last + 1);
+                                shell::resolve_index(index, length).ok_or_else(|| {
+                                    format!("{}[{}]: bad array subscript", name, key)
+                                })?
+                            }
+                            None => next,
+                        };
+                        map.insert(index, value);
+                        next = index + 1;
+                    }
+                    Value::Indexed(map)
+                }
+            };
+            shell.set_value(name, value);
+            Ok(())
+        }
+    }
+}
+
+/**
+    The key a subscript of `name` refers to: for associative arrays the expanded text,
+    otherwise the value of the subscript as an arithmetic expression (like `i+1`).
+*/
+pub fn subscript_key(name: &str, subscript: &str, shell: &Shell) -> Result<String, String> {
+    match shell.vars.get(name).map(|variable| &variable.value) {
+        Some(Value::Associative(_)) => expand::expand_single(subscript, shell),
+        _ => Ok(evaluate_subscript(subscript, shell)?.to_string()),
+    }
+}
+
+/// Evaluates the subscript of an indexed array: integers, variables, `+ - * / %` and parentheses
+pub fn evaluate_subscript(subscript: &str, shell: &Shell) -> Result<i64, String> {
+    let text = expand::expand_single(subscript, shell)?;
+    let mut parser = Arithmetic {
+        text: &text,
+        chars: text.chars().collect(),
+        position: 0,
+        shell,
+        depth: 0,
+    };
+    let value = parser.sum()?;
+    parser.skip_blanks();
+    match parser.chars.get(parser.position) {
+        None => Ok(value),
+        Some(_) => Err(parser.syntax_error()),
+    }
+}
+
+/// Recursive descent over an arithmetic expression
+struct Arithmetic<'a> {
+    text: &'a str,
+    chars: Vec<char>,
+    position: usize,
+    shell: &'a Shell,
+    /// How many variables are being evaluated (their values can be expressions too)
+    depth: usize,
+}
+
+impl Arithmetic<'_> {
+    fn syntax_error(&self) -> String {
+        format!("{}: syntax error in expression", self.text)
+    }
+
+    fn skip_blanks(&mut self) {
+        while self
+            .chars
+            .get(self.position)
+            .is_some_and(|c| c.is_whitespace())
+        {
+            self.position += 1;
+        }
+    }
+
+    fn sum(&mut self) -> Result<i64, String> {
+        let mut value = self.product()?;
+        loop {
+            self.skip_blanks();
+            match self.chars.get(self.position) {
+                Some('+') => {
+                    self.position += 1;
+                    value = value.wrapping_add(self.product()?);
+                }
+                Some('-') => {
+                    self.position += 1;
+                    value = value.wrapping_sub(self.product()?);
+                }
+                _ => return Ok(value),
+            }
+        }
+    }
+
+    fn product(&mut self) -> Result<i64, String> {
+        let mut value = self.unary()?;
+        loop {
+            self.skip_blanks();
+            let operator = match self.chars.get(self.position) {
+                Some(operator @ ('*' | '/' | '%')) => *operator,
+                _ => return Ok(value),
+            };
+            self.position += 1;
+            let right = self.unary()?;
+            value = match operator {
+                '*' => value.wrapping_mul(right),
+                _ if right == 0 => return Err(format!("{}: division by 0", self.text)),
+                '/' => value.wrapping_div(right),
+                _ => value.wrapping_rem(right),
+            };
+        }
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        self.skip_blanks();
+        match self.chars.get(self.position) {
+            Some('-') => {
+                self.position += 1;
+                Ok(self.unary()?.wrapping_neg())
+            }
+            Some('+') => {
+                self.position += 1;
+                self.unary()
+            }
+            Some('(') => {
+                self.position += 1;
+                let value = self.sum()?;
+                self.skip_blanks();
+                if self.chars.get(self.position) != Some(&')') {
+                    return Err(self.syntax_error());
+                }
+                self.position += 1;
+                Ok(value)
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let start = self.position;
+                while self
+                    .chars
+                    .get(self.position)
+                    .is_some_and(char::is_ascii_digit)
+                {
+                    self.position += 1;
+                }
+                let digits: String = self.chars[start..self.position].iter().collect();
+                digits.parse().map_err(|_| self.syntax_error())
+            }
+            Some(c) if c.is_ascii_alphabetic() || *c == '_' => {
+                let start = self.position;
+                while self
+                    .chars
+                    .get(self.position)
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || *c == '_')
+                {
+                    self.position += 1;
+                }
+                let name: String = self.chars[start..self.position].iter().collect();
+                self.variable(&name)
+            }
+            _ => Err(self.syntax_error()),
+        }
+    }
+
+    /// The value of a variable in an expression: unset or empty is 0
+    fn variable(&self, name: &str) -> Result<i64, String> {
+        let value = self.shell.get_var(name).unwrap_or_default().trim();
+        if value.is_empty() {
+            return Ok(0);
+        }
+        if let Ok(number) = value.parse() {
+            return Ok(number);
+        }
+        if self.depth >= 16 {
+            return Err(format!("{}: expression recursion level exceeded", name));
+        }
+        let mut parser = Arithmetic {
+            text: value,
+            chars: value.chars().collect(),
+            position: 0,
+            shell: self.shell,
+            depth: self.depth + 1,
+        };
+        let number = parser.sum()?;
+        parser.skip_blanks();
+        match parser.chars.get(parser.position) {
+            None => Ok(number),
+            Some(_) => Err(parser.syntax_error()),
+        }
+    }
+}
@@ -0,0 +1,348 @@
+use crate::commands;
+use crate::error::{self, ExitStatus, ShellError};
+use crate::shell::Shell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, LineWriter, Read, Write};
+use std::os::fd::RawFd;
+use std::sync::Arc;
+
+/**
+    The standard streams of a builtin.
+    Builtins write here instead of using `println!`, so their output can be captured.
+*/
+pub struct Io<'a> {
+    pub stdin: Box<dyn Read + 'a>,
+    pub stdout: Box<dyn Write + 'a>,
+    pub stderr: Box<dyn Write + 'a>,
+    /// The file descriptor `stdin` reads from, if any (`read -t` waits on it)
+    pub stdin_fd: Option<RawFd>,
+}
+
+impl<'a> Io<'a> {
+    pub fn new(stdin: impl Read + 'a, stdout: impl Write + 'a, stderr: impl Write + 'a) -> Io<'a> {
+        Io {
+            stdin: Box::new(stdin),
+            stdout: Box::new(stdout),
+            stderr: Box::new(stderr),
+            stdin_fd: None,
+        }
+    }
+
+    /// The streams of the shell process itself (fds 0, 1 and 2)
+    pub fn standard() -> Io<'static> {
+        Io::from_fds([libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO])
+    }
+
+    /// Streams on the given fds 0, 1 and 2, like the `standard_fds` of a shell
+    pub fn from_fds([stdin, stdout, stderr]: [RawFd; 3]) -> Io<'static> {
+        Io {
+            stdin_fd: Some(stdin),
+            ..Io::new(
+                FdStream(stdin),
+                LineWriter::new(FdStream(stdout)),
+                FdStream(stderr),
+            )
+        }
+    }
+}
+
+/**
+    Reads and writes an fd without a buffer: the input of one builtin (e.g. a here-document)
+    mustn't be left over for the next one. The fd isn't closed when the stream is dropped.
+*/
+pub struct FdStream(pub RawFd);
+
+impl Read for FdStream {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let count = unsafe { libc::read(self.0, buffer.as_mut_ptr().cast(), buffer.len()) };
+        if count < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(count as usize)
+    }
+}
+
+impl Write for FdStream {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        let count = unsafe { libc::write(self.0, buffer.as_ptr().cast(), buffer.len()) };
+        if count < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(count as usize)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/**
+    A command that runs inside the shell instead of as a separate program.
+    Implement it and call `Shell::register_builtin` to add commands when embedding the shell.
+*/
+pub trait Builtin: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// The usage line, followed by a short description (shown by `help`)
+    fn help(&self) -> &str;
+
+    fn run(&self, shell: &mut Shell, args: &[&str], io: &mut Io) -> Result<ExitStatus, ShellError>;
+}
+
+type BuiltinFn = fn(&mut Shell, &[&str], &mut Io) -> Result<ExitStatus, ShellError>;
+
+/// A builtin made from a plain function
+pub struct FunctionBuiltin {
+    name: &'static str,
+    help: &'static str,
+    function: BuiltinFn,
+}
+
+impl FunctionBuiltin {
+    pub fn new(name: &'static str, help: &'static str, function: BuiltinFn) -> FunctionBuiltin {
+        FunctionBuiltin {
+            name,
+            help,
+            function,
+        }
+    }
+}
+
+impl Builtin for FunctionBuiltin {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn help(&self) -> &str {
+        self.help
+    }
+
+    fn run(&self, shell: &mut Shell, args: &[&str], io: &mut Io) -> Result<ExitStatus, ShellError> {
+        (self.function)(shell, args, io)
+    }
+}
+
+/// The builtins of a shell by name, starting out with the ones every shell has
+#[derive(Clone)]
+pub struct Builtins {
+    registry: BTreeMap<String, Arc<dyn Builtin>>,
+}
+
+impl Builtins {
+    /// Adds a builtin, replacing any builtin with the same name
+    pub fn register(&mut self, builtin: Arc<dyn Builtin>) {
+        self.registry.insert(builtin.name().to_string(), builtin);
+    }
+
+    /// Removes a builtin, so the name runs a program again; returns whether it existed
+    pub fn unregister(&mut self, name: &str) -> bool {
+        self.registry.remove(name).is_some()
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<Arc<dyn Builtin>> {
+        self.registry.get(name).cloned()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.registry.contains_key(name)
+    }
+
+    /// The names of all builtins, sorted
+    pub fn names(&self) -> Vec<String> {
+        self.registry.keys().cloned().collect()
+    }
+}
+
+impl Default for Builtins {
+    fn default() -> Builtins {
+        let registry = default_builtins()
+            .into_iter()
+            .map(|builtin| (builtin.name().to_string(), builtin))
+            .collect();
+        Builtins { registry }
+    }
+}
+
+impl fmt::Debug for Builtins {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter
+            .debug_list()
+            .entries(self.registry.keys())
+            .finish()
+    }
+}
+
+fn default_builtins() -> Vec<Arc<dyn Builtin>> {
+    let builtins = [
+        FunctionBuiltin::new(
+            "[",
+            "[ expression ]\n    Evaluates a conditional expression, like test.",
+            |shell, args, _| commands::handle_left_bracket(args.to_vec(), shell),
+        ),
+        FunctionBuiltin::new(
+            "abbr",
+            "abbr [-a] [--position command|anywhere] [--regex pattern] name expansion ...\n    Defines abbreviations that expand while typing, or shows them (see also -e, -l, -q).",
+            |shell, args, io| commands::handle_abbr(args.to_vec(), shell, io),
+        ),
+        FunctionBuiltin::new(
+            "alias",
+            "alias [-p] [name[=value] ...]\n    Defines aliases, or shows them without arguments.",
+            |shell, args, io| commands::handle_alias(args.to_vec(), shell, io),
+        ),
+        FunctionBuiltin::new(
+            "bg",
+            "bg [job_spec]\n    Resumes a stopped job in the background.",
+            |shell, args, io| commands::handle_bg(args.to_vec(), shell, io),
+        ),
+        FunctionBuiltin::new(
+            "cd",
+            "cd [-L|-P] [dir]\n    Changes the current directory (to $HOME without dir, back to $OLDPWD with -).",
+            |shell, args, io| commands::handle_cd(args.to_vec(), shell, io),
+        ),
+        FunctionBuiltin::new(
+            "command",
+            "command [-pVv] name [arg ...]\n    Runs a builtin or program, ignoring aliases, or tells what it is with -v and -V.",
+            |shell, args, io| commands::handle_command(args.to_vec(), shell, io),
+        ),
+        FunctionBuiltin::new(
+            "declare",
+            "declare [-aAx] [-p] [name[=value] ...]\n    Sets variables and makes them arrays (-a indexed, -A associative) or exported, or shows them.",
+            |shell, args, io| commands::handle_declare(args.to_vec(), shell, io),
+        ),
+        FunctionBuiltin::new(
+            "dirs",
+            "dirs [-clpv] [+N | -N]\n    Shows the directory stack of pushd and popd, or clears it with -c.",
+            |shell, args, io| commands::handle_dirs(args.to_vec(), shell, io),
+        ),
+        FunctionBuiltin::new(
+            "disown",
+            "disown [-a] [job_spec ...]\n    Removes jobs from the job table.",
+            |shell, args, io| commands::handle_disown(args.to_vec(), shell, io),
+        ),
+        FunctionBuiltin::new(
+            "echo",
+            "echo [-neE] [arg ...]\n    Prints the arguments, separated by spaces (-e interprets backslash escapes, -n leaves out the newline).",
+            |_, args, io| commands::handle_echo(args.to_vec(), io),
+        ),
+        FunctionBuiltin::new(
+            "exit",
+            "exit [n]\n    Exits the shell with status n (the status of the last command without it).",
+            |shell, args, _| commands::handle_exit(args.to_vec(), shell),
+        ),
+        FunctionBuiltin::new(
+            "fg",
+            "fg [job_spec]\n    Moves a job to the foreground.",
+            |shell, args, io| commands::handle_fg(args.to_vec(), shell, io),
+        ),
+        FunctionBuiltin::new(
+            "hash",
+            "hash [-r] [-p path] [-d] [name ...]\n    Remembers or shows where commands were found in PATH.",
+            |shell, args, io| commands::handle_hash(args.to_vec(), shell, io),
+        ),
+        FunctionBuiltin::new(
+            "help",
+            "help [name ...]\n    Shows how to use builtins, or lists them without arguments.",
+            handle_help,
+        ),
+        FunctionBuiltin::new(
+            "history",
+            "history [-c] [-d offset] [-r | -w [file]] [n]\n    Shows or edits the command history.",
+            |shell, args, io| commands::handle_history(args.to_vec(), shell, io),
+        ),
+        FunctionBuiltin::new(
+            "jobs",
+            "jobs [-lprs] [job_spec ...]\n    Lists the jobs of the shell.",
+            |shell, args, io| commands::handle_jobs(args.to_vec(), shell, io),
+        ),
+        FunctionBuiltin::new(
+            "popd",
+            "popd [-n] [+N | -N]\n    Removes a directory from the stack and goes to the new top directory.",
+            |shell, args, io| commands::handle_popd(args.to_vec(), shell, io),
+        ),
+        FunctionBuiltin::new(
+            "printf",
+            "printf [-v var] format [arg ...]\n    Prints the arguments formatted like C's printf (or assigns the output to var).",
+            |shell, args, io| commands::handle_printf(args.to_vec(), shell, io),
+        ),
+        FunctionBuiltin::new(
+            "pushd",
+            "pushd [-n] [dir | +N | -N]\n    Goes to a directory and puts the old one on the stack, or rotates the stack.",
+            |shell, args, io| commands::handle_pushd(args.to_vec(), shell, io),
+        ),
+        FunctionBuiltin::new(
+            "pwd",
+            "pwd [-L|-P]\n    Prints the current directory.",
+            |shell, args, io| commands::handle_print_working_directory(args.to_vec(), shell, io),
+        ),
+        FunctionBuiltin::new(
+            "read",
+            "read [-rs] [-a array] [-d delim] [-n count] [-N count] [-p prompt] [-t timeout] [-u fd] [name ...]\n    Reads a line and splits it into the variables at IFS characters (into REPLY without names).",
+            |shell, args, io| commands::handle_read(args.to_vec(), shell, io),
+        ),
+        FunctionBuiltin::new(
+            "set",
+            "set [-efnux] [-o option] [+o option]\n    Sets shell options, or lists variables without arguments.",
+            |shell, args, io| commands::handle_set(args.to_vec(), shell, io),
+        ),
+        FunctionBuiltin::new(
+            "test",
+            "test [expression]\n    Evaluates a conditional expression on files, strings and integers.",
+            |shell, args, _| commands::handle_test(args.to_vec(), shell),
+        ),
+        FunctionBuiltin::new(
+            "trap",
+            "trap [-lp] [[action] condition ...]\n    Runs an action when the shell receives a signal.",
+            |shell, args, io| commands::handle_trap(args.to_vec(), shell, io),
+        ),
+        FunctionBuiltin::new(
+            "type",
+            "type [-aptP] name [name ...]\n    Tells how each name would be interpreted as a command.",
+            |shell, args, io| commands::handle_type(args.to_vec(), shell, io),
+        ),
+        FunctionBuiltin::new(
+            "unalias",
+            "unalias [-a] name ...\n    Removes aliases (all of them with -a).",
+            |shell, args, io| commands::handle_unalias(args.to_vec(), shell, io),
+        ),
+        FunctionBuiltin::new(
+            "wait",
+            "wait [id ...]\n    Waits for jobs or processes to finish.",
+            |shell, args, io| commands::handle_wait(args.to_vec(), shell, io),
+        ),
+    ];
+    builtins
+        .into_iter()
+        .map(|builtin| Arc::new(builtin) as Arc<dyn Builtin>)
+        .collect()
+}
+
+fn handle_help(shell: &mut Shell, args: &[&str], io: &mut Io) -> Result<ExitStatus, ShellError> {
+    if args.is_empty() {
+        for name in shell.builtins.names() {
+            let Some(builtin) = shell.builtins.lookup(&name) else {
+                continue;
+            };
+            let usage = builtin.help().lines().next().unwrap_or_default();
+            writeln!(io.stdout, "{}", usage)?;
+        }
+        return Ok(0);
+    }
+
+    let mut status = 0;
+    for name in args {
+        match shell.builtins.lookup(name) {
+            Some(builtin) => writeln!(io.stdout, "{}", builtin.help())?,
+            None => {
+                let message = format!("no help topics match `{}'", name);
+                error::report_to(
+                    &mut io.stderr,
+                    &ShellError::builtin("help", message),
+                    shell.location.as_ref(),
+                );
+                status = 1;
+            }
+        }
+    }
+    Ok(status)
+}
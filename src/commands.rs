@@ -1,134 +1,2056 @@
-use std::env;
+use crate::abbreviations::{Abbreviation, Position};
+use crate::assignment::{self, AssignedValue, Expanded};
+use crate::builtins::Io;
+use crate::conditional;
+use crate::dir_stack;
+use crate::error::{self, ExitStatus, ShellError};
+use crate::executor::{self, ChildSetup};
+use crate::expand;
+use crate::hash;
+use crate::history::History;
+use crate::jobs;
+use crate::options::ShellOption;
+use crate::parser;
+use crate::printf;
+use crate::read::{self, ReadEnd, ReadOptions, TerminalModes};
+use crate::shell::{Shell, Value};
+use crate::signals;
+use crate::traps::{self, Condition};
+use std::ffi::CString;
 use std::fs;
+use std::io::{self, Write};
+use std::mem::ManuallyDrop;
+use std::os::fd::{FromRawFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
 use std::path::*;
 use std::process;
 use std::str;
+use std::time::Duration;
 
-pub fn command_not_found(command: &str) {
+pub fn command_not_found(command: &str) -> ShellError {
     // Called when the command doesn't exist
-    println!("{}: command not found", command);
-    return;
+    ShellError::CommandNotFound(command.to_string())
 }
 
-pub fn handle_type(args: Vec<&str>) {
-    let cmd: &str = args[0];
+/// What a name runs as a command
+enum CommandKind {
+    Alias(String),
+    Keyword,
+    Function(String),
+    Builtin,
+    File(PathBuf),
+    /// A file remembered in the hash table
+    Hashed(PathBuf),
+}
+
+impl CommandKind {
+    /// The word `type -t` prints
+    fn word(&self) -> &'static str {
+        match self {
+            CommandKind::Alias(_) => "alias",
+            CommandKind::Keyword => "keyword",
+            CommandKind::Function(_) => "function",
+            CommandKind::Builtin => "builtin",
+            CommandKind::File(_) | CommandKind::Hashed(_) => "file",
+        }
+    }
+}
+
+/**
+    `type [-aptP] name...`: tells how each name would be interpreted as a command.
+    `-a` shows every match, `-t` a single word, `-p` the file that would run, `-P` the file in PATH.
+*/
+pub fn handle_type(
+    args: Vec<&str>,
+    shell: &mut Shell,
+    io: &mut Io,
+) -> Result<ExitStatus, ShellError> {
+    let mut all = false;
+    let mut word_only = false;
+    let mut path_only = false;
+    let mut force_path = false;
+    let mut names: &[&str] = &args;
+    while let Some(&option) = names.first() {
+        if option == "--" {
+            names = &names[1..];
+            break;
+        }
+        if !option.starts_with('-') || option.len() == 1 {
+            break;
+        }
+        for letter in option[1..].chars() {
+            match letter {
+                'a' => all = true,
+                't' => word_only = true,
+                'p' => path_only = true,
+                'P' => force_path = true,
+                _ => {
+                    return Err(ShellError::usage(
+                        "type",
+                        format!("-{}: invalid option", letter),
+                    ));
+                }
+            }
+        }
+        names = &names[1..];
+    }
+
+    let mut status = 0;
+    for name in names {
+        let mut kinds = command_kinds(name, all, force_path, shell);
+        if !all {
+            kinds.truncate(1);
+        }
+        if kinds.is_empty() {
+            if !word_only && !path_only && !force_path {
+                let error = ShellError::builtin("type", format!("{}: not found", name));
+                error::report_to(&mut io.stderr, &error, shell.location.as_ref());
+            }
+            status = 1;
+            continue;
+        }
+
+        for kind in kinds {
+            if word_only {
+                writeln!(io.stdout, "{}", kind.word())?;
+                continue;
+            }
+            match kind {
+                CommandKind::File(path) | CommandKind::Hashed(path) if path_only || force_path => {
+                    writeln!(io.stdout, "{}", path.display())?
+                }
+                _ if path_only || force_path => {}
+                CommandKind::Alias(value) => {
+                    writeln!(io.stdout, "{} is aliased to `{}`", name, value)?
+                }
+                CommandKind::Keyword => writeln!(io.stdout, "{} is a shell keyword", name)?,
+                CommandKind::Function(body) => {
+                    writeln!(io.stdout, "{} is a function", name)?;
+                    writeln!(io.stdout, "{}", body)?;
+                }
+                CommandKind::Builtin => writeln!(io.stdout, "{} is a shell builtin", name)?,
+                CommandKind::File(path) => writeln!(io.stdout, "{} is {}", name, path.display())?,
+                CommandKind::Hashed(path) => {
+                    writeln!(io.stdout, "{} is hashed ({})", name, path.display())?
+                }
+            }
+        }
+    }
+    Ok(status)
+}
+
+/**
+    The ways a name can run, in the order the shell tries them: alias, keyword, function,
+    builtin and file. Only files are searched with `path_only`, and `all` finds every file in PATH.
+*/
+fn command_kinds(name: &str, all: bool, path_only: bool, shell: &mut Shell) -> Vec<CommandKind> {
+    let mut kinds: Vec<CommandKind> = Vec::new();
+    if !path_only {
+        if let Some(value) = shell.aliases.get(name) {
+            kinds.push(CommandKind::Alias(value.clone()));
+        }
+        if parser::KEYWORDS.contains(&name) {
+            kinds.push(CommandKind::Keyword);
+        }
+        if let Some(body) = shell.functions.get(name) {
+            kinds.push(CommandKind::Function(body.clone()));
+        }
+        if shell.builtins.contains(name) {
+            kinds.push(CommandKind::Builtin);
+        }
+    }
+
+    if name.contains('/') {
+        if hash::is_executable(&shell.resolve_path(name)) {
+            kinds.push(CommandKind::File(PathBuf::from(name)));
+        }
+        return kinds;
+    }
+
+    let search_path: String = shell.get_var("PATH").unwrap_or_default().to_string();
+    if all {
+        let files = hash::search_all(name, &search_path, &shell.cwd);
+        kinds.extend(files.into_iter().map(|(path, _)| CommandKind::File(path)));
+    } else if let Some(entry) = shell.hash.get(name, &search_path) {
+        kinds.push(CommandKind::Hashed(entry.path.clone()));
+    } else if let Some((path, _)) = hash::search(name, &search_path, &shell.cwd) {
+        kinds.push(CommandKind::File(path));
+    }
+    kinds
+}
+
+/**
+    `hash [-r] [-p path] [-d] [name ...]`: shows or changes where commands were found in PATH.
+    Without names it lists the remembered commands and how often each one ran.
+*/
+pub fn handle_hash(
+    args: Vec<&str>,
+    shell: &mut Shell,
+    io: &mut Io,
+) -> Result<ExitStatus, ShellError> {
+    let mut reset = false;
+    let mut delete = false;
+    let mut program: Option<&str> = None;
+    let mut names: &[&str] = &args;
+    while let Some(&option) = names.first() {
+        match option {
+            "--" => {
+                names = &names[1..];
+                break;
+            }
+            "-r" => reset = true,
+            "-d" => delete = true,
+            "-p" => {
+                names = &names[1..];
+                let path = names
+                    .first()
+                    .ok_or_else(|| ShellError::usage("hash", "-p: option requires an argument"))?;
+                program = Some(path);
+            }
+            _ if option.starts_with('-') && option.len() > 1 => {
+                return Err(ShellError::usage(
+                    "hash",
+                    format!("{}: invalid option", option),
+                ));
+            }
+            _ => break,
+        }
+        names = &names[1..];
+    }
+
+    let search_path: String = shell.get_var("PATH").unwrap_or_default().to_string();
+    if reset {
+        shell.hash.clear();
+    }
+
+    if let Some(program) = program {
+        for name in names {
+            let path = shell.resolve_path(program);
+            shell.hash.insert(name, path, &search_path);
+        }
+        return Ok(0);
+    }
+
+    if names.is_empty() {
+        if !reset && !delete {
+            print_hash(&search_path, shell, io)?;
+        }
+        return Ok(0);
+    }
+
+    let mut status = 0;
+    for name in names {
+        let found = if delete {
+            shell.hash.remove(name, &search_path)
+        } else if name.contains('/') {
+            // Paths are never looked up, so there is nothing to remember
+            true
+        } else {
+            match hash::search(name, &search_path, &shell.cwd) {
+                Some((path, _)) => {
+                    let path = shell.resolve_path(path);
+                    shell.hash.insert(name, path, &search_path);
+                    true
+                }
+                None => false,
+            }
+        };
+        if !found {
+            let error = ShellError::builtin("hash", format!("{}: not found", name));
+            error::report_to(&mut io.stderr, &error, shell.location.as_ref());
+            status = 1;
+        }
+    }
+    Ok(status)
+}
+
+/// Lists the hash table like bash: how often each command ran, and its path
+fn print_hash(search_path: &str, shell: &mut Shell, io: &mut Io) -> io::Result<()> {
+    let mut entries = shell.hash.entries(search_path).peekable();
+    if entries.peek().is_none() {
+        writeln!(io.stdout, "hash: hash table empty")?;
+        return Ok(());
+    }
+    writeln!(io.stdout, "hits\tcommand")?;
+    for (_, entry) in entries {
+        writeln!(io.stdout, "{:>4}\t{}", entry.hits, entry.path.display())?;
+    }
+    Ok(())
+}
+
+/**
+    `alias [-p] [name[=value] ...]`: defines aliases, or shows them (all of them without names)
+    in a form that can be used as input again.
+*/
+pub fn handle_alias(
+    args: Vec<&str>,
+    shell: &mut Shell,
+    io: &mut Io,
+) -> Result<ExitStatus, ShellError> {
+    let mut names: &[&str] = &args;
+    while let Some(&option) = names.first() {
+        match option {
+            "--" => {
+                names = &names[1..];
+                break;
+            }
+            // Listing is what happens without names anyway
+            "-p" => {}
+            _ if option.starts_with('-') && option.len() > 1 => {
+                return Err(ShellError::usage(
+                    "alias",
+                    format!("{}: invalid option", option),
+                ));
+            }
+            _ => break,
+        }
+        names = &names[1..];
+    }
+
+    if names.is_empty() {
+        for (name, value) in &shell.aliases {
+            writeln!(io.stdout, "alias {}={}", name, traps::quote(value))?;
+        }
+        return Ok(0);
+    }
+
+    let mut status = 0;
+    for argument in names {
+        match argument.split_once('=') {
+            Some((name, value)) => {
+                if !is_valid_alias_name(name) {
+                    let message = format!("`{}': invalid alias name", name);
+                    error::report_to(
+                        &mut io.stderr,
+                        &ShellError::builtin("alias", message),
+                        shell.location.as_ref(),
+                    );
+                    status = 1;
+                    continue;
+                }
+                shell.aliases.insert(name.to_string(), value.to_string());
+            }
+            None => match shell.aliases.get(*argument) {
+                Some(value) => writeln!(io.stdout, "alias {}={}", argument, traps::quote(value))?,
+                None => {
+                    let message = format!("{}: not found", argument);
+                    error::report_to(
+                        &mut io.stderr,
+                        &ShellError::builtin("alias", message),
+                        shell.location.as_ref(),
+                    );
+                    status = 1;
+                }
+            },
+        }
+    }
+    Ok(status)
+}
+
+/// Alias names can't contain anything that would be quoted, expanded or split
+fn is_valid_alias_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name
+            .chars()
+            .any(|c| c.is_whitespace() || "/$`=\\'\"|&;<>()".contains(c))
+}
+
+/// `unalias [-a] name ...`: removes aliases (all of them with `-a`)
+pub fn handle_unalias(
+    args: Vec<&str>,
+    shell: &mut Shell,
+    io: &mut Io,
+) -> Result<ExitStatus, ShellError> {
+    let names: &[&str] = match args.first() {
+        Some(&"-a") => {
+            shell.aliases.clear();
+            return Ok(0);
+        }
+        Some(&"--") => &args[1..],
+        Some(option) if option.starts_with('-') && option.len() > 1 => {
+            return Err(ShellError::usage(
+                "unalias",
+                format!("{}: invalid option", option),
+            ));
+        }
+        _ => &args,
+    };
+    if names.is_empty() {
+        return Err(ShellError::usage("unalias", "usage: unalias [-a] name ..."));
+    }
 
-    match cmd {
-        // "" => println!(""),
-        "exit" => println!("exit is a shell builtin"),
-        "echo" => println!("echo is a shell builtin"),
-        "type" => println!("type is a shell builtin"),
-        "pwd" => println!("pwd is a shell builtin"),
-        "cd" => println!("cd is a shell builtin"),
-        _ => type_non_builtin(cmd),
+    let mut status = 0;
+    for name in names {
+        if shell.aliases.remove(*name).is_none() {
+            let message = format!("{}: not found", name);
+            error::report_to(
+                &mut io.stderr,
+                &ShellError::builtin("unalias", message),
+                shell.location.as_ref(),
+            );
+            status = 1;
+        }
     }
+    Ok(status)
 }
 
-fn type_non_builtin(cmd: &str) {
-    let path: String = env::var("PATH").unwrap_or_default();
-    let path_split: str::Split<'_, &str> = path.split(":");
+/**
+    `abbr [-a] [--position command|anywhere] [--regex pattern] name expansion...`: defines
+    an abbreviation the line editor expands while typing. Without arguments (or with `--show`)
+    it prints the definitions, `--list` prints the names, `--erase` removes abbreviations and
+    `--query` tells whether they exist.
+*/
+pub fn handle_abbr(
+    args: Vec<&str>,
+    shell: &mut Shell,
+    io: &mut Io,
+) -> Result<ExitStatus, ShellError> {
+    enum Mode {
+        Add,
+        Erase,
+        List,
+        Query,
+        Show,
+    }
+    let mut mode: Option<Mode> = None;
+    let mut position = Position::Command;
+    let mut regex: Option<&str> = None;
+    let mut rest: &[&str] = &args;
+    while let Some(&option) = rest.first() {
+        match option {
+            "--" => {
+                rest = &rest[1..];
+                break;
+            }
+            "-a" | "--add" => mode = Some(Mode::Add),
+            "-e" | "--erase" => mode = Some(Mode::Erase),
+            "-l" | "--list" => mode = Some(Mode::List),
+            "-q" | "--query" => mode = Some(Mode::Query),
+            "-s" | "--show" => mode = Some(Mode::Show),
+            "-p" | "--position" | "-r" | "--regex" => {
+                rest = &rest[1..];
+                let value = *rest.first().ok_or_else(|| {
+                    ShellError::usage("abbr", format!("{}: option requires an argument", option))
+                })?;
+                if option == "-r" || option == "--regex" {
+                    regex = Some(value);
+                } else {
+                    position = match value {
+                        "command" => Position::Command,
+                        "anywhere" => Position::Anywhere,
+                        _ => {
+                            return Err(ShellError::usage(
+                                "abbr",
+                                format!("{}: invalid position", value),
+                            ));
+                        }
+                    };
+                }
+            }
+            _ if option.starts_with('-') && option.len() > 1 => {
+                return Err(ShellError::usage(
+                    "abbr",
+                    format!("{}: invalid option", option),
+                ));
+            }
+            _ => break,
+        }
+        rest = &rest[1..];
+    }
+
+    let mode = mode.unwrap_or(if rest.is_empty() {
+        Mode::Show
+    } else {
+        Mode::Add
+    });
+    match mode {
+        Mode::Show => {
+            for abbreviation in shell.abbreviations.iter() {
+                writeln!(io.stdout, "{}", abbreviation.definition())?;
+            }
+        }
+        Mode::List => {
+            for abbreviation in shell.abbreviations.iter() {
+                writeln!(io.stdout, "{}", abbreviation.name)?;
+            }
+        }
+        Mode::Query => {
+            let found = rest
+                .iter()
+                .any(|name| shell.abbreviations.get(name).is_some());
+            return Ok(if found { 0 } else { 1 });
+        }
+        Mode::Erase => {
+            let mut status = 0;
+            for name in rest {
+                if !shell.abbreviations.remove(name) {
+                    let error = ShellError::builtin("abbr", format!("{}: not found", name));
+                    error::report_to(&mut io.stderr, &error, shell.location.as_ref());
+                    status = 1;
+                }
+            }
+            return Ok(status);
+        }
+        Mode::Add => {
+            let [name, expansion @ ..] = rest else {
+                return Err(ShellError::usage("abbr", "--add: requires a name"));
+            };
+            if expansion.is_empty() {
+                return Err(ShellError::usage(
+                    "abbr",
+                    format!("{}: requires an expansion", name),
+                ));
+            }
+            if name.chars().any(char::is_whitespace) {
+                return Err(ShellError::builtin(
+                    "abbr",
+                    format!("`{}': invalid abbreviation name", name),
+                ));
+            }
+            let mut abbreviation = Abbreviation::new(name, &expansion.join(" "));
+            abbreviation.position = position;
+            if let Some(pattern) = regex {
+                abbreviation.set_regex(pattern).map_err(|error| {
+                    ShellError::builtin("abbr", format!("{}: invalid regex: {}", pattern, error))
+                })?;
+            }
+            shell.abbreviations.add(abbreviation);
+        }
+    }
+    Ok(0)
+}
 
-    let elem_found_at: String = String::new();
+/// Where `command -p` looks for programs, whatever `PATH` is
+const DEFAULT_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
 
-    for folder in path_split {
-        let full_path = format!("{}/{}", folder, cmd);
-        let path_obj = std::path::Path::new(&full_path);
+/**
+    `command [-pVv] name [arg ...]`: runs a builtin or program, skipping aliases and functions.
+    `-p` searches a default `PATH`; `-v` prints what would run, `-V` describes it like `type`.
+*/
+pub fn handle_command(
+    args: Vec<&str>,
+    shell: &mut Shell,
+    io: &mut Io,
+) -> Result<ExitStatus, ShellError> {
+    let mut default_path = false;
+    let mut describe = false;
+    let mut verbose = false;
+    let mut rest: &[&str] = &args;
+    while let Some(&option) = rest.first() {
+        if option == "--" {
+            rest = &rest[1..];
+            break;
+        }
+        if !option.starts_with('-') || option.len() == 1 {
+            break;
+        }
+        for letter in option[1..].chars() {
+            match letter {
+                'p' => default_path = true,
+                'v' => describe = true,
+                'V' => verbose = true,
+                _ => {
+                    return Err(ShellError::usage(
+                        "command",
+                        format!("-{}: invalid option", letter),
+                    ));
+                }
+            }
+        }
+        rest = &rest[1..];
+    }
 
-        if path_obj.exists() {
-            use std::os::unix::fs::PermissionsExt;
-            let metadata = fs::metadata(&full_path);
-            if let Ok(meta) = metadata {
-                let permissions = meta.permissions();
-                let mode = permissions.mode();
-                if mode & 0o111 != 0 {
-                    println!("{} is {}", cmd, full_path);
-                    return;
+    if verbose {
+        return handle_type(rest.to_vec(), shell, io);
+    }
+    if describe {
+        let mut status = 0;
+        for name in rest {
+            match command_kinds(name, false, false, shell).into_iter().next() {
+                Some(CommandKind::Alias(value)) => {
+                    writeln!(io.stdout, "alias {}={}", name, traps::quote(&value))?
+                }
+                Some(CommandKind::File(path) | CommandKind::Hashed(path)) => {
+                    writeln!(io.stdout, "{}", shell.resolve_path(path).display())?
                 }
+                Some(_) => writeln!(io.stdout, "{}", name)?,
+                None => status = 1,
             }
         }
+        return Ok(status);
     }
 
-    if elem_found_at != "" {
-        println!("{} is {}", cmd, elem_found_at);
-        return;
+    let Some((&name, args)) = rest.split_first() else {
+        return Ok(0);
+    };
+    if let Some(builtin) = shell.builtins.lookup(name) {
+        return builtin.run(shell, args, io);
     }
 
-    println!("{}: not found", cmd)
+    io.stdout.flush()?;
+    let program: String = match hash::search(name, DEFAULT_PATH, &shell.cwd) {
+        Some((path, _)) if default_path && !name.contains('/') => path.display().to_string(),
+        _ => name.to_string(),
+    };
+    Ok(execute_external_program(&program, args.to_vec(), shell))
 }
 
-pub fn handle_exit() {
-    process::exit(0); // exit
-    // TODO: Implement better exit (stop running commands etc.)
+/**
+    Makes the shell stop running commands; it exits once the current line is done,
+    with status `n & 0xff` (the status of the last command without `n`).
+*/
+pub fn handle_exit(args: Vec<&str>, shell: &mut Shell) -> Result<ExitStatus, ShellError> {
+    let status: ExitStatus = match args.as_slice() {
+        [] => shell.last_status,
+        [n] => match n.parse::<i64>() {
+            Ok(n) => (n & 0xff) as ExitStatus,
+            Err(_) => {
+                shell.exit_status = Some(2);
+                return Err(ShellError::usage(
+                    "exit",
+                    format!("{}: numeric argument required", n),
+                ));
+            }
+        },
+        _ => return Err(ShellError::builtin("exit", "too many arguments")),
+    };
+    shell.exit_status = Some(status);
+    Ok(status)
+}
+
+/// Exits the shell with the given status, after running the EXIT trap
+pub fn exit_shell(status: i32, shell: &mut Shell) -> ! {
+    shell.last_status = status;
+    traps::run_exit_trap(shell);
+    let _ = io::stdout().flush();
+    process::exit(status)
 }
 
-pub fn handle_echo(args: Vec<&str>) {
-    let text_to_print: String = args.join(" ");
-    println!("{}", text_to_print);
-    return;
+/**
+    `echo [-neE] [arg ...]`: prints the arguments separated by spaces. `-n` leaves out the newline,
+    `-e` interprets backslash escapes (`\c` stops the output) and `-E` turns them off again.
+    Anything else starting with `-` is printed as it is.
+*/
+pub fn handle_echo(args: Vec<&str>, io: &mut Io) -> Result<ExitStatus, ShellError> {
+    let mut newline = true;
+    let mut escapes = false;
+    let mut words: &[&str] = &args;
+    while let Some(flags) = words.first().and_then(|word| word.strip_prefix('-'))
+        && !flags.is_empty()
+        && flags.chars().all(|flag| "neE".contains(flag))
+    {
+        for flag in flags.chars() {
+            match flag {
+                'n' => newline = false,
+                'e' => escapes = true,
+                _ => escapes = false,
+            }
+        }
+        words = &words[1..];
+    }
+
+    let text: String = words.join(" ");
+    if escapes {
+        let (bytes, stop) = printf::unescape(&text, printf::EscapeStyle::Echo);
+        io.stdout.write_all(&bytes)?;
+        if stop {
+            return Ok(0);
+        }
+    } else {
+        io.stdout.write_all(text.as_bytes())?;
+    }
+    if newline {
+        writeln!(io.stdout)?;
+    }
+    Ok(0)
 }
 
-pub fn execute_external_program(command: &str, args: Vec<&str>) {
-    let path: String = env::var("PATH").unwrap_or_default();
-    let path_split: str::Split<'_, &str> = path.split(":");
+/**
+    `printf [-v var] format [arg ...]`: prints the arguments formatted like C's `printf`,
+    reusing the format while arguments are left. `%b` expands backslash escapes in an argument
+    and `%q` quotes it for the shell. With `-v`, the output is assigned to `var` instead.
+*/
+pub fn handle_printf(
+    args: Vec<&str>,
+    shell: &mut Shell,
+    io: &mut Io,
+) -> Result<ExitStatus, ShellError> {
+    let mut variable: Option<&str> = None;
+    let mut rest: &[&str] = &args;
+    loop {
+        match rest.first() {
+            Some(&"-v") => {
+                let name = rest.get(1).ok_or_else(|| {
+                    ShellError::usage("printf", "-v: option requires an argument")
+                })?;
+                if !is_valid_variable_name(name) {
+                    return Err(ShellError::builtin(
+                        "printf",
+                        format!("`{}': not a valid identifier", name),
+                    ));
+                }
+                variable = Some(name);
+                rest = &rest[2..];
+            }
+            Some(&"--") => {
+                rest = &rest[1..];
+                break;
+            }
+            Some(option) if option.starts_with('-') && option.len() > 1 => {
+                return Err(ShellError::usage(
+                    "printf",
+                    format!("{}: invalid option", option),
+                ));
+            }
+            _ => break,
+        }
+    }
+    let Some((format, arguments)) = rest.split_first() else {
+        return Err(ShellError::usage(
+            "printf",
+            "usage: printf [-v var] format [arguments]",
+        ));
+    };
+
+    let formatted = printf::format(format, arguments);
+    match variable {
+        Some(name) => shell.set_var(name, &String::from_utf8_lossy(&formatted.output)),
+        None => io.stdout.write_all(&formatted.output)?,
+    }
+    for message in &formatted.errors {
+        error::report_to(
+            &mut io.stderr,
+            &ShellError::builtin("printf", message),
+            shell.location.as_ref(),
+        );
+    }
+    Ok(if formatted.errors.is_empty() { 0 } else { 1 })
+}
 
-    let mut elem_found_at: String = String::new();
+/// Variable names are letters, digits and underscores, not starting with a digit
+fn is_valid_variable_name(name: &str) -> bool {
+    name.chars()
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
 
-    for folder in path_split {
-        let full_path = format!("{}/{}", folder, command);
-        let path_obj = std::path::Path::new(&full_path);
+/// `declare [-aAx] [-p] [name[=value] ...]`: sets variables and their attributes, or shows them
+pub fn handle_declare(
+    args: Vec<&str>,
+    shell: &mut Shell,
+    io: &mut Io,
+) -> Result<ExitStatus, ShellError> {
+    let mut indexed = false;
+    let mut associative = false;
+    let mut export: Option<bool> = None;
+    let mut print = false;
 
-        if path_obj.exists() {
-            use std::os::unix::fs::PermissionsExt;
-            let metadata = fs::metadata(&full_path);
-            if let Ok(meta) = metadata {
-                let permissions = meta.permissions();
-                let mode = permissions.mode();
-                if mode & 0o111 != 0 {
-                    elem_found_at = full_path;
-                    break;
+    let mut rest: &[&str] = &args;
+    while let Some(arg) = rest.first()
+        && (arg.starts_with('-') || arg.starts_with('+'))
+        && arg.len() > 1
+    {
+        rest = &rest[1..];
+        if *arg == "--" {
+            break;
+        }
+        let enable = arg.starts_with('-');
+        for letter in arg[1..].chars() {
+            match letter {
+                'a' => indexed = enable,
+                'A' => associative = enable,
+                'x' => export = Some(enable),
+                'p' => print = true,
+                _ => {
+                    let message = format!("{}{}: invalid option", &arg[..1], letter);
+                    return Err(ShellError::usage("declare", message));
                 }
             }
         }
     }
+    if indexed && associative {
+        return Err(ShellError::usage(
+            "declare",
+            "-a and -A can't be used together",
+        ));
+    }
+
+    if print || rest.is_empty() {
+        let names: Vec<String> = if rest.is_empty() {
+            shell
+                .vars
+                .iter()
+                .filter(|(_, variable)| match &variable.value {
+                    Value::Indexed(_) => !associative,
+                    Value::Associative(_) => !indexed,
+                    Value::Scalar(_) => !indexed && !associative,
+                })
+                .filter(|(_, variable)| export.is_none_or(|export| variable.exported == export))
+                .map(|(name, _)| name.clone())
+                .collect()
+        } else {
+            rest.iter().map(|name| name.to_string()).collect()
+        };
+
+        let mut status = 0;
+        for name in names {
+            let Some(variable) = shell.vars.get(&name) else {
+                let message = format!("{}: not found", name);
+                error::report_to(
+                    &mut io.stderr,
+                    &ShellError::builtin("declare", message),
+                    shell.location.as_ref(),
+                );
+                status = 1;
+                continue;
+            };
+            let mut flags = String::new();
+            match variable.value {
+                Value::Indexed(_) => flags.push('a'),
+                Value::Associative(_) => flags.push('A'),
+                Value::Scalar(_) => {}
+            }
+            if variable.exported {
+                flags.push('x');
+            }
+            if flags.is_empty() {
+                flags.push('-');
+            }
+            let value = format_value(&variable.value);
+            writeln!(io.stdout, "declare -{} {}={}", flags, name, value)?;
+        }
+        return Ok(status);
+    }
 
-    if elem_found_at != "" {
-        // Execute the program and wait for it to finish
-        let mut cmd = process::Command::new(&elem_found_at);
-        cmd.args(&args)
-            .current_dir(env::current_dir().unwrap_or_default());
+    let mut status = 0;
+    for arg in rest {
+        let parsed = assignment::parse(arg);
+        let name: &str = parsed.as_ref().map_or(arg, |parsed| parsed.name.as_str());
+        if !is_valid_variable_name(name) {
+            let message = format!("`{}': not a valid identifier", arg);
+            error::report_to(
+                &mut io.stderr,
+                &ShellError::builtin("declare", message),
+                shell.location.as_ref(),
+            );
+            status = 1;
+            continue;
+        }
+
+        let mut result: Result<(), String> = Ok(());
+        if indexed || associative {
+            result = shell.declare_array(name, associative);
+        }
+        if let (Ok(()), Some(parsed)) = (&result, &parsed) {
+            // The executor expanded the value already; only the elements of a list are left
+            result = match &parsed.value {
+                AssignedValue::Scalar(value) => Ok(Expanded::Scalar(value.clone())),
+                value => assignment::expand_value(value, shell),
+            }
+            .and_then(|value| assignment::store(parsed, value, shell));
+        }
+        if let Err(message) = result {
+            error::report_to(
+                &mut io.stderr,
+                &ShellError::builtin("declare", message),
+                shell.location.as_ref(),
+            );
+            status = 1;
+            continue;
+        }
+
+        if let Some(export) = export
+            && let Some(variable) = shell.vars.get_mut(name)
+        {
+            variable.exported = export;
+        }
+    }
+    Ok(status)
+}
+
+/// `read [-rs] [-a array] [-d delim] [-n count] [-N count] [-p prompt] [-t timeout] [-u fd] [name ...]`
+pub fn handle_read(
+    args: Vec<&str>,
+    shell: &mut Shell,
+    io: &mut Io,
+) -> Result<ExitStatus, ShellError> {
+    let mut options = ReadOptions::default();
+    let mut array: Option<&str> = None;
+    let mut prompt: Option<&str> = None;
+    let mut silent = false;
+    let mut timeout: Option<Duration> = None;
+    let mut fd: Option<RawFd> = None;
+
+    let mut rest: &[&str] = &args;
+    while let Some(arg) = rest.first() {
+        if *arg == "--" {
+            rest = &rest[1..];
+            break;
+        }
+        if !arg.starts_with('-') || arg.len() == 1 {
+            break;
+        }
+        rest = &rest[1..];
+
+        for (index, letter) in arg[1..].char_indices() {
+            if !"adnNptu".contains(letter) {
+                match letter {
+                    'r' => options.raw = true,
+                    's' => silent = true,
+                    _ => {
+                        let message = format!("-{}: invalid option", letter);
+                        return Err(ShellError::usage("read", message));
+                    }
+                }
+                continue;
+            }
 
-        match cmd.spawn() {
-            Ok(mut child) => {
-                let _ = child.wait();
+            // The value is the rest of this argument or the next one
+            let attached = &arg[1 + index + letter.len_utf8()..];
+            let value: &str = if !attached.is_empty() {
+                attached
+            } else if let Some((value, others)) = rest.split_first() {
+                rest = others;
+                value
+            } else {
+                let message = format!("-{}: option requires an argument", letter);
+                return Err(ShellError::usage("read", message));
+            };
+            match letter {
+                'a' => array = Some(value),
+                'd' => options.delimiter = value.bytes().next().unwrap_or(0),
+                'n' | 'N' => {
+                    let count = value.parse::<usize>().map_err(|_| {
+                        ShellError::builtin("read", format!("{}: invalid number", value))
+                    })?;
+                    options.count = Some(count);
+                    options.exact_count = letter == 'N';
+                }
+                'p' => prompt = Some(value),
+                't' => {
+                    let seconds = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|seconds| *seconds >= 0.0 && seconds.is_finite())
+                        .ok_or_else(|| {
+                            let message = format!("{}: invalid timeout specification", value);
+                            ShellError::builtin("read", message)
+                        })?;
+                    timeout = Some(Duration::from_secs_f64(seconds));
+                }
+                _ => {
+                    let descriptor = value
+                        .parse::<RawFd>()
+                        .ok()
+                        .filter(|fd| unsafe { libc::fcntl(*fd, libc::F_GETFD) } != -1)
+                        .ok_or_else(|| {
+                            let message =
+                                format!("{}: invalid file descriptor: Bad file descriptor", value);
+                            ShellError::builtin("read", message)
+                        })?;
+                    fd = Some(descriptor);
+                }
             }
-            Err(_) => command_not_found(command),
+            break;
+        }
+    }
+
+    let names: &[&str] = rest;
+    for name in names.iter().chain(&array) {
+        if !is_valid_variable_name(name) {
+            return Err(ShellError::builtin(
+                "read",
+                format!("`{}': not a valid identifier", name),
+            ));
+        }
+    }
+
+    // An in-memory stream never blocks, so only a real fd is waited on
+    let input_fd: Option<RawFd> = fd.or(io.stdin_fd);
+    // `-t 0` only tells whether there is input
+    if timeout == Some(Duration::ZERO) {
+        return Ok(match input_fd {
+            Some(input_fd) if !read::is_readable(input_fd, Duration::ZERO) => 1,
+            _ => 0,
+        });
+    }
+    options.timeout = timeout.zip(input_fd);
+
+    let terminal = input_fd.is_some_and(|input_fd| unsafe { libc::isatty(input_fd) } == 1);
+    if let Some(prompt) = prompt
+        && terminal
+    {
+        write!(io.stderr, "{}", prompt)?;
+        io.stderr.flush()?;
+    }
+    let modes = match input_fd {
+        Some(input_fd) if terminal && (silent || options.count.is_some()) => {
+            TerminalModes::change(input_fd, !silent, options.count.is_none())
+        }
+        _ => None,
+    };
+
+    let input = match fd {
+        Some(fd) => {
+            // Borrowed: the fd stays open after reading
+            let mut file = ManuallyDrop::new(unsafe { fs::File::from_raw_fd(fd) });
+            read::read_input(&mut *file, &options)
+        }
+        None => read::read_input(&mut io.stdin, &options),
+    };
+    drop(modes);
+    let input = input.map_err(|error| ShellError::builtin("read", error::io_message(&error)))?;
+    if silent && terminal && input.end == ReadEnd::Delimiter {
+        writeln!(io.stderr)?;
+    }
+
+    let ifs: String = shell
+        .get_var("IFS")
+        .unwrap_or(expand::DEFAULT_IFS)
+        .to_string();
+    if let Some(array) = array {
+        shell.set_array(array, read::split_fields(&input.chars, &ifs, None));
+    } else if names.is_empty() {
+        shell.set_var("REPLY", &input.text());
+    } else {
+        // With -N the characters are taken as they are
+        let fields: Vec<String> = if options.exact_count {
+            vec![input.text()]
+        } else {
+            read::split_fields(&input.chars, &ifs, Some(names.len()))
+        };
+        for (index, name) in names.iter().enumerate() {
+            shell.set_var(name, fields.get(index).map_or("", String::as_str));
         }
-        return;
     }
 
-    // Fallback
-    command_not_found(command);
+    Ok(match input.end {
+        ReadEnd::Delimiter | ReadEnd::Count => 0,
+        ReadEnd::Eof => 1,
+        // Like a command killed by SIGALRM
+        ReadEnd::Timeout => 128 + libc::SIGALRM,
+    })
+}
+
+/// `test expression`: succeeds if the expression is true (see `conditional::test`)
+pub fn handle_test(args: Vec<&str>, shell: &mut Shell) -> Result<ExitStatus, ShellError> {
+    run_test("test", &args, shell)
+}
+
+/// `[ expression ]`: `test` with a closing bracket as the last argument
+pub fn handle_left_bracket(args: Vec<&str>, shell: &mut Shell) -> Result<ExitStatus, ShellError> {
+    match args.split_last() {
+        Some((&"]", args)) => run_test("[", args, shell),
+        _ => Err(ShellError::usage("[", "missing `]'")),
+    }
+}
+
+fn run_test(builtin: &str, args: &[&str], shell: &Shell) -> Result<ExitStatus, ShellError> {
+    match conditional::test(args, shell) {
+        Ok(true) => Ok(0),
+        Ok(false) => Ok(1),
+        Err(message) => Err(ShellError::usage(builtin, message)),
+    }
 }
 
-pub fn handle_print_working_directory() {
-    let binding = env::current_dir().unwrap_or_default();
-    let working_dir = binding.display();
+/**
+    Executes an external program in the foreground and waits for it to finish (or stop).
+    Returns its exit status (127 if it wasn't found).
+*/
+pub fn execute_external_program(command: &str, args: Vec<&str>, shell: &mut Shell) -> ExitStatus {
+    let setup = ChildSetup::foreground(shell.jobs.job_control_enabled());
+    let pid = match spawn_external_program(command, &args, setup, shell) {
+        Ok(pid) => pid,
+        Err(error) => {
+            shell.report(&error);
+            return error.status();
+        }
+    };
 
-    println!("{}", working_dir);
+    let pgid = shell.jobs.job_control_enabled().then_some(pid);
+    let mut full_command: Vec<&str> = vec![command];
+    full_command.extend(args);
+    let id = shell.jobs.add_job(pgid, vec![pid], full_command.join(" "));
+    let pipefail = shell.options.is_set(ShellOption::Pipefail);
+    shell.jobs.wait_for_job(id, pipefail)
 }
 
-pub fn handle_cd(args: Vec<&str>) {
-    let mut path_str: String = args[0].to_string();
+/**
+    Starts an external program without waiting for it, wired up as described by `setup`.
+    It runs in the working directory of the shell, with the exported variables as environment.
+    Returns the pid of the new process.
+*/
+pub fn spawn_external_program(
+    command: &str,
+    args: &[&str],
+    mut setup: ChildSetup,
+    shell: &mut Shell,
+) -> Result<i32, ShellError> {
+    setup
+        .inherit_standard_fds(shell)
+        .map_err(|error| ShellError::spawn(command, &error))?;
+    let search_path: String = shell.get_var("PATH").unwrap_or_default().to_string();
+
+    // Commands containing a slash are run directly, others are looked up in PATH
+    let (path, hashed): (PathBuf, bool) = if command.contains('/') {
+        (shell.resolve_path(command), false)
+    } else {
+        match shell.hash.resolve(command, &search_path, &shell.cwd) {
+            Some((path, hashed)) => (shell.resolve_path(path), hashed),
+            None => return Err(command_not_found(command)),
+        }
+    };
 
-    // Handle home directory paths
-    if path_str.starts_with("~") {
-        let home_dir = env::home_dir().unwrap_or_default();
-        let home_dir_str = home_dir.to_string_lossy();
-        path_str = path_str.replace("~", &home_dir_str);
+    match spawn_program(&path, command, args, &setup, shell) {
+        Ok(pid) => Ok(pid),
+        // The program was moved or removed since it was hashed: search PATH again
+        Err(error) if error.kind() == io::ErrorKind::NotFound && hashed => {
+            shell.hash.remove(command, &search_path);
+            spawn_external_program(command, args, setup, shell)
+        }
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Err(command_not_found(command)),
+        Err(error) => Err(ShellError::spawn(command, &error)),
     }
+}
 
-    let path: &Path = Path::new(&path_str);
-    if !env::set_current_dir(&path).is_ok() {
-        println!("cd: {}: No such file or directory", path_str);
+fn spawn_program(
+    path: &Path,
+    command: &str,
+    args: &[&str],
+    setup: &ChildSetup,
+    shell: &Shell,
+) -> io::Result<i32> {
+    let mut cmd = process::Command::new(path);
+    cmd.arg0(command)
+        .args(args)
+        .current_dir(&shell.cwd)
+        .env_clear()
+        .envs(shell.exported_vars());
+    if let Some(stdin) = &setup.stdin {
+        cmd.stdin(stdin.try_clone()?);
+    }
+    if let Some(stdout) = &setup.stdout {
+        cmd.stdout(stdout.try_clone()?);
+    }
+    if let Some(stderr) = &setup.stderr {
+        cmd.stderr(stderr.try_clone()?);
+    }
+
+    let process_group = setup.process_group;
+    let foreground = setup.foreground;
+    let fd_actions = setup.fd_actions.clone();
+    unsafe {
+        cmd.pre_exec(move || {
+            jobs::setup_child(process_group, foreground);
+            executor::apply_fd_actions(&fd_actions)
+        });
+    }
+
+    let pid = cmd.spawn()?.id() as i32;
+    // Set the group in the parent as well, so it exists before anyone waits for it
+    if let Some(group) = process_group {
+        unsafe {
+            libc::setpgid(pid, if group == 0 { pid } else { group });
+        }
+    }
+    Ok(pid)
+}
+
+/// `pwd [-LP]`: prints the working directory, as reached through symlinks (`-L`) or resolved (`-P`)
+pub fn handle_print_working_directory(
+    args: Vec<&str>,
+    shell: &mut Shell,
+    io: &mut Io,
+) -> Result<ExitStatus, ShellError> {
+    let physical = physical_option("pwd", &args)?.0;
+
+    if physical {
+        let working_dir = shell
+            .cwd
+            .canonicalize()
+            .map_err(|error| ShellError::builtin("pwd", error::io_message(&error)))?;
+        writeln!(io.stdout, "{}", working_dir.display())?;
+    } else {
+        writeln!(io.stdout, "{}", shell.cwd.display())?;
+    }
+    Ok(0)
+}
+
+/**
+    `cd [-L|-P] [dir]`: changes the working directory of the shell (to `$HOME` without `dir`).
+    `cd -` goes back to `$OLDPWD`, and relative directories are also searched in `CDPATH`.
+    By default `..` removes the last part of the path (`-L`), `-P` resolves symlinks first.
+*/
+pub fn handle_cd(
+    args: Vec<&str>,
+    shell: &mut Shell,
+    io: &mut Io,
+) -> Result<ExitStatus, ShellError> {
+    let (physical, operands) = physical_option("cd", &args)?;
+
+    let mut print_dir = false;
+    let target: String = match operands {
+        [] => shell
+            .get_var("HOME")
+            .ok_or_else(|| ShellError::builtin("cd", "HOME not set"))?
+            .to_string(),
+        ["-"] => {
+            print_dir = true;
+            shell
+                .get_var("OLDPWD")
+                .ok_or_else(|| ShellError::builtin("cd", "OLDPWD not set"))?
+                .to_string()
+        }
+        [dir] => match dir.strip_prefix('~') {
+            // Words are tilde-expanded already, this is for calls from outside the shell
+            Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+                format!("{}{}", shell.get_var("HOME").unwrap_or_default(), rest)
+            }
+            _ => dir.to_string(),
+        },
+        _ => return Err(ShellError::builtin("cd", "too many arguments")),
+    };
+
+    let candidates: Vec<(PathBuf, bool)> = cd_candidates(&target, physical, shell);
+    let Some((path, from_cdpath)) = candidates.into_iter().find(|(path, _)| path.is_dir()) else {
+        let message = match fs::metadata(shell.resolve_path(&target)) {
+            Ok(_) => "Not a directory",
+            Err(_) => "No such file or directory",
+        };
+        return Err(ShellError::builtin(
+            "cd",
+            format!("{}: {}", target, message),
+        ));
+    };
+
+    // Children start in the directory, so it must be searchable like for `chdir`
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| ShellError::builtin("cd", format!("{}: invalid path", target)))?;
+    if unsafe { libc::access(c_path.as_ptr(), libc::X_OK) } != 0 {
+        let error = io::Error::last_os_error();
+        return Err(ShellError::builtin(
+            "cd",
+            format!("{}: {}", target, error::io_message(&error)),
+        ));
+    }
+
+    let path: PathBuf = if physical {
+        path.canonicalize().map_err(|error| {
+            ShellError::builtin("cd", format!("{}: {}", target, error::io_message(&error)))
+        })?
+    } else {
+        path
+    };
+
+    // Only the shell's own directory changes; children are started in it
+    let old_dir = std::mem::replace(&mut shell.cwd, path);
+    shell.set_var("OLDPWD", &old_dir.to_string_lossy());
+    let new_dir = shell.cwd.to_string_lossy().into_owned();
+    shell.set_var("PWD", &new_dir);
+    if print_dir || from_cdpath {
+        writeln!(io.stdout, "{}", new_dir)?;
+    }
+    save_dir_stack(shell, io);
+    Ok(0)
+}
+
+/**
+    Where `cd dir` may go, in order: the directories of `CDPATH` (for relative names not starting
+    with `.` or `..`), then the working directory. Paths are made absolute; `.` and `..` are
+    removed unless they are to be resolved on the file system (`physical`, for `cd -P`).
+    The flag tells whether a path came from a non-empty `CDPATH` entry.
+*/
+fn cd_candidates(target: &str, physical: bool, shell: &Shell) -> Vec<(PathBuf, bool)> {
+    let absolute = |path: PathBuf| {
+        if physical {
+            path
+        } else {
+            normalize_path(&path)
+        }
+    };
+    let mut candidates: Vec<(PathBuf, bool)> = Vec::new();
+    let relative = !target.starts_with('/')
+        && !target.starts_with("./")
+        && !target.starts_with("../")
+        && target != "."
+        && target != "..";
+    if relative && let Some(cdpath) = shell.get_var("CDPATH") {
+        for entry in cdpath.split(':') {
+            let directory = if entry.is_empty() { "." } else { entry };
+            let path = absolute(shell.resolve_path(directory).join(target));
+            candidates.push((path, !entry.is_empty()));
+        }
+    }
+    candidates.push((absolute(shell.resolve_path(target)), false));
+    candidates
+}
+
+/// Removes `.` and `..` from an absolute path without looking at the file system
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
+        }
+    }
+    normalized
+}
+
+/// Parses the `-L`/`-P` options of `cd` and `pwd` (the last one counts); returns the rest as well
+fn physical_option<'a>(
+    builtin: &str,
+    args: &'a [&'a str],
+) -> Result<(bool, &'a [&'a str]), ShellError> {
+    let mut physical = false;
+    let mut rest: &[&str] = args;
+    while let Some(&option) = rest.first() {
+        if option == "--" {
+            rest = &rest[1..];
+            break;
+        }
+        if !option.starts_with('-') || option.len() == 1 {
+            break;
+        }
+        for letter in option[1..].chars() {
+            match letter {
+                'L' => physical = false,
+                'P' => physical = true,
+                _ => {
+                    return Err(ShellError::usage(
+                        builtin,
+                        format!("-{}: invalid option", letter),
+                    ));
+                }
+            }
+        }
+        rest = &rest[1..];
+    }
+    Ok((physical, rest))
+}
+
+/**
+    Handles the `pushd` builtin: goes to a directory and puts the old one on the stack,
+    swaps the top two directories without arguments, or rotates the stack with `+N`/`-N`.
+    `-n` only changes the stack, not the directory.
+*/
+pub fn handle_pushd(
+    args: Vec<&str>,
+    shell: &mut Shell,
+    io: &mut Io,
+) -> Result<ExitStatus, ShellError> {
+    let (no_cd, operands) = dir_stack_options("pushd", &args)?;
+    let mut stack: Vec<PathBuf> = shell.dir_stack.with_cwd(&shell.cwd);
+
+    match operands {
+        [] => {
+            if stack.len() < 2 {
+                return Err(ShellError::builtin("pushd", "no other directory"));
+            }
+            if !no_cd {
+                stack.swap(0, 1);
+                change_dir_for("pushd", &stack[0], shell, io)?;
+                stack[0] = shell.cwd.clone();
+            }
+        }
+        [operand] => match dir_stack::parse_index(operand, stack.len()) {
+            Some(Some(index)) => {
+                stack.rotate_left(index);
+                if no_cd {
+                    // The working directory stays on top, the rest is rotated
+                    let cwd = stack.remove(stack.len() - index);
+                    stack.insert(0, cwd);
+                } else {
+                    change_dir_for("pushd", &stack[0], shell, io)?;
+                    stack[0] = shell.cwd.clone();
+                }
+            }
+            Some(None) => return Err(index_out_of_range("pushd", operand)),
+            None => {
+                if no_cd {
+                    stack.insert(1, normalize_path(&shell.resolve_path(operand)));
+                } else {
+                    change_dir_for("pushd", Path::new(operand), shell, io)?;
+                    stack.insert(0, shell.cwd.clone());
+                }
+            }
+        },
+        _ => return Err(ShellError::builtin("pushd", "too many arguments")),
+    }
+
+    stack.remove(0);
+    shell.dir_stack.set_entries(stack);
+    save_dir_stack(shell, io);
+    print_dir_stack(shell, io)
+}
+
+/**
+    Handles the `popd` builtin: removes the top directory from the stack and goes to the next one,
+    or removes the `+N`/`-N`th directory. `-n` leaves the working directory alone.
+*/
+pub fn handle_popd(
+    args: Vec<&str>,
+    shell: &mut Shell,
+    io: &mut Io,
+) -> Result<ExitStatus, ShellError> {
+    let (no_cd, operands) = dir_stack_options("popd", &args)?;
+    let mut stack: Vec<PathBuf> = shell.dir_stack.with_cwd(&shell.cwd);
+    if stack.len() < 2 {
+        return Err(ShellError::builtin("popd", "directory stack empty"));
+    }
+
+    let index: usize = match operands {
+        [] => 0,
+        [operand] => match dir_stack::parse_index(operand, stack.len()) {
+            Some(Some(index)) => index,
+            Some(None) => return Err(index_out_of_range("popd", operand)),
+            None => {
+                return Err(ShellError::usage(
+                    "popd",
+                    format!("{}: invalid argument", operand),
+                ));
+            }
+        },
+        _ => return Err(ShellError::builtin("popd", "too many arguments")),
+    };
+
+    if index == 0 && no_cd {
+        // Without going anywhere, the top can't be removed, so the one below it is
+        stack.remove(1);
+    } else if index == 0 {
+        change_dir_for("popd", &stack[1], shell, io)?;
+        stack.remove(0);
+        stack[0] = shell.cwd.clone();
+    } else {
+        stack.remove(index);
+    }
+
+    stack.remove(0);
+    shell.dir_stack.set_entries(stack);
+    save_dir_stack(shell, io);
+    print_dir_stack(shell, io)
+}
+
+/**
+    Handles the `dirs` builtin: shows the directory stack with `~` for `$HOME` (`-l` shows full
+    paths), one per line with `-p`, numbered with `-v`, or only the `+N`/`-N`th directory.
+    `-c` clears the stack.
+*/
+pub fn handle_dirs(
+    args: Vec<&str>,
+    shell: &mut Shell,
+    io: &mut Io,
+) -> Result<ExitStatus, ShellError> {
+    let mut clear = false;
+    let mut long = false;
+    let mut per_line = false;
+    let mut verbose = false;
+    let mut index: Option<usize> = None;
+    let len = shell.dir_stack.entries().len() + 1;
+
+    for arg in &args {
+        if let Some(parsed) = dir_stack::parse_index(arg, len) {
+            index = Some(parsed.ok_or_else(|| index_out_of_range("dirs", arg))?);
+            continue;
+        }
+        match arg.strip_prefix('-') {
+            Some(flags) if !flags.is_empty() => {
+                for flag in flags.chars() {
+                    match flag {
+                        'c' => clear = true,
+                        'l' => long = true,
+                        'p' => per_line = true,
+                        'v' => verbose = true,
+                        _ => {
+                            return Err(ShellError::usage(
+                                "dirs",
+                                format!("-{}: invalid option", flag),
+                            ));
+                        }
+                    }
+                }
+            }
+            _ => {
+                return Err(ShellError::usage(
+                    "dirs",
+                    format!("{}: invalid argument", arg),
+                ));
+            }
+        }
+    }
+
+    if clear {
+        shell.dir_stack.set_entries(Vec::new());
+        save_dir_stack(shell, io);
+        return Ok(0);
+    }
+
+    let home: Option<&str> = if long { None } else { shell.get_var("HOME") };
+    let dirs: Vec<String> = shell
+        .dir_stack
+        .with_cwd(&shell.cwd)
+        .iter()
+        .map(|dir| dir_stack::abbreviate_home(dir, home))
+        .collect();
+
+    if let Some(index) = index {
+        if verbose {
+            writeln!(io.stdout, "{:2}  {}", index, dirs[index])?;
+        } else {
+            writeln!(io.stdout, "{}", dirs[index])?;
+        }
+    } else if verbose {
+        for (index, dir) in dirs.iter().enumerate() {
+            writeln!(io.stdout, "{:2}  {}", index, dir)?;
+        }
+    } else if per_line {
+        for dir in &dirs {
+            writeln!(io.stdout, "{}", dir)?;
+        }
+    } else {
+        writeln!(io.stdout, "{}", dirs.join(" "))?;
+    }
+    Ok(0)
+}
+
+/// Parses the `-n` option of `pushd` and `popd` (`-N` is an index, not an option)
+fn dir_stack_options<'a, 'b>(
+    builtin: &str,
+    args: &'b [&'a str],
+) -> Result<(bool, &'b [&'a str]), ShellError> {
+    let mut no_cd = false;
+    let mut rest: &[&str] = args;
+    while let Some(&arg) = rest.first() {
+        match arg {
+            "-n" => no_cd = true,
+            "--" => {
+                rest = &rest[1..];
+                break;
+            }
+            _ if arg.starts_with('-')
+                && arg.len() > 1
+                && !arg[1..].bytes().all(|byte| byte.is_ascii_digit()) =>
+            {
+                return Err(ShellError::usage(
+                    builtin,
+                    format!("{}: invalid option", arg),
+                ));
+            }
+            _ => break,
+        }
+        rest = &rest[1..];
+    }
+    Ok((no_cd, rest))
+}
+
+/// Goes to a directory like `cd` does, reporting errors as the given builtin's
+fn change_dir_for(
+    builtin: &str,
+    dir: &Path,
+    shell: &mut Shell,
+    io: &mut Io,
+) -> Result<(), ShellError> {
+    let dir = dir.to_string_lossy();
+    // The stack is printed instead of the directory
+    let mut cd_io = Io::new(io::empty(), io::sink(), &mut io.stderr);
+    match handle_cd(vec!["--", &dir], shell, &mut cd_io) {
+        Ok(_) => Ok(()),
+        Err(ShellError::Builtin {
+            message, status, ..
+        }) => Err(ShellError::Builtin {
+            builtin: builtin.to_string(),
+            message,
+            status,
+        }),
+        Err(error) => Err(error),
+    }
+}
+
+fn index_out_of_range(builtin: &str, index: &str) -> ShellError {
+    ShellError::builtin(
+        builtin,
+        format!("{}: directory stack index out of range", index),
+    )
+}
+
+/// The output of `pushd` and `popd`: the stack like `dirs` shows it
+fn print_dir_stack(shell: &mut Shell, io: &mut Io) -> Result<ExitStatus, ShellError> {
+    handle_dirs(vec![], shell, io)
+}
+
+/// Persists the directory stack (if it is persisted at all) after it or the directory changed
+fn save_dir_stack(shell: &mut Shell, io: &mut Io) {
+    shell.configure_dir_stack();
+    if let Err(error) = shell.dir_stack.save(&shell.cwd) {
+        let error = ShellError::builtin("dirs", error::io_message(&error));
+        error::report_to(&mut io.stderr, &error, shell.location.as_ref());
+    }
+}
+
+pub fn handle_history(
+    args: Vec<&str>,
+    shell: &mut Shell,
+    io: &mut Io,
+) -> Result<ExitStatus, ShellError> {
+    let cwd = shell.cwd.clone();
+    let history = &mut shell.history;
+    match args.first().copied() {
+        None => print_history(history, history.len(), io)?,
+        Some("-c") => history.clear(),
+        Some("-d") => {
+            let Some(offset) = args.get(1) else {
+                return Err(ShellError::usage(
+                    "history",
+                    "-d: option requires an argument",
+                ));
+            };
+            // A leading minus sign counts from the end, anything after that is a range like "3-5"
+            let (sign, rest) = match offset.strip_prefix('-') {
+                Some(rest) => ("-", rest),
+                None => ("", *offset),
+            };
+            let result = match rest.split_once('-') {
+                Some((start, end)) => {
+                    let start = format!("{}{}", sign, start);
+                    match (start.parse::<i64>(), end.parse::<i64>()) {
+                        (Ok(start), Ok(end)) => history.delete_range(start, end),
+                        _ => Err(format!("{}: history position out of range", offset)),
+                    }
+                }
+                None => match offset.parse::<i64>() {
+                    Ok(offset) => history.delete(offset),
+                    Err(_) => Err(format!("{}: history position out of range", offset)),
+                },
+            };
+            result.map_err(|message| ShellError::builtin("history", message))?;
+        }
+        Some(flag @ ("-w" | "-r")) => {
+            let path: PathBuf = match args.get(1) {
+                Some(file) => cwd.join(file),
+                None => match &history.file {
+                    Some(file) => file.clone(),
+                    None => return Err(ShellError::builtin("history", "no history file")),
+                },
+            };
+            let result = if flag == "-w" {
+                history.write_file(&path)
+            } else {
+                history.read_file(&path)
+            };
+            result.map_err(|error| {
+                ShellError::builtin("history", format!("{}: {}", path.display(), error))
+            })?;
+        }
+        Some(count) => match count.parse::<usize>() {
+            Ok(count) => print_history(history, count, io)?,
+            Err(_) => {
+                return Err(ShellError::usage(
+                    "history",
+                    format!("{}: numeric argument required", count),
+                ));
+            }
+        },
+    }
+    Ok(0)
+}
+
+fn print_history(history: &History, count: usize, io: &mut Io) -> io::Result<()> {
+    let skip: usize = history.len().saturating_sub(count);
+    for (index, entry) in history.entries().iter().enumerate().skip(skip) {
+        writeln!(io.stdout, "{:>5}  {}", index + 1, entry.command)?;
+    }
+    Ok(())
+}
+
+/// The job a `fg`/`bg`/`disown` argument refers to (the current job without one)
+fn job_argument(builtin: &str, args: &[&str], shell: &Shell) -> Result<jobs::Job, ShellError> {
+    let id = match args.first() {
+        Some(spec) => shell.jobs.resolve_job_spec(spec),
+        None => (shell.jobs.current_job()).ok_or_else(|| "current: no such job".to_string()),
+    }
+    .map_err(|message| ShellError::builtin(builtin, message))?;
+    (shell.jobs.get_job(id))
+        .ok_or_else(|| ShellError::builtin(builtin, format!("%{}: no such job", id)))
+}
+
+pub fn handle_jobs(
+    args: Vec<&str>,
+    shell: &mut Shell,
+    io: &mut Io,
+) -> Result<ExitStatus, ShellError> {
+    let mut with_pid = false;
+    let mut pids_only = false;
+    let mut running_only = false;
+    let mut stopped_only = false;
+    let mut specs: Vec<&str> = Vec::new();
+
+    for arg in args {
+        match arg {
+            "-l" => with_pid = true,
+            "-p" => pids_only = true,
+            "-r" => running_only = true,
+            "-s" => stopped_only = true,
+            flag if flag.starts_with('-') && flag.len() > 1 => {
+                return Err(ShellError::usage(
+                    "jobs",
+                    format!("{}: invalid option", flag),
+                ));
+            }
+            spec => specs.push(spec),
+        }
+    }
+
+    shell.jobs.update_job_states();
+    let mut selected: Vec<jobs::Job> = Vec::new();
+    let mut status = 0;
+    if specs.is_empty() {
+        selected = shell.jobs.list_jobs();
+    } else {
+        for spec in specs {
+            match shell
+                .jobs
+                .resolve_job_spec(spec)
+                .map(|id| shell.jobs.get_job(id))
+            {
+                Ok(Some(job)) => selected.push(job),
+                Ok(None) => {}
+                Err(message) => {
+                    error::report_to(
+                        &mut io.stderr,
+                        &ShellError::builtin("jobs", message),
+                        shell.location.as_ref(),
+                    );
+                    status = 1;
+                }
+            }
+        }
+    }
+
+    for job in selected {
+        if (running_only && job.is_stopped()) || (stopped_only && !job.is_stopped()) {
+            continue;
+        }
+        if pids_only {
+            writeln!(io.stdout, "{}", job.leader())?;
+        } else {
+            writeln!(io.stdout, "{}", shell.jobs.format_job(&job, with_pid))?;
+        }
+        // Finished jobs are reported once, then forgotten
+        if job.is_completed() {
+            shell.jobs.remove_job(job.id);
+        }
+    }
+    Ok(status)
+}
+
+pub fn handle_fg(
+    args: Vec<&str>,
+    shell: &mut Shell,
+    io: &mut Io,
+) -> Result<ExitStatus, ShellError> {
+    if !shell.jobs.job_control_enabled() {
+        return Err(ShellError::builtin("fg", "no job control"));
+    }
+    let job = job_argument("fg", &args, shell)?;
+
+    writeln!(io.stdout, "{}", job.command)?;
+    io.stdout.flush()?;
+    (shell.jobs.continue_job(job.id)).map_err(|message| ShellError::builtin("fg", message))?;
+    let pipefail = shell.options.is_set(ShellOption::Pipefail);
+    Ok(shell.jobs.wait_for_job(job.id, pipefail))
+}
+
+pub fn handle_bg(
+    args: Vec<&str>,
+    shell: &mut Shell,
+    io: &mut Io,
+) -> Result<ExitStatus, ShellError> {
+    if !shell.jobs.job_control_enabled() {
+        return Err(ShellError::builtin("bg", "no job control"));
+    }
+    let job = job_argument("bg", &args, shell)?;
+    if !job.is_stopped() {
+        let message = format!("job {} already in background", job.id);
+        error::report_to(
+            &mut io.stderr,
+            &ShellError::builtin("bg", message),
+            shell.location.as_ref(),
+        );
+        return Ok(0);
+    }
+
+    (shell.jobs.continue_job(job.id)).map_err(|message| ShellError::builtin("bg", message))?;
+    writeln!(io.stdout, "[{}]+ {} &", job.id, job.command)?;
+    Ok(0)
+}
+
+/**
+    Waits for the given jobs (`%n`) or processes (pids) to finish, or for all jobs without arguments.
+    Returns the status of the last one waited for.
+*/
+pub fn handle_wait(
+    args: Vec<&str>,
+    shell: &mut Shell,
+    io: &mut Io,
+) -> Result<ExitStatus, ShellError> {
+    let pipefail = shell.options.is_set(ShellOption::Pipefail);
+    if args.is_empty() {
+        for job in shell.jobs.list_jobs() {
+            shell.jobs.wait_for_completion(job.id, pipefail);
+        }
+        return Ok(0);
+    }
+
+    let mut status = 0;
+    for arg in args {
+        let id: Result<usize, String> = if arg.starts_with('%') {
+            shell.jobs.resolve_job_spec(arg)
+        } else {
+            match arg.parse::<i32>() {
+                Ok(pid) => (shell.jobs.job_of_pid(pid))
+                    .ok_or_else(|| format!("pid {} is not a child of this shell", pid)),
+                Err(_) => Err(format!("`{}': not a pid or valid job spec", arg)),
+            }
+        };
+        status = match id {
+            Ok(id) => shell.jobs.wait_for_completion(id, pipefail),
+            Err(message) => {
+                error::report_to(
+                    &mut io.stderr,
+                    &ShellError::builtin("wait", message),
+                    shell.location.as_ref(),
+                );
+                127
+            }
+        };
+    }
+    Ok(status)
+}
+
+/// Removes jobs from the table, so the shell no longer tracks (or waits for) them
+pub fn handle_disown(
+    args: Vec<&str>,
+    shell: &mut Shell,
+    io: &mut Io,
+) -> Result<ExitStatus, ShellError> {
+    if args.first() == Some(&"-a") {
+        for job in shell.jobs.list_jobs() {
+            shell.jobs.remove_job(job.id);
+        }
+        return Ok(0);
+    }
+
+    if args.is_empty() {
+        let job = job_argument("disown", &args, shell)?;
+        shell.jobs.remove_job(job.id);
+        return Ok(0);
+    }
+
+    let mut status = 0;
+    for spec in args {
+        match shell.jobs.resolve_job_spec(spec) {
+            Ok(id) => {
+                shell.jobs.remove_job(id);
+            }
+            Err(message) => {
+                error::report_to(
+                    &mut io.stderr,
+                    &ShellError::builtin("disown", message),
+                    shell.location.as_ref(),
+                );
+                status = 1;
+            }
+        }
+    }
+    Ok(status)
+}
+
+/**
+    `trap [-lp] [[action] condition...]`: sets commands to run when a signal arrives
+    or on the pseudo-signals EXIT, ERR and DEBUG.
+*/
+pub fn handle_trap(
+    args: Vec<&str>,
+    shell: &mut Shell,
+    io: &mut Io,
+) -> Result<ExitStatus, ShellError> {
+    let mut args: &[&str] = &args;
+
+    match args.first().copied() {
+        Some("-l") => {
+            let names: Vec<String> = signals::signal_names()
+                .map(|(number, name)| format!("{:>2}) SIG{:<8}", number, name))
+                .collect();
+            for row in names.chunks(5) {
+                writeln!(io.stdout, "{}", row.join(" ").trim_end())?;
+            }
+            return Ok(0);
+        }
+        None | Some("-p") => {
+            let conditions = args.get(1..).unwrap_or_default();
+            return print_traps(conditions, shell, io);
+        }
+        Some("--") => args = &args[1..],
+        _ => {}
+    }
+
+    // A single condition (or `-` as action) resets the conditions
+    let (action, conditions): (Option<&str>, &[&str]) = match args {
+        [] => return print_traps(&[], shell, io),
+        [condition] => (None, std::slice::from_ref(condition)),
+        ["-", conditions @ ..] => (None, conditions),
+        [action, conditions @ ..] => (Some(*action), conditions),
+    };
+
+    let mut status = 0;
+    for spec in conditions {
+        match parse_condition(spec) {
+            Ok(condition) => shell.traps.set(condition, action),
+            Err(error) => {
+                error::report_to(&mut io.stderr, &error, shell.location.as_ref());
+                status = 1;
+            }
+        }
+    }
+    Ok(status)
+}
+
+fn parse_condition(spec: &str) -> Result<Condition, ShellError> {
+    if spec.eq_ignore_ascii_case("RETURN") {
+        // Nothing could trigger it: there are no functions or sourced scripts yet
+        return Err(ShellError::builtin(
+            "trap",
+            format!("{}: unsupported pseudo-signal", spec),
+        ));
+    }
+    Condition::parse(spec).ok_or_else(|| {
+        ShellError::builtin("trap", format!("{}: invalid signal specification", spec))
+    })
+}
+
+/// Prints traps as commands that would set them again, e.g. `trap -- 'echo bye' EXIT`
+fn print_traps(conditions: &[&str], shell: &Shell, io: &mut Io) -> Result<ExitStatus, ShellError> {
+    if conditions.is_empty() {
+        for (condition, action) in shell.traps.list() {
+            writeln!(
+                io.stdout,
+                "trap -- {} {}",
+                traps::quote(&action),
+                condition.name()
+            )?;
+        }
+        return Ok(0);
+    }
+
+    let mut status = 0;
+    for spec in conditions {
+        match parse_condition(spec) {
+            Ok(condition) => {
+                if let Some(action) = shell.traps.get(condition) {
+                    writeln!(
+                        io.stdout,
+                        "trap -- {} {}",
+                        traps::quote(&action),
+                        condition.name()
+                    )?;
+                }
+            }
+            Err(error) => {
+                error::report_to(&mut io.stderr, &error, shell.location.as_ref());
+                status = 1;
+            }
+        }
+    }
+    Ok(status)
+}
+
+/// A value as it is assigned again: quoted, or an array like `([0]='a' [1]='b')`
+fn format_value(value: &Value) -> String {
+    if let Value::Scalar(value) = value {
+        return traps::quote(value);
+    }
+    let elements: Vec<String> = value
+        .keys()
+        .into_iter()
+        .zip(value.values())
+        .map(|(key, element)| {
+            let plain = !key.is_empty()
+                && key
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+            let key = if plain { key } else { traps::quote(&key) };
+            format!("[{}]={}", key, traps::quote(element))
+        })
+        .collect();
+    format!("({})", elements.join(" "))
+}
+
+/**
+    `set [-efnux] [-o option]`: turns shell options on (`-`) or off (`+`).
+    Without arguments it prints the variables, `set -o`/`set +o` print the options.
+*/
+pub fn handle_set(
+    args: Vec<&str>,
+    shell: &mut Shell,
+    io: &mut Io,
+) -> Result<ExitStatus, ShellError> {
+    if args.is_empty() {
+        for (name, variable) in &shell.vars {
+            writeln!(io.stdout, "{}={}", name, format_value(&variable.value))?;
+        }
+        return Ok(0);
+    }
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg {
+            "-o" | "+o" => {
+                let enable = arg == "-o";
+                let Some(name) = args.next() else {
+                    print_options(enable, shell, io)?;
+                    return Ok(0);
+                };
+                let option = ShellOption::from_name(name).ok_or_else(|| {
+                    ShellError::builtin("set", format!("{}: invalid option name", name))
+                })?;
+                shell.options.set(option, enable);
+            }
+            "--" | "-" => break,
+            flags if flags.starts_with('-') || flags.starts_with('+') => {
+                (shell.options.apply_letters(flags))
+                    .map_err(|message| ShellError::usage("set", message))?;
+            }
+            _ => {
+                return Err(ShellError::builtin(
+                    "set",
+                    format!("{}: positional parameters are not supported", arg),
+                ));
+            }
+        }
+    }
+    Ok(0)
+}
+
+/// `set -o` lists the options with their state, `set +o` as commands restoring them
+fn print_options(as_table: bool, shell: &Shell, io: &mut Io) -> io::Result<()> {
+    for option in ShellOption::all() {
+        let enabled = shell.options.is_set(option);
+        if as_table {
+            writeln!(
+                io.stdout,
+                "{:<15}\t{}",
+                option.name(),
+                if enabled { "on" } else { "off" }
+            )?;
+        } else {
+            let sign = if enabled { '-' } else { '+' };
+            writeln!(io.stdout, "set {}o {}", sign, option.name())?;
+        }
     }
+    Ok(())
 }
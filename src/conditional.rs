@@ -0,0 +1,476 @@
+use crate::expand;
+use crate::glob;
+use crate::options::ShellOption;
+use crate::shell::Shell;
+use regex::Regex;
+use std::cmp::Ordering;
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::Path;
+
+/// The operators taking one operand, like `-f file` or `-z string`
+const UNARY_OPERATORS: &[&str] = &[
+    "-a", "-b", "-c", "-d", "-e", "-f", "-g", "-G", "-h", "-k", "-L", "-n", "-N", "-o", "-O", "-p",
+    "-r", "-s", "-S", "-t", "-u", "-v", "-w", "-x", "-z",
+];
+
+/// The operators comparing two operands, like `a = b` or `1 -lt 2`
+const BINARY_OPERATORS: &[&str] = &[
+    "=", "==", "!=", "<", ">", "-eq", "-ne", "-lt", "-le", "-gt", "-ge", "-nt", "-ot", "-ef",
+];
+
+pub fn is_unary_operator(word: &str) -> bool {
+    UNARY_OPERATORS.contains(&word)
+}
+
+pub fn is_binary_operator(word: &str) -> bool {
+    BINARY_OPERATORS.contains(&word)
+}
+
+/// Evaluates a unary operator (which must be one of `UNARY_OPERATORS`)
+pub fn unary(operator: &str, operand: &str, shell: &Shell) -> bool {
+    match operator {
+        "-n" => return !operand.is_empty(),
+        "-z" => return operand.is_empty(),
+        "-o" => {
+            return ShellOption::from_name(operand)
+                .is_some_and(|option| shell.options.is_set(option));
+        }
+        "-v" => return shell.get_var(operand).is_some(),
+        "-t" => {
+            return operand
+                .trim()
+                .parse::<i32>()
+                .is_ok_and(|fd| unsafe { libc::isatty(fd) } == 1);
+        }
+        _ => {}
+    }
+
+    // The rest are file tests; an empty name is no file (not the current directory)
+    if operand.is_empty() {
+        return false;
+    }
+    let path = shell.resolve_path(operand);
+    let metadata = match operator {
+        "-h" | "-L" => fs::symlink_metadata(&path),
+        _ => fs::metadata(&path),
+    };
+    let Ok(metadata) = metadata else {
+        return false;
+    };
+    let file_type = metadata.file_type();
+    match operator {
+        "-a" | "-e" => true,
+        "-b" => file_type.is_block_device(),
+        "-c" => file_type.is_char_device(),
+        "-d" => file_type.is_dir(),
+        "-f" => file_type.is_file(),
+        "-g" => metadata.mode() & libc::S_ISGID != 0,
+        "-G" => metadata.gid() == unsafe { libc::getegid() },
+        "-h" | "-L" => file_type.is_symlink(),
+        "-k" => metadata.mode() & libc::S_ISVTX != 0,
+        "-N" => metadata.mtime() > metadata.atime(),
+        "-O" => metadata.uid() == unsafe { libc::geteuid() },
+        "-p" => file_type.is_fifo(),
+        "-r" => is_accessible(&path, libc::R_OK),
+        "-s" => metadata.len() > 0,
+        "-S" => file_type.is_socket(),
+        "-u" => metadata.mode() & libc::S_ISUID != 0,
+        "-w" => is_accessible(&path, libc::W_OK),
+        "-x" => is_accessible(&path, libc::X_OK),
+        _ => false,
+    }
+}
+
+/// Whether the shell may read, write or execute a file (by the real user, like `access(2)`)
+fn is_accessible(path: &Path, mode: libc::c_int) -> bool {
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    unsafe { libc::access(path.as_ptr(), mode) == 0 }
+}
+
+/**
+    Evaluates a binary operator (one of `BINARY_OPERATORS`) with plain string comparisons.
+    Fails if an operand of an integer comparison isn't an integer.
+*/
+pub fn binary(operator: &str, left: &str, right: &str, shell: &Shell) -> Result<bool, String> {
+    let result = match operator {
+        "=" | "==" => left == right,
+        "!=" => left != right,
+        "<" => left < right,
+        ">" => left > right,
+        "-nt" | "-ot" => {
+            let modified = |name: &str| {
+                fs::metadata(shell.resolve_path(name))
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+            };
+            let ordering = match (modified(left), modified(right)) {
+                (Some(left), Some(right)) => left.cmp(&right),
+                // A file that exists is newer than one that doesn't
+                (Some(_), None) => Ordering::Greater,
+                (None, Some(_)) => Ordering::Less,
+                (None, None) => return Ok(false),
+            };
+            let wanted = if operator == "-nt" {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            };
+            ordering == wanted
+        }
+        "-ef" => {
+            let identity = |name: &str| {
+                fs::metadata(shell.resolve_path(name))
+                    .ok()
+                    .map(|metadata| (metadata.dev(), metadata.ino()))
+            };
+            identity(left).is_some_and(|left| Some(left) == identity(right))
+        }
+        _ => {
+            let left = parse_integer(left)?;
+            let right = parse_integer(right)?;
+            match operator {
+                "-eq" => left == right,
+                "-ne" => left != right,
+                "-lt" => left < right,
+                "-le" => left <= right,
+                "-gt" => left > right,
+                _ => left >= right,
+            }
+        }
+    };
+    Ok(result)
+}
+
+/// Reads an operand of `-eq` and friends (surrounding blanks are allowed)
+fn parse_integer(text: &str) -> Result<i64, String> {
+    let trimmed = text.trim_matches([' ', '\t', '\n']);
+    let digits = trimmed.strip_prefix(['+', '-']).unwrap_or(trimmed);
+    if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(format!("{}: integer expression expected", text));
+    }
+    trimmed
+        .parse()
+        .map_err(|_| format!("{}: integer expression expected", text))
+}
+
+/**
+    Evaluates the arguments of `test` (or `[` without the final `]`).
+    Up to four arguments are handled like POSIX says, so e.g. `test -n` and `test ! =` mean
+    what they look like; longer expressions are parsed with `!`, `-a`, `-o` and parentheses.
+*/
+pub fn test(args: &[&str], shell: &Shell) -> Result<bool, String> {
+    match args {
+        [] => Ok(false),
+        [word] => Ok(!word.is_empty()),
+        ["!", rest @ ..] if args.len() <= 4 => test(rest, shell).map(|result| !result),
+        [operator, operand] => {
+            if is_unary_operator(operator) {
+                Ok(unary(operator, operand, shell))
+            } else {
+                Err(format!("{}: unary operator expected", operator))
+            }
+        }
+        [left, operator, right] => match *operator {
+            _ if is_binary_operator(operator) => binary(operator, left, right, shell),
+            "-a" => Ok(!left.is_empty() && !right.is_empty()),
+            "-o" => Ok(!left.is_empty() || !right.is_empty()),
+            _ if *left == "(" && *right == ")" => Ok(!operator.is_empty()),
+            _ => Err(format!("{}: binary operator expected", operator)),
+        },
+        ["(", inner @ .., ")"] if args.len() == 4 => test(inner, shell),
+        _ => {
+            let mut parser = TestParser {
+                args,
+                position: 0,
+                shell,
+            };
+            let result = parser.or()?;
+            match args.get(parser.position) {
+                None => Ok(result),
+                Some(_) => Err("too many arguments".to_string()),
+            }
+        }
+    }
+}
+
+/// Recursive descent over the arguments of a longer `test` expression
+struct TestParser<'a> {
+    args: &'a [&'a str],
+    position: usize,
+    shell: &'a Shell,
+}
+
+impl<'a> TestParser<'a> {
+    fn peek(&self, offset: usize) -> Option<&'a str> {
+        self.args.get(self.position + offset).copied()
+    }
+
+    fn or(&mut self) -> Result<bool, String> {
+        let mut result = self.and()?;
+        while self.peek(0) == Some("-o") {
+            self.position += 1;
+            // Both sides are always parsed, so errors show up either way
+            let right = self.and()?;
+            result = result || right;
+        }
+        Ok(result)
+    }
+
+    fn and(&mut self) -> Result<bool, String> {
+        let mut result = self.not()?;
+        while self.peek(0) == Some("-a") {
+            self.position += 1;
+            let right = self.not()?;
+            result = result && right;
+        }
+        Ok(result)
+    }
+
+    fn not(&mut self) -> Result<bool, String> {
+        if self.peek(0) == Some("!") && self.peek(1).is_some() {
+            self.position += 1;
+            return self.not().map(|result| !result);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<bool, String> {
+        let Some(word) = self.peek(0) else {
+            return Err("argument expected".to_string());
+        };
+
+        if let Some(operator) = self.peek(1)
+            && is_binary_operator(operator)
+            && let Some(right) = self.peek(2)
+        {
+            self.position += 3;
+            return binary(operator, word, right, self.shell);
+        }
+        if word == "(" {
+            self.position += 1;
+            let result = self.or()?;
+            if self.peek(0) != Some(")") {
+                return Err("`)' expected".to_string());
+            }
+            self.position += 1;
+            return Ok(result);
+        }
+        if is_unary_operator(word)
+            && let Some(operand) = self.peek(1)
+        {
+            self.position += 2;
+            return Ok(unary(word, operand, self.shell));
+        }
+        self.position += 1;
+        Ok(!word.is_empty())
+    }
+}
+
+/**
+    Evaluates the words between `[[` and `]]`, still unexpanded.
+    Words are expanded without field splitting or pathname expansion; the right side of
+    `==` and `!=` is a pattern and the one of `=~` an extended regular expression (quoted
+    parts of both match literally), whose match and groups are stored in `BASH_REMATCH`.
+*/
+pub fn evaluate(words: &[String], shell: &mut Shell) -> Result<bool, String> {
+    let mut parser = ConditionalParser {
+        words,
+        position: 0,
+        shell,
+    };
+    let result = parser.or()?;
+    match words.get(parser.position) {
+        None => Ok(result),
+        Some(word) => Err(syntax_error(Some(word))),
+    }
+}
+
+fn syntax_error(word: Option<&String>) -> String {
+    match word {
+        Some(word) => format!("syntax error in conditional expression near `{}'", word),
+        None => "syntax error in conditional expression".to_string(),
+    }
+}
+
+struct ConditionalParser<'a> {
+    words: &'a [String],
+    position: usize,
+    shell: &'a mut Shell,
+}
+
+impl ConditionalParser<'_> {
+    fn peek(&self, offset: usize) -> Option<&str> {
+        self.words.get(self.position + offset).map(String::as_str)
+    }
+
+    /// The next word if it is an operand (not an operator of the expression itself)
+    fn operand(&self, offset: usize) -> Option<&str> {
+        self.peek(offset)
+            .filter(|word| !matches!(*word, "&&" | "||" | ")"))
+    }
+
+    fn expand(&self, word: &str) -> Result<String, String> {
+        expand::expand_single(word, self.shell)
+    }
+
+    fn or(&mut self) -> Result<bool, String> {
+        let mut result = self.and()?;
+        while self.peek(0) == Some("||") {
+            self.position += 1;
+            if result {
+                // Short-circuited: the rest is parsed but not evaluated (e.g. no `=~` match)
+                self.skip_and()?;
+            } else {
+                result = self.and()?;
+            }
+        }
+        Ok(result)
+    }
+
+    fn and(&mut self) -> Result<bool, String> {
+        let mut result = self.not()?;
+        while self.peek(0) == Some("&&") {
+            self.position += 1;
+            if result {
+                result = self.not()?;
+            } else {
+                self.skip_not()?;
+            }
+        }
+        Ok(result)
+    }
+
+    fn not(&mut self) -> Result<bool, String> {
+        if self.peek(0) == Some("!") {
+            self.position += 1;
+            return self.not().map(|result| !result);
+        }
+        self.primary()
+    }
+
+    fn skip_and(&mut self) -> Result<(), String> {
+        self.skip_not()?;
+        while self.peek(0) == Some("&&") {
+            self.position += 1;
+            self.skip_not()?;
+        }
+        Ok(())
+    }
+
+    /// Steps over an operand of `&&` or `||` that isn't needed for the result
+    fn skip_not(&mut self) -> Result<(), String> {
+        match self.operand(0) {
+            None => Err(syntax_error(self.words.get(self.position))),
+            Some("!") => {
+                self.position += 1;
+                self.skip_not()
+            }
+            Some("(") => {
+                self.position += 1;
+                self.skip_and()?;
+                while self.peek(0) == Some("||") {
+                    self.position += 1;
+                    self.skip_and()?;
+                }
+                if self.peek(0) != Some(")") {
+                    return Err(syntax_error(self.words.get(self.position)));
+                }
+                self.position += 1;
+                Ok(())
+            }
+            Some(word) => {
+                let length = if self
+                    .peek(1)
+                    .is_some_and(|operator| is_binary_operator(operator) || operator == "=~")
+                {
+                    3
+                } else if is_unary_operator(word) {
+                    2
+                } else {
+                    1
+                };
+                if (1..length).any(|offset| self.operand(offset).is_none()) {
+                    return Err(syntax_error(self.words.get(self.position + 1)));
+                }
+                self.position += length;
+                Ok(())
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<bool, String> {
+        let Some(word) = self.operand(0) else {
+            return Err(syntax_error(self.words.get(self.position)));
+        };
+        let word = word.to_string();
+
+        if word == "(" {
+            self.position += 1;
+            let result = self.or()?;
+            if self.peek(0) != Some(")") {
+                return Err(syntax_error(self.words.get(self.position)));
+            }
+            self.position += 1;
+            return Ok(result);
+        }
+
+        if let Some(operator) = self.peek(1)
+            && (is_binary_operator(operator) || operator == "=~")
+        {
+            let operator = operator.to_string();
+            let Some(right) = self.operand(2).map(str::to_string) else {
+                return Err(syntax_error(self.words.get(self.position + 1)));
+            };
+            self.position += 3;
+            let left = self.expand(&word)?;
+            return match operator.as_str() {
+                "==" | "=" | "!=" => {
+                    let pattern = expand::expand_pattern(&right, self.shell, glob::escape)?;
+                    let matched = glob::matches(&pattern, &left);
+                    Ok(matched == (operator != "!="))
+                }
+                "=~" => {
+                    let pattern = expand::expand_pattern(&right, self.shell, regex::escape)?;
+                    self.match_regex(&pattern, &left)
+                }
+                _ => {
+                    let right = self.expand(&right)?;
+                    binary(&operator, &left, &right, self.shell)
+                }
+            };
+        }
+
+        if is_unary_operator(&word) {
+            let Some(operand) = self.operand(1).map(str::to_string) else {
+                return Err(syntax_error(self.words.get(self.position + 1)));
+            };
+            self.position += 2;
+            let operand = self.expand(&operand)?;
+            return Ok(unary(&word, &operand, self.shell));
+        }
+
+        self.position += 1;
+        Ok(!self.expand(&word)?.is_empty())
+    }
+
+    /// Matches a regex, storing the match and its groups (unmatched ones empty) in `BASH_REMATCH`
+    fn match_regex(&mut self, pattern: &str, text: &str) -> Result<bool, String> {
+        let regex =
+            Regex::new(pattern).map_err(|_| format!("{}: invalid regular expression", pattern))?;
+        let groups: Vec<String> = match regex.captures(text) {
+            Some(captures) => captures
+                .iter()
+                .map(|group| group.map_or("", |group| group.as_str()).to_string())
+                .collect(),
+            None => Vec::new(),
+        };
+        let matched = !groups.is_empty();
+        self.shell.set_array("BASH_REMATCH", groups);
+        Ok(matched)
+    }
+}
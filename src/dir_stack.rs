@@ -0,0 +1,114 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/**
+    The directory stack of `pushd`, `popd` and `dirs`. The top of the stack is always the
+    working directory, so only the directories below it are kept here.
+    With a `file`, the whole stack is written there whenever it changes, and a new session
+    can start with the stack of the last one (see `load`).
+*/
+#[derive(Debug, Clone, Default)]
+pub struct DirStack {
+    entries: Vec<PathBuf>,
+    /// Where the stack is persisted (`None` keeps it in memory only)
+    pub file: Option<PathBuf>,
+}
+
+impl DirStack {
+    /// Persists the stack to `BSH_DIRSTACK_FILE` if the shell (`get_var`) has it set
+    pub fn configure<'a>(&mut self, get_var: impl Fn(&str) -> Option<&'a str>) {
+        self.file = get_var("BSH_DIRSTACK_FILE")
+            .filter(|file| !file.is_empty())
+            .map(PathBuf::from);
+    }
+
+    /// The directories below the working directory, from the top down
+    pub fn entries(&self) -> &[PathBuf] {
+        &self.entries
+    }
+
+    /// Replaces the directories below the working directory
+    pub fn set_entries(&mut self, entries: Vec<PathBuf>) {
+        self.entries = entries;
+    }
+
+    /// The whole stack as `dirs` shows it, starting with the working directory
+    pub fn with_cwd(&self, cwd: &Path) -> Vec<PathBuf> {
+        std::iter::once(cwd.to_path_buf())
+            .chain(self.entries.iter().cloned())
+            .collect()
+    }
+
+    /**
+        Reads the stack of the last session from the file (if any), used on startup.
+        All of it ends up below the working directory, so `popd` goes back to where the last
+        session was. Directories that are gone by now are left out.
+    */
+    pub fn load(&mut self) -> io::Result<()> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+        let content = match fs::read_to_string(path) {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+            result => result?,
+        };
+        self.entries = content
+            .lines()
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_dir())
+            .collect();
+        Ok(())
+    }
+
+    /// Writes the stack (one directory per line, the working directory first) to the file
+    pub fn save(&self, cwd: &Path) -> io::Result<()> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut content = String::new();
+        for dir in self.with_cwd(cwd) {
+            content.push_str(&dir.to_string_lossy());
+            content.push('\n');
+        }
+        fs::write(path, content)
+    }
+}
+
+/**
+    Finds the stack entry meant by `+N` (counting from the top, which is 0) or `-N` (counting
+    from the bottom) in a stack of `len` directories.
+    Returns `None` for anything else, and `Some(None)` if the number is out of range.
+*/
+pub fn parse_index(arg: &str, len: usize) -> Option<Option<usize>> {
+    let (from_top, number) = match arg.split_at_checked(1)? {
+        ("+", number) => (true, number),
+        ("-", number) => (false, number),
+        _ => return None,
+    };
+    if number.is_empty() || !number.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let index = number
+        .parse::<usize>()
+        .ok()
+        .filter(|&number| number < len)
+        .map(|number| if from_top { number } else { len - 1 - number });
+    Some(index)
+}
+
+/// Shows a directory below `$HOME` as `~/...`
+pub fn abbreviate_home(dir: &Path, home: Option<&str>) -> String {
+    let dir = dir.to_string_lossy();
+    if let Some(home) = home.map(|home| home.trim_end_matches('/'))
+        && !home.is_empty()
+        && let Some(rest) = dir.strip_prefix(home)
+        && (rest.is_empty() || rest.starts_with('/'))
+    {
+        return format!("~{}", rest);
+    }
+    dir.into_owned()
+}
@@ -0,0 +1,122 @@
+use std::io::{self, Write};
+use thiserror::Error;
+
+/// The exit status of a command (0 means success)
+pub type ExitStatus = i32;
+
+/// A line of a script, named in front of the diagnostics of its commands
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub script: String,
+    pub line: usize,
+}
+
+#[derive(Debug, Error)]
+pub enum ShellError {
+    /// The command line isn't valid syntax
+    #[error("{0}")]
+    Parse(String),
+    /// A word couldn't be expanded (e.g. an unset variable with `set -u`)
+    #[error("{0}")]
+    Expansion(String),
+    /// The file of a redirection couldn't be opened
+    #[error("{target}: {message}")]
+    Redirection { target: String, message: String },
+    #[error("{0}: command not found")]
+    CommandNotFound(String),
+    /// A script to run couldn't be read
+    #[error("{script}: {message}")]
+    Script { script: String, message: String },
+    /// A program was found, but couldn't be started
+    #[error("{command}: {message}")]
+    Spawn { command: String, message: String },
+    /// A builtin failed with the given exit status
+    #[error("{builtin}: {message}")]
+    Builtin {
+        builtin: String,
+        message: String,
+        status: ExitStatus,
+    },
+    /// Reading or writing a stream failed (e.g. the other end of a pipe was closed)
+    #[error("{}", io_message(.0))]
+    Io(#[from] io::Error),
+}
+
+impl ShellError {
+    /// A builtin failed (exit status 1)
+    pub fn builtin(builtin: &str, message: impl Into<String>) -> ShellError {
+        ShellError::Builtin {
+            builtin: builtin.to_string(),
+            message: message.into(),
+            status: 1,
+        }
+    }
+
+    /// A builtin was used wrongly, like with an invalid option (exit status 2)
+    pub fn usage(builtin: &str, message: impl Into<String>) -> ShellError {
+        ShellError::Builtin {
+            builtin: builtin.to_string(),
+            message: message.into(),
+            status: 2,
+        }
+    }
+
+    pub fn redirection(target: &str, error: &io::Error) -> ShellError {
+        ShellError::Redirection {
+            target: target.to_string(),
+            message: io_message(error),
+        }
+    }
+
+    pub fn script(script: &str, error: &io::Error) -> ShellError {
+        ShellError::Script {
+            script: script.to_string(),
+            message: io_message(error),
+        }
+    }
+
+    pub fn spawn(command: &str, error: &io::Error) -> ShellError {
+        ShellError::Spawn {
+            command: command.to_string(),
+            message: io_message(error),
+        }
+    }
+
+    /// The exit status of the command that failed with this error
+    pub fn status(&self) -> ExitStatus {
+        match self {
+            ShellError::Parse(_) => 2,
+            ShellError::Expansion(_) | ShellError::Redirection { .. } | ShellError::Io(_) => 1,
+            ShellError::CommandNotFound(_) | ShellError::Script { .. } => 127,
+            ShellError::Spawn { .. } => 126,
+            ShellError::Builtin { status, .. } => *status,
+        }
+    }
+}
+
+/// The message of an io error without the "(os error N)" suffix
+pub fn io_message(error: &io::Error) -> String {
+    let message = error.to_string();
+    match message.find(" (os error") {
+        Some(index) => message[..index].to_string(),
+        None => message,
+    }
+}
+
+/// The start of every diagnostic, e.g. "bettershell: " or "bettershell: script.sh: line 3: "
+pub fn prefix(location: Option<&Location>) -> String {
+    match location {
+        Some(Location { script, line }) => format!("bettershell: {}: line {}: ", script, line),
+        None => "bettershell: ".to_string(),
+    }
+}
+
+/// Prints an error to fd 2 of the process (`Shell::report` prints to the stderr of a shell)
+pub fn report(error: &ShellError, location: Option<&Location>) {
+    report_to(&mut io::stderr(), error, location);
+}
+
+/// Prints an error to the given stream, like the stderr of a builtin
+pub fn report_to(stderr: &mut dyn Write, error: &ShellError, location: Option<&Location>) {
+    let _ = writeln!(stderr, "{}{}", prefix(location), error);
+}
@@ -0,0 +1,1008 @@
+use crate::assignment;
+use crate::builtins::{FdStream, Io};
+use crate::commands;
+use crate::conditional;
+use crate::error::{self, ExitStatus, Location, ShellError};
+use crate::expand;
+use crate::jobs;
+use crate::options::ShellOption;
+use crate::parser::{
+    self, AndOrList, Connector, ListItem, Pipeline, Redirect, RedirectKind, SimpleCommand,
+};
+use crate::shell::{self, Shell, Variable};
+use crate::traps;
+use std::env;
+use std::fs;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+/// What to do with a file descriptor in the new process (in order)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FdAction {
+    /// Make `target` a copy of `source` (`dup2`)
+    Duplicate {
+        source: RawFd,
+        target: RawFd,
+    },
+    Close(RawFd),
+}
+
+/// How a new child process is wired up
+#[derive(Debug, Default)]
+pub struct ChildSetup {
+    /// Read end of the pipe from the previous command
+    pub stdin: Option<OwnedFd>,
+    /// Write end of the pipe to the next command
+    pub stdout: Option<OwnedFd>,
+    pub stderr: Option<OwnedFd>,
+    /// Redirections, applied after the pipes
+    pub fd_actions: Vec<FdAction>,
+    /// Process group to join: `Some(0)` starts a new one, `None` stays in the shell's group
+    pub process_group: Option<i32>,
+    /// Whether the child gets the terminal
+    pub foreground: bool,
+}
+
+impl ChildSetup {
+    /// A stand-alone foreground process (in its own process group when job control is active)
+    pub fn foreground(job_control: bool) -> ChildSetup {
+        ChildSetup {
+            process_group: job_control.then_some(0),
+            foreground: true,
+            ..ChildSetup::default()
+        }
+    }
+
+    /**
+        Wires fds 0, 1 and 2 without a pipe to the `standard_fds` of the shell (copies of them,
+        so the child can take them over whatever their numbers are).
+    */
+    pub fn inherit_standard_fds(&mut self, shell: &Shell) -> io::Result<()> {
+        let slots = [&mut self.stdin, &mut self.stdout, &mut self.stderr];
+        for (fd, (slot, source)) in slots.into_iter().zip(shell.standard_fds).enumerate() {
+            if slot.is_some() || source == fd as RawFd {
+                continue;
+            }
+            if source < 0 {
+                self.fd_actions.insert(0, FdAction::Close(fd as RawFd));
+                continue;
+            }
+            let copy = unsafe { libc::fcntl(source, libc::F_DUPFD_CLOEXEC, 10) };
+            if copy < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            *slot = Some(unsafe { OwnedFd::from_raw_fd(copy) });
+        }
+        Ok(())
+    }
+}
+
+/**
+    Looks the command up in the builtin registry and runs it.
+    Returns `None` if the command isn't a builtin.
+*/
+pub fn command_matcher(
+    command: &str,
+    args: Vec<&str>,
+    shell: &mut Shell,
+    io: &mut Io,
+) -> Option<Result<ExitStatus, ShellError>> {
+    let builtin = shell.builtins.lookup(command)?;
+    Some(builtin.run(shell, &args, io))
+}
+
+/// Parses and runs a command line, returns the exit status
+pub fn run_line(line: &str, shell: &mut Shell) -> i32 {
+    match parser::parse_with_aliases(line, &shell.aliases) {
+        // `set -n` only checks the syntax (interactive shells ignore it)
+        Ok(_) if shell.options.is_set(ShellOption::Noexec) && !shell.options.is_interactive() => 0,
+        Ok(items) => execute_list(&items, shell),
+        Err(message) => {
+            let error = ShellError::Parse(message);
+            shell.report(&error);
+            shell.last_status = error.status();
+            error.status()
+        }
+    }
+}
+
+/**
+    Runs the lines of a script one after another (here-documents along with their command),
+    returns the status of the last command.
+    Diagnostics name the script and the line they happened on.
+*/
+pub fn run_script(name: &str, source: &str, shell: &mut Shell) -> i32 {
+    let outer = shell.location.take();
+    for (number, line) in parser::command_lines(source) {
+        shell.location = Some(Location {
+            script: name.to_string(),
+            line: number,
+        });
+        run_line(&line, shell);
+        if shell.exit_status.is_some() {
+            break;
+        }
+    }
+    shell.location = outer;
+    shell.last_status
+}
+
+pub fn execute_list(items: &[ListItem], shell: &mut Shell) -> i32 {
+    let mut status: i32 = shell.last_status;
+
+    for item in items {
+        status = if item.background {
+            run_in_background(&item.commands, shell)
+        } else {
+            execute_and_or(&item.commands, shell)
+        };
+        shell.last_status = status;
+        if shell.exit_status.is_some() {
+            break;
+        }
+        // Traps of signals that arrived in the meantime run between commands
+        traps::run_pending(shell);
+    }
+
+    status
+}
+
+fn execute_and_or(list: &AndOrList, shell: &mut Shell) -> i32 {
+    let mut status = execute_pipeline(&list.first, false, shell);
+    // Only a failure of the last pipeline counts for the ERR trap, not one tested by `&&`/`||`
+    let mut last_ran = list.rest.is_empty();
+
+    for (index, (connector, pipeline)) in list.rest.iter().enumerate() {
+        if shell.exit_status.is_some() {
+            return status;
+        }
+        shell.last_status = status;
+        // `a && b` only runs b if a succeeded, `a || b` only if it failed
+        let skip = match connector {
+            Connector::And => status != 0,
+            Connector::Or => status == 0,
+        };
+        if !skip {
+            status = execute_pipeline(pipeline, false, shell);
+            last_ran = index + 1 == list.rest.len();
+        }
+    }
+
+    if status != 0 && last_ran {
+        shell.last_status = status;
+        traps::run_err_trap(shell);
+        if shell.options.is_set(ShellOption::Errexit) {
+            shell.exit_status = Some(status);
+        }
+    }
+    status
+}
+
+/// Starts `cmd &`; returns immediately with status 0
+fn run_in_background(list: &AndOrList, shell: &mut Shell) -> i32 {
+    if list.rest.is_empty() && !has_process_substitution(&list.first) {
+        return execute_pipeline(&list.first, true, shell);
+    }
+
+    // A whole `a && b` list runs in a forked copy of the shell (as does a pipeline with
+    // process substitutions, whose helpers the copy waits for)
+    let setup = ChildSetup {
+        process_group: shell.jobs.job_control_enabled().then_some(0),
+        ..ChildSetup::default()
+    };
+    let pid = fork_child(setup, shell, |shell| {
+        enter_subshell(shell);
+        execute_and_or(list, shell)
+    });
+
+    match pid {
+        Ok(pid) => {
+            let pgid = shell.jobs.job_control_enabled().then_some(pid);
+            let id = shell.jobs.add_job(pgid, vec![pid], list.to_string());
+            announce_background_job(id, pid, shell);
+            0
+        }
+        Err(error) => {
+            shell.report(&error);
+            1
+        }
+    }
+}
+
+fn has_process_substitution(pipeline: &Pipeline) -> bool {
+    pipeline.commands.iter().any(|command| {
+        let targets = command.redirects.iter().map(|redirect| &redirect.target);
+        command
+            .words
+            .iter()
+            .chain(targets)
+            .any(|word| parser::find_process_substitution(word).is_some())
+    })
+}
+
+/// Forked copies of the shell don't manage the parent's jobs and traps
+fn enter_subshell(shell: &mut Shell) {
+    shell.jobs.reset_for_subshell();
+    shell.traps.reset_for_subshell();
+    // The copy works in the directory of the shell
+    let _ = env::set_current_dir(&shell.cwd);
+}
+
+fn announce_background_job(id: usize, pid: i32, shell: &mut Shell) {
+    shell.jobs.set_last_background_pid(pid);
+    if shell.jobs.job_control_enabled() {
+        let _ = writeln!(FdStream(shell.standard_fds[1]), "[{}] {}", id, pid);
+    }
+}
+
+/**
+    Runs the commands of a pipeline, each in its own process, as one job.
+    A single builtin in the foreground runs inside the shell instead (so `cd` works).
+*/
+fn execute_pipeline(pipeline: &Pipeline, background: bool, shell: &mut Shell) -> i32 {
+    traps::run_debug_trap(shell);
+
+    // `[[ ... ]]` needs its words unexpanded (patterns, `&&`); it always runs in the shell
+    if let [command] = pipeline.commands.as_slice()
+        && command.words.first().is_some_and(|word| word == "[[")
+    {
+        return with_redirects("[[", &command.redirects, shell, |shell| {
+            run_conditional(&command.words, shell)
+        });
+    }
+
+    // Assignments alone set variables of the shell (in a pipeline they'd happen in a subshell)
+    if let [command] = pipeline.commands.as_slice()
+        && !background
+        && is_assignment_only(&command.words)
+    {
+        return with_redirects(&command.words[0], &command.redirects, shell, |shell| {
+            run_assignments(&command.words, shell)
+        });
+    }
+
+    // `<(cmd)` and `>(cmd)` start their commands first and become `/dev/fd/N` paths
+    let mut substitutions = ProcessSubstitutions::default();
+    let commands: Vec<SimpleCommand> = match pipeline
+        .commands
+        .iter()
+        .map(|command| substitutions.substitute(command, shell))
+        .collect()
+    {
+        Ok(commands) => commands,
+        Err(error) => {
+            shell.report(&error);
+            substitutions.finish();
+            return error.status();
+        }
+    };
+    let status = execute_commands(pipeline, &commands, background, &substitutions, shell);
+    substitutions.finish();
+    status
+}
+
+/// Expands and runs the commands of a pipeline (with their process substitutions replaced)
+fn execute_commands(
+    pipeline: &Pipeline,
+    commands: &[SimpleCommand],
+    background: bool,
+    substitutions: &ProcessSubstitutions,
+    shell: &mut Shell,
+) -> i32 {
+    let expanded: Vec<Vec<String>> = match commands
+        .iter()
+        .map(|command| expand_command(&command.words, shell))
+        .collect()
+    {
+        Ok(expanded) => expanded,
+        Err(message) => {
+            shell.report(&ShellError::Expansion(message));
+            // A script can't continue after a failed expansion
+            if !shell.options.is_interactive() {
+                shell.exit_status = Some(1);
+            }
+            return 1;
+        }
+    };
+
+    if shell.options.is_set(ShellOption::Xtrace) {
+        for argv in expanded.iter().filter(|argv| !argv.is_empty()) {
+            trace_command(argv, shell);
+        }
+    }
+
+    if expanded.len() == 1 && !background {
+        let redirects = &commands[0].redirects;
+        match expanded[0].first() {
+            None => return run_redirects_only(redirects, shell),
+            Some(name) if shell.builtins.contains(name) => {
+                let status = with_prefix_assignments(&commands[0].words, shell, |shell| {
+                    run_builtin_in_shell(&expanded[0], redirects, shell)
+                });
+                return status.unwrap_or_else(|error| {
+                    shell.report(&error);
+                    error.status()
+                });
+            }
+            _ => {}
+        }
+    }
+
+    let job_control = shell.jobs.job_control_enabled();
+    let mut process_group: Option<i32> = job_control.then_some(0);
+    let mut pids: Vec<i32> = Vec::new();
+    let mut previous_read: Option<OwnedFd> = None;
+    let mut failed_status: Option<i32> = None;
+
+    for (index, (command, argv)) in commands.iter().zip(&expanded).enumerate() {
+        let is_last = index + 1 == expanded.len();
+
+        let (read, write): (Option<OwnedFd>, Option<OwnedFd>) = if is_last {
+            (None, None)
+        } else {
+            match io::pipe() {
+                Ok((reader, writer)) => (Some(reader.into()), Some(writer.into())),
+                Err(error) => {
+                    shell.report(&ShellError::spawn("pipe", &error));
+                    return 1;
+                }
+            }
+        };
+
+        let mut stdin: Option<OwnedFd> = previous_read.take();
+        // Without job control, background jobs must not read from the terminal
+        if background && !job_control && index == 0 {
+            stdin = fs::File::open("/dev/null").ok().map(OwnedFd::from);
+        }
+
+        // The opened files have to stay open until the child is started
+        let (fd_actions, _files) = match open_redirects(&command.redirects, shell) {
+            Ok((fd_actions, files)) => ([substitutions.fd_actions(), fd_actions].concat(), files),
+            Err(error) => {
+                shell.report(&error);
+                previous_read = read;
+                if is_last {
+                    failed_status = Some(error.status());
+                }
+                continue;
+            }
+        };
+
+        let setup = ChildSetup {
+            stdin,
+            stdout: write,
+            stderr: None,
+            fd_actions,
+            process_group,
+            foreground: !background,
+        };
+
+        let pid: Result<i32, ShellError> =
+            with_prefix_assignments(&command.words, shell, |shell| match argv.first() {
+                None => fork_child(setup, shell, |_| 0),
+                Some(name) if shell.builtins.contains(name) => fork_child(setup, shell, |shell| {
+                    enter_subshell(shell);
+                    run_builtin(argv, shell)
+                }),
+                Some(name) => {
+                    let args: Vec<&str> = argv[1..].iter().map(|arg| arg.as_str()).collect();
+                    commands::spawn_external_program(name, &args, setup, shell)
+                }
+            })
+            .and_then(|pid| pid);
+
+        match pid {
+            Ok(pid) => {
+                if process_group == Some(0) {
+                    process_group = Some(pid);
+                }
+                pids.push(pid);
+            }
+            Err(error) => {
+                shell.report(&error);
+                if is_last {
+                    failed_status = Some(error.status());
+                }
+            }
+        }
+        previous_read = read;
+    }
+
+    let Some(last_pid) = pids.last().copied() else {
+        return failed_status.unwrap_or(0);
+    };
+    let id = shell
+        .jobs
+        .add_job(process_group, pids, pipeline.to_string());
+
+    if background {
+        announce_background_job(id, last_pid, shell);
+        return 0;
+    }
+
+    let pipefail = shell.options.is_set(ShellOption::Pipefail);
+    let status = shell.jobs.wait_for_job(id, pipefail);
+    failed_status.unwrap_or(status)
+}
+
+/**
+    The helper processes of `<(cmd)` and `>(cmd)`, and the shell's ends of their pipes.
+    The pipe ends stay open until `finish`, which waits for the helpers.
+*/
+#[derive(Debug, Default)]
+struct ProcessSubstitutions {
+    pids: Vec<i32>,
+    fds: Vec<OwnedFd>,
+}
+
+impl ProcessSubstitutions {
+    /// Replaces the process substitutions in the words and redirection targets of a command
+    fn substitute(
+        &mut self,
+        command: &SimpleCommand,
+        shell: &mut Shell,
+    ) -> Result<SimpleCommand, ShellError> {
+        let mut substituted = command.clone();
+        for word in &mut substituted.words {
+            *word = self.substitute_word(word, shell)?;
+        }
+        for redirect in &mut substituted.redirects {
+            if redirect.here_document.is_none() {
+                redirect.target = self.substitute_word(&redirect.target, shell)?;
+            }
+        }
+        Ok(substituted)
+    }
+
+    fn substitute_word(&mut self, word: &str, shell: &mut Shell) -> Result<String, ShellError> {
+        let mut result = String::new();
+        let mut rest: &str = word;
+        while let Some(range) = parser::find_process_substitution(rest) {
+            let output = rest[range.start..].starts_with('>');
+            let command = &rest[range.start + 2..range.end - 1];
+            result.push_str(&rest[..range.start]);
+            result.push_str(&self.start(command, output, shell)?);
+            rest = &rest[range.end..];
+        }
+        result.push_str(rest);
+        Ok(result)
+    }
+
+    /**
+        Starts `command` with its stdout (or stdin, for `>(cmd)`) connected to a pipe.
+        Returns the path of the shell's end of the pipe.
+    */
+    fn start(
+        &mut self,
+        command: &str,
+        output: bool,
+        shell: &mut Shell,
+    ) -> Result<String, ShellError> {
+        let (reader, writer) = io::pipe().map_err(|error| ShellError::spawn("pipe", &error))?;
+        let (ours, theirs): (OwnedFd, OwnedFd) = if output {
+            (writer.into(), reader.into())
+        } else {
+            (reader.into(), writer.into())
+        };
+
+        // A high number keeps it out of the way of redirections (bash uses 63 as well)
+        let fd = unsafe { libc::fcntl(ours.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 63) };
+        if fd < 0 {
+            return Err(ShellError::spawn("pipe", &io::Error::last_os_error()));
+        }
+        drop(ours);
+        let ours = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let mut setup = ChildSetup::default();
+        if output {
+            setup.stdin = Some(theirs);
+        } else {
+            setup.stdout = Some(theirs);
+        }
+        // The helper mustn't hold the shell's pipe ends, or readers would never see the end
+        let shell_fds: Vec<RawFd> = self
+            .fds
+            .iter()
+            .map(|fd| fd.as_raw_fd())
+            .chain([fd])
+            .collect();
+        let pid = fork_child(setup, shell, |shell| {
+            for fd in shell_fds {
+                unsafe {
+                    libc::close(fd);
+                }
+            }
+            enter_subshell(shell);
+            let status = run_line(command, shell);
+            shell.exit_status.unwrap_or(status)
+        })?;
+
+        self.pids.push(pid);
+        self.fds.push(ours);
+        Ok(format!("/dev/fd/{}", fd))
+    }
+
+    /// Keeps the shell's pipe ends open across `exec`, so the program can open `/dev/fd/N`
+    fn fd_actions(&self) -> Vec<FdAction> {
+        self.fds
+            .iter()
+            .map(|fd| FdAction::Duplicate {
+                source: fd.as_raw_fd(),
+                target: fd.as_raw_fd(),
+            })
+            .collect()
+    }
+
+    /// Closes the shell's pipe ends and reaps the helpers
+    fn finish(self) {
+        drop(self.fds);
+        for pid in self.pids {
+            let mut status: i32 = 0;
+            while unsafe { libc::waitpid(pid, &mut status, 0) } < 0
+                && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted
+            {}
+        }
+    }
+}
+
+/// Prints a command for `set -x`: the expanded `PS4` followed by the words, quoted where needed
+fn trace_command(argv: &[String], shell: &Shell) {
+    let prompt: String = match shell.get_var("PS4") {
+        Some(ps4) => expand::expand_single(ps4, shell).unwrap_or_else(|_| ps4.to_string()),
+        None => "+ ".to_string(),
+    };
+    let words: Vec<String> = argv.iter().map(|word| quote_for_trace(word)).collect();
+    let _ = writeln!(shell.stderr(), "{}{}", prompt, words.join(" "));
+}
+
+fn quote_for_trace(word: &str) -> String {
+    let plain = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+%@^".contains(c));
+    if plain {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+/// Runs a builtin and reports its error (if any) on stderr
+fn run_builtin(argv: &[String], shell: &mut Shell) -> ExitStatus {
+    let args: Vec<&str> = argv[1..].iter().map(|arg| arg.as_str()).collect();
+    let mut io = Io::from_fds(shell.standard_fds);
+    let status = match command_matcher(&argv[0], args, shell, &mut io) {
+        Some(Ok(status)) => status,
+        Some(Err(error)) => {
+            let _ = io.stdout.flush();
+            error::report_to(&mut io.stderr, &error, shell.location.as_ref());
+            error.status()
+        }
+        None => 127,
+    };
+    let _ = io.stdout.flush();
+    status
+}
+
+/// Runs a builtin in the shell process, with its redirections applied temporarily
+fn run_builtin_in_shell(argv: &[String], redirects: &[Redirect], shell: &mut Shell) -> i32 {
+    with_redirects(&argv[0], redirects, shell, |shell| run_builtin(argv, shell))
+}
+
+/**
+    Runs `body` in the shell process with the redirections of command `name` applied.
+    Those of fds 0, 1 and 2 only change the `standard_fds` of the shell.
+*/
+fn with_redirects(
+    name: &str,
+    redirects: &[Redirect],
+    shell: &mut Shell,
+    body: impl FnOnce(&mut Shell) -> i32,
+) -> i32 {
+    let (fd_actions, _files) = match open_redirects(redirects, shell) {
+        Ok(result) => result,
+        Err(error) => {
+            shell.report(&error);
+            return error.status();
+        }
+    };
+
+    let mut standard_fds = shell.standard_fds;
+    let mut other_actions: Vec<FdAction> = Vec::new();
+    for action in fd_actions {
+        // Copies of fds 0, 1 and 2 are copies of what they stand for at that point
+        let resolve = |fd: RawFd| match usize::try_from(fd) {
+            Ok(index) if index < 3 => standard_fds[index],
+            _ => fd,
+        };
+        match action {
+            FdAction::Duplicate { source, target } if (0..3).contains(&target) => {
+                standard_fds[target as usize] = resolve(source);
+            }
+            FdAction::Duplicate { source, target } => other_actions.push(FdAction::Duplicate {
+                source: resolve(source),
+                target,
+            }),
+            FdAction::Close(target) if (0..3).contains(&target) => {
+                standard_fds[target as usize] = -1;
+            }
+            FdAction::Close(target) => other_actions.push(FdAction::Close(target)),
+        }
+    }
+
+    let saved = match save_fds(&other_actions) {
+        Ok(saved) => saved,
+        Err(error) => {
+            shell.report(&ShellError::redirection(name, &error));
+            return 1;
+        }
+    };
+
+    let status = match apply_fd_actions(&other_actions) {
+        Ok(()) => {
+            let outer = std::mem::replace(&mut shell.standard_fds, standard_fds);
+            let status = body(shell);
+            shell.standard_fds = outer;
+            status
+        }
+        Err(error) => {
+            shell.report(&ShellError::redirection(name, &error));
+            1
+        }
+    };
+
+    restore_fds(saved);
+    status
+}
+
+/// Whether a command consists of assignments like `name=value` only
+fn is_assignment_only(words: &[String]) -> bool {
+    !words.is_empty() && words.iter().all(|word| assignment::parse(word).is_some())
+}
+
+/// The number of `name=value` words in front of the name of a command
+fn prefix_assignment_count(words: &[String]) -> usize {
+    words
+        .iter()
+        .take_while(|word| assignment::parse(word).is_some())
+        .count()
+}
+
+/**
+    Runs `body` with the `name=value` words in front of a command assigned and exported
+    (`IFS=: read a b`, `LANG=C sort`), then puts those variables back as they were.
+*/
+fn with_prefix_assignments<T>(
+    words: &[String],
+    shell: &mut Shell,
+    body: impl FnOnce(&mut Shell) -> T,
+) -> Result<T, ShellError> {
+    let mut saved: Vec<(String, Option<Variable>)> = Vec::new();
+    let mut result: Result<(), String> = Ok(());
+    for word in &words[..prefix_assignment_count(words)] {
+        let Some(assignment) = assignment::parse(word) else {
+            continue;
+        };
+        saved.push((
+            assignment.name.clone(),
+            shell.vars.get(&assignment.name).cloned(),
+        ));
+        result = assignment::assign(&assignment, shell);
+        if result.is_err() {
+            break;
+        }
+        if let Some(variable) = shell.vars.get_mut(&assignment.name) {
+            variable.exported = true;
+        }
+    }
+
+    let output = result.map(|()| body(shell)).map_err(ShellError::Expansion);
+    for (name, variable) in saved.into_iter().rev() {
+        match variable {
+            Some(variable) => {
+                shell.vars.insert(name, variable);
+            }
+            None => shell.unset_var(&name),
+        }
+    }
+    output
+}
+
+fn run_assignments(words: &[String], shell: &mut Shell) -> i32 {
+    for word in words {
+        let Some(assignment) = assignment::parse(word) else {
+            continue;
+        };
+        if let Err(message) = assignment::assign(&assignment, shell) {
+            shell.report(&ShellError::Expansion(message));
+            return 1;
+        }
+    }
+    0
+}
+
+/**
+    Expands the words of a command into its arguments. Assignments alone expand to nothing,
+    those in front of the name aren't arguments, and the assignments given to `declare`
+    stay one argument each (the value isn't split).
+*/
+fn expand_command(words: &[String], shell: &Shell) -> Result<Vec<String>, String> {
+    if is_assignment_only(words) {
+        return Ok(Vec::new());
+    }
+    let words = &words[prefix_assignment_count(words)..];
+    let Some(("declare", arguments)) = words
+        .split_first()
+        .map(|(name, arguments)| (name.as_str(), arguments))
+    else {
+        return expand::expand_words(words, shell);
+    };
+
+    let mut argv: Vec<String> = vec!["declare".to_string()];
+    for word in arguments {
+        match assignment::parse(word) {
+            Some(assignment) => argv.push(assignment::expand_argument(&assignment, shell)?),
+            None => argv.extend(expand::expand_word(word, shell)?),
+        }
+    }
+    Ok(argv)
+}
+
+/// Evaluates `[[ expression ]]` (the words include the brackets)
+fn run_conditional(words: &[String], shell: &mut Shell) -> i32 {
+    let result = match words.iter().position(|word| word == "]]") {
+        Some(end) if end + 1 < words.len() => Err(format!(
+            "syntax error near unexpected token `{}'",
+            words[end + 1]
+        )),
+        Some(1) => Err("syntax error in conditional expression".to_string()),
+        Some(end) => conditional::evaluate(&words[1..end], shell),
+        None => Err("unexpected EOF while looking for `]]'".to_string()),
+    };
+    match result {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(message) => {
+            let error = ShellError::Parse(message);
+            shell.report(&error);
+            error.status()
+        }
+    }
+}
+
+/// A command consisting only of redirections (like `> file`) just opens the files
+fn run_redirects_only(redirects: &[Redirect], shell: &Shell) -> i32 {
+    match open_redirects(redirects, shell) {
+        Ok(_) => 0,
+        Err(error) => {
+            shell.report(&error);
+            error.status()
+        }
+    }
+}
+
+/**
+    Forks the shell and runs `body` in the child, which exits with the returned status.
+    Used for builtins in pipelines and for background lists.
+*/
+fn fork_child(
+    mut setup: ChildSetup,
+    shell: &mut Shell,
+    body: impl FnOnce(&mut Shell) -> i32,
+) -> Result<i32, ShellError> {
+    setup
+        .inherit_standard_fds(shell)
+        .map_err(|error| ShellError::spawn("fork", &error))?;
+    let _ = io::stdout().flush();
+
+    let pid = unsafe { libc::fork() };
+    if pid < 0 {
+        return Err(ShellError::spawn("fork", &io::Error::last_os_error()));
+    }
+
+    if pid == 0 {
+        jobs::setup_child(setup.process_group, setup.foreground);
+        unsafe {
+            if let Some(stdin) = &setup.stdin {
+                libc::dup2(stdin.as_raw_fd(), 0);
+            }
+            if let Some(stdout) = &setup.stdout {
+                libc::dup2(stdout.as_raw_fd(), 1);
+            }
+            if let Some(stderr) = &setup.stderr {
+                libc::dup2(stderr.as_raw_fd(), 2);
+            }
+        }
+        drop(setup.stdin);
+        drop(setup.stdout);
+        drop(setup.stderr);
+        // The copy's own fds are its standard fds now
+        shell.standard_fds = [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO];
+
+        let status = match apply_fd_actions(&setup.fd_actions) {
+            Ok(()) => body(shell),
+            Err(error) => {
+                shell.report(&ShellError::redirection("redirect", &error));
+                1
+            }
+        };
+        let _ = io::stdout().flush();
+        unsafe { libc::_exit(status) }
+    }
+
+    if let Some(group) = setup.process_group {
+        unsafe {
+            libc::setpgid(pid, if group == 0 { pid } else { group });
+        }
+    }
+    Ok(pid)
+}
+
+/**
+    Opens the files of the redirections and turns them into fd actions.
+    The returned files must be kept open until the actions have been applied.
+*/
+pub fn open_redirects(
+    redirects: &[Redirect],
+    shell: &Shell,
+) -> Result<(Vec<FdAction>, Vec<OwnedFd>), ShellError> {
+    let mut actions: Vec<FdAction> = Vec::new();
+    let mut files: Vec<OwnedFd> = Vec::new();
+
+    for redirect in redirects {
+        if let Some(text) = here_document_text(redirect, shell)? {
+            let fd = here_document_fd(&text)
+                .map_err(|error| ShellError::redirection(&redirect.target, &error))?;
+            actions.push(FdAction::Duplicate {
+                source: fd.as_raw_fd(),
+                target: redirect.fd,
+            });
+            files.push(fd);
+            continue;
+        }
+
+        let target: String =
+            expand::expand_single(&redirect.target, shell).map_err(ShellError::Expansion)?;
+        let mut options = fs::OpenOptions::new();
+
+        match redirect.kind {
+            RedirectKind::Input | RedirectKind::HereDocument { .. } | RedirectKind::HereString => {
+                options.read(true);
+            }
+            RedirectKind::Output | RedirectKind::OutputAll => {
+                options.write(true).create(true).truncate(true);
+            }
+            RedirectKind::Append | RedirectKind::AppendAll => {
+                options.append(true).create(true);
+            }
+            RedirectKind::DuplicateInput | RedirectKind::DuplicateOutput => {
+                if target == "-" {
+                    actions.push(FdAction::Close(redirect.fd));
+                } else if let Ok(source) = target.parse::<RawFd>() {
+                    actions.push(FdAction::Duplicate {
+                        source,
+                        target: redirect.fd,
+                    });
+                } else if redirect.kind == RedirectKind::DuplicateOutput && redirect.fd == 1 {
+                    // `>&file` is the same as `&>file`
+                    let file = fs::File::create(shell.resolve_path(&target))
+                        .map_err(|error| ShellError::redirection(&target, &error))?;
+                    let fd: OwnedFd = file.into();
+                    actions.push(FdAction::Duplicate {
+                        source: fd.as_raw_fd(),
+                        target: 1,
+                    });
+                    actions.push(FdAction::Duplicate {
+                        source: fd.as_raw_fd(),
+                        target: 2,
+                    });
+                    files.push(fd);
+                } else {
+                    return Err(ShellError::Redirection {
+                        target: redirect.target.clone(),
+                        message: "ambiguous redirect".to_string(),
+                    });
+                }
+                continue;
+            }
+        }
+
+        let file = options
+            .open(shell.resolve_path(&target))
+            .map_err(|error| ShellError::redirection(&target, &error))?;
+        let fd: OwnedFd = file.into();
+        actions.push(FdAction::Duplicate {
+            source: fd.as_raw_fd(),
+            target: redirect.fd,
+        });
+        if matches!(
+            redirect.kind,
+            RedirectKind::OutputAll | RedirectKind::AppendAll
+        ) {
+            actions.push(FdAction::Duplicate {
+                source: fd.as_raw_fd(),
+                target: 2,
+            });
+        }
+        files.push(fd);
+    }
+
+    Ok((actions, files))
+}
+
+/// The input of a here-document or here-string (expanded unless the delimiter was quoted)
+fn here_document_text(redirect: &Redirect, shell: &Shell) -> Result<Option<String>, ShellError> {
+    let text = match (redirect.kind, &redirect.here_document) {
+        (RedirectKind::HereString, _) => {
+            let word = expand::expand_single(&redirect.target, shell);
+            format!("{}\n", word.map_err(ShellError::Expansion)?)
+        }
+        (RedirectKind::HereDocument { .. }, Some(document)) if document.quoted => {
+            document.body.clone()
+        }
+        (RedirectKind::HereDocument { .. }, Some(document)) => {
+            expand::expand_here_document(&document.body, shell).map_err(ShellError::Expansion)?
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(text))
+}
+
+/// A file to read the text from, so it can be any size (a pipe would fill up)
+fn here_document_fd(text: &str) -> io::Result<OwnedFd> {
+    let mut file = shell::temporary_file()?;
+    file.write_all(text.as_bytes())?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file.into())
+}
+
+/// Applies fd actions to the current process (only async-signal-safe calls)
+pub fn apply_fd_actions(actions: &[FdAction]) -> io::Result<()> {
+    for action in actions {
+        match *action {
+            FdAction::Duplicate { source, target } => unsafe {
+                if source == target {
+                    // Keep the fd open across exec
+                    libc::fcntl(target, libc::F_SETFD, 0);
+                } else if libc::dup2(source, target) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            },
+            FdAction::Close(target) => unsafe {
+                libc::close(target);
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Saves copies of the fds the actions will replace (`-1` if an fd wasn't open)
+pub fn save_fds(actions: &[FdAction]) -> io::Result<Vec<(RawFd, RawFd)>> {
+    let mut saved: Vec<(RawFd, RawFd)> = Vec::new();
+    for action in actions {
+        let target = match *action {
+            FdAction::Duplicate { target, .. } | FdAction::Close(target) => target,
+        };
+        if saved.iter().any(|(fd, _)| *fd == target) {
+            continue;
+        }
+        let copy = unsafe { libc::fcntl(target, libc::F_DUPFD_CLOEXEC, 10) };
+        saved.push((target, copy));
+    }
+    Ok(saved)
+}
+
+/// Puts back the fds saved by `save_fds`
+pub fn restore_fds(saved: Vec<(RawFd, RawFd)>) {
+    let _ = io::stdout().flush();
+    for (target, copy) in saved.into_iter().rev() {
+        unsafe {
+            if copy >= 0 {
+                libc::dup2(copy, target);
+                libc::close(copy);
+            } else {
+                libc::close(target);
+            }
+        }
+    }
+}
@@ -0,0 +1,631 @@
+use crate::assignment;
+use crate::glob;
+use crate::options::ShellOption;
+use crate::shell::{self, Shell, Value};
+use std::env;
+use std::process;
+
+/// Characters separating fields when `IFS` isn't set
+pub const DEFAULT_IFS: &str = " \t\n";
+
+/**
+    Expands a list of words into the arguments of a command: tilde and parameter expansion,
+    field splitting of unquoted expansions, pathname expansion and quote removal.
+    Fails for unset variables when `set -u` is active.
+*/
+pub fn expand_words(words: &[String], shell: &Shell) -> Result<Vec<String>, String> {
+    let mut fields: Vec<String> = Vec::new();
+    for word in words {
+        fields.extend(expand_word(word, shell)?);
+    }
+    Ok(fields)
+}
+
+/// Expands a single word, which may result in zero or more fields
+pub fn expand_word(word: &str, shell: &Shell) -> Result<Vec<String>, String> {
+    let mut expander = WordExpander::new(shell, true);
+    expander.expand(word);
+    expander.finish()
+}
+
+/// Expands a word without field splitting and globbing (used for redirection targets)
+pub fn expand_single(word: &str, shell: &Shell) -> Result<String, String> {
+    let mut expander = WordExpander::new(shell, false);
+    expander.expand(word);
+    Ok(expander.finish()?.join(" "))
+}
+
+/**
+    Expands the body of a here-document: parameters are expanded, a backslash only escapes
+    `$`, `` ` `` and itself (or removes a newline), and quotes are ordinary characters.
+*/
+pub fn expand_here_document(body: &str, shell: &Shell) -> Result<String, String> {
+    let mut expander = WordExpander::new(shell, false);
+    let chars: Vec<char> = body.chars().collect();
+    let mut position: usize = 0;
+
+    while position < chars.len() {
+        match chars[position] {
+            '\\' if chars.get(position + 1) == Some(&'\n') => position += 2,
+            '\\' if matches!(chars.get(position + 1), Some('$' | '`' | '\\')) => {
+                expander.push_literal(&chars[position + 1].to_string());
+                position += 2;
+            }
+            '$' => {
+                let (parameter, end) = expander.parameter(&chars, position);
+                expander.push_parameter(parameter, true);
+                position = end;
+            }
+            ch => {
+                expander.push_literal(&ch.to_string());
+                position += 1;
+            }
+        }
+    }
+    Ok(expander.finish()?.concat())
+}
+
+/**
+    Expands a word without field splitting into a pattern for `[[ ]]`: quoted parts are
+    escaped with `escape` (e.g. `glob::escape` or `regex::escape`) so they only match
+    themselves, while unquoted text and expansions keep their special characters.
+*/
+pub fn expand_pattern(
+    word: &str,
+    shell: &Shell,
+    escape: fn(&str) -> String,
+) -> Result<String, String> {
+    let mut expander = WordExpander::new(shell, false);
+    expander.escape = escape;
+    expander.expand(word);
+    if let Some(error) = expander.error {
+        return Err(error);
+    }
+    Ok(expander.current.pattern)
+}
+
+/// Looks up a variable, including the special parameters `$?`, `$!`, `$$`, `$-` and `$0`
+pub fn lookup_variable(name: &str, shell: &Shell) -> Option<String> {
+    match name {
+        "?" => Some(shell.last_status.to_string()),
+        "!" => shell.jobs.last_background_pid().map(|pid| pid.to_string()),
+        "$" => Some(process::id().to_string()),
+        "-" => Some(shell.options.flags()),
+        "0" => Some("bettershell".to_string()),
+        _ => shell.get_var(name).map(str::to_string),
+    }
+}
+
+/// What a parameter expands to
+enum Parameter {
+    /// A single value (`None` if unset)
+    Value(Option<String>),
+    /// The elements of `${name[@]}`, or of `${name[*]}` (`joined` into one field when quoted)
+    Elements { values: Vec<String>, joined: bool },
+}
+
+/// A field being built, with the pattern used for pathname expansion
+#[derive(Default)]
+struct Field {
+    text: String,
+    /// The text with quoted pattern characters escaped
+    pattern: String,
+    /// Whether unquoted `*`, `?` or `[` occur
+    has_wildcards: bool,
+}
+
+struct WordExpander<'a> {
+    shell: &'a Shell,
+    /// Field splitting and pathname expansion only happen for command arguments
+    split_fields: bool,
+    fields: Vec<Field>,
+    current: Field,
+    /// Set once the current field exists, even if empty (e.g. `""`)
+    has_field: bool,
+    error: Option<String>,
+    /// How quoted text is escaped in the pattern of a field
+    escape: fn(&str) -> String,
+    /// Set when `"${name[@]}"` had no elements inside the current double quotes
+    empty_elements: bool,
+}
+
+impl<'a> WordExpander<'a> {
+    fn new(shell: &'a Shell, split_fields: bool) -> WordExpander<'a> {
+        WordExpander {
+            shell,
+            split_fields,
+            fields: Vec::new(),
+            current: Field::default(),
+            has_field: false,
+            error: None,
+            escape: glob::escape,
+            empty_elements: false,
+        }
+    }
+
+    fn finish(mut self) -> Result<Vec<String>, String> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        if self.has_field {
+            self.fields.push(self.current);
+        }
+
+        let glob = self.split_fields && !self.shell.options.is_set(ShellOption::Noglob);
+        let mut result: Vec<String> = Vec::new();
+        for field in self.fields {
+            if glob && field.has_wildcards {
+                let paths = glob::expand_pattern(&field.pattern, &self.shell.cwd);
+                // A pattern without matches stays as it is
+                if !paths.is_empty() {
+                    result.extend(paths);
+                    continue;
+                }
+            }
+            result.push(field.text);
+        }
+        Ok(result)
+    }
+
+    /// Adds quoted text: it never splits and doesn't take part in pathname expansion
+    fn push_literal(&mut self, text: &str) {
+        self.current.text.push_str(text);
+        self.current.pattern.push_str(&(self.escape)(text));
+        self.has_field = true;
+    }
+
+    /// Adds an unquoted character of the word itself
+    fn push_unquoted_char(&mut self, ch: char) {
+        self.current.text.push(ch);
+        self.current.pattern.push(ch);
+        self.current.has_wildcards |= matches!(ch, '*' | '?' | '[');
+        self.has_field = true;
+    }
+
+    /// Adds the result of an unquoted expansion, splitting it into fields at `IFS` characters
+    fn push_unquoted(&mut self, value: &str) {
+        if !self.split_fields {
+            // Without splitting the text stays one field, but its pattern characters count
+            self.current.text.push_str(value);
+            self.current.pattern.push_str(value);
+            self.has_field = true;
+            return;
+        }
+
+        let ifs: String = self.shell.get_var("IFS").unwrap_or(DEFAULT_IFS).to_string();
+        for ch in value.chars() {
+            if ifs.contains(ch) {
+                if self.has_field {
+                    self.fields.push(std::mem::take(&mut self.current));
+                    self.has_field = false;
+                }
+            } else if ch == '\\' {
+                // A backslash from a variable is no escape character
+                self.push_literal("\\");
+            } else {
+                self.push_unquoted_char(ch);
+            }
+        }
+    }
+
+    /// Looks up the parameter at `position`; returns its value and the position after it
+    fn parameter(&mut self, chars: &[char], position: usize) -> (Parameter, usize) {
+        let braced = chars.get(position + 1) == Some(&'{');
+        let (name, end) = parameter_name(chars, position);
+        let Some(name) = name else {
+            return (Parameter::Value(Some("$".to_string())), end);
+        };
+
+        let result = if braced {
+            self.braced_parameter(&name)
+        } else {
+            self.variable(&name)
+        };
+        match result {
+            Ok(parameter) => (parameter, end),
+            Err(error) => {
+                self.error.get_or_insert(error);
+                (Parameter::Value(None), end)
+            }
+        }
+    }
+
+    /**
+        Expands the inside of `${...}`: a variable or array element (`name[subscript]`),
+        all elements (`name[@]` and `name[*]`), their keys (`!name[@]`), a length (`#name`,
+        `#name[@]`), a slice of any of them (`name:offset:length`) or a default
+        (`name:-word`, `name:+word`, `name:?word`, also without the colon).
+        Anything else, including assigning defaults with `name:=word`, is a bad substitution.
+    */
+    fn braced_parameter(&self, inside: &str) -> Result<Parameter, String> {
+        let bad_substitution = || format!("${{{}}}: bad substitution", inside);
+
+        if let Some(reference) = inside.strip_prefix('#')
+            && !reference.is_empty()
+        {
+            let (name, subscript, rest) = split_reference(reference);
+            if !rest.is_empty() {
+                return Err(bad_substitution());
+            }
+            let length = match subscript {
+                Some("@" | "*") => self.value(name).map_or(0, |value| value.values().len()),
+                _ => match self.reference(name, subscript)? {
+                    Parameter::Value(value) => value.unwrap_or_default().chars().count(),
+                    Parameter::Elements { values, .. } => values.len(),
+                },
+            };
+            return Ok(Parameter::Value(Some(length.to_string())));
+        }
+
+        if let Some(reference) = inside.strip_prefix('!') {
+            let (name, subscript, rest) = split_reference(reference);
+            if let Some(subscript @ ("@" | "*")) = subscript
+                && rest.is_empty()
+            {
+                return Ok(Parameter::Elements {
+                    values: self.value(name).map_or(Vec::new(), Value::keys),
+                    joined: subscript == "*",
+                });
+            }
+            return Err(bad_substitution());
+        }
+
+        let (name, subscript, rest) = split_reference(inside);
+        if name.is_empty() {
+            return Err(bad_substitution());
+        }
+        let operator_length = if rest.starts_with(':') { 2 } else { 1 };
+        if let Some(operator) = rest.get(..operator_length) {
+            match operator.trim_start_matches(':') {
+                "-" | "+" | "?" => {
+                    let word = &rest[operator_length..];
+                    return self.alternative(name, subscript, operator, word);
+                }
+                "=" => return Err(bad_substitution()),
+                _ => {}
+            }
+        }
+        let slice = match rest.strip_prefix(':') {
+            None if rest.is_empty() => None,
+            Some(slice) => Some(slice),
+            None => return Err(bad_substitution()),
+        };
+        let Some(slice) = slice else {
+            return self.reference(name, subscript);
+        };
+
+        let (offset, length) = match slice.split_once(':') {
+            Some((offset, length)) => (offset, Some(length)),
+            None => (slice, None),
+        };
+        let offset = assignment::evaluate_subscript(offset, self.shell)?;
+        let length = length
+            .map(|length| assignment::evaluate_subscript(length, self.shell))
+            .transpose()?;
+        Ok(match self.reference(name, subscript)? {
+            Parameter::Value(value) => {
+                Parameter::Value(value.map(|value| substring(&value, offset, length)))
+            }
+            Parameter::Elements { joined, .. } => Parameter::Elements {
+                values: self.slice_elements(name, offset, length),
+                joined,
+            },
+        })
+    }
+
+    fn value(&self, name: &str) -> Option<&Value> {
+        self.shell.vars.get(name).map(|variable| &variable.value)
+    }
+
+    /**
+        `${name-word}` uses the word if the parameter is unset, `${name+word}` only if it is set
+        and `${name?word}` fails with the word as message if it is unset.
+        With a colon (`${name:-word}`) an empty parameter counts as unset as well.
+    */
+    fn alternative(
+        &self,
+        name: &str,
+        subscript: Option<&str>,
+        operator: &str,
+        word: &str,
+    ) -> Result<Parameter, String> {
+        let parameter = self.lookup(name, subscript)?;
+        let null_is_unset = operator.starts_with(':');
+        let is_set = match &parameter {
+            Parameter::Value(value) => value
+                .as_ref()
+                .is_some_and(|value| !null_is_unset || !value.is_empty()),
+            Parameter::Elements { values, .. } => values
+                .iter()
+                .any(|value| !null_is_unset || !value.is_empty()),
+        };
+
+        match (operator.trim_start_matches(':'), is_set) {
+            ("-" | "?", true) => Ok(parameter),
+            ("+", false) => Ok(Parameter::Value(None)),
+            ("?", false) if word.is_empty() => Err(format!("{}: parameter null or not set", name)),
+            ("?", false) => Err(format!("{}: {}", name, expand_single(word, self.shell)?)),
+            _ => Ok(Parameter::Value(Some(expand_single(word, self.shell)?))),
+        }
+    }
+
+    /// The value of a variable, which is an error for unset ones with `set -u`
+    fn variable(&self, name: &str) -> Result<Parameter, String> {
+        self.reference(name, None)
+    }
+
+    /// A variable (`None`), an element or all elements (`@` and `*`); `set -u` fails if unset
+    fn reference(&self, name: &str, subscript: Option<&str>) -> Result<Parameter, String> {
+        let parameter = self.lookup(name, subscript)?;
+        if matches!(parameter, Parameter::Value(None))
+            && self.shell.options.is_set(ShellOption::Nounset)
+        {
+            return Err(match subscript {
+                Some(subscript) => format!("{}[{}]: unbound variable", name, subscript),
+                None => format!("{}: unbound variable", name),
+            });
+        }
+        Ok(parameter)
+    }
+
+    fn lookup(&self, name: &str, subscript: Option<&str>) -> Result<Parameter, String> {
+        match subscript {
+            None => Ok(Parameter::Value(lookup_variable(name, self.shell))),
+            Some(subscript @ ("@" | "*")) => Ok(Parameter::Elements {
+                values: self.value(name).map_or(Vec::new(), |value| {
+                    value.values().into_iter().map(str::to_string).collect()
+                }),
+                joined: subscript == "*",
+            }),
+            Some(subscript) => {
+                let key = assignment::subscript_key(name, subscript, self.shell)?;
+                let element = self.shell.get_element(name, &key).map(str::to_string);
+                Ok(Parameter::Value(element))
+            }
+        }
+    }
+
+    /**
+        The elements of `${name[@]:offset:length}`: of an indexed array the ones from index
+        `offset` on (negative counts back from the end), otherwise by position.
+    */
+    fn slice_elements(&self, name: &str, offset: i64, length: Option<i64>) -> Vec<String> {
+        let elements: Vec<(usize, &str)> = match self.value(name) {
+            Some(Value::Indexed(elements)) => elements
+                .iter()
+                .map(|(index, element)| (*index, element.as_str()))
+                .collect(),
+            Some(value) => value.values().into_iter().enumerate().collect(),
+            None => Vec::new(),
+        };
+        let end = elements.last().map_or(0, |(index, _)| index + 1);
+        let Some(start) = shell::resolve_index(offset, end) else {
+            return Vec::new();
+        };
+        let length = length.map_or(usize::MAX, |length| usize::try_from(length).unwrap_or(0));
+        elements
+            .into_iter()
+            .filter(|(index, _)| *index >= start)
+            .take(length)
+            .map(|(_, element)| element.to_string())
+            .collect()
+    }
+
+    /// Adds what a parameter expanded to, unquoted or inside double quotes
+    fn push_parameter(&mut self, parameter: Parameter, quoted: bool) {
+        let (values, joined) = match parameter {
+            Parameter::Value(None) => return,
+            Parameter::Value(Some(value)) => (vec![value], true),
+            Parameter::Elements { values, joined } => (values, joined),
+        };
+
+        // Joined are `"${name[*]}"` (with the first IFS character) and anything not split
+        if (quoted && joined) || !self.split_fields {
+            let separator: String = match self.shell.get_var("IFS") {
+                Some(ifs) if joined => ifs.chars().take(1).collect(),
+                _ => " ".to_string(),
+            };
+            let value = values.join(&separator);
+            if quoted {
+                self.push_literal(&value);
+            } else {
+                self.push_unquoted(&value);
+            }
+            return;
+        }
+
+        if quoted && values.is_empty() {
+            self.empty_elements = true;
+        }
+        for (index, value) in values.iter().enumerate() {
+            if index > 0 {
+                // Each element is a field of its own (in quotes even an empty one)
+                if self.has_field || quoted {
+                    self.fields.push(std::mem::take(&mut self.current));
+                }
+                self.has_field = quoted;
+            }
+            if quoted {
+                self.push_literal(value);
+            } else {
+                self.push_unquoted(value);
+            }
+        }
+    }
+
+    fn expand(&mut self, word: &str) {
+        let chars: Vec<char> = word.chars().collect();
+        let mut position: usize = 0;
+
+        // A leading `~` (alone or followed by a slash) is the home directory
+        if chars.first() == Some(&'~') && matches!(chars.get(1), None | Some('/')) {
+            let home = self
+                .shell
+                .get_var("HOME")
+                .map(str::to_string)
+                .or_else(|| env::home_dir().map(|home| home.to_string_lossy().into_owned()));
+            if let Some(home) = home {
+                self.push_literal(&home);
+                position = 1;
+            }
+        }
+
+        while position < chars.len() {
+            match chars[position] {
+                '\\' => {
+                    if let Some(next) = chars.get(position + 1) {
+                        self.push_literal(&next.to_string());
+                    }
+                    position += 2;
+                }
+                '\'' => {
+                    let end = find(&chars, position + 1, '\'');
+                    let text: String = chars[position + 1..end].iter().collect();
+                    self.push_literal(&text);
+                    position = end + 1;
+                }
+                '"' => {
+                    position = self.expand_double_quoted(&chars, position + 1);
+                }
+                '$' => {
+                    let (parameter, end) = self.parameter(&chars, position);
+                    if end == position + 1 {
+                        // A `$` that isn't followed by a name stays as it is
+                        self.push_unquoted_char('$');
+                    } else {
+                        self.push_parameter(parameter, false);
+                    }
+                    position = end;
+                }
+                ch => {
+                    self.push_unquoted_char(ch);
+                    position += 1;
+                }
+            }
+        }
+    }
+
+    /// Expands the inside of double quotes starting at `start`, returns the position after them
+    fn expand_double_quoted(&mut self, chars: &[char], start: usize) -> usize {
+        let had_field = self.has_field;
+        self.has_field = true;
+        self.empty_elements = false;
+        let mut position = start;
+
+        while position < chars.len() {
+            match chars[position] {
+                '"' => {
+                    // `"${name[@]}"` of an empty array is no field at all
+                    if self.empty_elements && !had_field && self.current.text.is_empty() {
+                        self.has_field = false;
+                    }
+                    return position + 1;
+                }
+                // Inside double quotes, a backslash only escapes these characters
+                '\\' if matches!(chars.get(position + 1), Some('$' | '`' | '"' | '\\')) => {
+                    self.push_literal(&chars[position + 1].to_string());
+                    position += 2;
+                }
+                '$' => {
+                    let (parameter, end) = self.parameter(chars, position);
+                    self.push_parameter(parameter, true);
+                    position = end;
+                }
+                ch => {
+                    self.push_literal(&ch.to_string());
+                    position += 1;
+                }
+            }
+        }
+
+        position
+    }
+}
+
+/// Splits `name[subscript]rest` (the subscript is optional) into its parts
+fn split_reference(text: &str) -> (&str, Option<&str>, &str) {
+    let name_end = match text.chars().next() {
+        Some('?' | '!' | '$' | '-') => 1,
+        _ => text
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(text.len()),
+    };
+    let (name, rest) = text.split_at(name_end);
+    if let Some(inside) = rest.strip_prefix('[')
+        && let Some(end) = inside.find(']')
+    {
+        return (name, Some(&inside[..end]), &inside[end + 1..]);
+    }
+    (name, None, rest)
+}
+
+/// The characters of `${name:offset:length}`; negative numbers count back from the end
+fn substring(value: &str, offset: i64, length: Option<i64>) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    let Some(start) = shell::resolve_index(offset, chars.len()) else {
+        return String::new();
+    };
+    let start = start.min(chars.len());
+    let end = match length {
+        None => chars.len(),
+        Some(length) if length < 0 => shell::resolve_index(length, chars.len()).unwrap_or(0),
+        Some(length) => start.saturating_add(usize::try_from(length).unwrap_or(0)),
+    };
+    chars[start..end.clamp(start, chars.len())].iter().collect()
+}
+
+/// Finds the `}` closing a `${`, skipping nested `${...}` (as in `${name:-${other}}`)
+fn find_closing_brace(chars: &[char], start: usize) -> usize {
+    let mut depth: usize = 0;
+    let mut position = start;
+    while position < chars.len() {
+        match chars[position] {
+            '\\' => position += 1,
+            '$' if chars.get(position + 1) == Some(&'{') => {
+                depth += 1;
+                position += 1;
+            }
+            '}' if depth == 0 => return position,
+            '}' => depth -= 1,
+            _ => {}
+        }
+        position += 1;
+    }
+    chars.len()
+}
+
+fn find(chars: &[char], start: usize, wanted: char) -> usize {
+    (start..chars.len())
+        .find(|index| chars[*index] == wanted)
+        .unwrap_or(chars.len())
+}
+
+/**
+    Reads a parameter reference (`$NAME`, `${NAME}` or a special parameter) starting at the `$`.
+    Returns the name and the position after the reference.
+    A `$` that doesn't start a reference is returned as `(None, position + 1)`.
+*/
+fn parameter_name(chars: &[char], start: usize) -> (Option<String>, usize) {
+    let position = start + 1;
+
+    match chars.get(position) {
+        Some('{') => {
+            let end = find_closing_brace(chars, position + 1);
+            let name: String = chars[position + 1..end].iter().collect();
+            (Some(name), end + 1)
+        }
+        Some(c @ ('?' | '!' | '$' | '-' | '0')) => (Some(c.to_string()), position + 1),
+        Some(c) if c.is_ascii_alphabetic() || *c == '_' => {
+            let mut end = position;
+            while chars
+                .get(end)
+                .is_some_and(|c| c.is_ascii_alphanumeric() || *c == '_')
+            {
+                end += 1;
+            }
+            let name: String = chars[position..end].iter().collect();
+            (Some(name), end)
+        }
+        _ => (None, position),
+    }
+}
@@ -0,0 +1,358 @@
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long `git` may take before the prompt gives up on it (in milliseconds)
+pub const DEFAULT_TIMEOUT_MS: u64 = 300;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitHead {
+    /// HEAD points at a branch (short name, e.g. `main`)
+    Branch(String),
+    /// HEAD points directly at a commit (abbreviated hash)
+    Detached(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitStatus {
+    pub head: GitHead,
+    pub ahead: usize,
+    pub behind: usize,
+    pub staged: bool,
+    pub dirty: bool,
+    pub untracked: bool,
+}
+
+/**
+    A repository found on disk. `git_dir` is where HEAD lives, `common_dir` is where
+    refs and config live (they only differ for linked worktrees).
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitRepo {
+    pub worktree: PathBuf,
+    pub git_dir: PathBuf,
+    pub common_dir: PathBuf,
+}
+
+impl GitRepo {
+    /// Walks up from `start` until a `.git` directory (or `.git` file of a worktree) is found
+    pub fn discover(start: &Path) -> Option<GitRepo> {
+        for dir in start.ancestors() {
+            let dot_git = dir.join(".git");
+
+            let git_dir: PathBuf = if dot_git.is_dir() {
+                dot_git
+            } else if dot_git.is_file() {
+                // Worktrees and submodules use a file containing "gitdir: <path>"
+                let content = fs::read_to_string(&dot_git).ok()?;
+                let target = content.trim().strip_prefix("gitdir:")?.trim();
+                dir.join(target)
+            } else {
+                continue;
+            };
+
+            let common_dir: PathBuf = match fs::read_to_string(git_dir.join("commondir")) {
+                Ok(content) => git_dir.join(content.trim()),
+                Err(_) => git_dir.clone(),
+            };
+
+            return Some(GitRepo {
+                worktree: dir.to_path_buf(),
+                git_dir,
+                common_dir,
+            });
+        }
+
+        None
+    }
+
+    /// Reads `.git/HEAD` without invoking git
+    pub fn head(&self) -> Option<GitHead> {
+        let content = fs::read_to_string(self.git_dir.join("HEAD")).ok()?;
+        let content = content.trim();
+
+        if let Some(reference) = content.strip_prefix("ref:") {
+            let reference = reference.trim();
+            let name = reference.strip_prefix("refs/heads/").unwrap_or(reference);
+            return Some(GitHead::Branch(name.to_string()));
+        }
+
+        if content.len() >= 7 && content.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Some(GitHead::Detached(content[..7].to_string()));
+        }
+
+        None
+    }
+
+    /// Resolves a full ref name (e.g. `refs/heads/main`) via loose refs or `packed-refs`
+    pub fn resolve_ref(&self, name: &str) -> Option<String> {
+        if let Ok(content) = fs::read_to_string(self.common_dir.join(name)) {
+            let content = content.trim();
+            // Symbolic refs (like refs/remotes/origin/HEAD) point to another ref
+            return match content.strip_prefix("ref:") {
+                Some(target) => self.resolve_ref(target.trim()),
+                None => Some(content.to_string()),
+            };
+        }
+
+        let packed = fs::read_to_string(self.common_dir.join("packed-refs")).ok()?;
+        for line in packed.lines() {
+            if line.starts_with('#') || line.starts_with('^') {
+                continue;
+            }
+            if let Some((hash, reference)) = line.split_once(' ')
+                && reference == name
+            {
+                return Some(hash.to_string());
+            }
+        }
+
+        None
+    }
+
+    /// Looks up `branch.<name>.remote` and `branch.<name>.merge` in the repository config
+    pub fn upstream_ref(&self, branch: &str) -> Option<String> {
+        let config = fs::read_to_string(self.common_dir.join("config")).ok()?;
+        let wanted_section = format!("[branch \"{}\"]", branch);
+
+        let mut in_section = false;
+        let mut remote: Option<String> = None;
+        let mut merge: Option<String> = None;
+
+        for line in config.lines() {
+            let line = line.trim();
+            if line.starts_with('[') {
+                in_section = line == wanted_section;
+                continue;
+            }
+            if !in_section {
+                continue;
+            }
+            if let Some((key, value)) = line.split_once('=') {
+                match key.trim() {
+                    "remote" => remote = Some(value.trim().to_string()),
+                    "merge" => merge = Some(value.trim().to_string()),
+                    _ => {}
+                }
+            }
+        }
+
+        let merge = merge?;
+        match remote?.as_str() {
+            // "." means the upstream is a local branch
+            "." => Some(merge),
+            remote => {
+                let merged_branch = merge.strip_prefix("refs/heads/").unwrap_or(&merge);
+                Some(format!("refs/remotes/{}/{}", remote, merged_branch))
+            }
+        }
+    }
+}
+
+impl GitStatus {
+    /// Renders the segment, e.g. `(main ↑1↓2 +*?)`
+    pub fn format_segment(&self) -> String {
+        let mut segment: String = match &self.head {
+            GitHead::Branch(name) => name.clone(),
+            GitHead::Detached(hash) => format!("detached {}", hash),
+        };
+
+        if self.ahead > 0 || self.behind > 0 {
+            segment.push(' ');
+            if self.ahead > 0 {
+                segment.push_str(&format!("↑{}", self.ahead));
+            }
+            if self.behind > 0 {
+                segment.push_str(&format!("↓{}", self.behind));
+            }
+        }
+
+        let mut markers = String::new();
+        if self.staged {
+            markers.push('+');
+        }
+        if self.dirty {
+            markers.push('*');
+        }
+        if self.untracked {
+            markers.push('?');
+        }
+        if !markers.is_empty() {
+            segment.push(' ');
+            segment.push_str(&markers);
+        }
+
+        format!("({})", segment)
+    }
+}
+
+/// The segment is opt-in: `value` is that of `BSH_GIT_PROMPT`, enabled with `1`, `true`, `yes` or `on`
+pub fn is_enabled(value: Option<&str>) -> bool {
+    matches!(value.unwrap_or_default(), "1" | "true" | "yes" | "on")
+}
+
+/// Timeout for calls to `git`: the value of `BSH_GIT_PROMPT_TIMEOUT`, in milliseconds
+pub fn timeout(value: Option<&str>) -> Duration {
+    let millis: u64 = value
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_TIMEOUT_MS);
+    Duration::from_millis(millis)
+}
+
+/// Returns the rendered segment for `dir`, or `None` outside of a repository
+pub fn prompt_segment(dir: &Path, timeout: Duration) -> Option<String> {
+    read_status(dir, timeout).map(|status| status.format_segment())
+}
+
+/**
+    Collects the status of the repository containing `dir`.
+    Branch and upstream are read from `.git` directly; git is only invoked (with `timeout`)
+    for the working tree markers and when the refs can't be compared on disk.
+*/
+pub fn read_status(dir: &Path, timeout: Duration) -> Option<GitStatus> {
+    let repo = GitRepo::discover(dir)?;
+    let deadline = Instant::now() + timeout;
+
+    // A HEAD we can't make sense of (e.g. a reftable repository) is left for git to report
+    let head: Option<GitHead> = repo.head();
+
+    let mut status = GitStatus {
+        head: head.clone().unwrap_or(GitHead::Detached(String::new())),
+        ahead: 0,
+        behind: 0,
+        staged: false,
+        dirty: false,
+        untracked: false,
+    };
+
+    // If local and upstream point to the same commit, we already know there is nothing to count
+    let mut counts_known = head.is_some();
+    if let GitHead::Branch(branch) = &status.head
+        && let Some(upstream) = repo.upstream_ref(branch)
+    {
+        let local = repo.resolve_ref(&format!("refs/heads/{}", branch));
+        let remote = repo.resolve_ref(&upstream);
+        counts_known = local.is_some() && local == remote;
+    }
+
+    let mut args: Vec<&str> = vec!["status", "--porcelain=v2", "--untracked-files=normal"];
+    if !counts_known {
+        args.push("--branch");
+    }
+
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if let Some(output) = run_git(&repo.worktree, &args, remaining) {
+        parse_porcelain_v2(&output, &mut status);
+    }
+
+    if status.head == GitHead::Detached(String::new()) {
+        return None;
+    }
+    Some(status)
+}
+
+/// Applies the output of `git status --porcelain=v2 [--branch]` to `status`
+pub fn parse_porcelain_v2(output: &str, status: &mut GitStatus) {
+    for line in output.lines() {
+        if let Some(header) = line.strip_prefix("# ") {
+            let mut fields = header.split_whitespace();
+            match fields.next() {
+                Some("branch.head") => {
+                    if let Some(name) = fields.next()
+                        && name != "(detached)"
+                    {
+                        status.head = GitHead::Branch(name.to_string());
+                    }
+                }
+                Some("branch.oid") => {
+                    if let GitHead::Detached(_) = status.head
+                        && let Some(hash) = fields.next()
+                        && hash.len() >= 7
+                        && hash.chars().all(|c| c.is_ascii_hexdigit())
+                    {
+                        status.head = GitHead::Detached(hash[..7].to_string());
+                    }
+                }
+                Some("branch.ab") => {
+                    let ahead = fields
+                        .next()
+                        .and_then(|v| v.trim_start_matches('+').parse().ok());
+                    let behind = fields
+                        .next()
+                        .and_then(|v| v.trim_start_matches('-').parse().ok());
+                    status.ahead = ahead.unwrap_or(0);
+                    status.behind = behind.unwrap_or(0);
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        let mut fields = line.split(' ');
+        match fields.next() {
+            // Ordinary and renamed/copied entries: "1 XY ..." / "2 XY ..."
+            Some("1") | Some("2") => {
+                let xy: Vec<char> = fields.next().unwrap_or("..").chars().collect();
+                if xy.first().is_some_and(|c| *c != '.') {
+                    status.staged = true;
+                }
+                if xy.get(1).is_some_and(|c| *c != '.') {
+                    status.dirty = true;
+                }
+            }
+            // Unmerged entries
+            Some("u") => status.dirty = true,
+            Some("?") => status.untracked = true,
+            _ => {}
+        }
+    }
+}
+
+/**
+    Runs git in `worktree` and returns its stdout.
+    Returns `None` if git fails or doesn't finish before `timeout` (the process gets killed).
+*/
+fn run_git(worktree: &Path, args: &[&str], timeout: Duration) -> Option<String> {
+    let mut child = process::Command::new("git")
+        .arg("-C")
+        .arg(worktree)
+        .args(args)
+        .env("GIT_OPTIONAL_LOCKS", "0")
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::null())
+        .spawn()
+        .ok()?;
+
+    // Read in a separate thread, otherwise a full pipe would block git forever
+    let mut stdout = child.stdout.take()?;
+    let (sender, receiver) = mpsc::channel::<String>();
+    thread::spawn(move || {
+        let mut output = String::new();
+        let _ = stdout.read_to_string(&mut output);
+        let _ = sender.send(output);
+    });
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(exit_status)) if exit_status.success() => break,
+            Ok(Some(_)) | Err(_) => return None,
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(5)),
+        }
+    }
+
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    receiver
+        .recv_timeout(remaining.max(Duration::from_millis(50)))
+        .ok()
+}
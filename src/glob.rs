@@ -0,0 +1,202 @@
+use std::fs;
+use std::path::Path;
+
+/// Whether the pattern contains unescaped `*`, `?` or `[` (and so needs matching)
+pub fn has_wildcards(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Escapes the characters that have a meaning in patterns, so the text only matches itself
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Removes the backslashes of an escaped pattern
+pub fn unescape(pattern: &str) -> String {
+    let mut text = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.extend(chars.next()),
+            c => text.push(c),
+        }
+    }
+    text
+}
+
+/**
+    Matches `text` against a shell pattern: `*` matches any string, `?` any character,
+    `[abc]`, `[a-z]` and `[!abc]` (or `[^abc]`) a character of a set and `\x` the character x.
+*/
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    matches_from(&pattern, &text)
+}
+
+fn matches_from(pattern: &[char], text: &[char]) -> bool {
+    let mut p: usize = 0;
+    let mut t: usize = 0;
+    // Where to continue if the text after the last `*` doesn't match
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        let step: Option<usize> = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match match_bracket(pattern, p, text[t]) {
+                Some((true, length)) => Some(length),
+                Some((false, _)) => None,
+                // An unclosed bracket is a literal `[`
+                None => (text[t] == '[').then_some(1),
+            },
+            Some('\\') if p + 1 < pattern.len() => (pattern[p + 1] == text[t]).then_some(2),
+            Some(c) => (*c == text[t]).then_some(1),
+            None => None,
+        };
+
+        match step {
+            Some(length) => {
+                p += length;
+                t += 1;
+            }
+            None => match backtrack {
+                Some((star, position)) => {
+                    p = star + 1;
+                    t = position + 1;
+                    backtrack = Some((star, position + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/**
+    Matches a character against the bracket expression starting at `start`.
+    Returns whether it matched and the length of the expression, or `None` if it isn't closed.
+*/
+fn match_bracket(pattern: &[char], start: usize, c: char) -> Option<(bool, usize)> {
+    let mut position = start + 1;
+    let negated = matches!(pattern.get(position), Some('!' | '^'));
+    if negated {
+        position += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let mut low = *pattern.get(position)?;
+        // A `]` right at the start is part of the set
+        if low == ']' && !first {
+            break;
+        }
+        first = false;
+        if low == '\\' {
+            position += 1;
+            low = *pattern.get(position)?;
+        }
+
+        if pattern.get(position + 1) == Some(&'-')
+            && pattern.get(position + 2).is_some_and(|c| *c != ']')
+        {
+            let high = pattern[position + 2];
+            matched |= low <= c && c <= high;
+            position += 3;
+        } else {
+            matched |= low == c;
+            position += 1;
+        }
+    }
+
+    Some((matched != negated, position + 1 - start))
+}
+
+/**
+    Expands a pattern into the sorted list of matching paths, relative paths from `cwd`.
+    Hidden files only match when the pattern component starts with a `.` itself.
+*/
+pub fn expand_pattern(pattern: &str, cwd: &Path) -> Vec<String> {
+    let (root, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => ("/".to_string(), rest),
+        None => (String::new(), pattern),
+    };
+
+    let mut paths: Vec<String> = vec![root];
+    let components: Vec<&str> = rest.split('/').collect();
+
+    for (index, component) in components.iter().enumerate() {
+        let is_last = index + 1 == components.len();
+        let mut next: Vec<String> = Vec::new();
+
+        for path in &paths {
+            if component.is_empty() {
+                // A trailing or double slash
+                next.push(format!("{}/", path.trim_end_matches('/')));
+                continue;
+            }
+
+            if !has_wildcards(component) {
+                let candidate = format!("{}{}", path, unescape(component));
+                if cwd.join(&candidate).exists() {
+                    next.push(if is_last {
+                        candidate
+                    } else {
+                        format!("{}/", candidate)
+                    });
+                }
+                continue;
+            }
+
+            let Ok(entries) = fs::read_dir(cwd.join(path)) else {
+                continue;
+            };
+            let mut names: Vec<String> = entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| is_last || entry.path().is_dir())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .filter(|name| !name.starts_with('.') || component.starts_with('.'))
+                .filter(|name| matches(component, name))
+                .collect();
+            names.sort();
+
+            for name in names {
+                next.push(if is_last {
+                    format!("{}{}", path, name)
+                } else {
+                    format!("{}{}/", path, name)
+                });
+            }
+        }
+
+        paths = next;
+        if paths.is_empty() {
+            break;
+        }
+    }
+
+    paths
+}
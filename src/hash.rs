@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+/// Where a command was found, and how often it has been run from there
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashEntry {
+    pub path: PathBuf,
+    pub hits: usize,
+}
+
+/**
+    Remembers where commands were found in `PATH`, so running them again doesn't search it
+    (the table of the `hash` builtin). It empties itself when `PATH` changes.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct CommandHash {
+    /// The `PATH` the entries were found with
+    search_path: String,
+    entries: BTreeMap<String, HashEntry>,
+}
+
+impl CommandHash {
+    /**
+        Finds the program to run for a command name without a slash, from the table or `PATH`.
+        Returns the path and whether it came from the table.
+    */
+    pub fn resolve(
+        &mut self,
+        name: &str,
+        search_path: &str,
+        cwd: &Path,
+    ) -> Option<(PathBuf, bool)> {
+        self.check_search_path(search_path);
+        if let Some(entry) = self.entries.get_mut(name) {
+            entry.hits += 1;
+            return Some((entry.path.clone(), true));
+        }
+
+        let (path, relative) = search(name, search_path, cwd)?;
+        // Programs found through relative directories depend on the working directory
+        if !relative {
+            let entry = HashEntry {
+                path: path.clone(),
+                hits: 1,
+            };
+            self.entries.insert(name.to_string(), entry);
+        }
+        Some((path, false))
+    }
+
+    /// The entry of a command, if it is in the table for this `PATH`
+    pub fn get(&mut self, name: &str, search_path: &str) -> Option<&HashEntry> {
+        self.check_search_path(search_path);
+        self.entries.get(name)
+    }
+
+    /// Puts a command in the table, like `hash -p path name`
+    pub fn insert(&mut self, name: &str, path: PathBuf, search_path: &str) {
+        self.check_search_path(search_path);
+        self.entries
+            .insert(name.to_string(), HashEntry { path, hits: 0 });
+    }
+
+    /// Forgets a command (e.g. because its program is gone); returns whether it was known
+    pub fn remove(&mut self, name: &str, search_path: &str) -> bool {
+        self.check_search_path(search_path);
+        self.entries.remove(name).is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// The commands in the table, sorted by name
+    pub fn entries(&mut self, search_path: &str) -> impl Iterator<Item = (&str, &HashEntry)> {
+        self.check_search_path(search_path);
+        self.entries
+            .iter()
+            .map(|(name, entry)| (name.as_str(), entry))
+    }
+
+    fn check_search_path(&mut self, search_path: &str) {
+        if self.search_path != search_path {
+            self.entries.clear();
+            self.search_path = search_path.to_string();
+        }
+    }
+}
+
+/**
+    Searches the directories of `PATH` for an executable file, without the table.
+    Returns the path and whether it was found in a relative directory (like `.` or an empty entry).
+*/
+pub fn search(name: &str, search_path: &str, cwd: &Path) -> Option<(PathBuf, bool)> {
+    candidates(name, search_path, cwd).next()
+}
+
+/// All executable files named `name` in the directories of `PATH`, in order
+pub fn search_all(name: &str, search_path: &str, cwd: &Path) -> Vec<(PathBuf, bool)> {
+    candidates(name, search_path, cwd).collect()
+}
+
+fn candidates<'a>(
+    name: &'a str,
+    search_path: &'a str,
+    cwd: &'a Path,
+) -> impl Iterator<Item = (PathBuf, bool)> + 'a {
+    search_path.split(':').filter_map(move |directory| {
+        // An empty entry means the working directory
+        let directory = if directory.is_empty() { "." } else { directory };
+        let relative = !directory.starts_with('/');
+        let path = Path::new(directory).join(name);
+        is_executable(&cwd.join(&path)).then_some((path, relative))
+    })
+}
+
+pub fn is_executable(path: &Path) -> bool {
+    match fs::metadata(path) {
+        Ok(metadata) => metadata.is_file() && metadata.permissions().mode() & 0o111 != 0,
+        Err(_) => false,
+    }
+}
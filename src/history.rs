@@ -0,0 +1,391 @@
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Used when `HISTSIZE` isn't set (same as bash)
+pub const DEFAULT_HISTSIZE: usize = 500;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    /// Seconds since the unix epoch (0 if unknown)
+    pub timestamp: u64,
+    pub command: String,
+}
+
+/// The settings from `HISTCONTROL`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistoryControl {
+    /// Don't record lines starting with a space
+    pub ignore_space: bool,
+    /// Don't record a line matching the previous entry
+    pub ignore_dups: bool,
+    /// Remove all earlier entries matching a new line
+    pub erase_dups: bool,
+}
+
+impl HistoryControl {
+    /// Parses a colon separated list like `ignoreboth:erasedups`
+    pub fn parse(value: &str) -> HistoryControl {
+        let mut control = HistoryControl::default();
+        for setting in value.split(':') {
+            match setting {
+                "ignorespace" => control.ignore_space = true,
+                "ignoredups" => control.ignore_dups = true,
+                "ignoreboth" => {
+                    control.ignore_space = true;
+                    control.ignore_dups = true;
+                }
+                "erasedups" => control.erase_dups = true,
+                _ => {}
+            }
+        }
+        control
+    }
+}
+
+/**
+    The command history of a session.
+    Every added line is also appended to `file` right away (under a file lock),
+    so several shells running at the same time don't overwrite each other's history.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    entries: Vec<HistoryEntry>,
+    /// Where history is persisted (`None` keeps it in memory only)
+    pub file: Option<PathBuf>,
+    /// Maximum number of entries kept in memory (`HISTSIZE`, `None` = unlimited)
+    pub size: Option<usize>,
+    /// Maximum number of entries kept in the file (`HISTFILESIZE`, `None` = unlimited)
+    pub file_size: Option<usize>,
+    pub control: HistoryControl,
+}
+
+impl History {
+    /// An empty in-memory history without any limits
+    pub fn new() -> History {
+        History::default()
+    }
+
+    /**
+        Takes `HISTFILE`, `HISTSIZE`, `HISTFILESIZE` and `HISTCONTROL` from the variables of the
+        shell (through `get_var`), keeping the entries. Assignments in the shell apply once the
+        history is configured again.
+    */
+    pub fn configure<'a>(&mut self, get_var: impl Fn(&str) -> Option<&'a str>) {
+        self.size = match get_var("HISTSIZE") {
+            Some(value) => parse_limit(value).unwrap_or(Some(DEFAULT_HISTSIZE)),
+            None => Some(DEFAULT_HISTSIZE),
+        };
+        // Like bash, the file keeps as many entries as memory unless told otherwise
+        self.file_size = match get_var("HISTFILESIZE") {
+            Some(value) => parse_limit(value).unwrap_or(self.size),
+            None => self.size,
+        };
+        self.file = default_file(&get_var);
+        self.control = HistoryControl::parse(get_var("HISTCONTROL").unwrap_or_default());
+    }
+
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the entry with the given history number (starting at 1)
+    pub fn get(&self, number: usize) -> Option<&HistoryEntry> {
+        number
+            .checked_sub(1)
+            .and_then(|index| self.entries.get(index))
+    }
+
+    /// Reads the history file (if any) into memory, used on startup
+    pub fn load(&mut self) -> io::Result<()> {
+        match self.file.clone() {
+            Some(path) => match self.read_file(&path) {
+                Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+                result => result,
+            },
+            None => Ok(()),
+        }
+    }
+
+    /**
+        Records a line typed by the user, honoring `HISTCONTROL`.
+        Returns whether the line was recorded.
+    */
+    pub fn add(&mut self, line: &str) -> bool {
+        let command: &str = line.trim_end_matches(['\n', '\r']);
+
+        if command.trim().is_empty() || self.size == Some(0) {
+            return false;
+        }
+        if self.control.ignore_space && command.starts_with(' ') {
+            return false;
+        }
+        if self.control.ignore_dups
+            && self
+                .entries
+                .last()
+                .is_some_and(|last| last.command == command)
+        {
+            return false;
+        }
+        if self.control.erase_dups {
+            self.entries.retain(|entry| entry.command != command);
+        }
+
+        let entry = HistoryEntry {
+            timestamp: now(),
+            command: command.to_string(),
+        };
+        self.entries.push(entry.clone());
+        self.trim_to_size();
+
+        if let Some(path) = self.file.clone()
+            && let Err(error) = self.append_to_file(&path, &[entry])
+        {
+            eprintln!("bettershell: history: {}: {}", path.display(), error);
+        }
+
+        true
+    }
+
+    /// Removes all entries from memory (`history -c`)
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /**
+        Deletes the entry at `offset` (`history -d`).
+        Negative offsets count from the end, so `-1` is the last entry.
+    */
+    pub fn delete(&mut self, offset: i64) -> Result<(), String> {
+        let index = self
+            .resolve_offset(offset)
+            .ok_or_else(|| format!("{}: history position out of range", offset))?;
+        self.entries.remove(index);
+        Ok(())
+    }
+
+    /// Deletes the entries from `start` to `end` inclusive (`history -d start-end`)
+    pub fn delete_range(&mut self, start: i64, end: i64) -> Result<(), String> {
+        let out_of_range = || format!("{}-{}: history position out of range", start, end);
+        let first = self.resolve_offset(start).ok_or_else(out_of_range)?;
+        let last = self.resolve_offset(end).ok_or_else(out_of_range)?;
+        if first > last {
+            return Err(out_of_range());
+        }
+        self.entries.drain(first..=last);
+        Ok(())
+    }
+
+    /// Appends the contents of `path` to the history in memory (`history -r`)
+    pub fn read_file(&mut self, path: &Path) -> io::Result<()> {
+        let mut file = fs::File::open(path)?;
+        file.lock_shared()?;
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        file.unlock()?;
+
+        self.entries.extend(parse_file(&content));
+        self.trim_to_size();
+        Ok(())
+    }
+
+    /// Overwrites `path` with the history in memory (`history -w`)
+    pub fn write_file(&self, path: &Path) -> io::Result<()> {
+        let mut file = open_for_update(path)?;
+        file.lock()?;
+        let entries = self.limit_for_file(self.entries.clone());
+        file.set_len(0)?;
+        file.write_all(format_file(&entries).as_bytes())?;
+        file.unlock()
+    }
+
+    /**
+        Appends `new_entries` to `path` while holding an exclusive lock.
+        If the file grows beyond `HISTFILESIZE`, the oldest entries are dropped.
+    */
+    pub fn append_to_file(&self, path: &Path, new_entries: &[HistoryEntry]) -> io::Result<()> {
+        let mut file = open_for_update(path)?;
+        file.lock()?;
+
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        let mut entries: Vec<HistoryEntry> = parse_file(&content);
+        entries.extend_from_slice(new_entries);
+
+        let limited = self.limit_for_file(entries.clone());
+        if limited.len() == entries.len() {
+            file.seek(SeekFrom::End(0))?;
+            file.write_all(format_file(new_entries).as_bytes())?;
+        } else {
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(format_file(&limited).as_bytes())?;
+        }
+
+        file.unlock()
+    }
+
+    fn resolve_offset(&self, offset: i64) -> Option<usize> {
+        let len = self.entries.len() as i64;
+        let index: i64 = if offset < 0 { len + offset } else { offset - 1 };
+        if index < 0 || index >= len {
+            return None;
+        }
+        Some(index as usize)
+    }
+
+    fn trim_to_size(&mut self) {
+        if let Some(size) = self.size
+            && self.entries.len() > size
+        {
+            let excess = self.entries.len() - size;
+            self.entries.drain(..excess);
+        }
+    }
+
+    fn limit_for_file(&self, mut entries: Vec<HistoryEntry>) -> Vec<HistoryEntry> {
+        if self.control.erase_dups {
+            // Keep only the most recent occurrence of every command
+            let mut seen = std::collections::HashSet::new();
+            entries.reverse();
+            entries.retain(|entry| seen.insert(entry.command.clone()));
+            entries.reverse();
+        }
+        if let Some(file_size) = self.file_size
+            && entries.len() > file_size
+        {
+            let excess = entries.len() - file_size;
+            entries.drain(..excess);
+        }
+        entries
+    }
+}
+
+/**
+    `$HISTFILE` if set, otherwise `$XDG_STATE_HOME/bettershell/history`
+    (falling back to `~/.local/state/bettershell/history`).
+*/
+pub fn default_file<'a>(get_var: impl Fn(&str) -> Option<&'a str>) -> Option<PathBuf> {
+    if let Some(file) = get_var("HISTFILE") {
+        return if file.is_empty() {
+            None
+        } else {
+            Some(PathBuf::from(file))
+        };
+    }
+
+    let state_home: PathBuf = match get_var("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(get_var("HOME")?).join(".local/state"),
+    };
+    Some(state_home.join("bettershell").join("history"))
+}
+
+/**
+    Parses the history file format: every command is preceded by a `#<timestamp>` line
+    (the same format bash writes when `HISTTIMEFORMAT` is set).
+    The lines up to the next timestamp belong to the same command (like a here-document);
+    in those, a leading backslash escapes the line. Lines before any timestamp are one
+    command each.
+*/
+pub fn parse_file(content: &str) -> Vec<HistoryEntry> {
+    let mut entries: Vec<HistoryEntry> = Vec::new();
+    // The command after the last timestamp
+    let mut current: Option<HistoryEntry> = None;
+
+    for line in content.lines() {
+        if let Some(stamp) = line.strip_prefix('#')
+            && let Ok(stamp) = stamp.parse::<u64>()
+        {
+            entries.extend(current.take().and_then(finish_entry));
+            current = Some(HistoryEntry {
+                timestamp: stamp,
+                command: String::new(),
+            });
+            continue;
+        }
+        match &mut current {
+            Some(entry) if !entry.command.is_empty() => {
+                entry.command.push('\n');
+                entry
+                    .command
+                    .push_str(line.strip_prefix('\\').unwrap_or(line));
+            }
+            Some(entry) => entry.command.push_str(line),
+            None if line.is_empty() => {}
+            None => entries.push(HistoryEntry {
+                timestamp: 0,
+                command: line.to_string(),
+            }),
+        }
+    }
+    entries.extend(current.and_then(finish_entry));
+
+    entries
+}
+
+/// Drops the blank lines at the end of a command read from the file (and empty commands)
+fn finish_entry(mut entry: HistoryEntry) -> Option<HistoryEntry> {
+    entry
+        .command
+        .truncate(entry.command.trim_end_matches('\n').len());
+    (!entry.command.is_empty()).then_some(entry)
+}
+
+/**
+    Writes commands in the format of `parse_file`. Lines after the first one that start with
+    `#` (so they can't pass for a timestamp) or a backslash are escaped with a backslash.
+*/
+pub fn format_file(entries: &[HistoryEntry]) -> String {
+    let mut content = String::new();
+    for entry in entries {
+        content.push_str(&format!("#{}\n", entry.timestamp));
+        for (index, line) in entry.command.split('\n').enumerate() {
+            if index > 0 && line.starts_with(['#', '\\']) {
+                content.push('\\');
+            }
+            content.push_str(line);
+            content.push('\n');
+        }
+    }
+    content
+}
+
+/// Parses `HISTSIZE`-style values: negative means unlimited, garbage is `None`
+fn parse_limit(value: &str) -> Option<Option<usize>> {
+    let number: i64 = value.trim().parse().ok()?;
+    if number < 0 {
+        Some(None)
+    } else {
+        Some(Some(number as usize))
+    }
+}
+
+fn open_for_update(path: &Path) -> io::Result<fs::File> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
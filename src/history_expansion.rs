@@ -0,0 +1,495 @@
+use crate::history::History;
+
+/// The result of expanding a line that contained history references
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expansion {
+    pub line: String,
+    /// The `:p` modifier was used: show the line, but don't execute it
+    pub print_only: bool,
+}
+
+/// The text replaced by an `:s` modifier, remembered for `:&` and empty patterns
+#[derive(Default)]
+struct Substitution {
+    old: String,
+    new: String,
+}
+
+/**
+    Performs csh-style history expansion (`!!`, `!n`, `!-n`, `!prefix`, `!?substr?`,
+    word designators, modifiers and `^old^new^`) on `line`.
+    Returns `Ok(None)` if the line contains nothing to expand.
+*/
+pub fn expand(line: &str, history: &History) -> Result<Option<Expansion>, String> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut expander = Expander {
+        chars: &chars,
+        position: 0,
+        history,
+        output: String::new(),
+        print_only: false,
+        substitution: None,
+        expanded: false,
+    };
+
+    if chars.first() == Some(&'^') {
+        expander.quick_substitution()?;
+    }
+    expander.expand_rest()?;
+
+    if !expander.expanded {
+        return Ok(None);
+    }
+    Ok(Some(Expansion {
+        line: expander.output,
+        print_only: expander.print_only,
+    }))
+}
+
+/// Splits a command into words, keeping quoted parts (and their quotes) together
+pub fn split_words(command: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut in_quote: Option<char> = None;
+    let mut escaped = false;
+
+    for ch in command.chars() {
+        if escaped {
+            current.push(ch);
+            escaped = false;
+            continue;
+        }
+        match ch {
+            '\\' if in_quote != Some('\'') => {
+                current.push(ch);
+                escaped = true;
+            }
+            '"' | '\'' => {
+                if in_quote == Some(ch) {
+                    in_quote = None;
+                } else if in_quote.is_none() {
+                    in_quote = Some(ch);
+                }
+                current.push(ch);
+            }
+            c if c.is_whitespace() && in_quote.is_none() => {
+                if !current.is_empty() {
+                    words.push(std::mem::take(&mut current));
+                }
+            }
+            _ => current.push(ch),
+        }
+    }
+
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+struct Expander<'a> {
+    chars: &'a [char],
+    position: usize,
+    history: &'a History,
+    output: String,
+    print_only: bool,
+    substitution: Option<Substitution>,
+    expanded: bool,
+}
+
+impl Expander<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.position + offset).copied()
+    }
+
+    fn text(&self, start: usize) -> String {
+        self.chars[start..self.position].iter().collect()
+    }
+
+    /// `^old^new^` is a shorthand for `!!:s/old/new/`
+    fn quick_substitution(&mut self) -> Result<(), String> {
+        self.position = 1;
+        let old = self.read_until('^');
+        let new = self.read_until('^');
+        let event = self.previous_event(1, "^")?;
+
+        match event.find(&old).filter(|_| !old.is_empty()) {
+            Some(index) => {
+                self.output.push_str(&format!(
+                    "{}{}{}",
+                    &event[..index],
+                    new,
+                    &event[index + old.len()..]
+                ));
+            }
+            None => return Err(format!("^{}^{}: substitution failed", old, new)),
+        }
+
+        self.substitution = Some(Substitution { old, new });
+        self.expanded = true;
+        Ok(())
+    }
+
+    fn expand_rest(&mut self) -> Result<(), String> {
+        let mut in_single_quote = false;
+
+        while let Some(ch) = self.peek() {
+            match ch {
+                '\\' => {
+                    // An escaped character (like \!) is copied as is
+                    self.output.push(ch);
+                    self.position += 1;
+                    if let Some(next) = self.peek() {
+                        self.output.push(next);
+                        self.position += 1;
+                    }
+                }
+                '\'' => {
+                    in_single_quote = !in_single_quote;
+                    self.output.push(ch);
+                    self.position += 1;
+                }
+                '!' if !in_single_quote && self.starts_event() => {
+                    let start = self.position;
+                    self.position += 1;
+                    let expansion = self.expand_reference(start)?;
+                    self.output.push_str(&expansion);
+                    self.expanded = true;
+                }
+                _ => {
+                    self.output.push(ch);
+                    self.position += 1;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+        A `!` followed by a blank, `=`, the end of the line or a character that ends an event
+        name (like `;` or the closing `"` of `"hey!"`) is just a `!`
+    */
+    fn starts_event(&self) -> bool {
+        match self.peek_at(1) {
+            None => false,
+            Some(next) => !(next.is_whitespace() || next == '=' || ";&|<>()'\"`".contains(next)),
+        }
+    }
+
+    /// Expands one `!...` reference, `start` is the position of the `!`
+    fn expand_reference(&mut self, start: usize) -> Result<String, String> {
+        let mut search_word: Option<String> = None;
+
+        let event: String = match self.peek() {
+            Some('!') => {
+                self.position += 1;
+                self.previous_event(1, &self.text(start))?
+            }
+            // `!$`, `!^`, `!*`, `!:n` and `!%` refer to the previous command
+            Some(c @ ('$' | '^' | '*' | ':' | '%')) => {
+                self.previous_event(1, &format!("!{}", c))?
+            }
+            Some('#') => {
+                self.position += 1;
+                self.output.clone()
+            }
+            Some('-') if self.peek_at(1).is_some_and(|c| c.is_ascii_digit()) => {
+                self.position += 1;
+                let offset = self.read_number();
+                self.previous_event(offset, &self.text(start))?
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let number = self.read_number();
+                match self.history.get(number) {
+                    Some(entry) => entry.command.clone(),
+                    None => return Err(format!("{}: event not found", self.text(start))),
+                }
+            }
+            Some('?') => {
+                self.position += 1;
+                let substring = self.read_until_any(&['?', '\n']);
+                if self.peek() == Some('?') {
+                    self.position += 1;
+                }
+                search_word = Some(substring.clone());
+                self.find_event(|command| command.contains(&substring))
+                    .ok_or_else(|| format!("{}: event not found", self.text(start)))?
+            }
+            _ => {
+                let prefix = self.read_event_name();
+                self.find_event(|command| command.starts_with(&prefix))
+                    .ok_or_else(|| format!("{}: event not found", self.text(start)))?
+            }
+        };
+
+        let mut text: String = self.word_designator(&event, search_word.as_deref())?;
+        self.apply_modifiers(&mut text)?;
+        Ok(text)
+    }
+
+    fn previous_event(&self, offset: usize, reference: &str) -> Result<String, String> {
+        let entries = self.history.entries();
+        match entries.len().checked_sub(offset) {
+            Some(index) if offset > 0 => Ok(entries[index].command.clone()),
+            _ => Err(format!("{}: event not found", reference)),
+        }
+    }
+
+    /// Finds the most recent command matching `predicate`
+    fn find_event(&self, predicate: impl Fn(&str) -> bool) -> Option<String> {
+        self.history
+            .entries()
+            .iter()
+            .rev()
+            .find(|entry| predicate(&entry.command))
+            .map(|entry| entry.command.clone())
+    }
+
+    fn read_number(&mut self) -> usize {
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.position += 1;
+        }
+        self.text(start).parse().unwrap_or(0)
+    }
+
+    fn read_until(&mut self, delimiter: char) -> String {
+        let text = self.read_until_any(&[delimiter]);
+        if self.peek() == Some(delimiter) {
+            self.position += 1;
+        }
+        text
+    }
+
+    fn read_until_any(&mut self, delimiters: &[char]) -> String {
+        let start = self.position;
+        while self.peek().is_some_and(|c| !delimiters.contains(&c)) {
+            self.position += 1;
+        }
+        self.text(start)
+    }
+
+    /// The `prefix` of `!prefix` ends at a blank, a colon or a shell metacharacter
+    fn read_event_name(&mut self) -> String {
+        let start = self.position;
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || ":;&|<>()'\"`".contains(c) {
+                break;
+            }
+            self.position += 1;
+        }
+        self.text(start)
+    }
+
+    /// Handles `:0`, `:n`, `:^`, `:$`, `:*`, `:x-y`, `:x*`, `:x-` and `:%`
+    fn word_designator(
+        &mut self,
+        event: &str,
+        search_word: Option<&str>,
+    ) -> Result<String, String> {
+        let explicit = self.peek() == Some(':')
+            && self
+                .peek_at(1)
+                .is_some_and(|c| c.is_ascii_digit() || "^$*-%".contains(c));
+        let implicit = self.peek().is_some_and(|c| "^$*-%".contains(c));
+
+        if !explicit && !implicit {
+            return Ok(event.to_string());
+        }
+        if explicit {
+            self.position += 1;
+        }
+
+        let designator_start = self.position;
+        let words: Vec<String> = split_words(event);
+        let last: usize = words.len().saturating_sub(1);
+        let bad_designator = |designator: &str| format!(":{}: bad word specifier", designator);
+
+        let (first, end): (usize, usize) = match self.peek() {
+            Some('*') => {
+                self.position += 1;
+                if words.len() <= 1 {
+                    return Ok(String::new());
+                }
+                (1, last)
+            }
+            Some('%') => {
+                self.position += 1;
+                let found = search_word
+                    .and_then(|search| words.iter().position(|word| word.contains(search)));
+                match found {
+                    Some(index) => (index, index),
+                    None => return Err(bad_designator("%")),
+                }
+            }
+            _ => {
+                let first: usize = match self.read_word_index(last) {
+                    Some(index) => index,
+                    // `-y` is short for `0-y`
+                    None if self.peek() == Some('-') => 0,
+                    None => return Err(bad_designator(&self.text(designator_start))),
+                };
+                match self.peek() {
+                    Some('*') => {
+                        self.position += 1;
+                        (first, last)
+                    }
+                    Some('-') => {
+                        self.position += 1;
+                        match self.read_word_index(last) {
+                            Some(end) => (first, end),
+                            // `x-` is like `x*`, but without the last word
+                            None => (first, last.saturating_sub(1)),
+                        }
+                    }
+                    _ => (first, first),
+                }
+            }
+        };
+
+        if first > end || end >= words.len() {
+            return Err(bad_designator(&self.text(designator_start)));
+        }
+        Ok(words[first..=end].join(" "))
+    }
+
+    fn read_word_index(&mut self, last: usize) -> Option<usize> {
+        match self.peek()? {
+            '^' => {
+                self.position += 1;
+                Some(1)
+            }
+            '$' => {
+                self.position += 1;
+                Some(last)
+            }
+            c if c.is_ascii_digit() => Some(self.read_number()),
+            _ => None,
+        }
+    }
+
+    /// Applies `:h`, `:t`, `:r`, `:e`, `:p`, `:s/old/new/`, `:gs/old/new/` and `:&`
+    fn apply_modifiers(&mut self, text: &mut String) -> Result<(), String> {
+        while self.peek() == Some(':') {
+            let Some(modifier) = self.peek_at(1) else {
+                break;
+            };
+
+            match modifier {
+                'h' => {
+                    if let Some(index) = text.rfind('/') {
+                        text.truncate(index.max(if text.starts_with('/') { 1 } else { 0 }));
+                    }
+                }
+                't' => {
+                    if let Some(index) = text.rfind('/') {
+                        *text = text[index + 1..].to_string();
+                    }
+                }
+                'r' => {
+                    if let Some(index) = suffix_start(text) {
+                        text.truncate(index);
+                    }
+                }
+                'e' => {
+                    *text = match suffix_start(text) {
+                        Some(index) => text[index..].to_string(),
+                        None => String::new(),
+                    };
+                }
+                'p' => self.print_only = true,
+                's' | '&' | 'g' => {
+                    self.position += 1;
+                    return self.substitute(text);
+                }
+                _ => break,
+            }
+            self.position += 2;
+        }
+        Ok(())
+    }
+
+    /// Parses and applies `s/old/new/`, `gs/old/new/`, `&` or `g&` (position is after the colon)
+    fn substitute(&mut self, text: &mut String) -> Result<(), String> {
+        let start = self.position;
+        let global = self.peek() == Some('g');
+        if global {
+            self.position += 1;
+        }
+
+        match self.peek() {
+            Some('s') => {
+                self.position += 1;
+                let Some(delimiter) = self.peek() else {
+                    return Err(format!(":{}: bad word specifier", self.text(start)));
+                };
+                self.position += 1;
+
+                let mut old = self.read_substitution_part(delimiter);
+                let new = self.read_substitution_part(delimiter);
+                if old.is_empty() {
+                    // An empty pattern reuses the previous one
+                    old = match &self.substitution {
+                        Some(previous) => previous.old.clone(),
+                        None => return Err("no previous substitution".to_string()),
+                    };
+                }
+                // `&` in the replacement stands for the pattern
+                let new = new.replace('&', &old);
+                self.substitution = Some(Substitution { old, new });
+            }
+            Some('&') => {
+                self.position += 1;
+                if self.substitution.is_none() {
+                    return Err("no previous substitution".to_string());
+                }
+            }
+            _ => return Err(format!(":{}: bad word specifier", self.text(start))),
+        }
+
+        let substitution = self.substitution.as_ref().unwrap();
+        if !text.contains(&substitution.old) {
+            return Err(format!(":{}: substitution failed", self.text(start)));
+        }
+        *text = if global {
+            text.replace(&substitution.old, &substitution.new)
+        } else {
+            text.replacen(&substitution.old, &substitution.new, 1)
+        };
+
+        // More modifiers may follow
+        self.apply_modifiers(text)
+    }
+
+    /// Reads up to the next unescaped `delimiter` (the last delimiter may be left out)
+    fn read_substitution_part(&mut self, delimiter: char) -> String {
+        let mut part = String::new();
+        while let Some(c) = self.peek() {
+            self.position += 1;
+            if c == delimiter {
+                break;
+            }
+            if c == '\\' && self.peek() == Some(delimiter) {
+                part.push(delimiter);
+                self.position += 1;
+                continue;
+            }
+            part.push(c);
+        }
+        part
+    }
+}
+
+/// Position of the `.suffix` in the last path component, if there is one
+fn suffix_start(text: &str) -> Option<usize> {
+    let name_start = text.rfind('/').map(|index| index + 1).unwrap_or(0);
+    text[name_start..]
+        .rfind('.')
+        .filter(|index| *index > 0)
+        .map(|index| name_start + index)
+}
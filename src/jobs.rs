@@ -0,0 +1,582 @@
+use crate::signals;
+use std::io::{self, IsTerminal, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessState {
+    Running,
+    Stopped(i32),
+    Exited(i32),
+    /// Killed by a signal (and whether a core was dumped)
+    Signaled(i32, bool),
+}
+
+impl ProcessState {
+    /// The status as seen by `$?` (128 + N for signals)
+    pub fn status(self) -> i32 {
+        match self {
+            ProcessState::Exited(code) => code,
+            ProcessState::Signaled(signal, _) | ProcessState::Stopped(signal) => 128 + signal,
+            ProcessState::Running => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Process {
+    pub pid: i32,
+    pub state: ProcessState,
+}
+
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub id: usize,
+    /// Process group of the job (`None` without job control)
+    pub pgid: Option<i32>,
+    pub command: String,
+    pub processes: Vec<Process>,
+    /// Terminal settings of a stopped job, restored by `fg`
+    terminal_modes: Option<libc::termios>,
+    /// Whether the user was already told that the job stopped
+    stop_reported: bool,
+}
+
+impl Job {
+    pub fn is_completed(&self) -> bool {
+        self.processes.iter().all(|process| {
+            matches!(
+                process.state,
+                ProcessState::Exited(_) | ProcessState::Signaled(..)
+            )
+        })
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        !self.is_completed()
+            && self
+                .processes
+                .iter()
+                .any(|process| matches!(process.state, ProcessState::Stopped(_)))
+    }
+
+    /**
+        The exit status of the job is the status of its last process.
+        With `set -o pipefail` it is the status of the last process that failed.
+    */
+    pub fn status(&self, pipefail: bool) -> i32 {
+        if pipefail {
+            return self
+                .processes
+                .iter()
+                .rev()
+                .map(|process| process.state.status())
+                .find(|status| *status != 0)
+                .unwrap_or(0);
+        }
+        self.processes
+            .last()
+            .map(|process| process.state.status())
+            .unwrap_or(0)
+    }
+
+    /// The pid `jobs -p` and `$!` refer to
+    pub fn leader(&self) -> i32 {
+        self.pgid
+            .unwrap_or_else(|| self.processes.first().map(|p| p.pid).unwrap_or(0))
+    }
+
+    /// The state as shown by `jobs` and notifications ("Running", "Done", "Exit 1", ...)
+    pub fn state_label(&self) -> String {
+        if self.is_stopped() {
+            return "Stopped".to_string();
+        }
+        if !self.is_completed() {
+            return "Running".to_string();
+        }
+        match self.processes.last().map(|process| process.state) {
+            Some(ProcessState::Exited(0)) | None => "Done".to_string(),
+            Some(ProcessState::Exited(code)) => format!("Exit {}", code),
+            Some(ProcessState::Signaled(signal, core_dumped)) => {
+                let mut label = signals::signal_description(signal);
+                if core_dumped {
+                    label.push_str(" (core dumped)");
+                }
+                label
+            }
+            Some(_) => "Running".to_string(),
+        }
+    }
+}
+
+/**
+    All jobs of the session; `recency` orders job ids so the last one is the current job (`%+`).
+    Also holds what the shell needs to hand the terminal to jobs and take it back.
+*/
+#[derive(Debug, Default)]
+pub struct JobTable {
+    jobs: Vec<Job>,
+    recency: Vec<usize>,
+    /// Set when the shell runs interactively and owns the terminal
+    job_control: bool,
+    shell_pgid: i32,
+    /// Terminal settings of the shell, restored whenever a job stops or finishes
+    terminal_modes: Option<libc::termios>,
+    /// The pid behind `$!`
+    last_background_pid: Option<i32>,
+}
+
+impl JobTable {
+    fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|job| job.id == id)
+    }
+
+    fn make_current(&mut self, id: usize) {
+        self.recency.retain(|other| *other != id);
+        self.recency.push(id);
+    }
+
+    fn marker(&self, id: usize) -> char {
+        let length = self.recency.len();
+        if length >= 1 && self.recency[length - 1] == id {
+            '+'
+        } else if length >= 2 && self.recency[length - 2] == id {
+            '-'
+        } else {
+            ' '
+        }
+    }
+
+    /// Formats a job like `jobs` does, e.g. `[1]+  Running                 sleep 10 &`
+    pub fn format_job(&self, job: &Job, with_pid: bool) -> String {
+        let suffix = if !job.is_completed() && !job.is_stopped() {
+            " &"
+        } else {
+            ""
+        };
+        let pid = if with_pid {
+            format!("{} ", job.leader())
+        } else {
+            " ".to_string()
+        };
+        format!(
+            "[{}]{} {}{:<24}{}{}",
+            job.id,
+            self.marker(job.id),
+            pid,
+            job.state_label(),
+            job.command,
+            suffix
+        )
+    }
+
+    /**
+        Sets up job control if the shell is interactive: puts the shell into its own
+        process group, takes over the terminal and ignores the job control signals.
+    */
+    pub fn init_job_control(&mut self) {
+        if !io::stdin().is_terminal() {
+            return;
+        }
+
+        unsafe {
+            // Wait until we are in the foreground (e.g. when started from another shell's background)
+            loop {
+                let pgid = libc::getpgrp();
+                if libc::tcgetpgrp(libc::STDIN_FILENO) == pgid {
+                    break;
+                }
+                libc::kill(-pgid, libc::SIGTTIN);
+            }
+
+            // Ctrl-C, Ctrl-Z and background terminal access must not stop the shell itself
+            signals::ignore_interactive_signals();
+            libc::signal(libc::SIGTTIN, libc::SIG_IGN);
+            libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+
+            let pid = libc::getpid();
+            if libc::getpgrp() != pid && libc::setpgid(pid, pid) < 0 {
+                return;
+            }
+            libc::tcsetpgrp(libc::STDIN_FILENO, pid);
+            self.shell_pgid = pid;
+
+            let mut modes: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut modes) == 0 {
+                self.terminal_modes = Some(modes);
+            }
+        }
+
+        self.job_control = true;
+    }
+
+    pub fn job_control_enabled(&self) -> bool {
+        self.job_control
+    }
+
+    /// Subshells (forked copies of the shell) don't manage the parent's jobs or the terminal
+    pub fn reset_for_subshell(&mut self) {
+        self.job_control = false;
+        self.jobs.clear();
+        self.recency.clear();
+    }
+
+    pub fn last_background_pid(&self) -> Option<i32> {
+        self.last_background_pid
+    }
+
+    pub fn set_last_background_pid(&mut self, pid: i32) {
+        self.last_background_pid = Some(pid);
+    }
+
+    /// Adds a job to the table and returns its number
+    pub fn add_job(&mut self, pgid: Option<i32>, pids: Vec<i32>, command: String) -> usize {
+        let id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        self.jobs.push(Job {
+            id,
+            pgid,
+            command,
+            processes: pids
+                .into_iter()
+                .map(|pid| Process {
+                    pid,
+                    state: ProcessState::Running,
+                })
+                .collect(),
+            terminal_modes: None,
+            stop_reported: false,
+        });
+        self.make_current(id);
+        id
+    }
+
+    pub fn get_job(&self, id: usize) -> Option<Job> {
+        self.jobs.iter().find(|job| job.id == id).cloned()
+    }
+
+    pub fn list_jobs(&self) -> Vec<Job> {
+        self.jobs.clone()
+    }
+
+    pub fn remove_job(&mut self, id: usize) -> Option<Job> {
+        self.recency.retain(|other| *other != id);
+        let index = self.jobs.iter().position(|job| job.id == id)?;
+        Some(self.jobs.remove(index))
+    }
+
+    /**
+        Resolves a job specification: `%n`, `%%`, `%+`, `%-`, `%prefix`, `%?substring`
+        (a plain number is treated as a job number as well).
+    */
+    pub fn resolve_job_spec(&self, spec: &str) -> Result<usize, String> {
+        let spec_body: &str = spec.strip_prefix('%').unwrap_or(spec);
+        let no_such_job = || format!("{}: no such job", spec);
+
+        let found: Option<usize> = match spec_body {
+            "" | "%" | "+" => self.recency.last().copied(),
+            "-" => {
+                let length = self.recency.len();
+                if length >= 2 {
+                    Some(self.recency[length - 2])
+                } else {
+                    self.recency.last().copied()
+                }
+            }
+            body if body.chars().all(|c| c.is_ascii_digit()) => {
+                let id: usize = body.parse().map_err(|_| no_such_job())?;
+                self.jobs.iter().find(|job| job.id == id).map(|job| job.id)
+            }
+            body => {
+                let matches: Vec<usize> = match body.strip_prefix('?') {
+                    Some(substring) => self
+                        .jobs
+                        .iter()
+                        .filter(|job| job.command.contains(substring))
+                        .map(|job| job.id)
+                        .collect(),
+                    None => self
+                        .jobs
+                        .iter()
+                        .filter(|job| job.command.starts_with(body))
+                        .map(|job| job.id)
+                        .collect(),
+                };
+                if matches.len() > 1 {
+                    return Err(format!("{}: ambiguous job spec", spec));
+                }
+                matches.first().copied()
+            }
+        };
+
+        found.ok_or_else(no_such_job)
+    }
+
+    /// Finds the job a process belongs to
+    pub fn job_of_pid(&self, pid: i32) -> Option<usize> {
+        self.jobs
+            .iter()
+            .find(|job| job.processes.iter().any(|process| process.pid == pid))
+            .map(|job| job.id)
+    }
+
+    /// The current job (`%+`), used by `fg` and `bg` without arguments
+    pub fn current_job(&self) -> Option<usize> {
+        self.recency.last().copied()
+    }
+
+    fn set_process_state(&mut self, id: usize, pid: i32, state: ProcessState) {
+        if let Some(job) = self.get_mut(id)
+            && let Some(process) = job.processes.iter_mut().find(|process| process.pid == pid)
+        {
+            process.state = state;
+        }
+    }
+
+    /**
+        Waits for a job in the foreground. With job control the job gets the terminal
+        while it runs; if it is stopped (Ctrl-Z) it stays in the table, otherwise it is removed.
+        Returns the exit status of the job (see `Job::status` for `pipefail`).
+    */
+    pub fn wait_for_job(&mut self, id: usize, pipefail: bool) -> i32 {
+        let Some(job) = self.get_job(id) else {
+            return 127;
+        };
+
+        let job_control = self.job_control;
+        if job_control && let Some(pgid) = job.pgid {
+            give_terminal_to(pgid, job.terminal_modes.as_ref());
+        }
+
+        let mut stopped = false;
+        for process in &job.processes {
+            if !matches!(
+                process.state,
+                ProcessState::Running | ProcessState::Stopped(_)
+            ) {
+                continue;
+            }
+            if let Some(state) = wait_pid(process.pid, libc::WUNTRACED) {
+                self.set_process_state(id, process.pid, state);
+                if let ProcessState::Stopped(_) = state {
+                    stopped = true;
+                    break;
+                }
+            }
+        }
+
+        let job_modes: Option<libc::termios> = if job_control {
+            self.take_terminal_back()
+        } else {
+            None
+        };
+
+        if stopped {
+            self.make_current(id);
+            if let Some(job) = self.get_mut(id) {
+                job.terminal_modes = job_modes;
+                job.stop_reported = true;
+            }
+            let job = self.get_job(id).unwrap();
+            println!();
+            println!("{}", self.format_job(&job, false));
+            return job.status(pipefail);
+        }
+
+        let Some(job) = self.remove_job(id) else {
+            return 0;
+        };
+        report_termination(&job);
+        job.status(pipefail)
+    }
+
+    /// Waits until a background job has finished (used by `wait`); returns its status
+    pub fn wait_for_completion(&mut self, id: usize, pipefail: bool) -> i32 {
+        let Some(job) = self.get_job(id) else {
+            return 127;
+        };
+        for process in &job.processes {
+            if let ProcessState::Running = process.state
+                && let Some(state) = wait_pid(process.pid, 0)
+            {
+                self.set_process_state(id, process.pid, state);
+            }
+        }
+        let status = self
+            .get_job(id)
+            .map(|job| job.status(pipefail))
+            .unwrap_or(0);
+        self.remove_job(id);
+        status
+    }
+
+    /// Sends SIGCONT to a stopped job and marks it as running
+    pub fn continue_job(&mut self, id: usize) -> Result<(), String> {
+        let job = self
+            .get_mut(id)
+            .ok_or_else(|| format!("%{}: no such job", id))?;
+
+        let result = match job.pgid {
+            Some(pgid) => unsafe { libc::kill(-pgid, libc::SIGCONT) },
+            None => {
+                let mut result = 0;
+                for process in &job.processes {
+                    result |= unsafe { libc::kill(process.pid, libc::SIGCONT) };
+                }
+                result
+            }
+        };
+        if result < 0 {
+            return Err(format!("%{}: {}", id, io::Error::last_os_error()));
+        }
+
+        for process in job.processes.iter_mut() {
+            if let ProcessState::Stopped(_) = process.state {
+                process.state = ProcessState::Running;
+            }
+        }
+        job.stop_reported = false;
+        self.make_current(id);
+        Ok(())
+    }
+
+    /// Checks all jobs for processes that finished, stopped or continued (without blocking)
+    pub fn update_job_states(&mut self) {
+        for job in self.jobs.iter_mut() {
+            for process in job.processes.iter_mut() {
+                if matches!(
+                    process.state,
+                    ProcessState::Exited(_) | ProcessState::Signaled(..)
+                ) {
+                    continue;
+                }
+                let options = libc::WNOHANG | libc::WUNTRACED | libc::WCONTINUED;
+                if let Some(state) = wait_pid(process.pid, options) {
+                    process.state = state;
+                }
+            }
+        }
+    }
+
+    /**
+        Prints a line for every background job that finished or stopped since the last prompt,
+        e.g. `[1]+  Done                    sleep 1`, and forgets finished jobs.
+    */
+    pub fn notify_changed_jobs(&mut self) {
+        self.update_job_states();
+
+        let mut finished: Vec<usize> = Vec::new();
+        let jobs = self.jobs.clone();
+        for job in &jobs {
+            if job.is_completed() {
+                if self.job_control {
+                    println!("{}", self.format_job(job, false));
+                }
+                finished.push(job.id);
+            } else if job.is_stopped() && !job.stop_reported {
+                println!("{}", self.format_job(job, false));
+                if let Some(job) = self.get_mut(job.id) {
+                    job.stop_reported = true;
+                }
+            }
+        }
+
+        for id in finished {
+            self.remove_job(id);
+        }
+        let _ = io::stdout().flush();
+    }
+
+    /// Puts the shell back into the foreground and returns the terminal settings the job left behind
+    fn take_terminal_back(&self) -> Option<libc::termios> {
+        unsafe {
+            libc::tcsetpgrp(libc::STDIN_FILENO, self.shell_pgid);
+
+            let mut job_modes: libc::termios = std::mem::zeroed();
+            let saved = libc::tcgetattr(libc::STDIN_FILENO, &mut job_modes) == 0;
+
+            if let Some(modes) = self.terminal_modes.as_ref() {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, modes);
+            }
+            saved.then_some(job_modes)
+        }
+    }
+}
+
+/**
+    Runs in a new child process before it executes the command:
+    joins the process group (`Some(0)` starts a new one), takes the terminal for
+    foreground jobs and restores the default signal dispositions.
+    Only async-signal-safe calls are allowed here.
+*/
+pub fn setup_child(process_group: Option<i32>, foreground: bool) {
+    unsafe {
+        if let Some(group) = process_group {
+            let pid = libc::getpid();
+            let group = if group == 0 { pid } else { group };
+            libc::setpgid(pid, group);
+            if foreground {
+                libc::tcsetpgrp(libc::STDIN_FILENO, group);
+            }
+        }
+    }
+    signals::restore_default_signals();
+}
+
+/// Waits for a process to change state; `None` if nothing happened (with `WNOHANG`)
+fn wait_pid(pid: i32, options: i32) -> Option<ProcessState> {
+    let mut status: i32 = 0;
+    loop {
+        let result = unsafe { libc::waitpid(pid, &mut status, options) };
+        if result == pid {
+            break;
+        }
+        if result == 0 {
+            return None;
+        }
+        let error = io::Error::last_os_error();
+        if error.kind() == io::ErrorKind::Interrupted {
+            continue;
+        }
+        // The process was already reaped somewhere else
+        return Some(ProcessState::Exited(0));
+    }
+
+    Some(if libc::WIFEXITED(status) {
+        ProcessState::Exited(libc::WEXITSTATUS(status))
+    } else if libc::WIFSIGNALED(status) {
+        ProcessState::Signaled(libc::WTERMSIG(status), libc::WCOREDUMP(status))
+    } else if libc::WIFSTOPPED(status) {
+        ProcessState::Stopped(libc::WSTOPSIG(status))
+    } else {
+        ProcessState::Running
+    })
+}
+
+/// Tells the user about a foreground job killed by a signal, like "Segmentation fault (core dumped)"
+fn report_termination(job: &Job) {
+    let killed_by = job
+        .processes
+        .iter()
+        .rev()
+        .find_map(|process| match process.state {
+            ProcessState::Signaled(signal, core_dumped) => Some((signal, core_dumped)),
+            _ => None,
+        });
+    let Some((signal, core_dumped)) = killed_by else {
+        return;
+    };
+
+    match signals::termination_message(signal, core_dumped) {
+        Some(message) => eprintln!("{}", message),
+        // The terminal echoed "^C", the prompt belongs on the next line
+        None if signal == libc::SIGINT => println!(),
+        None => {}
+    }
+}
+
+fn give_terminal_to(pgid: i32, modes: Option<&libc::termios>) {
+    unsafe {
+        if let Some(modes) = modes {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, modes);
+        }
+        libc::tcsetpgrp(libc::STDIN_FILENO, pgid);
+    }
+}
@@ -1,2 +1,24 @@
-// Library module to expose internal modules for testing
+// Library module to expose internal modules for testing and embedding (see `shell::Shell`)
+pub mod abbreviations;
+pub mod assignment;
+pub mod builtins;
 pub mod commands;
+pub mod conditional;
+pub mod dir_stack;
+pub mod error;
+pub mod executor;
+pub mod expand;
+pub mod git_prompt;
+pub mod glob;
+pub mod hash;
+pub mod history;
+pub mod history_expansion;
+pub mod jobs;
+pub mod line_editor;
+pub mod options;
+pub mod parser;
+pub mod printf;
+pub mod read;
+pub mod shell;
+pub mod signals;
+pub mod traps;
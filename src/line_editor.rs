@@ -0,0 +1,686 @@
+use crate::abbreviations::Abbreviations;
+use crate::history::History;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::os::fd::RawFd;
+
+/// How long to wait for the rest of an escape sequence before treating ESC as a key
+const ESCAPE_TIMEOUT_MS: i32 = 30;
+
+const HIGHLIGHT: &str = "\x1b[7m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    /// Control combinations like Ctrl-R (stored as the lowercase letter)
+    Ctrl(char),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Escape,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    Unknown,
+}
+
+/// What the user did with the line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReadResult {
+    Line(String),
+    Eof,
+    /// The line was discarded with Ctrl-C
+    Interrupted,
+}
+
+/**
+    Decodes raw terminal bytes into keys.
+    When reading from a terminal (`tty_fd`), a lone ESC is told apart from an escape
+    sequence by waiting a short moment for more bytes.
+*/
+pub struct KeyReader<R: Read> {
+    input: R,
+    pending: Option<u8>,
+    tty_fd: Option<RawFd>,
+}
+
+impl<R: Read> KeyReader<R> {
+    pub fn new(input: R) -> KeyReader<R> {
+        KeyReader {
+            input,
+            pending: None,
+            tty_fd: None,
+        }
+    }
+
+    fn for_tty(input: R, fd: RawFd) -> KeyReader<R> {
+        KeyReader {
+            input,
+            pending: None,
+            tty_fd: Some(fd),
+        }
+    }
+
+    /// Returns the next key, or `None` once the input is exhausted
+    pub fn read_key(&mut self) -> io::Result<Option<Key>> {
+        let Some(byte) = self.next_byte()? else {
+            return Ok(None);
+        };
+
+        let key: Key = match byte {
+            b'\r' | b'\n' => Key::Enter,
+            b'\t' => Key::Tab,
+            0x7f | 0x08 => Key::Backspace,
+            0x1b => self.read_escape_sequence()?,
+            0x01..=0x1a => Key::Ctrl((b'a' + byte - 1) as char),
+            0x00..=0x1f => Key::Unknown,
+            0x20..=0x7e => Key::Char(byte as char),
+            _ => self.read_utf8(byte)?,
+        };
+        Ok(Some(key))
+    }
+
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        if let Some(byte) = self.pending.take() {
+            return Ok(Some(byte));
+        }
+        let mut buffer = [0u8; 1];
+        loop {
+            match self.input.read(&mut buffer) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(buffer[0])),
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    fn byte_available(&self) -> bool {
+        if self.pending.is_some() {
+            return true;
+        }
+        let Some(fd) = self.tty_fd else {
+            // Without a terminal the rest of a sequence is already in the input
+            return true;
+        };
+        let mut poll_fd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        unsafe { libc::poll(&mut poll_fd, 1, ESCAPE_TIMEOUT_MS) > 0 }
+    }
+
+    fn read_escape_sequence(&mut self) -> io::Result<Key> {
+        if !self.byte_available() {
+            return Ok(Key::Escape);
+        }
+        let Some(byte) = self.next_byte()? else {
+            return Ok(Key::Escape);
+        };
+
+        match byte {
+            // CSI sequences: ESC [ <parameters> <final byte>
+            b'[' => {
+                let mut parameters = String::new();
+                loop {
+                    let Some(byte) = self.next_byte()? else {
+                        return Ok(Key::Unknown);
+                    };
+                    if (0x40..=0x7e).contains(&byte) {
+                        return Ok(match (byte, parameters.as_str()) {
+                            (b'A', _) => Key::Up,
+                            (b'B', _) => Key::Down,
+                            (b'C', _) => Key::Right,
+                            (b'D', _) => Key::Left,
+                            (b'H', _) => Key::Home,
+                            (b'F', _) => Key::End,
+                            (b'~', "1" | "7") => Key::Home,
+                            (b'~', "4" | "8") => Key::End,
+                            (b'~', "3") => Key::Delete,
+                            _ => Key::Unknown,
+                        });
+                    }
+                    parameters.push(byte as char);
+                }
+            }
+            // SS3 sequences sent by some terminals for arrows and Home/End
+            b'O' => Ok(match self.next_byte()? {
+                Some(b'A') => Key::Up,
+                Some(b'B') => Key::Down,
+                Some(b'C') => Key::Right,
+                Some(b'D') => Key::Left,
+                Some(b'H') => Key::Home,
+                Some(b'F') => Key::End,
+                _ => Key::Unknown,
+            }),
+            // Not a sequence, the byte is a key of its own
+            _ => {
+                self.pending = Some(byte);
+                Ok(Key::Escape)
+            }
+        }
+    }
+
+    fn read_utf8(&mut self, first: u8) -> io::Result<Key> {
+        let length: usize = match first {
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => return Ok(Key::Unknown),
+        };
+        let mut bytes: Vec<u8> = vec![first];
+        for _ in 1..length {
+            match self.next_byte()? {
+                Some(byte) => bytes.push(byte),
+                None => return Ok(Key::Unknown),
+            }
+        }
+        Ok(
+            match str::from_utf8(&bytes).ok().and_then(|s| s.chars().next()) {
+                Some(c) => Key::Char(c),
+                None => Key::Unknown,
+            },
+        )
+    }
+}
+
+/// State of an incremental history search (Ctrl-R / Ctrl-S)
+struct Search {
+    query: String,
+    forward: bool,
+    failed: bool,
+    /// History index and character offset of the current match
+    found: Option<(usize, usize)>,
+    /// The line as it was before searching, restored by Ctrl-G
+    original: (Vec<char>, usize),
+}
+
+/**
+    A single line editor with cursor movement, history navigation,
+    and bash/zsh-style incremental history search.
+*/
+pub struct Editor<'a> {
+    prompt: String,
+    history: &'a History,
+    abbreviations: Option<&'a Abbreviations>,
+    buffer: Vec<char>,
+    cursor: usize,
+    /// Position while walking the history with Up/Down (`history.len()` is the new line)
+    history_position: usize,
+    draft: Vec<char>,
+    search: Option<Search>,
+    last_query: String,
+}
+
+enum Action {
+    Continue,
+    Done(ReadResult),
+}
+
+impl<'a> Editor<'a> {
+    pub fn new(prompt: &str, history: &'a History) -> Editor<'a> {
+        Editor {
+            prompt: prompt.to_string(),
+            history,
+            abbreviations: None,
+            buffer: Vec::new(),
+            cursor: 0,
+            history_position: history.len(),
+            draft: Vec::new(),
+            search: None,
+            last_query: String::new(),
+        }
+    }
+
+    /// Expands these abbreviations when space or Enter is pressed after one
+    pub fn with_abbreviations(mut self, abbreviations: &'a Abbreviations) -> Editor<'a> {
+        self.abbreviations = Some(abbreviations);
+        self
+    }
+
+    /// Reads keys from `keys` and redraws the line on `output` until the line is done
+    pub fn run<R: Read, W: Write>(
+        &mut self,
+        keys: &mut KeyReader<R>,
+        output: &mut W,
+    ) -> io::Result<ReadResult> {
+        self.refresh(output)?;
+
+        loop {
+            let Some(key) = keys.read_key()? else {
+                // Input ended: hand out what was typed, or report EOF
+                write!(output, "\r\n")?;
+                output.flush()?;
+                if self.buffer.is_empty() {
+                    return Ok(ReadResult::Eof);
+                }
+                return Ok(ReadResult::Line(self.line()));
+            };
+
+            if let Action::Done(result) = self.handle_key(key, output)? {
+                return Ok(result);
+            }
+            self.refresh(output)?;
+        }
+    }
+
+    pub fn line(&self) -> String {
+        self.buffer.iter().collect()
+    }
+
+    fn handle_key<W: Write>(&mut self, key: Key, output: &mut W) -> io::Result<Action> {
+        if self.search.is_some() {
+            match self.handle_search_key(key) {
+                Some(action) => return Ok(action),
+                // The key ends the search and is then handled like a normal key
+                None => self.accept_search(),
+            }
+        }
+
+        match key {
+            Key::Enter => {
+                self.expand_abbreviation();
+                self.history_position = self.history.len();
+                self.search = None;
+                self.refresh(output)?;
+                write!(output, "\r\n")?;
+                output.flush()?;
+                return Ok(Action::Done(ReadResult::Line(self.line())));
+            }
+            Key::Char(c) => {
+                if c == ' ' {
+                    self.expand_abbreviation();
+                }
+                self.buffer.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+            Key::Backspace | Key::Ctrl('h') if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.buffer.remove(self.cursor);
+            }
+            Key::Ctrl('c') => {
+                self.search = None;
+                write!(output, "^C\r\n")?;
+                output.flush()?;
+                return Ok(Action::Done(ReadResult::Interrupted));
+            }
+            Key::Ctrl('d') if self.buffer.is_empty() => {
+                write!(output, "\r\n")?;
+                output.flush()?;
+                return Ok(Action::Done(ReadResult::Eof));
+            }
+            Key::Delete | Key::Ctrl('d') if self.cursor < self.buffer.len() => {
+                self.buffer.remove(self.cursor);
+            }
+            Key::Left | Key::Ctrl('b') => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right | Key::Ctrl('f') => self.cursor = (self.cursor + 1).min(self.buffer.len()),
+            Key::Home | Key::Ctrl('a') => self.cursor = 0,
+            Key::End | Key::Ctrl('e') => self.cursor = self.buffer.len(),
+            Key::Up | Key::Ctrl('p') => self.history_previous(),
+            Key::Down | Key::Ctrl('n') => self.history_next(),
+            Key::Ctrl('u') => {
+                self.buffer.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            Key::Ctrl('k') => {
+                self.buffer.truncate(self.cursor);
+            }
+            Key::Ctrl('w') => {
+                // Delete the word before the cursor (and the spaces after it)
+                let mut start = self.cursor;
+                while start > 0 && self.buffer[start - 1] == ' ' {
+                    start -= 1;
+                }
+                while start > 0 && self.buffer[start - 1] != ' ' {
+                    start -= 1;
+                }
+                self.buffer.drain(start..self.cursor);
+                self.cursor = start;
+            }
+            Key::Ctrl('l') => write!(output, "\x1b[H\x1b[2J")?,
+            Key::Ctrl('r') => self.start_search(false),
+            Key::Ctrl('s') => self.start_search(true),
+            _ => {}
+        }
+
+        Ok(Action::Continue)
+    }
+
+    /**
+        Replaces the word right before the cursor with its abbreviation (if it has one).
+        Words that are quoted or continue after the cursor are left alone.
+    */
+    fn expand_abbreviation(&mut self) {
+        let Some(abbreviations) = self.abbreviations else {
+            return;
+        };
+        if self
+            .buffer
+            .get(self.cursor)
+            .is_some_and(|c| !c.is_whitespace())
+        {
+            return;
+        }
+
+        let is_separator = |c: &char| c.is_whitespace() || ";|&(".contains(*c);
+        let mut start = self.cursor;
+        while start > 0 && !is_separator(&self.buffer[start - 1]) {
+            start -= 1;
+        }
+        let word: String = self.buffer[start..self.cursor].iter().collect();
+        if word.is_empty() || word.contains(['\\', '\'', '"']) {
+            return;
+        }
+        // The first word of the line, or after an operator, is a command name
+        let command_position = self.buffer[..start]
+            .iter()
+            .rev()
+            .find(|c| !c.is_whitespace())
+            .is_none_or(|c| ";|&(".contains(*c));
+
+        if let Some(expansion) = abbreviations.expand(&word, command_position) {
+            let expansion: Vec<char> = expansion.chars().collect();
+            self.cursor = start + expansion.len();
+            self.buffer
+                .splice(start..start + word.chars().count(), expansion);
+        }
+    }
+
+    fn history_previous(&mut self) {
+        if self.history_position == 0 {
+            return;
+        }
+        if self.history_position == self.history.len() {
+            self.draft = self.buffer.clone();
+        }
+        self.history_position -= 1;
+        self.load_history_entry();
+    }
+
+    fn history_next(&mut self) {
+        if self.history_position >= self.history.len() {
+            return;
+        }
+        self.history_position += 1;
+        self.load_history_entry();
+    }
+
+    fn load_history_entry(&mut self) {
+        self.buffer = match self.history.entries().get(self.history_position) {
+            Some(entry) => entry.command.chars().collect(),
+            None => self.draft.clone(),
+        };
+        self.cursor = self.buffer.len();
+    }
+
+    fn start_search(&mut self, forward: bool) {
+        self.search = Some(Search {
+            query: String::new(),
+            forward,
+            failed: false,
+            found: None,
+            original: (self.buffer.clone(), self.cursor),
+        });
+    }
+
+    /// Handles a key during a search, `None` means the key ends the search
+    fn handle_search_key(&mut self, key: Key) -> Option<Action> {
+        let search = self.search.as_mut()?;
+
+        match key {
+            Key::Char(c) => {
+                search.query.push(c);
+                self.research(false);
+            }
+            Key::Backspace | Key::Ctrl('h') => {
+                search.query.pop();
+                search.found = None;
+                self.research(false);
+            }
+            Key::Ctrl('r') | Key::Ctrl('s') => {
+                search.forward = key == Key::Ctrl('s');
+                if search.query.is_empty() {
+                    // Repeating Ctrl-R on an empty search reuses the previous query
+                    search.query = self.last_query.clone();
+                    self.research(false);
+                } else {
+                    self.research(true);
+                }
+            }
+            Key::Ctrl('g') => {
+                let search = self.search.take()?;
+                (self.buffer, self.cursor) = search.original;
+            }
+            Key::Escape => self.accept_search(),
+            _ => return None,
+        }
+
+        Some(Action::Continue)
+    }
+
+    /**
+        Looks for the query in the history, starting at the current match.
+        With `skip_current`, the search moves past the current match to the next older
+        (or newer, for Ctrl-S) entry.
+    */
+    fn research(&mut self, skip_current: bool) {
+        let history = self.history;
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+        if search.query.is_empty() {
+            search.failed = false;
+            search.found = None;
+            return;
+        }
+
+        let entries = history.entries();
+        let current_command: Option<&str> = search
+            .found
+            .map(|(index, _)| entries[index].command.as_str());
+        let start: Option<usize> = match (search.found, skip_current, search.forward) {
+            (None, _, _) => entries.len().checked_sub(1),
+            (Some((index, _)), false, _) => Some(index),
+            (Some((index, _)), true, false) => index.checked_sub(1),
+            (Some((index, _)), true, true) => Some(index + 1),
+        };
+
+        let mut result: Option<(usize, usize)> = None;
+        if let Some(start) = start {
+            let candidates: Box<dyn Iterator<Item = usize>> = if search.forward {
+                Box::new(start..entries.len())
+            } else {
+                Box::new((0..=start.min(entries.len().saturating_sub(1))).rev())
+            };
+
+            for index in candidates {
+                let command = entries[index].command.as_str();
+                // Don't show the same line twice when walking through matches
+                if skip_current && Some(command) == current_command {
+                    continue;
+                }
+                if let Some(position) = command.find(&search.query) {
+                    result = Some((index, command[..position].chars().count()));
+                    break;
+                }
+            }
+        }
+
+        match result {
+            Some(found) => {
+                search.found = Some(found);
+                search.failed = false;
+            }
+            None => search.failed = true,
+        }
+    }
+
+    /// Leaves the search with the match in the buffer (cursor at the matched text)
+    fn accept_search(&mut self) {
+        let Some(search) = self.search.take() else {
+            return;
+        };
+        if !search.query.is_empty() {
+            self.last_query = search.query.clone();
+        }
+        match search.found {
+            Some((index, offset)) => {
+                self.buffer = self.history.entries()[index].command.chars().collect();
+                self.cursor = offset;
+                self.history_position = index;
+            }
+            None => (self.buffer, self.cursor) = search.original,
+        }
+    }
+
+    fn refresh<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        let (line, cursor_column) = match &self.search {
+            Some(search) => self.render_search(search),
+            None => {
+                let text: String = self.buffer.iter().collect();
+                let column = self.prompt.chars().count() + self.cursor;
+                (format!("{}{}", self.prompt, text), column)
+            }
+        };
+
+        // Redraw the whole line, clear what's left of the old one and place the cursor
+        write!(output, "\r{}\x1b[K\r", line)?;
+        if cursor_column > 0 {
+            write!(output, "\x1b[{}C", cursor_column)?;
+        }
+        output.flush()
+    }
+
+    fn render_search(&self, search: &Search) -> (String, usize) {
+        let label = match (search.failed, search.forward) {
+            (false, false) => "(reverse-i-search)",
+            (false, true) => "(i-search)",
+            (true, false) => "(failed reverse-i-search)",
+            (true, true) => "(failed i-search)",
+        };
+        let prefix = format!("{}`{}': ", label, search.query);
+        let prefix_width = prefix.chars().count();
+
+        let Some((index, offset)) = search.found else {
+            let text: String = search.original.0.iter().collect();
+            return (
+                format!("{}{}", prefix, text),
+                prefix_width + search.original.1,
+            );
+        };
+
+        // Highlight the matched part of the history entry
+        let command: Vec<char> = self.history.entries()[index].command.chars().collect();
+        let end = (offset + search.query.chars().count()).min(command.len());
+        let before: String = command[..offset].iter().collect();
+        let matched: String = command[offset..end].iter().collect();
+        let after: String = command[end..].iter().collect();
+
+        (
+            format!(
+                "{}{}{}{}{}{}",
+                prefix, before, HIGHLIGHT, matched, RESET, after
+            ),
+            prefix_width + offset,
+        )
+    }
+}
+
+/// Unbuffered reads from a file descriptor (a buffered reader would hide pending bytes from `poll`)
+struct FdInput(RawFd);
+
+impl Read for FdInput {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let count = unsafe { libc::read(self.0, buffer.as_mut_ptr().cast(), buffer.len()) };
+        if count < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(count as usize)
+    }
+}
+
+/// Puts the terminal into raw mode and restores the previous settings when dropped
+struct RawMode {
+    fd: RawFd,
+    original: libc::termios,
+}
+
+impl RawMode {
+    fn enable(fd: RawFd) -> io::Result<RawMode> {
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut raw = original;
+        // No line buffering and echo; Ctrl-C, Ctrl-Z and Ctrl-S/Ctrl-Q become normal keys
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::IEXTEN | libc::ISIG);
+        raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+
+        if unsafe { libc::tcsetattr(fd, libc::TCSADRAIN, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(RawMode { fd, original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(self.fd, libc::TCSADRAIN, &self.original);
+        }
+    }
+}
+
+/**
+    Reads a line from `input`, redrawing it on `output` (used for testing and by `read_line`)
+*/
+pub fn read_line_from<R: Read, W: Write>(
+    prompt: &str,
+    history: &History,
+    input: R,
+    output: &mut W,
+) -> io::Result<ReadResult> {
+    let mut keys = KeyReader::new(input);
+    Editor::new(prompt, history).run(&mut keys, output)
+}
+
+/**
+    Prints the prompt and reads a line from stdin.
+    Abbreviations are expanded while typing.
+    When stdin isn't a terminal (e.g. a script is piped in), it falls back to plain line reading.
+*/
+pub fn read_line(
+    prompt: &str,
+    history: &History,
+    abbreviations: &Abbreviations,
+) -> io::Result<ReadResult> {
+    let stdin = io::stdin();
+
+    if !stdin.is_terminal() {
+        print!("{}", prompt);
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        if stdin.lock().read_line(&mut input)? == 0 {
+            return Ok(ReadResult::Eof);
+        }
+        return Ok(ReadResult::Line(
+            input.trim_end_matches(['\n', '\r']).to_string(),
+        ));
+    }
+
+    let fd: RawFd = libc::STDIN_FILENO;
+    let _raw_mode = RawMode::enable(fd)?;
+    let mut keys = KeyReader::for_tty(FdInput(fd), fd);
+    Editor::new(prompt, history)
+        .with_abbreviations(abbreviations)
+        .run(&mut keys, &mut io::stdout())
+}
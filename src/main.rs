@@ -1,84 +1,207 @@
+use bettershell::abbreviations::Abbreviations;
 use bettershell::commands;
+use bettershell::error::ShellError;
+use bettershell::executor;
+use bettershell::git_prompt;
+use bettershell::history_expansion;
+use bettershell::line_editor::{self, ReadResult};
+use bettershell::options::{Options, ShellOption};
+use bettershell::parser;
+use bettershell::shell::Shell;
+use bettershell::traps;
+use std::env;
+use std::fs;
 #[allow(unused_imports)]
-use std::io::{self, Write}; // Use the library module
+use std::io::{self, IsTerminal, Write}; // Use the library module
+use std::process;
+
+/// What to run, according to the command line
+enum Input {
+    Interactive,
+    /// `-c 'commands'`
+    Command(String),
+    Script(String),
+}
 
 fn main() {
+    let mut shell = Shell::new();
+    let input: Input = match parse_arguments(env::args().skip(1).collect(), &mut shell.options) {
+        Ok(input) => input,
+        Err(message) => {
+            eprintln!("bettershell: {}", message);
+            eprintln!("usage: bettershell [-efnux] [-o option] [-c command | script]");
+            process::exit(2);
+        }
+    };
+
+    match input {
+        Input::Command(command) => {
+            let status = executor::run_line(&command, &mut shell);
+            commands::exit_shell(shell.exit_status.unwrap_or(status), &mut shell);
+        }
+        Input::Script(path) => {
+            let source = match fs::read_to_string(&path) {
+                Ok(source) => source,
+                Err(error) => {
+                    let error = ShellError::script(&path, &error);
+                    shell.report(&error);
+                    process::exit(error.status());
+                }
+            };
+            let status = executor::run_script(&path, &source, &mut shell);
+            commands::exit_shell(shell.exit_status.unwrap_or(status), &mut shell);
+        }
+        Input::Interactive => {}
+    }
+
+    // Take over the terminal (if there is one) for job control
+    shell.options.set_interactive(io::stdin().is_terminal());
+    shell.jobs.init_job_control();
+
+    // Load the history of previous sessions
+    shell.configure_history();
+    if let Err(error) = shell.history.load() {
+        eprintln!("bettershell: history: {}", error);
+    }
+
+    // And the directory stack, if it is persisted
+    shell.configure_dir_stack();
+    if let Err(error) = shell.dir_stack.load() {
+        eprintln!("bettershell: dirs: {}", error);
+    }
+
     // Just loops the REPL (read-evaluate-print loop)
     loop {
-        read_eval_print_cycle();
+        read_eval_print_cycle(&mut shell);
     }
 }
 
-fn read_eval_print_cycle() {
-    // Print the iconic shell prompt
-    print!(
-        "TODO (USER) : {} $ ",
-        std::env::current_dir()
-            .unwrap_or_default()
-            .to_string_lossy()
-    );
-    io::stdout().flush().unwrap();
+/// Applies the options on the command line (`-eux`, `-o pipefail`) and finds out what to run
+fn parse_arguments(arguments: Vec<String>, options: &mut Options) -> Result<Input, String> {
+    let mut arguments = arguments.into_iter();
+
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "-c" => {
+                let command = arguments.next().ok_or("-c: option requires an argument")?;
+                return Ok(Input::Command(command));
+            }
+            "-o" | "+o" => {
+                let name = arguments
+                    .next()
+                    .ok_or(format!("{}: option requires an argument", argument))?;
+                let option = ShellOption::from_name(&name)
+                    .ok_or(format!("{}: invalid option name", name))?;
+                options.set(option, argument == "-o");
+            }
+            "--" => break,
+            flags if flags.len() > 1 && (flags.starts_with('-') || flags.starts_with('+')) => {
+                options.apply_letters(flags)?;
+            }
+            _ => return Ok(Input::Script(argument)),
+        }
+    }
 
-    let mut input = String::new(); // Initialize the input variable
-    io::stdin().read_line(&mut input).unwrap(); // Read input
+    Ok(match arguments.next() {
+        Some(script) => Input::Script(script),
+        None => Input::Interactive,
+    })
+}
 
-    // Trim the input to remove newline characters
-    let input: &str = input.trim();
+fn read_eval_print_cycle(shell: &mut Shell) {
+    // Run traps of signals that arrived, then report background jobs that finished or stopped
+    traps::run_pending(shell);
+    shell.jobs.notify_changed_jobs();
+    // Assignments to HISTSIZE, HISTCONTROL and the like apply from the next line on
+    shell.configure_history();
 
-    // Split by white spaces while preserving quoted segments
-    let input_split: Vec<String> = split_input_preserving_quotes(input);
+    let current_dir = shell.cwd.clone();
 
-    // Get first element (command)
-    let command: &str = input_split.get(0).map(|s| s.as_str()).unwrap_or("");
-    // extract remaining parts to args
-    let args: Vec<&str> = input_split.iter().skip(1).map(|s| s.as_str()).collect();
+    // Optional git segment (branch, ahead/behind, markers)
+    let mut git_segment = String::new();
+    let timeout = git_prompt::timeout(shell.get_var("BSH_GIT_PROMPT_TIMEOUT"));
+    if git_prompt::is_enabled(shell.get_var("BSH_GIT_PROMPT"))
+        && let Some(segment) = git_prompt::prompt_segment(&current_dir, timeout)
+    {
+        git_segment = format!(" {}", segment);
+    }
 
-    // match the commands
-    command_matcher(command, args);
-}
+    // The iconic shell prompt
+    let prompt: String = format!(
+        "TODO (USER) : {}{} $ ",
+        current_dir.to_string_lossy(),
+        git_segment
+    );
 
-fn split_input_preserving_quotes(input: &str) -> Vec<String> {
-    let mut parts: Vec<String> = Vec::new();
-    let mut current = String::new();
-    let mut in_quote: Option<char> = None;
-
-    for ch in input.chars() {
-        match ch {
-            '"' | '\'' => {
-                if in_quote == Some(ch) {
-                    in_quote = None;
-                } else if in_quote.is_none() {
-                    in_quote = Some(ch);
-                } else {
-                    current.push(ch);
-                }
-            }
-            c if c.is_whitespace() && in_quote.is_none() => {
-                if !current.is_empty() {
-                    parts.push(std::mem::take(&mut current));
-                }
+    // Read input (with line editing when running in a terminal)
+    let input: String = match line_editor::read_line(&prompt, &shell.history, &shell.abbreviations)
+    {
+        Ok(ReadResult::Line(line)) => line,
+        Ok(ReadResult::Eof) => commands::exit_shell(0, shell),
+        Ok(ReadResult::Interrupted) => {
+            shell.last_status = 130;
+            traps::run_signal_trap(libc::SIGINT, shell);
+            return;
+        }
+        Err(error) => {
+            eprintln!("bettershell: {}", error);
+            commands::exit_shell(0, shell)
+        }
+    };
+
+    // History expansion (!!, !$, ^old^new^, ...) happens before anything else, but only
+    // when a user is typing (not for commands piped into the shell)
+    let expansion = if shell.options.is_interactive() {
+        history_expansion::expand(&input, &shell.history)
+    } else {
+        Ok(None)
+    };
+    let input: String = match expansion {
+        Ok(Some(expansion)) => {
+            // Show what is actually going to run
+            println!("{}", expansion.line);
+            if expansion.print_only {
+                shell.history.add(&expansion.line);
+                return;
             }
-            _ => current.push(ch),
+            expansion.line
         }
-    }
+        Ok(None) => input,
+        Err(message) => {
+            eprintln!("bettershell: {}", message);
+            return;
+        }
+    };
 
-    if !current.is_empty() {
-        parts.push(current);
+    // Here-documents continue on the next lines
+    let mut input: String = input;
+    while parser::is_incomplete(&input) {
+        let prompt: String = shell.get_var("PS2").unwrap_or("> ").to_string();
+        match line_editor::read_line(&prompt, &shell.history, &Abbreviations::default()) {
+            Ok(ReadResult::Line(line)) => {
+                input.push('\n');
+                input.push_str(&line);
+            }
+            // The parser reports the missing delimiter
+            Ok(ReadResult::Eof) => break,
+            Ok(ReadResult::Interrupted) => {
+                shell.last_status = 130;
+                traps::run_signal_trap(libc::SIGINT, shell);
+                return;
+            }
+            Err(error) => {
+                eprintln!("bettershell: {}", error);
+                return;
+            }
+        }
     }
 
-    parts
-}
+    // Remember the line (before trimming, so HISTCONTROL=ignorespace works)
+    shell.history.add(&input);
 
-/**
-    This matches the command and calls according function (from commands.rs)
-*/
-fn command_matcher(command: &str, args: Vec<&str>) {
-    match command {
-        "type" => commands::handle_type(args),
-        "echo" => commands::handle_echo(args),
-        "cd" => commands::handle_cd(args),
-        "pwd" => commands::handle_print_working_directory(),
-        "exit" => commands::handle_exit(),
-        _ => commands::execute_external_program(command, args),
+    // Parse and run the line
+    executor::run_line(&input, shell);
+    if let Some(status) = shell.exit_status {
+        commands::exit_shell(status, shell);
     }
 }
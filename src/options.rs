@@ -0,0 +1,113 @@
+/// The options of the `set` builtin (and the command line)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellOption {
+    /// `-e`: exit when a command fails
+    Errexit,
+    /// `-f`: no pathname expansion
+    Noglob,
+    /// `-n`: read commands without executing them
+    Noexec,
+    /// `-u`: expanding an unset variable is an error
+    Nounset,
+    /// `-x`: print commands before executing them
+    Xtrace,
+    /// `-o pipefail`: a pipeline fails if any of its commands fails
+    Pipefail,
+}
+
+/// Each option with its letter (if it has one) and its name for `set -o`
+const OPTIONS: &[(ShellOption, Option<char>, &str)] = &[
+    (ShellOption::Errexit, Some('e'), "errexit"),
+    (ShellOption::Noglob, Some('f'), "noglob"),
+    (ShellOption::Noexec, Some('n'), "noexec"),
+    (ShellOption::Nounset, Some('u'), "nounset"),
+    (ShellOption::Xtrace, Some('x'), "xtrace"),
+    (ShellOption::Pipefail, None, "pipefail"),
+];
+
+impl ShellOption {
+    pub fn from_letter(letter: char) -> Option<ShellOption> {
+        OPTIONS
+            .iter()
+            .find(|(_, other, _)| *other == Some(letter))
+            .map(|(option, _, _)| *option)
+    }
+
+    pub fn from_name(name: &str) -> Option<ShellOption> {
+        OPTIONS
+            .iter()
+            .find(|(_, _, other)| *other == name)
+            .map(|(option, _, _)| *option)
+    }
+
+    pub fn name(self) -> &'static str {
+        OPTIONS[self as usize].2
+    }
+
+    pub fn letter(self) -> Option<char> {
+        OPTIONS[self as usize].1
+    }
+
+    /// All options, in the order `set -o` lists them
+    pub fn all() -> impl Iterator<Item = ShellOption> {
+        OPTIONS.iter().map(|(option, _, _)| *option)
+    }
+}
+
+/// The state of all options of a shell
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    enabled: [bool; OPTIONS.len()],
+    /// Set when the shell reads commands from a terminal
+    interactive: bool,
+}
+
+impl Options {
+    pub fn is_set(&self, option: ShellOption) -> bool {
+        self.enabled[option as usize]
+    }
+
+    pub fn set(&mut self, option: ShellOption, enabled: bool) {
+        self.enabled[option as usize] = enabled;
+    }
+
+    pub fn is_interactive(&self) -> bool {
+        self.interactive
+    }
+
+    pub fn set_interactive(&mut self, interactive: bool) {
+        self.interactive = interactive;
+    }
+
+    /**
+        Applies a group of option letters like `-eux` (enable) or `+x` (disable).
+        Errors name the invalid letter, e.g. "-z: invalid option".
+    */
+    pub fn apply_letters(&mut self, argument: &str) -> Result<(), String> {
+        let (enabled, letters) = match argument.split_at_checked(1) {
+            Some(("-", letters)) => (true, letters),
+            Some(("+", letters)) => (false, letters),
+            _ => return Err(format!("{}: invalid option", argument)),
+        };
+
+        for letter in letters.chars() {
+            match ShellOption::from_letter(letter) {
+                Some(option) => self.set(option, enabled),
+                None => return Err(format!("{}{}: invalid option", &argument[..1], letter)),
+            }
+        }
+        Ok(())
+    }
+
+    /// The letters of the enabled options, as shown by `$-` (with `i` for interactive shells)
+    pub fn flags(&self) -> String {
+        let mut flags: String = ShellOption::all()
+            .filter(|option| self.is_set(*option))
+            .filter_map(|option| option.letter())
+            .collect();
+        if self.interactive {
+            flags.push('i');
+        }
+        flags
+    }
+}
@@ -0,0 +1,755 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+
+/**
+    The reserved words the parser understands (`type` reports them as keywords).
+    More are added as the grammar grows (`if`, `for`, `while`, ...).
+*/
+pub const KEYWORDS: &[&str] = &["[[", "]]"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectKind {
+    /// `<file`
+    Input,
+    /// `>file`
+    Output,
+    /// `>>file`
+    Append,
+    /// `<&n` (or `<&-` to close)
+    DuplicateInput,
+    /// `>&n` (or `>&-` to close)
+    DuplicateOutput,
+    /// `&>file` (stdout and stderr)
+    OutputAll,
+    /// `&>>file` (stdout and stderr)
+    AppendAll,
+    /// `<<word` (or `<<-word`, which strips leading tabs) followed by the lines of a document
+    HereDocument { strip_tabs: bool },
+    /// `<<<word`: the word followed by a newline
+    HereString,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redirect {
+    pub fd: i32,
+    pub kind: RedirectKind,
+    /// The target word, still unexpanded (the delimiter of a here-document)
+    pub target: String,
+    /// The lines of a here-document
+    pub here_document: Option<HereDocument>,
+}
+
+/// The body of a here-document, taken from the lines after the command
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HereDocument {
+    /// The lines up to the delimiter, each ending with a newline (tabs already stripped)
+    pub body: String,
+    /// A quoted delimiter (`<<'EOF'`) turns off expansion in the body
+    pub quoted: bool,
+}
+
+/// A command with its arguments; words are kept as typed (quotes included) until expansion
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SimpleCommand {
+    pub words: Vec<String>,
+    pub redirects: Vec<Redirect>,
+}
+
+/// Commands connected with `|`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pipeline {
+    pub commands: Vec<SimpleCommand>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connector {
+    /// `&&`
+    And,
+    /// `||`
+    Or,
+}
+
+/// Pipelines connected with `&&` and `||`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AndOrList {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+}
+
+/// One entry of a command line, terminated by `;`, `&` or the end of the line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListItem {
+    pub commands: AndOrList,
+    /// Terminated by `&`
+    pub background: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Pipe,
+    And,
+    Or,
+    Semicolon,
+    Ampersand,
+    Redirect(i32, RedirectKind),
+    /// Follows the delimiter of a here-document once its lines have been read
+    HereDocument(HereDocument),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{}", word),
+            Token::Pipe => write!(f, "|"),
+            Token::And => write!(f, "&&"),
+            Token::Or => write!(f, "||"),
+            Token::Semicolon => write!(f, ";"),
+            Token::Ampersand => write!(f, "&"),
+            Token::Redirect(_, kind) => write!(f, "{}", redirect_operator(*kind)),
+            Token::HereDocument(_) => write!(f, "newline"),
+        }
+    }
+}
+
+fn redirect_operator(kind: RedirectKind) -> &'static str {
+    match kind {
+        RedirectKind::Input => "<",
+        RedirectKind::Output => ">",
+        RedirectKind::Append => ">>",
+        RedirectKind::DuplicateInput => "<&",
+        RedirectKind::DuplicateOutput => ">&",
+        RedirectKind::OutputAll => "&>",
+        RedirectKind::AppendAll => "&>>",
+        RedirectKind::HereDocument { strip_tabs: false } => "<<",
+        RedirectKind::HereDocument { strip_tabs: true } => "<<-",
+        RedirectKind::HereString => "<<<",
+    }
+}
+
+impl fmt::Display for Redirect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let default_fd = match self.kind {
+            RedirectKind::Input
+            | RedirectKind::DuplicateInput
+            | RedirectKind::HereDocument { .. }
+            | RedirectKind::HereString => 0,
+            RedirectKind::OutputAll | RedirectKind::AppendAll => self.fd,
+            _ => 1,
+        };
+        if self.fd != default_fd {
+            write!(f, "{}", self.fd)?;
+        }
+        write!(f, "{}{}", redirect_operator(self.kind), self.target)
+    }
+}
+
+impl fmt::Display for SimpleCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts: Vec<String> = self.words.clone();
+        parts.extend(self.redirects.iter().map(|redirect| redirect.to_string()));
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let commands: Vec<String> = self.commands.iter().map(|c| c.to_string()).collect();
+        write!(f, "{}", commands.join(" | "))
+    }
+}
+
+impl fmt::Display for AndOrList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.first)?;
+        for (connector, pipeline) in &self.rest {
+            match connector {
+                Connector::And => write!(f, " && {}", pipeline)?,
+                Connector::Or => write!(f, " || {}", pipeline)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/**
+    Parses a command line into a list of and-or lists.
+    Errors are returned as messages like "syntax error near unexpected token `|'".
+*/
+pub fn parse(input: &str) -> Result<Vec<ListItem>, String> {
+    parse_with_aliases(input, &BTreeMap::new())
+}
+
+/// Parses a command line like `parse`, expanding the aliases defined with `alias` first
+pub fn parse_with_aliases(
+    input: &str,
+    aliases: &BTreeMap<String, String>,
+) -> Result<Vec<ListItem>, String> {
+    let tokens: Vec<Token> = tokenize(input)?;
+    let tokens: Vec<Token> = if aliases.is_empty() {
+        tokens
+    } else {
+        expand_aliases(tokens, aliases, &mut Vec::new())?.0
+    };
+    let mut position: usize = 0;
+    let mut items: Vec<ListItem> = Vec::new();
+
+    while position < tokens.len() {
+        let commands = parse_and_or(&tokens, &mut position)?;
+        let background = match tokens.get(position) {
+            Some(Token::Ampersand) => {
+                position += 1;
+                true
+            }
+            Some(Token::Semicolon) => {
+                position += 1;
+                false
+            }
+            None => false,
+            Some(token) => return Err(unexpected(token)),
+        };
+        items.push(ListItem {
+            commands,
+            background,
+        });
+    }
+
+    Ok(items)
+}
+
+fn unexpected(token: &Token) -> String {
+    format!("syntax error near unexpected token `{}'", token)
+}
+
+fn parse_and_or(tokens: &[Token], position: &mut usize) -> Result<AndOrList, String> {
+    let first = parse_pipeline(tokens, position)?;
+    let mut rest: Vec<(Connector, Pipeline)> = Vec::new();
+
+    loop {
+        let connector = match tokens.get(*position) {
+            Some(Token::And) => Connector::And,
+            Some(Token::Or) => Connector::Or,
+            _ => break,
+        };
+        *position += 1;
+        if *position >= tokens.len() {
+            return Err("syntax error: unexpected end of file".to_string());
+        }
+        rest.push((connector, parse_pipeline(tokens, position)?));
+    }
+
+    Ok(AndOrList { first, rest })
+}
+
+fn parse_pipeline(tokens: &[Token], position: &mut usize) -> Result<Pipeline, String> {
+    let mut commands: Vec<SimpleCommand> = vec![parse_command(tokens, position)?];
+
+    while tokens.get(*position) == Some(&Token::Pipe) {
+        *position += 1;
+        if *position >= tokens.len() {
+            return Err("syntax error: unexpected end of file".to_string());
+        }
+        commands.push(parse_command(tokens, position)?);
+    }
+
+    Ok(Pipeline { commands })
+}
+
+fn parse_command(tokens: &[Token], position: &mut usize) -> Result<SimpleCommand, String> {
+    let mut command = SimpleCommand::default();
+
+    while let Some(token) = tokens.get(*position) {
+        match token {
+            Token::Word(word) => command.words.push(word.clone()),
+            Token::Redirect(fd, kind) => {
+                *position += 1;
+                match tokens.get(*position) {
+                    Some(Token::Word(target)) => {
+                        // The lines of a here-document are there once the line has ended
+                        let here_document = match tokens.get(*position + 1) {
+                            Some(Token::HereDocument(document)) => {
+                                *position += 1;
+                                Some(document.clone())
+                            }
+                            _ => None,
+                        };
+                        command.redirects.push(Redirect {
+                            fd: *fd,
+                            kind: *kind,
+                            target: target.clone(),
+                            here_document,
+                        });
+                    }
+                    Some(token) => return Err(unexpected(token)),
+                    None => return Err("syntax error near unexpected token `newline'".to_string()),
+                }
+            }
+            _ => break,
+        }
+        *position += 1;
+    }
+
+    if command.words.is_empty() && command.redirects.is_empty() {
+        return Err(match tokens.get(*position) {
+            Some(token) => unexpected(token),
+            None => "syntax error near unexpected token `newline'".to_string(),
+        });
+    }
+    Ok(command)
+}
+
+/**
+    Replaces the first word of every simple command with its alias, if it has one.
+    An alias isn't expanded again inside its own value (`active` are the ones being expanded),
+    quoted words like `\ls` or `'ls'` are never expanded, and if a value ends with a blank,
+    the word after it is expanded as well.
+    Also returns whether the word following the tokens would be expanded.
+*/
+fn expand_aliases(
+    tokens: Vec<Token>,
+    aliases: &BTreeMap<String, String>,
+    active: &mut Vec<String>,
+) -> Result<(Vec<Token>, bool), String> {
+    let mut expanded: Vec<Token> = Vec::new();
+    // Whether the next word is in command position
+    let mut check_next = true;
+    let mut redirect_target = false;
+
+    for token in tokens {
+        match token {
+            Token::Word(word) if redirect_target => {
+                redirect_target = false;
+                expanded.push(Token::Word(word));
+            }
+            Token::Word(word) if check_next => {
+                check_next = false;
+                let quoted = word.contains(['\\', '\'', '"']);
+                match aliases.get(&word) {
+                    Some(value) if !quoted && !active.contains(&word) => {
+                        active.push(word);
+                        let (tokens, check_after) =
+                            expand_aliases(tokenize(value)?, aliases, active)?;
+                        active.pop();
+                        expanded.extend(tokens);
+                        check_next = check_after || value.ends_with([' ', '\t']);
+                    }
+                    _ => expanded.push(Token::Word(word)),
+                }
+            }
+            Token::Word(word) => expanded.push(Token::Word(word)),
+            Token::HereDocument(_) => expanded.push(token),
+            Token::Redirect(..) => {
+                redirect_target = true;
+                expanded.push(token);
+            }
+            _ => {
+                check_next = true;
+                expanded.push(token);
+            }
+        }
+    }
+
+    Ok((expanded, check_next))
+}
+
+/**
+    Splits the input into words and operators.
+    Quotes and backslashes are kept inside the words; they are removed during expansion.
+    Between `[[` and `]]`, `&&`, `||`, `<` and `>` are words of the conditional expression
+    and `|` is part of a word (as in `[[ $x =~ a|b ]]`).
+*/
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    match scan(input)? {
+        (tokens, None) => Ok(tokens),
+        (_, Some(delimiter)) => Err(format!(
+            "here-document delimited by end-of-file (wanted `{}')",
+            delimiter
+        )),
+    }
+}
+
+/// Whether the input ends inside a here-document, so it needs more lines before it can run
+pub fn is_incomplete(input: &str) -> bool {
+    matches!(scan(input), Ok((_, Some(_))))
+}
+
+/**
+    Groups the lines of a script into command lines, keeping here-documents with their command.
+    Returns the number of the first line of each group along with its text.
+*/
+pub fn command_lines(source: &str) -> Vec<(usize, String)> {
+    let mut groups: Vec<(usize, String)> = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+
+    for (index, line) in source.lines().enumerate() {
+        let (number, mut text) = current.take().unwrap_or((index + 1, String::new()));
+        if !text.is_empty() || number != index + 1 {
+            text.push('\n');
+        }
+        text.push_str(line);
+        if is_incomplete(&text) {
+            current = Some((number, text));
+        } else {
+            groups.push((number, text));
+        }
+    }
+    // An unfinished here-document runs into the syntax error at the end
+    groups.extend(current);
+    groups
+}
+
+/**
+    Tokenizes the input, reading the lines of here-documents after the line they start on.
+    Also returns the delimiter of a here-document the input ends in, if any.
+*/
+fn scan(input: &str) -> Result<(Vec<Token>, Option<String>), String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut current = String::new();
+    let mut position: usize = 0;
+    // Inside `[[ ... ]]`
+    let mut conditional = false;
+    // The `<<` tokens still waiting for their lines
+    let mut here_documents: Vec<usize> = Vec::new();
+
+    // Finishes the current word (if any), noticing where conditional expressions start and end
+    fn flush(current: &mut String, tokens: &mut Vec<Token>, conditional: &mut bool) {
+        if current.is_empty() {
+            return;
+        }
+        let word = std::mem::take(current);
+        let command_position = matches!(
+            tokens.last(),
+            None | Some(Token::Pipe | Token::And | Token::Or | Token::Semicolon | Token::Ampersand)
+        );
+        if word == "[[" && command_position {
+            *conditional = true;
+        } else if word == "]]" {
+            *conditional = false;
+        }
+        tokens.push(Token::Word(word));
+    }
+
+    while position < chars.len() {
+        let ch = chars[position];
+        let next = chars.get(position + 1).copied();
+
+        match ch {
+            // A newline ends the command (unless an operator wants more), then come the
+            // lines of its here-documents
+            '\n' if !conditional => {
+                flush(&mut current, &mut tokens, &mut conditional);
+                if matches!(tokens.last(), Some(Token::Word(_) | Token::HereDocument(_))) {
+                    tokens.push(Token::Semicolon);
+                }
+                position += 1;
+                // Each body goes right after its delimiter word
+                let pending = std::mem::take(&mut here_documents);
+                for (inserted, index) in pending.into_iter().enumerate() {
+                    let index = index + inserted;
+                    let Some(Token::Word(word)) = tokens.get(index + 1) else {
+                        return Err("syntax error near unexpected token `newline'".to_string());
+                    };
+                    let strip_tabs = matches!(
+                        tokens[index],
+                        Token::Redirect(_, RedirectKind::HereDocument { strip_tabs: true })
+                    );
+                    let (delimiter, quoted) = unquote_delimiter(word);
+                    let Some(body) =
+                        read_here_document(&chars, &mut position, &delimiter, strip_tabs)
+                    else {
+                        return Ok((tokens, Some(delimiter)));
+                    };
+                    tokens.insert(
+                        index + 2,
+                        Token::HereDocument(HereDocument { body, quoted }),
+                    );
+                }
+            }
+            c if c.is_whitespace() => {
+                flush(&mut current, &mut tokens, &mut conditional);
+                position += 1;
+            }
+            // Comments start at the beginning of a word and go to the end of the line
+            '#' if current.is_empty() => {
+                while position < chars.len() && chars[position] != '\n' {
+                    position += 1;
+                }
+            }
+            '\\' => {
+                current.push(ch);
+                if let Some(next) = next {
+                    current.push(next);
+                }
+                position += 2;
+            }
+            '\'' => {
+                let end = find_closing(&chars, position + 1, '\'')
+                    .ok_or("unexpected EOF while looking for matching `''")?;
+                current.extend(&chars[position..=end]);
+                position = end + 1;
+            }
+            '"' => {
+                let end = find_closing(&chars, position + 1, '"')
+                    .ok_or("unexpected EOF while looking for matching `\"'")?;
+                current.extend(&chars[position..=end]);
+                position = end + 1;
+            }
+            '$' if next == Some('{') => {
+                let end = find_closing(&chars, position + 2, '}')
+                    .ok_or("unexpected EOF while looking for matching `}'")?;
+                current.extend(&chars[position..=end]);
+                position = end + 1;
+            }
+            // Process substitutions, `<(cmd)` and `>(cmd)`, are (part of) a word
+            '<' | '>' if next == Some('(') && !conditional => {
+                let end = find_closing_parenthesis(&chars, position + 2)
+                    .ok_or("unexpected EOF while looking for matching `)'")?;
+                current.extend(&chars[position..=end]);
+                position = end + 1;
+            }
+            // (a `]]` right before the operator ends the conditional expression first)
+            '|' if conditional && current != "]]" && next != Some('|') => {
+                current.push(ch);
+                position += 1;
+            }
+            '|' | '&' | ';' | '<' | '>' if conditional && current != "]]" => {
+                flush(&mut current, &mut tokens, &mut conditional);
+                let operator: String = match (ch, next) {
+                    ('&', Some('&')) | ('|', Some('|')) => [ch, ch].iter().collect(),
+                    ('<' | '>', next) if next != Some(ch) && next != Some('&') => ch.to_string(),
+                    _ => {
+                        return Err(format!(
+                            "syntax error in conditional expression: unexpected token `{}'",
+                            ch
+                        ));
+                    }
+                };
+                position += operator.len();
+                tokens.push(Token::Word(operator));
+            }
+            '|' | '&' | ';' | '<' | '>' => {
+                // A word made of digits directly in front of a redirection is the fd
+                let io_number: Option<i32> = if (ch == '<' || ch == '>')
+                    && !current.is_empty()
+                    && current.chars().all(|c| c.is_ascii_digit())
+                {
+                    current.parse().ok()
+                } else {
+                    None
+                };
+                if io_number.is_some() {
+                    current.clear();
+                } else {
+                    flush(&mut current, &mut tokens, &mut conditional);
+                }
+
+                let rest: String = chars[position..chars.len().min(position + 3)]
+                    .iter()
+                    .collect();
+                let (token, length): (Token, usize) = if rest.starts_with("<<<") {
+                    (
+                        Token::Redirect(io_number.unwrap_or(0), RedirectKind::HereString),
+                        3,
+                    )
+                } else if rest.starts_with("<<") {
+                    let strip_tabs = rest.starts_with("<<-");
+                    here_documents.push(tokens.len());
+                    let kind = RedirectKind::HereDocument { strip_tabs };
+                    (
+                        Token::Redirect(io_number.unwrap_or(0), kind),
+                        if strip_tabs { 3 } else { 2 },
+                    )
+                } else if rest.starts_with("&&") {
+                    (Token::And, 2)
+                } else if rest.starts_with("||") {
+                    (Token::Or, 2)
+                } else if rest.starts_with("&>>") {
+                    (Token::Redirect(1, RedirectKind::AppendAll), 3)
+                } else if rest.starts_with("&>") {
+                    (Token::Redirect(1, RedirectKind::OutputAll), 2)
+                } else if rest.starts_with(">>") {
+                    (
+                        Token::Redirect(io_number.unwrap_or(1), RedirectKind::Append),
+                        2,
+                    )
+                } else if rest.starts_with(">&") {
+                    let fd = io_number.unwrap_or(1);
+                    (Token::Redirect(fd, RedirectKind::DuplicateOutput), 2)
+                } else if rest.starts_with("<&") {
+                    let fd = io_number.unwrap_or(0);
+                    (Token::Redirect(fd, RedirectKind::DuplicateInput), 2)
+                } else if rest.starts_with(">|") {
+                    (
+                        Token::Redirect(io_number.unwrap_or(1), RedirectKind::Output),
+                        2,
+                    )
+                } else {
+                    match ch {
+                        '|' => (Token::Pipe, 1),
+                        '&' => (Token::Ampersand, 1),
+                        ';' => (Token::Semicolon, 1),
+                        '<' => (
+                            Token::Redirect(io_number.unwrap_or(0), RedirectKind::Input),
+                            1,
+                        ),
+                        _ => (
+                            Token::Redirect(io_number.unwrap_or(1), RedirectKind::Output),
+                            1,
+                        ),
+                    }
+                };
+                tokens.push(token);
+                position += length;
+            }
+            // The elements of an array assignment, `name=(a b c)`, belong to the word
+            '(' if is_compound_assignment_start(&current) => {
+                let end = find_closing_parenthesis(&chars, position + 1)
+                    .ok_or("unexpected EOF while looking for matching `)'")?;
+                current.extend(&chars[position..=end]);
+                position = end + 1;
+            }
+            _ => {
+                current.push(ch);
+                position += 1;
+            }
+        }
+    }
+
+    flush(&mut current, &mut tokens, &mut conditional);
+    if conditional {
+        return Err("unexpected EOF while looking for `]]'".to_string());
+    }
+    // Without a delimiter word the parser reports the missing target
+    let unfinished = here_documents
+        .first()
+        .and_then(|index| match tokens.get(index + 1) {
+            Some(Token::Word(word)) => Some(unquote_delimiter(word).0),
+            _ => None,
+        });
+    Ok((tokens, unfinished))
+}
+
+/**
+    Reads the lines of a here-document up to the delimiter line, starting at `position`.
+    Returns `None` if the input ends first.
+*/
+fn read_here_document(
+    chars: &[char],
+    position: &mut usize,
+    delimiter: &str,
+    strip_tabs: bool,
+) -> Option<String> {
+    let mut body = String::new();
+    while *position < chars.len() {
+        let end = (*position..chars.len())
+            .find(|index| chars[*index] == '\n')
+            .unwrap_or(chars.len());
+        let line: String = chars[*position..end].iter().collect();
+        *position = (end + 1).min(chars.len());
+        let line = if strip_tabs {
+            line.trim_start_matches('\t')
+        } else {
+            &line
+        };
+        if line == delimiter {
+            return Some(body);
+        }
+        body.push_str(line);
+        body.push('\n');
+    }
+    None
+}
+
+/// Removes the quotes from the delimiter of a here-document; returns whether there were any
+fn unquote_delimiter(word: &str) -> (String, bool) {
+    let mut delimiter = String::new();
+    let mut chars = word.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\'' | '"' => {}
+            '\\' => delimiter.extend(chars.next()),
+            ch => delimiter.push(ch),
+        }
+    }
+    (delimiter, word.contains(['\'', '"', '\\']))
+}
+
+/// Splits text into words like a command line, failing at operators (for `name=(...)`)
+pub fn split_words(input: &str) -> Result<Vec<String>, String> {
+    tokenize(input)?
+        .into_iter()
+        .map(|token| match token {
+            Token::Word(word) => Ok(word),
+            token => Err(unexpected(&token)),
+        })
+        .collect()
+}
+
+/**
+    Finds the first process substitution (`<(cmd)` or `>(cmd)`) outside of quotes in a word.
+    Returns its byte range; the command is inside the parentheses.
+*/
+pub fn find_process_substitution(word: &str) -> Option<Range<usize>> {
+    let chars: Vec<char> = word.chars().collect();
+    let mut position: usize = 0;
+    while position < chars.len() {
+        match chars[position] {
+            '\\' => position += 1,
+            '\'' | '"' => position = find_closing(&chars, position + 1, chars[position])?,
+            '$' if chars.get(position + 1) == Some(&'{') => {
+                position = find_closing(&chars, position + 2, '}')?;
+            }
+            '<' | '>' if chars.get(position + 1) == Some(&'(') => {
+                let end = find_closing_parenthesis(&chars, position + 2)?;
+                let offset = |index: usize| chars[..index].iter().map(|c| c.len_utf8()).sum();
+                return Some(offset(position)..offset(end + 1));
+            }
+            _ => {}
+        }
+        position += 1;
+    }
+    None
+}
+
+/// Whether a word so far is `name=` or `name+=`
+fn is_compound_assignment_start(word: &str) -> bool {
+    let Some(name) = word.strip_suffix('=') else {
+        return false;
+    };
+    let name = name.strip_suffix('+').unwrap_or(name);
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Finds the parenthesis closing the one before `start`, skipping quotes and nested parentheses
+fn find_closing_parenthesis(chars: &[char], start: usize) -> Option<usize> {
+    let mut depth: usize = 0;
+    let mut position = start;
+    while position < chars.len() {
+        match chars[position] {
+            '\\' => position += 1,
+            quote @ ('\'' | '"') => position = find_closing(chars, position + 1, quote)?,
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some(position),
+            ')' => depth -= 1,
+            _ => {}
+        }
+        position += 1;
+    }
+    None
+}
+
+/// Finds the closing quote (or brace), skipping backslash escapes where they apply
+fn find_closing(chars: &[char], start: usize, closing: char) -> Option<usize> {
+    let mut position = start;
+    while position < chars.len() {
+        match chars[position] {
+            '\\' if closing != '\'' => position += 2,
+            c if c == closing => return Some(position),
+            _ => position += 1,
+        }
+    }
+    None
+}
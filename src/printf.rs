@@ -0,0 +1,610 @@
+// Output formatting of the `printf` and `echo` builtins: backslash escapes and `%` conversions
+
+/// What `printf` produced, and the problems it reported along the way
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Formatted {
+    pub output: Vec<u8>,
+    pub errors: Vec<String>,
+}
+
+/// How a backslash escape is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EscapeStyle {
+    /// `echo -e` and `%b`: octal numbers start with `\0` (`\0NNN`)
+    Echo,
+    /// The format of `printf`: `\NNN`
+    Printf,
+}
+
+/**
+    Replaces backslash escapes (`\n`, `\t`, `\xHH`, octal numbers, ...) in `text`.
+    Returns the bytes and whether `\c` asked to stop all further output.
+*/
+pub fn unescape(text: &str, style: EscapeStyle) -> (Vec<u8>, bool) {
+    let chars: Vec<char> = text.chars().collect();
+    let mut output: Vec<u8> = Vec::new();
+    let mut position = 0;
+
+    while position < chars.len() {
+        let ch = chars[position];
+        position += 1;
+        if ch != '\\' || position == chars.len() {
+            push_char(&mut output, ch);
+            continue;
+        }
+
+        let escape = chars[position];
+        position += 1;
+        let byte: Option<u8> = match escape {
+            'a' => Some(0x07),
+            'b' => Some(0x08),
+            'e' | 'E' => Some(0x1b),
+            'f' => Some(0x0c),
+            'n' => Some(b'\n'),
+            'r' => Some(b'\r'),
+            't' => Some(b'\t'),
+            'v' => Some(0x0b),
+            '\\' => Some(b'\\'),
+            'c' => return (output, true),
+            '0'..='7' => {
+                // `\0NNN` for echo, `\NNN` for printf
+                let (start, max_digits) = match style {
+                    EscapeStyle::Echo if escape == '0' => (position, 3),
+                    EscapeStyle::Echo => {
+                        output.push(b'\\');
+                        push_char(&mut output, escape);
+                        continue;
+                    }
+                    EscapeStyle::Printf => (position - 1, 3),
+                };
+                let (value, length) = parse_digits(&chars[start..], 8, max_digits);
+                position = start + length;
+                Some(value as u8)
+            }
+            'x' => match parse_digits(&chars[position..], 16, 2) {
+                (_, 0) => None,
+                (value, length) => {
+                    position += length;
+                    Some(value as u8)
+                }
+            },
+            'u' | 'U' => {
+                let max_digits = if escape == 'u' { 4 } else { 8 };
+                match parse_digits(&chars[position..], 16, max_digits) {
+                    (_, 0) => None,
+                    (value, length) => {
+                        position += length;
+                        push_char(&mut output, char::from_u32(value).unwrap_or('\u{fffd}'));
+                        continue;
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        match byte {
+            Some(byte) => output.push(byte),
+            // Unknown escapes are kept as they are
+            None => {
+                output.push(b'\\');
+                push_char(&mut output, escape);
+            }
+        }
+    }
+
+    (output, false)
+}
+
+fn push_char(output: &mut Vec<u8>, ch: char) {
+    let mut buffer = [0; 4];
+    output.extend(ch.encode_utf8(&mut buffer).as_bytes());
+}
+
+/// Reads up to `max_digits` digits in `radix`; returns the value and how many were read
+fn parse_digits(chars: &[char], radix: u32, max_digits: usize) -> (u32, usize) {
+    let mut value: u32 = 0;
+    let mut length = 0;
+    while length < max_digits
+        && let Some(digit) = chars.get(length).and_then(|c| c.to_digit(radix))
+    {
+        value = value * radix + digit;
+        length += 1;
+    }
+    (value, length)
+}
+
+/// The largest width or precision (bigger ones would exhaust memory or the float formatter)
+const MAX_FIELD_SIZE: usize = 10_000;
+
+/// A `%` conversion: `%[flags][width][.precision]conversion`
+#[derive(Debug, Default)]
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    alternate: bool,
+    zero: bool,
+    width: Option<usize>,
+    precision: Option<usize>,
+}
+
+/**
+    Formats `args` according to `format`, like the `printf` builtin.
+    The format is used again as long as there are arguments left; missing arguments count as
+    empty strings or zero. Invalid numbers are reported and count as zero; an invalid conversion
+    stops the output.
+*/
+pub fn format(format: &str, args: &[&str]) -> Formatted {
+    let chars: Vec<char> = format.chars().collect();
+    let mut formatted = Formatted::default();
+    let mut args = args.iter();
+
+    loop {
+        let remaining = args.len();
+        match format_once(&chars, &mut args, &mut formatted) {
+            Pass::Stop => break,
+            Pass::Done => {}
+        }
+        // Only formats that use arguments are repeated for the rest
+        if args.len() == 0 || args.len() == remaining {
+            break;
+        }
+    }
+
+    formatted
+}
+
+enum Pass {
+    Done,
+    /// `\c`, `%b` with `\c` or an invalid conversion: nothing more is printed
+    Stop,
+}
+
+fn format_once(
+    chars: &[char],
+    args: &mut std::slice::Iter<&str>,
+    formatted: &mut Formatted,
+) -> Pass {
+    let mut position = 0;
+
+    while position < chars.len() {
+        match chars[position] {
+            '\\' => {
+                // Hand the escape (and its digits) over to `unescape`
+                let end = escape_end(chars, position);
+                let escape: String = chars[position..end].iter().collect();
+                let (bytes, stop) = unescape(&escape, EscapeStyle::Printf);
+                formatted.output.extend(bytes);
+                if stop {
+                    return Pass::Stop;
+                }
+                position = end;
+            }
+            '%' => {
+                position += 1;
+                if chars.get(position) == Some(&'%') {
+                    formatted.output.push(b'%');
+                    position += 1;
+                    continue;
+                }
+                let mut spec = Spec::default();
+                while let Some(flag) = chars.get(position) {
+                    match flag {
+                        '-' => spec.left = true,
+                        '+' => spec.plus = true,
+                        ' ' => spec.space = true,
+                        '#' => spec.alternate = true,
+                        '0' => spec.zero = true,
+                        _ => break,
+                    }
+                    position += 1;
+                }
+                if chars.get(position) == Some(&'*') {
+                    position += 1;
+                    let width = next_number(args, formatted);
+                    spec.left |= width < 0;
+                    spec.width = Some(width.unsigned_abs() as usize);
+                } else {
+                    spec.width = read_number(chars, &mut position);
+                }
+                if chars.get(position) == Some(&'.') {
+                    position += 1;
+                    if chars.get(position) == Some(&'*') {
+                        position += 1;
+                        // A negative precision counts as none
+                        let precision = next_number(args, formatted);
+                        spec.precision = usize::try_from(precision).ok();
+                    } else {
+                        spec.precision = Some(read_number(chars, &mut position).unwrap_or(0));
+                    }
+                }
+
+                if let Some(width) = spec.width.filter(|width| *width > MAX_FIELD_SIZE) {
+                    formatted
+                        .errors
+                        .push(format!("{}: field width out of range", width));
+                    return Pass::Stop;
+                }
+                if let Some(precision) = spec.precision.filter(|p| *p > MAX_FIELD_SIZE) {
+                    formatted
+                        .errors
+                        .push(format!("{}: precision out of range", precision));
+                    return Pass::Stop;
+                }
+
+                let Some(&conversion) = chars.get(position) else {
+                    formatted
+                        .errors
+                        .push("`%': missing format character".to_string());
+                    return Pass::Stop;
+                };
+                position += 1;
+                if !convert(conversion, &spec, args, formatted) {
+                    return Pass::Stop;
+                }
+            }
+            ch => {
+                push_char(&mut formatted.output, ch);
+                position += 1;
+            }
+        }
+    }
+
+    Pass::Done
+}
+
+/// Where the escape starting at the backslash at `start` ends
+fn escape_end(chars: &[char], start: usize) -> usize {
+    let Some(&escape) = chars.get(start + 1) else {
+        return start + 1;
+    };
+    let (digits, max) = match escape {
+        '0'..='7' => (8, 2),
+        'x' => (16, 2),
+        'u' => (16, 4),
+        'U' => (16, 8),
+        _ => return start + 2,
+    };
+    let mut end = start + 2;
+    while end < chars.len() && end - start - 2 < max && chars[end].is_digit(digits) {
+        end += 1;
+    }
+    end
+}
+
+fn read_number(chars: &[char], position: &mut usize) -> Option<usize> {
+    let start = *position;
+    while chars.get(*position).is_some_and(|c| c.is_ascii_digit()) {
+        *position += 1;
+    }
+    let digits: String = chars[start..*position].iter().collect();
+    digits.parse().ok()
+}
+
+/// The next argument as an integer (for `*` widths and integer conversions)
+fn next_number(args: &mut std::slice::Iter<&str>, formatted: &mut Formatted) -> i64 {
+    match args.next() {
+        Some(arg) => parse_integer(arg).unwrap_or_else(|| {
+            formatted.errors.push(format!("{}: invalid number", arg));
+            0
+        }),
+        None => 0,
+    }
+}
+
+/**
+    Parses an integer argument: decimal, `0x` hex, `0` octal, or `'c` / `"c` for the
+    code of a character.
+*/
+pub fn parse_integer(arg: &str) -> Option<i64> {
+    let text = arg.trim_start();
+    if let Some(quoted) = text.strip_prefix(['\'', '"']) {
+        return Some(quoted.chars().next().map_or(0, |c| c as i64));
+    }
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let value = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        i64::from_str_radix(&digits[1..], 8).ok()?
+    } else {
+        digits.parse::<i64>().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// Formats one argument; returns false if the conversion is invalid or `%b` stopped the output
+fn convert(
+    conversion: char,
+    spec: &Spec,
+    args: &mut std::slice::Iter<&str>,
+    formatted: &mut Formatted,
+) -> bool {
+    let text: String = match conversion {
+        's' => {
+            let arg = args.next().copied().unwrap_or_default();
+            truncate(arg, spec.precision)
+        }
+        'b' => {
+            let arg = args.next().copied().unwrap_or_default();
+            let (bytes, stop) = unescape(arg, EscapeStyle::Echo);
+            let text = String::from_utf8_lossy(&bytes).into_owned();
+            formatted
+                .output
+                .extend(pad(&truncate(&text, spec.precision), spec).bytes());
+            return !stop;
+        }
+        'q' => quote(args.next().copied().unwrap_or_default()),
+        'c' => args
+            .next()
+            .and_then(|arg| arg.chars().next())
+            .map(String::from)
+            .unwrap_or_default(),
+        'd' | 'i' => {
+            let value = next_number(args, formatted);
+            let sign = if value < 0 {
+                "-"
+            } else if spec.plus {
+                "+"
+            } else if spec.space {
+                " "
+            } else {
+                ""
+            };
+            let digits = with_precision(value.unsigned_abs().to_string(), spec.precision);
+            return push_number(sign, "", &digits, spec, formatted);
+        }
+        'u' | 'o' | 'x' | 'X' => {
+            let value = next_number(args, formatted) as u64;
+            let (digits, prefix) = match conversion {
+                'o' => (
+                    format!("{:o}", value),
+                    if spec.alternate { "0" } else { "" },
+                ),
+                'x' => (
+                    format!("{:x}", value),
+                    if spec.alternate && value != 0 {
+                        "0x"
+                    } else {
+                        ""
+                    },
+                ),
+                'X' => (
+                    format!("{:X}", value),
+                    if spec.alternate && value != 0 {
+                        "0X"
+                    } else {
+                        ""
+                    },
+                ),
+                _ => (value.to_string(), ""),
+            };
+            let digits = with_precision(digits, spec.precision);
+            // The octal `0` only counts if the digits don't start with one already
+            let prefix = if digits.starts_with('0') && conversion == 'o' {
+                ""
+            } else {
+                prefix
+            };
+            return push_number("", prefix, &digits, spec, formatted);
+        }
+        'f' | 'F' | 'e' | 'E' | 'g' | 'G' => {
+            let arg = args.next().copied().unwrap_or("0");
+            let value: f64 = parse_float(arg).unwrap_or_else(|| {
+                formatted.errors.push(format!("{}: invalid number", arg));
+                0.0
+            });
+            let sign = if value.is_sign_negative() && !value.is_nan() {
+                "-"
+            } else if spec.plus {
+                "+"
+            } else if spec.space {
+                " "
+            } else {
+                ""
+            };
+            let digits = format_float(value.abs(), conversion, spec);
+            if !value.is_finite() {
+                return push_number(
+                    sign,
+                    "",
+                    &digits,
+                    &Spec {
+                        zero: false,
+                        ..*spec
+                    },
+                    formatted,
+                );
+            }
+            return push_number(sign, "", &digits, spec, formatted);
+        }
+        _ => {
+            formatted
+                .errors
+                .push(format!("`{}': invalid format character", conversion));
+            return false;
+        }
+    };
+
+    formatted.output.extend(pad(&text, spec).bytes());
+    true
+}
+
+fn truncate(text: &str, precision: Option<usize>) -> String {
+    match precision {
+        Some(precision) => text.chars().take(precision).collect(),
+        None => text.to_string(),
+    }
+}
+
+/// Pads to the width with spaces
+fn pad(text: &str, spec: &Spec) -> String {
+    let width = spec.width.unwrap_or(0);
+    let length = text.chars().count();
+    if length >= width {
+        return text.to_string();
+    }
+    let padding = " ".repeat(width - length);
+    if spec.left {
+        format!("{}{}", text, padding)
+    } else {
+        format!("{}{}", padding, text)
+    }
+}
+
+/// A precision on integers is the minimum number of digits
+fn with_precision(digits: String, precision: Option<usize>) -> String {
+    match precision {
+        Some(0) if digits == "0" => String::new(),
+        Some(precision) if digits.len() < precision => {
+            format!("{}{}", "0".repeat(precision - digits.len()), digits)
+        }
+        _ => digits,
+    }
+}
+
+fn push_number(
+    sign: &str,
+    prefix: &str,
+    digits: &str,
+    spec: &Spec,
+    formatted: &mut Formatted,
+) -> bool {
+    let width = spec.width.unwrap_or(0);
+    let length = sign.len() + prefix.len() + digits.len();
+    // Zero padding doesn't apply with `-`, or with a precision for integers
+    let zero =
+        spec.zero && !spec.left && (spec.precision.is_none() || digits.contains(['.', 'e', 'E']));
+    let text = if zero && length < width {
+        format!("{}{}{}{}", sign, prefix, "0".repeat(width - length), digits)
+    } else {
+        pad(&format!("{}{}{}", sign, prefix, digits), spec)
+    };
+    formatted.output.extend(text.bytes());
+    true
+}
+
+fn parse_float(arg: &str) -> Option<f64> {
+    let text = arg.trim();
+    if let Some(quoted) = text.strip_prefix(['\'', '"']) {
+        return Some(quoted.chars().next().map_or(0.0, |c| c as u32 as f64));
+    }
+    text.parse::<f64>()
+        .ok()
+        .or_else(|| parse_integer(text).map(|value| value as f64))
+}
+
+/// Formats a non-negative float for `%f`, `%e` or `%g` (and their uppercase forms)
+fn format_float(value: f64, conversion: char, spec: &Spec) -> String {
+    let uppercase = conversion.is_ascii_uppercase();
+    if !value.is_finite() {
+        let text = if value.is_nan() { "nan" } else { "inf" };
+        return if uppercase {
+            text.to_uppercase()
+        } else {
+            text.to_string()
+        };
+    }
+
+    let precision = spec.precision.unwrap_or(6);
+    let text = match conversion.to_ascii_lowercase() {
+        'f' => {
+            let text = format!("{:.*}", precision, value);
+            if spec.alternate && precision == 0 {
+                format!("{}.", text)
+            } else {
+                text
+            }
+        }
+        'e' => exponential(value, precision),
+        _ => {
+            // `%g` uses `%e` for very small or large numbers, and drops trailing zeros
+            let precision = precision.max(1);
+            let exponent = if value == 0.0 {
+                0
+            } else {
+                let text = exponential(value, precision - 1);
+                text[text.find('e').unwrap() + 1..].parse::<i32>().unwrap()
+            };
+            let text = if exponent < -4 || exponent >= precision as i32 {
+                exponential(value, precision - 1)
+            } else {
+                format!("{:.*}", (precision as i32 - 1 - exponent) as usize, value)
+            };
+            if spec.alternate {
+                text
+            } else {
+                trim_fraction_zeros(&text)
+            }
+        }
+    };
+    if uppercase { text.to_uppercase() } else { text }
+}
+
+/// `%e` notation like C: `1.500000e+00`
+fn exponential(value: f64, precision: usize) -> String {
+    let text = format!("{:.*e}", precision, value);
+    let (mantissa, exponent) = text.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{}e{}{:02}", mantissa, sign, exponent.abs())
+}
+
+fn trim_fraction_zeros(text: &str) -> String {
+    let (number, exponent) = match text.find('e') {
+        Some(index) => text.split_at(index),
+        None => (text, ""),
+    };
+    let number = if number.contains('.') {
+        number.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        number
+    };
+    format!("{}{}", number, exponent)
+}
+
+/**
+    Quotes a word so the shell reads it back as the same word (`%q`): special characters get
+    a backslash, control characters make it a `$'...'` string, and the empty word is `''`.
+*/
+pub fn quote(word: &str) -> String {
+    if word.is_empty() {
+        return "''".to_string();
+    }
+    if word.chars().any(|c| c.is_control()) {
+        let mut quoted = String::from("$'");
+        for ch in word.chars() {
+            match ch {
+                '\n' => quoted.push_str("\\n"),
+                '\t' => quoted.push_str("\\t"),
+                '\r' => quoted.push_str("\\r"),
+                '\x1b' => quoted.push_str("\\E"),
+                '\'' | '\\' => {
+                    quoted.push('\\');
+                    quoted.push(ch);
+                }
+                c if c.is_control() => quoted.push_str(&format!("\\{:03o}", c as u32)),
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('\'');
+        return quoted;
+    }
+
+    let mut quoted = String::new();
+    for (index, ch) in word.chars().enumerate() {
+        let special = " \t'\"\\|&;()<>$`*?[]{}!#^".contains(ch)
+            || (ch == '~' && index == 0)
+            || (ch == '=' && index == 0);
+        if special {
+            quoted.push('\\');
+        }
+        quoted.push(ch);
+    }
+    quoted
+}
@@ -0,0 +1,255 @@
+// Input handling of the `read` builtin: reading up to a delimiter and splitting at `IFS`
+use std::io::{self, Read};
+use std::os::fd::RawFd;
+use std::time::{Duration, Instant};
+
+/// How much `read` reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadOptions {
+    /// `-r`: backslashes are normal characters
+    pub raw: bool,
+    /// The byte ending the input (`-d`, a newline by default)
+    pub delimiter: u8,
+    /// `-n`: stop after this many characters
+    pub count: Option<usize>,
+    /// `-N`: read exactly this many characters, the delimiter included
+    pub exact_count: bool,
+    /// `-t`: give up after this long, watching the given fd for input
+    pub timeout: Option<(Duration, RawFd)>,
+}
+
+impl Default for ReadOptions {
+    fn default() -> ReadOptions {
+        ReadOptions {
+            raw: false,
+            delimiter: b'\n',
+            count: None,
+            exact_count: false,
+            timeout: None,
+        }
+    }
+}
+
+/// Why reading stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadEnd {
+    Delimiter,
+    /// The count of `-n` or `-N` was reached
+    Count,
+    Eof,
+    Timeout,
+}
+
+/// What was read: the characters (and whether a backslash escaped them) and why it stopped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Input {
+    pub chars: Vec<(char, bool)>,
+    pub end: ReadEnd,
+}
+
+impl Input {
+    pub fn text(&self) -> String {
+        self.chars.iter().map(|(ch, _)| ch).collect()
+    }
+}
+
+/**
+    Reads one byte at a time (so nothing after the delimiter is taken from a pipe).
+    Unless `raw`, a backslash escapes the next character and a backslash-newline is removed.
+*/
+pub fn read_input(reader: &mut dyn Read, options: &ReadOptions) -> io::Result<Input> {
+    let deadline = options
+        .timeout
+        .map(|(timeout, fd)| (Instant::now() + timeout, fd));
+    let mut chars: Vec<(char, bool)> = Vec::new();
+    let mut escaped = false;
+
+    loop {
+        if options.count.is_some_and(|count| chars.len() >= count) {
+            return Ok(Input {
+                chars,
+                end: ReadEnd::Count,
+            });
+        }
+        if let Some((deadline, fd)) = deadline
+            && !wait_for_input(fd, deadline)
+        {
+            return Ok(Input {
+                chars,
+                end: ReadEnd::Timeout,
+            });
+        }
+        let Some(bytes) = read_char(reader)? else {
+            return Ok(Input {
+                chars,
+                end: ReadEnd::Eof,
+            });
+        };
+
+        if escaped {
+            escaped = false;
+            // A backslash-newline continues the line
+            if bytes != [b'\n'] {
+                chars.extend(String::from_utf8_lossy(&bytes).chars().map(|ch| (ch, true)));
+            }
+            continue;
+        }
+        if bytes == [options.delimiter] && !options.exact_count {
+            return Ok(Input {
+                chars,
+                end: ReadEnd::Delimiter,
+            });
+        }
+        if bytes == [b'\\'] && !options.raw {
+            escaped = true;
+            continue;
+        }
+        chars.extend(
+            String::from_utf8_lossy(&bytes)
+                .chars()
+                .map(|ch| (ch, false)),
+        );
+    }
+}
+
+/// Reads the bytes of one UTF-8 character (fewer if the input ends early)
+fn read_char(reader: &mut dyn Read) -> io::Result<Option<Vec<u8>>> {
+    let Some(first) = read_byte(reader)? else {
+        return Ok(None);
+    };
+    let length = match first {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 1,
+    };
+    let mut bytes = vec![first];
+    while bytes.len() < length {
+        match read_byte(reader)? {
+            Some(byte) => bytes.push(byte),
+            None => break,
+        }
+    }
+    Ok(Some(bytes))
+}
+
+fn read_byte(reader: &mut dyn Read) -> io::Result<Option<u8>> {
+    let mut buffer = [0u8; 1];
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(buffer[0])),
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Waits until `fd` has input or the deadline passes; returns whether there is input
+fn wait_for_input(fd: RawFd, deadline: Instant) -> bool {
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if is_readable(fd, remaining) {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+    }
+}
+
+/// Whether `fd` can be read without blocking, waiting at most `timeout` (`read -t 0` uses zero)
+pub fn is_readable(fd: RawFd, timeout: Duration) -> bool {
+    let mut poll_fd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let milliseconds = timeout.as_millis().min(i32::MAX as u128) as i32;
+    unsafe { libc::poll(&mut poll_fd, 1, milliseconds) > 0 }
+}
+
+/**
+    Splits input into fields at unescaped `IFS` characters, like the shell splits words:
+    runs of `IFS` whitespace count as one separator and are trimmed at the ends.
+    With `max` fields, the last one gets the rest of the input (without trailing whitespace).
+*/
+pub fn split_fields(chars: &[(char, bool)], ifs: &str, max: Option<usize>) -> Vec<String> {
+    let is_separator = |(ch, escaped): (char, bool)| !escaped && ifs.contains(ch);
+    let is_blank = |item: (char, bool)| is_separator(item) && matches!(item.0, ' ' | '\t' | '\n');
+    let skip_blanks = |position: &mut usize| {
+        while *position < chars.len() && is_blank(chars[*position]) {
+            *position += 1;
+        }
+    };
+
+    let mut fields: Vec<String> = Vec::new();
+    let mut position: usize = 0;
+    skip_blanks(&mut position);
+
+    while position < chars.len() {
+        if max.is_some_and(|max| fields.len() + 1 >= max) {
+            let mut end = chars.len();
+            while end > position && is_blank(chars[end - 1]) {
+                end -= 1;
+            }
+            fields.push(chars[position..end].iter().map(|(ch, _)| ch).collect());
+            break;
+        }
+
+        let start = position;
+        while position < chars.len() && !is_separator(chars[position]) {
+            position += 1;
+        }
+        fields.push(chars[start..position].iter().map(|(ch, _)| ch).collect());
+
+        // One separator: blanks around at most one other IFS character
+        skip_blanks(&mut position);
+        if position < chars.len() && is_separator(chars[position]) && !is_blank(chars[position]) {
+            position += 1;
+            skip_blanks(&mut position);
+        }
+    }
+    fields
+}
+
+/// Changes the modes of a terminal while `read` runs; the old ones are restored when dropped
+pub struct TerminalModes {
+    fd: RawFd,
+    original: libc::termios,
+}
+
+impl TerminalModes {
+    /**
+        Turns off echoing (for `-s`) and line buffering (so `-n` returns right away).
+        Returns `None` if the fd isn't a terminal.
+    */
+    pub fn change(fd: RawFd, echo: bool, line_buffered: bool) -> Option<TerminalModes> {
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+            return None;
+        }
+
+        let mut modes = original;
+        if !echo {
+            modes.c_lflag &= !libc::ECHO;
+        }
+        if !line_buffered {
+            modes.c_lflag &= !libc::ICANON;
+            modes.c_cc[libc::VMIN] = 1;
+            modes.c_cc[libc::VTIME] = 0;
+        }
+        if unsafe { libc::tcsetattr(fd, libc::TCSADRAIN, &modes) } != 0 {
+            return None;
+        }
+        Some(TerminalModes { fd, original })
+    }
+}
+
+impl Drop for TerminalModes {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(self.fd, libc::TCSADRAIN, &self.original);
+        }
+    }
+}
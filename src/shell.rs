@@ -0,0 +1,550 @@
+use crate::abbreviations::Abbreviations;
+use crate::builtins::{Builtin, Builtins, FdStream};
+use crate::dir_stack::DirStack;
+use crate::error::{self, ExitStatus, Location, ShellError};
+use crate::executor;
+use crate::hash::CommandHash;
+use crate::history::History;
+use crate::jobs::JobTable;
+use crate::options::{Options, ShellOption};
+use crate::parser;
+use crate::traps::{self, Traps};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{self, Read, Seek};
+use std::os::fd::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A shell variable; exported ones are passed on to the environment of commands
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+    pub value: Value,
+    pub exported: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Scalar(String),
+    /// Elements by index (indexes may be left out)
+    Indexed(BTreeMap<usize, String>),
+    /// Elements by key (`declare -A`)
+    Associative(BTreeMap<String, String>),
+}
+
+impl Value {
+    /// What `$name` expands to: the value, or element 0 of an array
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Scalar(value) => Some(value),
+            Value::Indexed(elements) => elements.get(&0).map(String::as_str),
+            Value::Associative(elements) => elements.get("0").map(String::as_str),
+        }
+    }
+
+    /// The values of the elements in order (a plain value is a single element)
+    pub fn values(&self) -> Vec<&str> {
+        match self {
+            Value::Scalar(value) => vec![value],
+            Value::Indexed(elements) => elements.values().map(String::as_str).collect(),
+            Value::Associative(elements) => elements.values().map(String::as_str).collect(),
+        }
+    }
+
+    /// The indexes or keys of the elements, as `${!name[@]}` lists them
+    pub fn keys(&self) -> Vec<String> {
+        match self {
+            Value::Scalar(_) => vec!["0".to_string()],
+            Value::Indexed(elements) => elements.keys().map(usize::to_string).collect(),
+            Value::Associative(elements) => elements.keys().cloned().collect(),
+        }
+    }
+}
+
+/// The output of commands, collected while a shell captures it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CapturedOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+/**
+    Everything a running shell knows about: its working directory, variables, options,
+    functions, aliases, jobs, traps, history and the status of the last command.
+    The working directory of the process itself is only changed for child processes.
+*/
+#[derive(Debug)]
+pub struct Shell {
+    pub cwd: PathBuf,
+    pub vars: BTreeMap<String, Variable>,
+    pub options: Options,
+    /// Function bodies by name
+    pub functions: BTreeMap<String, String>,
+    /// Alias values by name
+    pub aliases: BTreeMap<String, String>,
+    pub builtins: Builtins,
+    /// Words the line editor expands while typing (`abbr`)
+    pub abbreviations: Abbreviations,
+    pub jobs: JobTable,
+    pub traps: Traps,
+    /// Where commands were found in `PATH`
+    pub hash: CommandHash,
+    pub history: History,
+    /// The directories of `pushd` and `popd`
+    pub dir_stack: DirStack,
+    /// The status behind `$?`
+    pub last_status: ExitStatus,
+    /// Set by `exit` (and failures with `set -e`): no more commands run, the shell exits with it
+    pub exit_status: Option<ExitStatus>,
+    /// Where stdout and stderr go while `run_str` and `run_file` run (`None` keeps them as they are)
+    pub output: Option<CapturedOutput>,
+    /// The script line running, named in diagnostics (`None` at the prompt)
+    pub location: Option<Location>,
+    /**
+        Where fds 0, 1 and 2 of builtins and new processes go (`-1` if closed).
+        Redirections of builtins and captured output change these, not the fds of the shell.
+    */
+    pub standard_fds: [RawFd; 3],
+}
+
+impl Shell {
+    /// A shell starting in the current directory, with the environment as exported variables
+    pub fn new() -> Shell {
+        let mut vars: BTreeMap<String, Variable> = env::vars()
+            .map(|(name, value)| {
+                let variable = Variable {
+                    value: Value::Scalar(value),
+                    exported: true,
+                };
+                (name, variable)
+            })
+            .collect();
+
+        // Keep the path the shell was started in (through symlinks) if `PWD` is still right
+        let physical = env::current_dir().unwrap_or_else(|_| PathBuf::from("/"));
+        let pwd: Option<&str> = vars.get("PWD").and_then(|pwd| pwd.value.as_str());
+        let cwd: PathBuf = match pwd {
+            Some(pwd)
+                if pwd.starts_with('/')
+                    && Path::new(pwd).canonicalize().ok() == physical.canonicalize().ok() =>
+            {
+                PathBuf::from(pwd)
+            }
+            _ => physical,
+        };
+        let pwd = Variable {
+            value: Value::Scalar(cwd.to_string_lossy().into_owned()),
+            exported: true,
+        };
+        vars.insert("PWD".to_string(), pwd);
+
+        Shell {
+            cwd,
+            vars,
+            options: Options::default(),
+            functions: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+            builtins: Builtins::default(),
+            abbreviations: Abbreviations::default(),
+            jobs: JobTable::default(),
+            traps: Traps::default(),
+            hash: CommandHash::default(),
+            history: History::new(),
+            dir_stack: DirStack::default(),
+            last_status: 0,
+            exit_status: None,
+            output: None,
+            location: None,
+            standard_fds: [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO],
+        }
+    }
+
+    /// Sets up a shell with options, variables and captured output
+    pub fn builder() -> ShellBuilder {
+        ShellBuilder::default()
+    }
+
+    /// Applies `HISTFILE`, `HISTSIZE`, `HISTFILESIZE` and `HISTCONTROL` as set in the shell
+    pub fn configure_history(&mut self) {
+        let vars = &self.vars;
+        self.history
+            .configure(|name| vars.get(name).and_then(|variable| variable.value.as_str()));
+    }
+
+    /// Applies `BSH_DIRSTACK_FILE` as set in the shell
+    pub fn configure_dir_stack(&mut self) {
+        let vars = &self.vars;
+        self.dir_stack
+            .configure(|name| vars.get(name).and_then(|variable| variable.value.as_str()));
+    }
+
+    /// Adds a builtin to this shell, replacing any builtin with the same name
+    pub fn register_builtin(&mut self, builtin: Arc<dyn Builtin>) {
+        self.builtins.register(builtin);
+    }
+
+    /// Removes a builtin, so the name runs a program again; returns whether it existed
+    pub fn unregister_builtin(&mut self, name: &str) -> bool {
+        self.builtins.unregister(name)
+    }
+
+    /**
+        Runs commands, one line after another, and returns the status of the last one.
+        Nothing runs if a line isn't valid syntax. `exit` only stops running the commands.
+    */
+    pub fn run_str(&mut self, source: &str) -> Result<ExitStatus, ShellError> {
+        self.run_source(None, source)
+    }
+
+    /// Runs a script file like `run_str`; diagnostics name the script and line
+    pub fn run_file(&mut self, path: impl AsRef<Path>) -> Result<ExitStatus, ShellError> {
+        let path = self.resolve_path(path);
+        let name = path.display().to_string();
+        let source =
+            fs::read_to_string(&path).map_err(|error| ShellError::script(&name, &error))?;
+        self.run_source(Some(&name), &source)
+    }
+
+    /// Takes the output captured so far, leaving the buffers empty
+    pub fn take_output(&mut self) -> CapturedOutput {
+        match &mut self.output {
+            Some(output) => std::mem::take(output),
+            None => CapturedOutput::default(),
+        }
+    }
+
+    fn run_source(&mut self, name: Option<&str>, source: &str) -> Result<ExitStatus, ShellError> {
+        let lines = parser::command_lines(source);
+        for (number, line) in &lines {
+            parser::parse(line)
+                .map_err(|message| ShellError::Parse(format!("line {}: {}", number, message)))?;
+        }
+        if self.options.is_set(ShellOption::Noexec) {
+            return Ok(0);
+        }
+
+        self.with_captured_output(|shell| {
+            // Lines are parsed again when they run, with the aliases defined by the lines before
+            let outer = shell.location.take();
+            for (number, line) in &lines {
+                shell.location = name.map(|name| Location {
+                    script: name.to_string(),
+                    line: *number,
+                });
+                executor::run_line(line, shell);
+                if shell.exit_status.is_some() {
+                    break;
+                }
+            }
+            shell.location = outer;
+
+            if let Some(status) = shell.exit_status.take() {
+                shell.last_status = status;
+                traps::run_exit_trap(shell);
+            }
+            shell.last_status
+        })
+    }
+
+    /**
+        Runs `body` with stdout and stderr going to temporary files when output is captured.
+        Only `standard_fds` change: the fds of the process stay as they are.
+    */
+    fn with_captured_output(
+        &mut self,
+        body: impl FnOnce(&mut Shell) -> ExitStatus,
+    ) -> Result<ExitStatus, ShellError> {
+        if self.output.is_none() {
+            return Ok(body(self));
+        }
+
+        let mut stdout = temporary_file()?;
+        let mut stderr = temporary_file()?;
+        let outer = self.standard_fds;
+        self.standard_fds = [outer[0], stdout.as_raw_fd(), stderr.as_raw_fd()];
+        let status = body(self);
+        self.standard_fds = outer;
+
+        let output = self.output.get_or_insert_default();
+        for (file, buffer) in [
+            (&mut stdout, &mut output.stdout),
+            (&mut stderr, &mut output.stderr),
+        ] {
+            file.rewind()?;
+            file.read_to_end(buffer)?;
+        }
+        Ok(status)
+    }
+
+    /// The stream of `standard_fds` for diagnostics
+    pub fn stderr(&self) -> FdStream {
+        FdStream(self.standard_fds[2])
+    }
+
+    /// Prints an error to the shell's stderr, naming the script line running
+    pub fn report(&self, error: &ShellError) {
+        error::report_to(&mut self.stderr(), error, self.location.as_ref());
+    }
+
+    pub fn get_var(&self, name: &str) -> Option<&str> {
+        self.vars
+            .get(name)
+            .and_then(|variable| variable.value.as_str())
+    }
+
+    /// Sets a variable, keeping whether it is exported (new variables aren't)
+    pub fn set_var(&mut self, name: &str, value: &str) {
+        match self.vars.get_mut(name) {
+            // Like `$name`, assigning to an array means its element 0
+            Some(Variable {
+                value: Value::Indexed(elements),
+                ..
+            }) => {
+                elements.insert(0, value.to_string());
+            }
+            Some(Variable {
+                value: Value::Associative(elements),
+                ..
+            }) => {
+                elements.insert("0".to_string(), value.to_string());
+            }
+            Some(variable) => variable.value = Value::Scalar(value.to_string()),
+            None => {
+                let variable = Variable {
+                    value: Value::Scalar(value.to_string()),
+                    exported: false,
+                };
+                self.vars.insert(name.to_string(), variable);
+            }
+        }
+    }
+
+    /// The elements of an array in order (a plain variable is an array of one element)
+    pub fn get_array(&self, name: &str) -> Option<Vec<&str>> {
+        Some(self.vars.get(name)?.value.values())
+    }
+
+    /// Makes a variable an array of these elements (indexed from 0)
+    pub fn set_array(&mut self, name: &str, elements: Vec<String>) {
+        self.set_value(
+            name,
+            Value::Indexed(elements.into_iter().enumerate().collect()),
+        );
+    }
+
+    /**
+        An element of an array by key; for indexed arrays the key is a number, where negative
+        ones count from the end. Element 0 of a plain variable is its value.
+    */
+    pub fn get_element(&self, name: &str, key: &str) -> Option<&str> {
+        match &self.vars.get(name)?.value {
+            Value::Associative(elements) => elements.get(key).map(String::as_str),
+            Value::Indexed(elements) => {
+                let index = array_index(elements, key)?;
+                elements.get(&index).map(String::as_str)
+            }
+            Value::Scalar(value) => (key.parse::<i64>().ok()? == 0).then_some(value.as_str()),
+        }
+    }
+
+    /// Sets an element of an array (a plain variable becomes an array first)
+    pub fn set_element(&mut self, name: &str, key: &str, value: &str) -> Result<(), String> {
+        if let Some(Variable {
+            value: Value::Associative(elements),
+            ..
+        }) = self.vars.get_mut(name)
+        {
+            elements.insert(key.to_string(), value.to_string());
+            return Ok(());
+        }
+
+        self.declare_array(name, false)?;
+        let Some(Variable {
+            value: Value::Indexed(elements),
+            ..
+        }) = self.vars.get_mut(name)
+        else {
+            unreachable!("declare_array makes an indexed array");
+        };
+        let index = array_index(elements, key)
+            .ok_or_else(|| format!("{}[{}]: bad array subscript", name, key))?;
+        elements.insert(index, value.to_string());
+        Ok(())
+    }
+
+    /**
+        Makes a variable an array (`declare -a` or `declare -A`), keeping its elements.
+        A plain value becomes element 0; indexed and associative arrays can't be converted.
+    */
+    pub fn declare_array(&mut self, name: &str, associative: bool) -> Result<(), String> {
+        let value = match self.vars.get(name).map(|variable| &variable.value) {
+            None if associative => Value::Associative(BTreeMap::new()),
+            None => Value::Indexed(BTreeMap::new()),
+            Some(Value::Scalar(value)) if associative => {
+                Value::Associative(BTreeMap::from([("0".to_string(), value.clone())]))
+            }
+            Some(Value::Scalar(value)) => Value::Indexed(BTreeMap::from([(0, value.clone())])),
+            Some(Value::Indexed(_)) if associative => {
+                return Err(format!(
+                    "{}: cannot convert indexed to associative array",
+                    name
+                ));
+            }
+            Some(Value::Associative(_)) if !associative => {
+                return Err(format!(
+                    "{}: cannot convert associative to indexed array",
+                    name
+                ));
+            }
+            Some(_) => return Ok(()),
+        };
+        self.set_value(name, value);
+        Ok(())
+    }
+
+    /// Replaces the value of a variable, keeping whether it is exported
+    pub fn set_value(&mut self, name: &str, value: Value) {
+        match self.vars.get_mut(name) {
+            Some(variable) => variable.value = value,
+            None => {
+                let variable = Variable {
+                    value,
+                    exported: false,
+                };
+                self.vars.insert(name.to_string(), variable);
+            }
+        }
+    }
+
+    pub fn unset_var(&mut self, name: &str) {
+        self.vars.remove(name);
+    }
+
+    /// The variables passed to the environment of commands
+    pub fn exported_vars(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.vars
+            .iter()
+            .filter(|(_, variable)| variable.exported)
+            // Arrays can't be passed on
+            .filter_map(|(name, variable)| match &variable.value {
+                Value::Scalar(value) => Some((name.as_str(), value.as_str())),
+                Value::Indexed(_) | Value::Associative(_) => None,
+            })
+    }
+
+    /// A path relative to the working directory of the shell (absolute paths stay as they are)
+    pub fn resolve_path(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.cwd.join(path)
+    }
+}
+
+/// The index a key of an indexed array refers to; negative keys count from the end
+fn array_index(elements: &BTreeMap<usize, String>, key: &str) -> Option<usize> {
+    let length = elements.keys().next_back().map_or(0, |last| last + 1);
+    resolve_index(key.parse().ok()?, length)
+}
+
+/// An index of an array with `length` (its last index + 1); negative ones count back from it
+pub fn resolve_index(index: i64, length: usize) -> Option<usize> {
+    if index >= 0 {
+        return usize::try_from(index).ok();
+    }
+    length.checked_sub(usize::try_from(index.unsigned_abs()).ok()?)
+}
+
+impl Default for Shell {
+    fn default() -> Shell {
+        Shell::new()
+    }
+}
+
+/// An unnamed temporary file (removed right away, it lives as long as it is open)
+pub fn temporary_file() -> io::Result<fs::File> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let name = format!(
+        "bettershell_{}_{}",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    );
+    let path = env::temp_dir().join(name);
+    let file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    fs::remove_file(&path)?;
+    Ok(file)
+}
+
+/**
+    Builds a shell for embedding, e.g.
+    `Shell::builder().option(ShellOption::Errexit, true).capture_output().build()`
+*/
+#[derive(Debug, Default)]
+pub struct ShellBuilder {
+    options: Options,
+    vars: Vec<(String, String, bool)>,
+    cwd: Option<PathBuf>,
+    clear_env: bool,
+    capture_output: bool,
+}
+
+impl ShellBuilder {
+    pub fn option(mut self, option: ShellOption, enabled: bool) -> ShellBuilder {
+        self.options.set(option, enabled);
+        self
+    }
+
+    /// A shell variable, not passed on to commands
+    pub fn var(mut self, name: &str, value: &str) -> ShellBuilder {
+        self.vars.push((name.to_string(), value.to_string(), false));
+        self
+    }
+
+    /// An exported variable, passed on to commands
+    pub fn env(mut self, name: &str, value: &str) -> ShellBuilder {
+        self.vars.push((name.to_string(), value.to_string(), true));
+        self
+    }
+
+    /// Starts without the environment of the process
+    pub fn clear_env(mut self) -> ShellBuilder {
+        self.clear_env = true;
+        self
+    }
+
+    /// The working directory (default: the one of the process)
+    pub fn cwd(mut self, cwd: impl Into<PathBuf>) -> ShellBuilder {
+        self.cwd = Some(cwd.into());
+        self
+    }
+
+    /// Collects stdout and stderr of commands in `Shell::output` instead of printing them
+    pub fn capture_output(mut self) -> ShellBuilder {
+        self.capture_output = true;
+        self
+    }
+
+    pub fn build(self) -> Shell {
+        let mut shell = Shell::new();
+        shell.options = self.options;
+        if self.clear_env {
+            shell.vars.clear();
+        }
+        for (name, value, exported) in self.vars {
+            let value = Value::Scalar(value);
+            shell.vars.insert(name, Variable { value, exported });
+        }
+        if let Some(cwd) = self.cwd {
+            shell.cwd = shell.resolve_path(cwd);
+            let pwd = shell.cwd.to_string_lossy().into_owned();
+            shell.set_var("PWD", &pwd);
+        }
+        if self.capture_output {
+            shell.output = Some(CapturedOutput::default());
+        }
+        shell
+    }
+}
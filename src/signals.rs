@@ -0,0 +1,206 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Signals are numbered below this on Linux (including the real-time ones)
+const SIGNAL_COUNT: usize = 65;
+
+/// Set by the signal handler, cleared when the trap runs
+static PENDING: [AtomicBool; SIGNAL_COUNT] = [const { AtomicBool::new(false) }; SIGNAL_COUNT];
+/// Signals with a handler installed by `trap`
+static CAUGHT: [AtomicBool; SIGNAL_COUNT] = [const { AtomicBool::new(false) }; SIGNAL_COUNT];
+/// Signals ignored with `trap '' SIGNAL` (these stay ignored in children)
+static IGNORED_BY_USER: [AtomicBool; SIGNAL_COUNT] =
+    [const { AtomicBool::new(false) }; SIGNAL_COUNT];
+/// Set once the shell ignores the interactive signals
+static INTERACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Signal numbers with their names and the descriptions shown when a job is killed
+const SIGNALS: &[(i32, &str, &str)] = &[
+    (libc::SIGHUP, "HUP", "Hangup"),
+    (libc::SIGINT, "INT", "Interrupt"),
+    (libc::SIGQUIT, "QUIT", "Quit"),
+    (libc::SIGILL, "ILL", "Illegal instruction"),
+    (libc::SIGTRAP, "TRAP", "Trace/breakpoint trap"),
+    (libc::SIGABRT, "ABRT", "Aborted"),
+    (libc::SIGBUS, "BUS", "Bus error"),
+    (libc::SIGFPE, "FPE", "Floating point exception"),
+    (libc::SIGKILL, "KILL", "Killed"),
+    (libc::SIGUSR1, "USR1", "User defined signal 1"),
+    (libc::SIGSEGV, "SEGV", "Segmentation fault"),
+    (libc::SIGUSR2, "USR2", "User defined signal 2"),
+    (libc::SIGPIPE, "PIPE", "Broken pipe"),
+    (libc::SIGALRM, "ALRM", "Alarm clock"),
+    (libc::SIGTERM, "TERM", "Terminated"),
+    (libc::SIGCHLD, "CHLD", "Child exited"),
+    (libc::SIGCONT, "CONT", "Continued"),
+    (libc::SIGSTOP, "STOP", "Stopped (signal)"),
+    (libc::SIGTSTP, "TSTP", "Stopped"),
+    (libc::SIGTTIN, "TTIN", "Stopped (tty input)"),
+    (libc::SIGTTOU, "TTOU", "Stopped (tty output)"),
+    (libc::SIGURG, "URG", "Urgent I/O condition"),
+    (libc::SIGXCPU, "XCPU", "CPU time limit exceeded"),
+    (libc::SIGXFSZ, "XFSZ", "File size limit exceeded"),
+    (libc::SIGVTALRM, "VTALRM", "Virtual timer expired"),
+    (libc::SIGPROF, "PROF", "Profiling timer expired"),
+    (libc::SIGWINCH, "WINCH", "Window changed"),
+    (libc::SIGIO, "IO", "I/O possible"),
+    (libc::SIGSYS, "SYS", "Bad system call"),
+];
+
+/// Signals the interactive shell ignores itself, but its children must not
+const IGNORED_BY_SHELL: &[i32] = &[libc::SIGINT, libc::SIGQUIT, libc::SIGTSTP];
+
+/// Signals whose disposition is reset to the default in every new child
+const RESET_IN_CHILD: &[i32] = &[
+    libc::SIGINT,
+    libc::SIGQUIT,
+    libc::SIGTSTP,
+    libc::SIGTTIN,
+    libc::SIGTTOU,
+    libc::SIGPIPE,
+];
+
+/**
+    Makes an interactive shell immune to Ctrl-C, Ctrl-\ and Ctrl-Z.
+    The signals still reach the foreground job, which runs with the default dispositions.
+*/
+pub fn ignore_interactive_signals() {
+    INTERACTIVE.store(true, Ordering::SeqCst);
+    for signal in IGNORED_BY_SHELL {
+        unsafe {
+            libc::signal(*signal, libc::SIG_IGN);
+        }
+    }
+}
+
+/**
+    Restores the default dispositions in a new child (async-signal-safe).
+    Signals ignored with `trap ''` stay ignored, caught ones get their default back.
+*/
+pub fn restore_default_signals() {
+    for signal in RESET_IN_CHILD {
+        if !IGNORED_BY_USER[*signal as usize].load(Ordering::Relaxed) {
+            unsafe {
+                libc::signal(*signal, libc::SIG_DFL);
+            }
+        }
+    }
+    for (signal, caught) in CAUGHT.iter().enumerate() {
+        if caught.swap(false, Ordering::Relaxed) {
+            unsafe {
+                libc::signal(signal as i32, libc::SIG_DFL);
+            }
+        }
+    }
+}
+
+extern "C" fn record_signal(signal: libc::c_int) {
+    if let Some(pending) = PENDING.get(signal as usize) {
+        pending.store(true, Ordering::SeqCst);
+    }
+}
+
+fn is_valid(signal: i32) -> bool {
+    signal > 0 && (signal as usize) < SIGNAL_COUNT
+}
+
+/// Installs a handler that marks the signal as pending (the trap runs later, at a safe point)
+pub fn catch_signal(signal: i32) {
+    if !is_valid(signal) {
+        return;
+    }
+    IGNORED_BY_USER[signal as usize].store(false, Ordering::SeqCst);
+    CAUGHT[signal as usize].store(true, Ordering::SeqCst);
+    let handler = record_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(signal, handler);
+    }
+}
+
+/// Ignores the signal in the shell and in its children
+pub fn ignore_signal(signal: i32) {
+    if !is_valid(signal) {
+        return;
+    }
+    CAUGHT[signal as usize].store(false, Ordering::SeqCst);
+    IGNORED_BY_USER[signal as usize].store(true, Ordering::SeqCst);
+    unsafe {
+        libc::signal(signal, libc::SIG_IGN);
+    }
+}
+
+/// Gives the signal back its disposition from when the shell started
+pub fn reset_signal(signal: i32) {
+    if !is_valid(signal) {
+        return;
+    }
+    CAUGHT[signal as usize].store(false, Ordering::SeqCst);
+    IGNORED_BY_USER[signal as usize].store(false, Ordering::SeqCst);
+    let shell_ignores = INTERACTIVE.load(Ordering::SeqCst) && IGNORED_BY_SHELL.contains(&signal);
+    unsafe {
+        libc::signal(
+            signal,
+            if shell_ignores {
+                libc::SIG_IGN
+            } else {
+                libc::SIG_DFL
+            },
+        );
+    }
+}
+
+/// Returns the signals that arrived since the last call (lowest number first)
+pub fn take_pending() -> Vec<i32> {
+    (1..SIGNAL_COUNT)
+        .filter(|signal| PENDING[*signal].swap(false, Ordering::SeqCst))
+        .map(|signal| signal as i32)
+        .collect()
+}
+
+/// All known signals as (number, name) pairs, e.g. `(2, "INT")`
+pub fn signal_names() -> impl Iterator<Item = (i32, &'static str)> {
+    SIGNALS.iter().map(|(number, name, _)| (*number, *name))
+}
+
+/// Parses a signal given as number or name (`INT`, `SIGINT` or `sigint`)
+pub fn signal_number(spec: &str) -> Option<i32> {
+    if let Ok(number) = spec.parse::<i32>() {
+        return is_valid(number).then_some(number);
+    }
+    let upper = spec.to_ascii_uppercase();
+    let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+    SIGNALS
+        .iter()
+        .find(|(_, other, _)| *other == name)
+        .map(|(number, _, _)| *number)
+}
+
+/// The name of a signal without the `SIG` prefix, e.g. `INT`
+pub fn signal_name(signal: i32) -> Option<&'static str> {
+    SIGNALS
+        .iter()
+        .find(|(number, _, _)| *number == signal)
+        .map(|(_, name, _)| *name)
+}
+
+/// A readable description of a signal, e.g. "Segmentation fault"
+pub fn signal_description(signal: i32) -> String {
+    match SIGNALS.iter().find(|(number, _, _)| *number == signal) {
+        Some((_, _, description)) => description.to_string(),
+        None => format!("Signal {}", signal),
+    }
+}
+
+/**
+    The message for a process killed by a signal, e.g. "Segmentation fault (core dumped)".
+    Returns `None` for signals that are reported silently (Ctrl-C and broken pipes).
+*/
+pub fn termination_message(signal: i32, core_dumped: bool) -> Option<String> {
+    if signal == libc::SIGINT || signal == libc::SIGPIPE {
+        return None;
+    }
+    let mut message = signal_description(signal);
+    if core_dumped {
+        message.push_str(" (core dumped)");
+    }
+    Some(message)
+}
@@ -0,0 +1,159 @@
+use crate::executor;
+use crate::shell::Shell;
+use crate::signals;
+use std::collections::BTreeMap;
+
+/// What a trap waits for: a real signal or one of the pseudo-signals
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Condition {
+    /// The shell exits
+    Exit,
+    Signal(i32),
+    /// Before every command
+    Debug,
+    /// A command failed
+    Err,
+}
+
+impl Condition {
+    /**
+        Parses `EXIT`, `ERR`, `DEBUG` or a signal (number or name, `0` is `EXIT`).
+        `RETURN` isn't supported: there are no functions or sourced scripts to return from yet.
+    */
+    pub fn parse(spec: &str) -> Option<Condition> {
+        match spec.to_ascii_uppercase().as_str() {
+            "0" | "EXIT" | "SIGEXIT" => Some(Condition::Exit),
+            "ERR" => Some(Condition::Err),
+            "DEBUG" => Some(Condition::Debug),
+            _ => signals::signal_number(spec).map(Condition::Signal),
+        }
+    }
+
+    /// The name `trap -p` shows, e.g. `SIGINT` or `EXIT`
+    pub fn name(&self) -> String {
+        match self {
+            Condition::Exit => "EXIT".to_string(),
+            Condition::Debug => "DEBUG".to_string(),
+            Condition::Err => "ERR".to_string(),
+            Condition::Signal(signal) => match signals::signal_name(*signal) {
+                Some(name) => format!("SIG{}", name),
+                None => signal.to_string(),
+            },
+        }
+    }
+}
+
+/// The commands set with `trap`, by condition
+#[derive(Debug, Clone, Default)]
+pub struct Traps {
+    actions: BTreeMap<Condition, String>,
+    /// Set while a trap action runs (no DEBUG or ERR traps inside of traps)
+    running: bool,
+}
+
+impl Traps {
+    /**
+        Sets the action for a condition: `None` resets it to the default,
+        an empty action ignores the signal.
+    */
+    pub fn set(&mut self, condition: Condition, action: Option<&str>) {
+        if let Condition::Signal(signal) = condition {
+            match action {
+                None => signals::reset_signal(signal),
+                Some("") => signals::ignore_signal(signal),
+                Some(_) => signals::catch_signal(signal),
+            }
+        }
+
+        match action {
+            Some(action) => {
+                self.actions.insert(condition, action.to_string());
+            }
+            None => {
+                self.actions.remove(&condition);
+            }
+        }
+    }
+
+    pub fn get(&self, condition: Condition) -> Option<String> {
+        self.actions.get(&condition).cloned()
+    }
+
+    /// All traps that are set, ordered like `trap -p` prints them
+    pub fn list(&self) -> Vec<(Condition, String)> {
+        self.actions
+            .iter()
+            .map(|(condition, action)| (*condition, action.clone()))
+            .collect()
+    }
+
+    /// Subshells keep ignored signals, but not the trap actions of their parent
+    pub fn reset_for_subshell(&mut self) {
+        self.actions.retain(|condition, action| {
+            matches!(condition, Condition::Signal(_)) && action.is_empty()
+        });
+    }
+}
+
+/**
+    Runs the action of a trap (if there is one), leaving `$?` as it was.
+    Returns whether an action ran.
+*/
+fn run_trap(condition: Condition, shell: &mut Shell) -> bool {
+    let Some(action) = shell.traps.get(condition) else {
+        return false;
+    };
+    if action.is_empty() {
+        return false;
+    }
+
+    let was_running = std::mem::replace(&mut shell.traps.running, true);
+    let status = shell.last_status;
+    executor::run_line(&action, shell);
+    shell.last_status = status;
+    shell.traps.running = was_running;
+    true
+}
+
+/// Runs the traps of signals that arrived since the last safe point
+pub fn run_pending(shell: &mut Shell) {
+    for signal in signals::take_pending() {
+        run_trap(Condition::Signal(signal), shell);
+    }
+}
+
+/// Runs the trap of a signal the shell noticed itself (like Ctrl-C at the prompt)
+pub fn run_signal_trap(signal: i32, shell: &mut Shell) -> bool {
+    run_trap(Condition::Signal(signal), shell)
+}
+
+/// Runs the DEBUG trap before a command
+pub fn run_debug_trap(shell: &mut Shell) {
+    if !shell.traps.running {
+        run_trap(Condition::Debug, shell);
+    }
+}
+
+/// Runs the ERR trap after a command failed
+pub fn run_err_trap(shell: &mut Shell) {
+    if !shell.traps.running {
+        run_trap(Condition::Err, shell);
+    }
+}
+
+/// Runs the EXIT trap (only once, even if it calls `exit` itself)
+pub fn run_exit_trap(shell: &mut Shell) {
+    let action = shell.traps.actions.remove(&Condition::Exit);
+    if let Some(action) = action
+        && !action.is_empty()
+    {
+        shell.traps.running = true;
+        executor::run_line(&action, shell);
+        shell.traps.running = false;
+    }
+}
+
+/// Quotes an action for `trap -p`, so the output can be used as input again
+pub fn quote(action: &str) -> String {
+    format!("'{}'", action.replace('\'', "'\\''"))
+}
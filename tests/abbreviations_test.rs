@@ -0,0 +1,144 @@
+// Tests for fish-style abbreviations: the abbr builtin and expansion in the line editor
+use bettershell::abbreviations::{Abbreviation, Abbreviations, Position};
+use bettershell::builtins::Io;
+use bettershell::commands::handle_abbr;
+use bettershell::history::History;
+use bettershell::line_editor::{Editor, KeyReader, ReadResult};
+use bettershell::shell::Shell;
+use std::io;
+
+/// Types `input` into an editor with the abbreviations; returns the line
+fn type_line(input: &str, abbreviations: &Abbreviations) -> String {
+    let history = History::new();
+    let mut output: Vec<u8> = Vec::new();
+    let result = Editor::new("$ ", &history)
+        .with_abbreviations(abbreviations)
+        .run(&mut KeyReader::new(input.as_bytes()), &mut output)
+        .unwrap();
+    match result {
+        ReadResult::Line(line) => line,
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+fn abbr(args: Vec<&str>, shell: &mut Shell) -> (i32, String, String) {
+    let mut stdout: Vec<u8> = Vec::new();
+    let mut stderr: Vec<u8> = Vec::new();
+    let result = handle_abbr(
+        args,
+        shell,
+        &mut Io::new(io::empty(), &mut stdout, &mut stderr),
+    );
+    let status = result.unwrap_or_else(|error| {
+        stderr.extend(format!("{}\n", error).bytes());
+        error.status()
+    });
+    (
+        status,
+        String::from_utf8(stdout).unwrap(),
+        String::from_utf8(stderr).unwrap(),
+    )
+}
+
+#[test]
+fn test_expansion_while_typing() {
+    let mut abbreviations = Abbreviations::default();
+    abbreviations.add(Abbreviation::new("gco", "git checkout"));
+    let mut anywhere = Abbreviation::new("L", "| less");
+    anywhere.position = Position::Anywhere;
+    abbreviations.add(anywhere);
+
+    // Space and Enter both expand
+    assert_eq!(type_line("gco main\r", &abbreviations), "git checkout main");
+    assert_eq!(type_line("gco\r", &abbreviations), "git checkout");
+    // Only in command position, unless it may be anywhere
+    assert_eq!(type_line("echo gco\r", &abbreviations), "echo gco");
+    assert_eq!(
+        type_line("ls; gco x\r", &abbreviations),
+        "ls; git checkout x"
+    );
+    assert_eq!(type_line("cat f L\r", &abbreviations), "cat f | less");
+    // Quoted words and words the cursor is inside of stay as they are
+    assert_eq!(type_line("\\gco \r", &abbreviations), "\\gco ");
+    assert_eq!(type_line("gcox\x1b[D \r", &abbreviations), "gco x");
+    assert_eq!(
+        type_line("gcoo\x7f\x01\x05 x\r", &abbreviations),
+        "git checkout x"
+    );
+}
+
+#[test]
+fn test_regex_triggers() {
+    let mut abbreviations = Abbreviations::default();
+    let mut numbered = Abbreviation::new("gN", "git checkout HEAD~");
+    numbered.set_regex("g[0-9]+").unwrap();
+    abbreviations.add(numbered);
+    abbreviations.add(Abbreviation::new("g1", "git one"));
+
+    // Names win over regexes, and the regex has to match the whole word
+    assert_eq!(abbreviations.expand("g2", true), Some("git checkout HEAD~"));
+    assert_eq!(abbreviations.expand("g1", true), Some("git one"));
+    assert_eq!(abbreviations.expand("g2x", true), None);
+    assert_eq!(abbreviations.expand("g2", false), None);
+    assert_eq!(type_line("g12 \r", &abbreviations), "git checkout HEAD~ ");
+}
+
+#[test]
+fn test_abbr_builtin() {
+    let mut shell = Shell::new();
+
+    assert_eq!(abbr(vec!["gco", "git", "checkout"], &mut shell).0, 0);
+    assert_eq!(
+        abbr(
+            vec![
+                "-a",
+                "--position",
+                "anywhere",
+                "--regex",
+                "\\.\\.+",
+                "dots",
+                "cd .."
+            ],
+            &mut shell
+        )
+        .0,
+        0
+    );
+    assert_eq!(shell.abbreviations.expand("...", false), Some("cd .."));
+    assert_eq!(
+        abbr(vec![], &mut shell).1,
+        "abbr -a -- gco 'git checkout'\nabbr -a --position anywhere --regex '\\.\\.+' -- dots 'cd ..'\n"
+    );
+    assert_eq!(abbr(vec!["--list"], &mut shell).1, "gco\ndots\n");
+    assert_eq!(abbr(vec!["-q", "gco"], &mut shell).0, 0);
+
+    assert_eq!(abbr(vec!["-e", "gco"], &mut shell).0, 0);
+    assert_eq!(abbr(vec!["-q", "gco"], &mut shell).0, 1);
+    assert_eq!(
+        abbr(vec!["-e", "gco"], &mut shell),
+        (
+            1,
+            String::new(),
+            "bettershell: abbr: gco: not found\n".to_string()
+        )
+    );
+    assert_eq!(
+        abbr(vec!["-p", "somewhere", "x", "y"], &mut shell),
+        (
+            2,
+            String::new(),
+            "abbr: somewhere: invalid position\n".to_string()
+        )
+    );
+    assert_eq!(
+        abbr(vec!["x"], &mut shell),
+        (
+            2,
+            String::new(),
+            "abbr: x: requires an expansion\n".to_string()
+        )
+    );
+    let (status, _, stderr) = abbr(vec!["-r", "(", "x", "y"], &mut shell);
+    assert_eq!(status, 1);
+    assert!(stderr.starts_with("abbr: (: invalid regex: "), "{}", stderr);
+}
@@ -0,0 +1,106 @@
+// Tests for aliases: the alias, unalias and command builtins, and expansion when running
+mod common;
+
+use bettershell::commands::{handle_alias, handle_command, handle_type, handle_unalias};
+use bettershell::shell::{CapturedOutput, Shell};
+use common::run_builtin;
+
+/// Runs commands in a shell capturing their output; returns the status and stdout
+fn run(shell: &mut Shell, commands: &str) -> (i32, String) {
+    let status = shell.run_str(commands).unwrap();
+    let CapturedOutput { stdout, .. } = shell.take_output();
+    (status, String::from_utf8(stdout).unwrap())
+}
+
+#[test]
+fn test_alias_builtin() {
+    let mut shell = Shell::new();
+
+    assert_eq!(
+        run_builtin(handle_alias, vec!["ll=ls -l", "q=it's"], &mut shell),
+        (0, String::new(), String::new())
+    );
+    assert_eq!(
+        run_builtin(handle_alias, vec![], &mut shell).1,
+        "alias ll='ls -l'\nalias q='it'\\''s'\n"
+    );
+    assert_eq!(
+        run_builtin(handle_alias, vec!["ll", "nope"], &mut shell),
+        (
+            1,
+            "alias ll='ls -l'\n".to_string(),
+            "bettershell: alias: nope: not found\n".to_string()
+        )
+    );
+    assert_eq!(
+        run_builtin(handle_alias, vec!["a/b=x"], &mut shell),
+        (
+            1,
+            String::new(),
+            "bettershell: alias: `a/b': invalid alias name\n".to_string()
+        )
+    );
+    assert_eq!(
+        run_builtin(handle_type, vec!["ll"], &mut shell).1,
+        "ll is aliased to `ls -l`\n"
+    );
+
+    assert_eq!(run_builtin(handle_unalias, vec!["ll"], &mut shell).0, 0);
+    assert_eq!(
+        run_builtin(handle_unalias, vec!["ll"], &mut shell),
+        (
+            1,
+            String::new(),
+            "bettershell: unalias: ll: not found\n".to_string()
+        )
+    );
+    assert_eq!(run_builtin(handle_unalias, vec!["-a"], &mut shell).0, 0);
+    assert!(shell.aliases.is_empty());
+}
+
+#[test]
+fn test_aliases_are_expanded() {
+    let mut shell = Shell::builder().capture_output().build();
+
+    // An alias applies from the next line on
+    let (status, stdout) = run(
+        &mut shell,
+        "alias greet='echo hello'\ngreet world\nalias say='echo ' you='to you'\nsay you",
+    );
+    assert_eq!(status, 0);
+    assert_eq!(stdout, "hello world\nto you\n");
+
+    // Recursion stops at the alias being expanded
+    let (_, stdout) = run(&mut shell, "alias echo='echo [x]'\necho a");
+    assert_eq!(stdout, "[x] a\n");
+
+    // Quoting the name and `command` skip aliases
+    let (_, stdout) = run(&mut shell, "\\echo b; 'echo' c; command echo d");
+    assert_eq!(stdout, "b\nc\nd\n");
+}
+
+#[test]
+fn test_command_builtin() {
+    let mut shell = Shell::new();
+    shell.aliases.insert("ll".to_string(), "ls -l".to_string());
+
+    assert_eq!(
+        run_builtin(handle_command, vec!["echo", "hi"], &mut shell),
+        (0, "hi\n".to_string(), String::new())
+    );
+    assert_eq!(
+        run_builtin(
+            handle_command,
+            vec!["-v", "ll", "cd", "bsh_nope"],
+            &mut shell
+        ),
+        (1, "alias ll='ls -l'\ncd\n".to_string(), String::new())
+    );
+    assert_eq!(
+        run_builtin(handle_command, vec!["-V", "cd"], &mut shell).1,
+        "cd is a shell builtin\n"
+    );
+    let (status, stdout, _) = run_builtin(handle_command, vec!["-v", "sh"], &mut shell);
+    assert_eq!(status, 0);
+    assert!(stdout.ends_with("/sh\n"), "{}", stdout);
+}
@@ -0,0 +1,188 @@
+// Tests for indexed and associative arrays: assignments, expansion and declare
+use bettershell::assignment::{self, AssignedValue};
+use bettershell::builtins::Io;
+use bettershell::commands::handle_declare;
+use bettershell::expand::{expand_single, expand_word};
+use bettershell::shell::{CapturedOutput, Shell};
+use std::io;
+
+/// Runs commands in a shell capturing their output; returns the status and stdout
+fn run(shell: &mut Shell, commands: &str) -> (i32, String) {
+    let status = shell.run_str(commands).unwrap();
+    let CapturedOutput { stdout, .. } = shell.take_output();
+    (status, String::from_utf8(stdout).unwrap())
+}
+
+fn declare(args: Vec<&str>, shell: &mut Shell) -> (i32, String, String) {
+    let mut stdout: Vec<u8> = Vec::new();
+    let mut stderr: Vec<u8> = Vec::new();
+    let result = handle_declare(
+        args,
+        shell,
+        &mut Io::new(io::empty(), &mut stdout, &mut stderr),
+    );
+    let status = result.unwrap_or_else(|error| {
+        stderr.extend(format!("{}\n", error).bytes());
+        error.status()
+    });
+    (
+        status,
+        String::from_utf8(stdout).unwrap(),
+        String::from_utf8(stderr).unwrap(),
+    )
+}
+
+/// A shell with `arr=(a 'b c' d)` and `map=([x]=1 [y]=2)`
+fn shell_with_arrays() -> Shell {
+    let mut shell = Shell::new();
+    shell.set_array("arr", vec!["a".into(), "b c".into(), "d".into()]);
+    shell.declare_array("map", true).unwrap();
+    shell.set_element("map", "x", "1").unwrap();
+    shell.set_element("map", "y", "2").unwrap();
+    shell
+}
+
+#[test]
+fn test_parse_assignments() {
+    let parsed = assignment::parse("arr[i+1]+=x").unwrap();
+    assert_eq!(parsed.name, "arr");
+    assert_eq!(parsed.subscript.as_deref(), Some("i+1"));
+    assert!(parsed.append);
+    assert_eq!(parsed.value, AssignedValue::Scalar("x".to_string()));
+
+    let parsed = assignment::parse("arr=(a 'b c' [5]=\"d e\")").unwrap();
+    assert_eq!(
+        parsed.value,
+        AssignedValue::Compound(vec![
+            "a".to_string(),
+            "'b c'".to_string(),
+            "[5]=\"d e\"".to_string()
+        ])
+    );
+    assert_eq!(
+        assignment::parse("x=(a)b").unwrap().value,
+        AssignedValue::Scalar("(a)b".to_string())
+    );
+
+    assert_eq!(assignment::parse("'x'=1"), None);
+    assert_eq!(assignment::parse("1x=1"), None);
+    assert_eq!(assignment::parse("x[1=1"), None);
+    assert_eq!(assignment::parse("echo"), None);
+}
+
+#[test]
+fn test_elements() {
+    let mut shell = shell_with_arrays();
+    assert_eq!(shell.get_element("arr", "-1"), Some("d"));
+    assert_eq!(shell.get_element("arr", "-4"), None);
+    assert_eq!(shell.get_var("arr"), Some("a"));
+
+    // A plain variable becomes element 0
+    shell.set_var("plain", "p");
+    shell.set_element("plain", "2", "q").unwrap();
+    assert_eq!(shell.get_array("plain"), Some(vec!["p", "q"]));
+    assert_eq!(
+        shell.set_element("plain", "-5", "x"),
+        Err("plain[-5]: bad array subscript".to_string())
+    );
+    assert_eq!(
+        shell.declare_array("arr", true),
+        Err("arr: cannot convert indexed to associative array".to_string())
+    );
+}
+
+#[test]
+fn test_expansion() {
+    let shell = shell_with_arrays();
+    let expand = |word: &str| expand_word(word, &shell).unwrap();
+
+    assert_eq!(expand("\"${arr[@]}\""), ["a", "b c", "d"]);
+    assert_eq!(expand("${arr[@]}"), ["a", "b", "c", "d"]);
+    assert_eq!(expand("\"${arr[*]}\""), ["a b c d"]);
+    assert_eq!(expand("\"x${arr[@]}y\""), ["xa", "b c", "dy"]);
+    assert_eq!(expand("${#arr[@]}/${#arr[1]}"), ["3/3"]);
+    assert_eq!(expand("${!arr[@]}"), ["0", "1", "2"]);
+    assert_eq!(expand("\"${arr[@]:1}\""), ["b c", "d"]);
+    assert_eq!(expand("\"${arr[@]: -1:1}\""), ["d"]);
+    assert_eq!(expand("${arr[1+1]}"), ["d"]);
+    assert_eq!(expand("\"${map[@]}\""), ["1", "2"]);
+    assert_eq!(expand("\"${!map[*]}\""), ["x y"]);
+    assert_eq!(expand("${map[y]}"), ["2"]);
+    // An empty array in quotes is no argument at all
+    assert_eq!(expand("\"${nothing[@]}\""), Vec::<String>::new());
+    assert_eq!(expand("\"${nothing[*]}\""), [""]);
+
+    assert_eq!(expand_single("${arr[@]}", &shell).unwrap(), "a b c d");
+    assert_eq!(expand_single("${arr[1]:1:2}", &shell).unwrap(), " c");
+}
+
+#[test]
+fn test_assignments() {
+    let mut shell = Shell::builder().capture_output().build();
+
+    let (status, stdout) = run(
+        &mut shell,
+        "arr=(one 'two three')\n\
+         arr+=(four)\n\
+         arr[10]=ten\n\
+         i=1\n\
+         arr[i]+=!\n\
+         str=ab\n\
+         str+=cd\n\
+         printf '<%s>' \"${arr[@]}\" \"${!arr[@]}\" $str",
+    );
+    assert_eq!(status, 0);
+    assert_eq!(stdout, "<one><two three!><four><ten><0><1><2><10><abcd>");
+
+    // Elements are split and globbed like arguments, unless they have a subscript
+    let (_, stdout) = run(
+        &mut shell,
+        "words='x y'\nlist=($words [5]=$words next)\nprintf '<%s>' \"${list[@]}\" \"${!list[@]}\"",
+    );
+    assert_eq!(stdout, "<x><y><x y><next><0><1><5><6>");
+
+    // Word boundaries survive on the way to programs
+    let (_, stdout) = run(&mut shell, "/usr/bin/printf '[%s]' \"${arr[@]:0:2}\"");
+    assert_eq!(stdout, "[one][two three!]");
+}
+
+#[test]
+fn test_declare() {
+    let mut shell = shell_with_arrays();
+
+    assert_eq!(
+        declare(vec!["-p", "arr", "map"], &mut shell),
+        (
+            0,
+            "declare -a arr=([0]='a' [1]='b c' [2]='d')\ndeclare -A map=([x]='1' [y]='2')\n"
+                .to_string(),
+            String::new()
+        )
+    );
+    assert_eq!(declare(vec!["-A", "m2=(['k 1']=v)"], &mut shell).0, 0);
+    assert_eq!(shell.get_element("m2", "k 1"), Some("v"));
+    assert_eq!(declare(vec!["-x", "plain=a b"], &mut shell).0, 0);
+    assert_eq!(
+        declare(vec!["-p", "plain"], &mut shell).1,
+        "declare -x plain='a b'\n"
+    );
+    assert_eq!(
+        declare(vec!["-a"], &mut shell).1,
+        "declare -a arr=([0]='a' [1]='b c' [2]='d')\n"
+    );
+
+    assert_eq!(
+        declare(vec!["-a", "map", "1x=2", "nope"], &mut shell),
+        (
+            1,
+            String::new(),
+            "bettershell: declare: map: cannot convert associative to indexed array\nbettershell: declare: `1x=2': not a valid identifier\n"
+                .to_string()
+        )
+    );
+    assert_eq!(
+        declare(vec!["-A", "m3=(v)"], &mut shell).2,
+        "bettershell: declare: m3: v: must use subscript when assigning associative array\n"
+    );
+    assert_eq!(declare(vec!["-q"], &mut shell).0, 2);
+}
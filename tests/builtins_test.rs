@@ -0,0 +1,108 @@
+// Tests for the builtin registry and registering builtins from outside the shell
+use bettershell::builtins::{Builtin, FunctionBuiltin, Io};
+use bettershell::error::{ExitStatus, ShellError};
+use bettershell::executor;
+use bettershell::shell::Shell;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+/// A builtin remembering the arguments it was called with
+struct Recorder {
+    calls: Mutex<Vec<Vec<String>>>,
+}
+
+impl Builtin for Recorder {
+    fn name(&self) -> &str {
+        "bsh_record"
+    }
+
+    fn help(&self) -> &str {
+        "bsh_record [arg ...]\n    Remembers its arguments."
+    }
+
+    fn run(&self, _: &mut Shell, args: &[&str], _: &mut Io) -> Result<ExitStatus, ShellError> {
+        let args = args.iter().map(|arg| arg.to_string()).collect();
+        self.calls.lock().unwrap().push(args);
+        Ok(3)
+    }
+}
+
+#[test]
+fn test_default_builtins_are_registered() {
+    let shell = Shell::new();
+    let names = shell.builtins.names();
+    for name in ["cd", "echo", "exit", "help", "jobs", "set", "trap", "type"] {
+        assert!(names.iter().any(|other| other == name), "{} missing", name);
+    }
+    assert!(shell.builtins.contains("pwd"));
+    assert!(!shell.builtins.contains("ls"));
+    assert!(
+        shell
+            .builtins
+            .lookup("cd")
+            .unwrap()
+            .help()
+            .starts_with("cd [-L|-P] [dir]")
+    );
+}
+
+#[test]
+fn test_registered_builtin_is_dispatched() {
+    let recorder = Arc::new(Recorder {
+        calls: Mutex::new(Vec::new()),
+    });
+    let mut shell = Shell::new();
+    shell.register_builtin(recorder.clone());
+    assert!(shell.builtins.contains("bsh_record"));
+    // Other shells don't have it
+    assert!(!Shell::new().builtins.contains("bsh_record"));
+
+    assert_eq!(
+        executor::run_line("bsh_record one 'two words'", &mut shell),
+        3
+    );
+    assert_eq!(
+        *recorder.calls.lock().unwrap(),
+        vec![vec!["one".to_string(), "two words".to_string()]]
+    );
+
+    assert_eq!(
+        executor::run_line("type -t bsh_record > /dev/null", &mut shell),
+        0
+    );
+
+    assert!(shell.unregister_builtin("bsh_record"));
+    assert!(!shell.builtins.contains("bsh_record"));
+    assert_eq!(
+        executor::run_line("type -t bsh_record 2> /dev/null", &mut shell),
+        1
+    );
+}
+
+#[test]
+fn test_function_builtin() {
+    let mut shell = Shell::new();
+    shell.register_builtin(Arc::new(FunctionBuiltin::new(
+        "bsh_fail",
+        "bsh_fail\n    Always fails.",
+        |_, _, _| Err(ShellError::usage("bsh_fail", "nope")),
+    )));
+    assert_eq!(executor::run_line("bsh_fail", &mut shell), 2);
+}
+
+#[test]
+fn test_help_builtin() {
+    let output = Command::new(env!("CARGO_BIN_EXE_bettershell"))
+        .args(["-c", "help type; help bsh_nope"])
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "type [-aptP] name [name ...]\n    Tells how each name would be interpreted as a command.\n"
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "bettershell: help: no help topics match `bsh_nope'\n"
+    );
+    assert_eq!(output.status.code(), Some(1));
+}
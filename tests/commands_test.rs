@@ -1,153 +1,392 @@
 // Direct unit tests for bettershell commands module
 // These tests directly invoke command functions from the bettershell library
+#![allow(clippy::len_zero, clippy::useless_vec)]
 
+mod common;
+
+use bettershell::shell::Shell;
+use common::capture;
 use std::env;
+use std::path::Path;
 
 #[test]
 fn test_handle_echo_single_word() {
     use bettershell::commands::handle_echo;
 
-    // Capture output by redirecting stdout
-    let args = vec!["hello"];
-    handle_echo(args);
-    // Output: "hello" should be printed
+    let (result, stdout, _) = capture(&mut Shell::new(), |_, io| handle_echo(vec!["hello"], io));
+    assert_eq!(result.unwrap(), 0);
+    assert_eq!(stdout, "hello\n");
 }
 
 #[test]
 fn test_handle_echo_multiple_words() {
     use bettershell::commands::handle_echo;
 
-    let args = vec!["hello", "world", "test"];
-    handle_echo(args);
-    // Output: "hello world test" should be printed
+    let (result, stdout, _) = capture(&mut Shell::new(), |_, io| {
+        handle_echo(vec!["hello", "world", "test"], io)
+    });
+    assert_eq!(result.unwrap(), 0);
+    assert_eq!(stdout, "hello world test\n");
 }
 
 #[test]
 fn test_handle_pwd() {
     use bettershell::commands::handle_print_working_directory;
 
-    handle_print_working_directory();
-    // Should print current working directory
+    let mut shell = Shell::new();
+    shell.cwd = "/usr".into();
+    let (result, stdout, _) = capture(&mut shell, |shell, io| {
+        handle_print_working_directory(vec![], shell, io)
+    });
+    assert_eq!(result.unwrap(), 0);
+    assert_eq!(stdout, "/usr\n");
 }
 
 #[test]
 fn test_handle_type_builtin_echo() {
     use bettershell::commands::handle_type;
 
-    let args = vec!["echo"];
-    handle_type(args);
-    // Should print "echo is a shell builtin"
+    let (result, stdout, _) = capture(&mut Shell::new(), |shell, io| {
+        handle_type(vec!["echo"], shell, io)
+    });
+    assert_eq!(result.unwrap(), 0);
+    assert_eq!(stdout, "echo is a shell builtin\n");
 }
 
 #[test]
 fn test_handle_type_builtin_exit() {
     use bettershell::commands::handle_type;
 
-    let args = vec!["exit"];
-    handle_type(args);
-    // Should print "exit is a shell builtin"
+    let (result, stdout, _) = capture(&mut Shell::new(), |shell, io| {
+        handle_type(vec!["exit"], shell, io)
+    });
+    assert_eq!(result.unwrap(), 0);
+    assert_eq!(stdout, "exit is a shell builtin\n");
 }
 
 #[test]
 fn test_handle_type_builtin_type() {
     use bettershell::commands::handle_type;
 
-    let args = vec!["type"];
-    handle_type(args);
-    // Should print "type is a shell builtin"
+    let (result, stdout, _) = capture(&mut Shell::new(), |shell, io| {
+        handle_type(vec!["type"], shell, io)
+    });
+    assert_eq!(result.unwrap(), 0);
+    assert_eq!(stdout, "type is a shell builtin\n");
 }
 
 #[test]
 fn test_handle_type_builtin_pwd() {
     use bettershell::commands::handle_type;
 
-    let args = vec!["pwd"];
-    handle_type(args);
-    // Should print "pwd is a shell builtin"
+    let (result, stdout, _) = capture(&mut Shell::new(), |shell, io| {
+        handle_type(vec!["pwd"], shell, io)
+    });
+    assert_eq!(result.unwrap(), 0);
+    assert_eq!(stdout, "pwd is a shell builtin\n");
 }
 
 #[test]
 fn test_handle_type_builtin_cd() {
     use bettershell::commands::handle_type;
 
-    let args = vec!["cd"];
-    handle_type(args);
-    // Should print "cd is a shell builtin"
+    let (result, stdout, _) = capture(&mut Shell::new(), |shell, io| {
+        handle_type(vec!["cd"], shell, io)
+    });
+    assert_eq!(result.unwrap(), 0);
+    assert_eq!(stdout, "cd is a shell builtin\n");
 }
 
 #[test]
 fn test_handle_type_external_command() {
     use bettershell::commands::handle_type;
 
-    let args = vec!["ls"];
-    handle_type(args);
-    // Should find ls in PATH or print "not found"
+    let (result, stdout, _) = capture(&mut Shell::new(), |shell, io| {
+        handle_type(vec!["ls"], shell, io)
+    });
+    assert_eq!(result.unwrap(), 0);
+    assert!(stdout.starts_with("ls is /"));
+    assert!(stdout.ends_with("/ls\n"));
 }
 
 #[test]
 fn test_handle_type_nonexistent_command() {
     use bettershell::commands::handle_type;
 
-    let args = vec!["nonexistentcommand123"];
-    handle_type(args);
-    // Should print "nonexistentcommand123: not found"
+    let (result, stdout, stderr) = capture(&mut Shell::new(), |shell, io| {
+        handle_type(vec!["nonexistentcommand123"], shell, io)
+    });
+    assert_eq!(result.unwrap(), 1);
+    assert_eq!(stdout, "");
+    assert_eq!(
+        stderr,
+        "bettershell: type: nonexistentcommand123: not found\n"
+    );
+}
+
+#[test]
+fn test_handle_type_flags() {
+    use bettershell::commands::handle_type;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    let base = env::temp_dir().join(format!("bsh_type_{}", std::process::id()));
+    for dir in ["one", "two"] {
+        fs::create_dir_all(base.join(dir)).unwrap();
+        let program = base.join(dir).join("echo");
+        fs::write(&program, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&program, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let mut shell = Shell::new();
+    let search_path = format!("{0}/one:{0}/two", base.display());
+    shell.set_var("PATH", &search_path);
+    shell.aliases.insert("ll".to_string(), "ls -l".to_string());
+    let mut run = |args: Vec<&str>| {
+        let (result, stdout, stderr) =
+            capture(&mut shell, |shell, io| handle_type(args, shell, io));
+        (result.unwrap(), stdout, stderr)
+    };
+
+    assert_eq!(
+        run(vec!["ll", "[[", "echo", "nope"]),
+        (
+            1,
+            "ll is aliased to `ls -l`\n[[ is a shell keyword\necho is a shell builtin\n"
+                .to_string(),
+            "bettershell: type: nope: not found\n".to_string()
+        )
+    );
+    assert_eq!(
+        run(vec!["-t", "ll", "[[", "echo", "nope"]),
+        (1, "alias\nkeyword\nbuiltin\n".to_string(), String::new())
+    );
+    assert_eq!(
+        run(vec!["-a", "echo"]).1,
+        format!(
+            "echo is a shell builtin\necho is {0}/one/echo\necho is {0}/two/echo\n",
+            base.display()
+        )
+    );
+    assert_eq!(run(vec!["-at", "echo"]).1, "builtin\nfile\nfile\n");
+    assert_eq!(run(vec!["-p", "echo"]), (0, String::new(), String::new()));
+    // Keywords the parser doesn't know yet aren't reported as keywords
+    assert_eq!(run(vec!["-t", "if"]).0, 1);
+    assert_eq!(
+        run(vec!["-P", "echo"]).1,
+        format!("{}/one/echo\n", base.display())
+    );
+    assert_eq!(
+        run(vec!["-pa", "echo"]).1,
+        format!("{0}/one/echo\n{0}/two/echo\n", base.display())
+    );
+    assert_eq!(run(vec!["-P", "cd"]), (1, String::new(), String::new()));
+    assert_eq!(run(vec![]), (0, String::new(), String::new()));
+}
+
+#[test]
+fn test_handle_type_invalid_option() {
+    use bettershell::commands::handle_type;
+
+    let (result, _, _) = capture(&mut Shell::new(), |shell, io| {
+        handle_type(vec!["-x"], shell, io)
+    });
+    let error = result.unwrap_err();
+    assert_eq!(error.to_string(), "type: -x: invalid option");
+    assert_eq!(error.status(), 2);
 }
 
 #[test]
 fn test_command_not_found() {
     use bettershell::commands::command_not_found;
 
-    command_not_found("nonexistentcommand123");
-    // Should print "nonexistentcommand123: command not found"
+    assert_eq!(
+        command_not_found("nonexistentcommand123").to_string(),
+        "nonexistentcommand123: command not found"
+    );
 }
 
 #[test]
 fn test_handle_cd_to_tmp() {
     use bettershell::commands::handle_cd;
 
-    let original_dir = env::current_dir().unwrap();
-
-    let args = vec!["/tmp"];
-    handle_cd(args);
-
-    let new_dir = env::current_dir().unwrap();
-    assert_eq!(new_dir.to_str().unwrap(), "/tmp");
-
-    // Restore original directory
-    env::set_current_dir(&original_dir).ok();
+    let mut shell = Shell::new();
+    let (result, _, _) = capture(&mut shell, |shell, io| handle_cd(vec!["/tmp"], shell, io));
+    assert_eq!(result.unwrap(), 0);
+    assert_eq!(shell.cwd, Path::new("/tmp"));
 }
 
 #[test]
 fn test_handle_cd_invalid_directory() {
     use bettershell::commands::handle_cd;
 
-    let original_dir = env::current_dir().unwrap();
+    let mut shell = Shell::new();
+    let original_dir = shell.cwd.clone();
 
-    let args = vec!["/nonexistent_directory_12345"];
-    handle_cd(args);
+    let (result, _, _) = capture(&mut shell, |shell, io| {
+        handle_cd(vec!["/nonexistent_directory_12345"], shell, io)
+    });
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "cd: /nonexistent_directory_12345: No such file or directory"
+    );
 
     // Should stay in same directory
-    let current_dir = env::current_dir().unwrap();
-    assert_eq!(current_dir, original_dir);
+    assert_eq!(shell.cwd, original_dir);
 }
 
 #[test]
 fn test_handle_cd_with_tilde() {
     use bettershell::commands::handle_cd;
 
-    let original_dir = env::current_dir().unwrap();
+    let mut shell = Shell::new();
+    let (result, _, _) = capture(&mut shell, |shell, io| handle_cd(vec!["~"], shell, io));
+    assert_eq!(result.unwrap(), 0);
+
+    if let Some(home) = shell.get_var("HOME") {
+        assert_eq!(shell.cwd, Path::new(home));
+    }
+}
 
-    let args = vec!["~"];
-    handle_cd(args);
+#[test]
+fn test_handle_cd_home_and_back() {
+    use bettershell::commands::handle_cd;
 
-    let current_dir = env::current_dir().unwrap();
+    let mut shell = Shell::new();
+    shell.cwd = "/usr".into();
+    shell.set_var("HOME", "/tmp");
+
+    let (result, stdout, _) = capture(&mut shell, |shell, io| handle_cd(vec![], shell, io));
+    assert_eq!(result.unwrap(), 0);
+    assert_eq!(stdout, "");
+    assert_eq!(shell.cwd, Path::new("/tmp"));
+    assert_eq!(shell.get_var("PWD"), Some("/tmp"));
+    assert_eq!(shell.get_var("OLDPWD"), Some("/usr"));
+
+    // `cd -` prints the directory it goes back to
+    let (result, stdout, _) = capture(&mut shell, |shell, io| handle_cd(vec!["-"], shell, io));
+    assert_eq!(result.unwrap(), 0);
+    assert_eq!(stdout, "/usr\n");
+    assert_eq!(shell.cwd, Path::new("/usr"));
+    assert_eq!(shell.get_var("OLDPWD"), Some("/tmp"));
+
+    shell.unset_var("HOME");
+    let (result, _, _) = capture(&mut shell, |shell, io| handle_cd(vec![], shell, io));
+    assert_eq!(result.unwrap_err().to_string(), "cd: HOME not set");
+    let (result, _, _) = capture(&mut shell, |shell, io| {
+        handle_cd(vec!["/", "/tmp"], shell, io)
+    });
+    assert_eq!(result.unwrap_err().to_string(), "cd: too many arguments");
+}
 
-    if let Some(home) = env::home_dir() {
-        assert_eq!(current_dir, home);
+#[test]
+fn test_handle_cd_cdpath() {
+    use bettershell::commands::handle_cd;
+    use std::fs;
+
+    let base = env::temp_dir().join(format!("bsh_cdpath_{}", std::process::id()));
+    fs::create_dir_all(base.join("projects/app")).unwrap();
+    fs::create_dir_all(base.join("app")).unwrap();
+    let mut shell = Shell::new();
+    shell.cwd = base.clone();
+    shell.set_var("CDPATH", &format!("{}/projects", base.display()));
+
+    // A directory found through CDPATH is printed
+    let (result, stdout, _) = capture(&mut shell, |shell, io| handle_cd(vec!["app"], shell, io));
+    assert_eq!(result.unwrap(), 0);
+    assert_eq!(stdout, format!("{}/projects/app\n", base.display()));
+    assert_eq!(shell.cwd, base.join("projects/app"));
+
+    // `./` skips CDPATH
+    shell.cwd = base.clone();
+    let (result, stdout, _) = capture(&mut shell, |shell, io| handle_cd(vec!["./app"], shell, io));
+    assert_eq!(result.unwrap(), 0);
+    assert_eq!(stdout, "");
+    assert_eq!(shell.cwd, base.join("app"));
+}
+
+#[test]
+fn test_handle_cd_logical_and_physical() {
+    use bettershell::commands::{handle_cd, handle_print_working_directory};
+    use std::fs;
+
+    let base = env::temp_dir()
+        .canonicalize()
+        .unwrap()
+        .join(format!("bsh_cd_links_{}", std::process::id()));
+    fs::create_dir_all(base.join("real/inner")).unwrap();
+    let link = base.join("link");
+    if !link.exists() {
+        std::os::unix::fs::symlink(base.join("real/inner"), &link).unwrap();
     }
+    let mut shell = Shell::new();
+    shell.cwd = base.clone();
+
+    // Logically, `..` goes back through the symlink
+    let (result, _, _) = capture(&mut shell, |shell, io| handle_cd(vec!["link"], shell, io));
+    assert_eq!(result.unwrap(), 0);
+    assert_eq!(shell.cwd, link);
+    let (_, stdout, _) = capture(&mut shell, |shell, io| {
+        handle_print_working_directory(vec!["-P"], shell, io)
+    });
+    assert_eq!(stdout, format!("{}/real/inner\n", base.display()));
+    let (result, _, _) = capture(&mut shell, |shell, io| handle_cd(vec![".."], shell, io));
+    assert_eq!(result.unwrap(), 0);
+    assert_eq!(shell.cwd, base);
+
+    // Physically, the symlink is resolved first
+    let (result, _, _) = capture(&mut shell, |shell, io| {
+        handle_cd(vec!["-P", "link"], shell, io)
+    });
+    assert_eq!(result.unwrap(), 0);
+    assert_eq!(shell.cwd, base.join("real/inner"));
+    let (_, stdout, _) = capture(&mut shell, |shell, io| {
+        handle_print_working_directory(vec!["-L"], shell, io)
+    });
+    assert_eq!(stdout, format!("{}/real/inner\n", base.display()));
+    let (result, _, _) = capture(&mut shell, |shell, io| handle_cd(vec![".."], shell, io));
+    assert_eq!(result.unwrap(), 0);
+    assert_eq!(shell.cwd, base.join("real"));
+
+    // With -P, `..` after a symlink is the parent of its target
+    shell.cwd = base.clone();
+    let (result, _, _) = capture(&mut shell, |shell, io| {
+        handle_cd(vec!["-P", "link/.."], shell, io)
+    });
+    assert_eq!(result.unwrap(), 0);
+    assert_eq!(shell.cwd, base.join("real"));
+    let (result, _, _) = capture(&mut shell, |shell, io| {
+        handle_cd(vec!["-L", "../link/.."], shell, io)
+    });
+    assert_eq!(result.unwrap(), 0);
+    assert_eq!(shell.cwd, base);
+}
 
-    // Restore original directory
-    env::set_current_dir(&original_dir).ok();
+#[test]
+fn test_handle_cd_needs_search_permission() {
+    use bettershell::commands::handle_cd;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    // root may search any directory
+    if unsafe { libc::geteuid() } == 0 {
+        return;
+    }
+    let locked = env::temp_dir().join(format!("bsh_cd_locked_{}", std::process::id()));
+    fs::create_dir_all(&locked).unwrap();
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o600)).unwrap();
+    let mut shell = Shell::new();
+    let before = shell.cwd.clone();
+
+    let target = locked.display().to_string();
+    let (result, _, _) = capture(&mut shell, |shell, io| handle_cd(vec![&target], shell, io));
+    let error = result.unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        format!("cd: {}: Permission denied", target)
+    );
+    assert_eq!(error.status(), 1);
+    assert_eq!(shell.cwd, before);
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o700)).unwrap();
 }
 
 #[test]
@@ -156,8 +395,11 @@ fn test_execute_external_program_not_found() {
 
     let command = "nonexistentprogram12345";
     let args = vec![];
-    execute_external_program(command, args);
-    // Should print command not found message
+    assert_eq!(
+        execute_external_program(command, args, &mut Shell::new()),
+        127
+    );
+    // Should report "nonexistentprogram12345: command not found" on stderr
 }
 
 #[cfg(test)]
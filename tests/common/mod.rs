@@ -0,0 +1,67 @@
+//! Helpers shared by the integration tests (not every test file uses all of them)
+#![allow(dead_code)]
+
+use bettershell::builtins::Io;
+use bettershell::error::{ExitStatus, ShellError};
+use bettershell::shell::Shell;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// Runs a builtin in a shell with its output captured; returns its result, stdout and stderr
+pub fn capture(
+    shell: &mut Shell,
+    builtin: impl FnOnce(&mut Shell, &mut Io) -> Result<ExitStatus, ShellError>,
+) -> (Result<ExitStatus, ShellError>, String, String) {
+    let mut stdout: Vec<u8> = Vec::new();
+    let mut stderr: Vec<u8> = Vec::new();
+    let result = builtin(shell, &mut Io::new(io::empty(), &mut stdout, &mut stderr));
+    (
+        result,
+        String::from_utf8(stdout).unwrap(),
+        String::from_utf8(stderr).unwrap(),
+    )
+}
+
+/// Like `capture`, but an error is reported on stderr; returns the status, stdout and stderr
+pub fn run_builtin(
+    builtin: fn(Vec<&str>, &mut Shell, &mut Io) -> Result<ExitStatus, ShellError>,
+    args: Vec<&str>,
+    shell: &mut Shell,
+) -> (i32, String, String) {
+    let (result, stdout, mut stderr) = capture(shell, |shell, io| builtin(args, shell, io));
+    let status = result.unwrap_or_else(|error| {
+        stderr.push_str(&format!("{}\n", error));
+        error.status()
+    });
+    (status, stdout, stderr)
+}
+
+/// A path in a temporary directory of its own for every test binary
+pub fn temp_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "bettershell_{}_{}",
+        env!("CARGO_CRATE_NAME"),
+        std::process::id()
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+/// Runs the bettershell binary with the given arguments
+pub fn bettershell(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_bettershell"))
+        .args(args)
+        .env_remove("PS4")
+        .output()
+        .unwrap()
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
@@ -0,0 +1,178 @@
+// Tests for conditional expressions: the test and [ builtins and the [[ ]] keyword
+use bettershell::commands::{handle_left_bracket, handle_test};
+use bettershell::conditional;
+use bettershell::shell::{CapturedOutput, Shell};
+use std::fs;
+
+/// Runs commands in a shell capturing their output; returns the status, stdout and stderr
+fn run(shell: &mut Shell, commands: &str) -> (i32, String, String) {
+    let status = shell.run_str(commands).unwrap();
+    let CapturedOutput { stdout, stderr } = shell.take_output();
+    (
+        status,
+        String::from_utf8(stdout).unwrap(),
+        String::from_utf8(stderr).unwrap(),
+    )
+}
+
+/// The status of `test args...`, with the message of an error
+fn test(args: &[&str], shell: &mut Shell) -> Result<i32, String> {
+    handle_test(args.to_vec(), shell).map_err(|error| error.to_string())
+}
+
+#[test]
+fn test_strings_and_integers() {
+    let mut shell = Shell::new();
+
+    assert_eq!(test(&[], &mut shell), Ok(1));
+    assert_eq!(test(&["x"], &mut shell), Ok(0));
+    assert_eq!(test(&[""], &mut shell), Ok(1));
+    // One argument is a string even if it looks like an operator
+    assert_eq!(test(&["-n"], &mut shell), Ok(0));
+    assert_eq!(test(&["-z", ""], &mut shell), Ok(0));
+    assert_eq!(test(&["!", "-n", "x"], &mut shell), Ok(1));
+    assert_eq!(test(&["!", "="], &mut shell), Ok(1));
+    assert_eq!(test(&["a", "!=", "b"], &mut shell), Ok(0));
+    assert_eq!(test(&["abc", "<", "abd"], &mut shell), Ok(0));
+    assert_eq!(test(&[" 10", "-gt", "9"], &mut shell), Ok(0));
+    assert_eq!(test(&["-3", "-le", "-3"], &mut shell), Ok(0));
+    assert_eq!(test(&["(", "x", ")"], &mut shell), Ok(0));
+    assert_eq!(test(&["(", "!", "x", ")"], &mut shell), Ok(1));
+
+    assert_eq!(
+        test(&["a", "-eq", "1"], &mut shell),
+        Err("test: a: integer expression expected".to_string())
+    );
+    assert_eq!(
+        test(&["-q", "x"], &mut shell),
+        Err("test: -q: unary operator expected".to_string())
+    );
+    assert_eq!(
+        test(&["a", "b", "c"], &mut shell),
+        Err("test: b: binary operator expected".to_string())
+    );
+    assert_eq!(
+        handle_test(vec!["-n", "x", "-eq"], &mut shell)
+            .unwrap_err()
+            .status(),
+        2
+    );
+}
+
+#[test]
+fn test_connectives_and_parentheses() {
+    let shell = Shell::new();
+    let evaluate = |args: &[&str]| conditional::test(args, &shell);
+
+    assert_eq!(evaluate(&["a", "=", "a", "-a", "b", "=", "c"]), Ok(false));
+    assert_eq!(evaluate(&["a", "=", "a", "-o", "b", "=", "c"]), Ok(true));
+    // -a binds tighter than -o
+    assert_eq!(evaluate(&["x", "-o", "x", "-a", ""]), Ok(true));
+    assert_eq!(
+        evaluate(&["!", "(", "a", "=", "b", "-o", "-z", "x", ")"]),
+        Ok(true)
+    );
+    assert_eq!(
+        evaluate(&["(", "a", "=", "a", "b"]),
+        Err("`)' expected".to_string())
+    );
+    assert_eq!(
+        evaluate(&["a", "=", "a", "b", "c"]),
+        Err("too many arguments".to_string())
+    );
+}
+
+#[test]
+fn test_file_operators() {
+    let directory = std::env::temp_dir().join(format!("bsh_test_files_{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join("full"), "x").unwrap();
+    fs::write(directory.join("empty"), "").unwrap();
+    std::os::unix::fs::symlink("full", directory.join("link")).unwrap();
+
+    let mut shell = Shell::new();
+    shell.cwd = directory.clone();
+    // Relative names are looked up in the shell's directory
+    assert_eq!(test(&["-f", "full"], &mut shell), Ok(0));
+    assert_eq!(test(&["-d", "full"], &mut shell), Ok(1));
+    assert_eq!(test(&["-d", "."], &mut shell), Ok(0));
+    assert_eq!(test(&["-s", "full"], &mut shell), Ok(0));
+    assert_eq!(test(&["-s", "empty"], &mut shell), Ok(1));
+    assert_eq!(test(&["-e", "missing"], &mut shell), Ok(1));
+    assert_eq!(test(&["-e", ""], &mut shell), Ok(1));
+    assert_eq!(test(&["-h", "link"], &mut shell), Ok(0));
+    assert_eq!(test(&["-L", "full"], &mut shell), Ok(1));
+    assert_eq!(test(&["-r", "full"], &mut shell), Ok(0));
+    assert_eq!(test(&["-x", "full"], &mut shell), Ok(1));
+    assert_eq!(test(&["-O", "full"], &mut shell), Ok(0));
+    assert_eq!(test(&["link", "-ef", "full"], &mut shell), Ok(0));
+    assert_eq!(test(&["full", "-nt", "missing"], &mut shell), Ok(0));
+    assert_eq!(test(&["missing", "-ot", "full"], &mut shell), Ok(0));
+
+    assert_eq!(
+        handle_left_bracket(vec!["-f", "full", "]"], &mut shell).unwrap(),
+        0
+    );
+    assert_eq!(
+        handle_left_bracket(vec!["-f", "full"], &mut shell)
+            .unwrap_err()
+            .to_string(),
+        "[: missing `]'"
+    );
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_double_brackets() {
+    let mut shell = Shell::builder().capture_output().build();
+
+    // The right side of == is a pattern unless quoted
+    let (status, stdout, _) = run(
+        &mut shell,
+        "printf -v pattern 'a*'\n\
+         [[ abc == a* ]] && echo 1\n\
+         [[ abc == \"a*\" ]] || echo 2\n\
+         [[ abc == $pattern && ! abc != a?c ]] && echo 3\n\
+         [[ a < b && b > a ]] && echo 4\n\
+         [[ -z x || ( 1 -eq 1 && -n x ) ]] && echo 5",
+    );
+    assert_eq!(status, 0);
+    assert_eq!(stdout, "1\n2\n3\n4\n5\n");
+
+    // < and > compare instead of redirecting
+    let (_, stdout, _) = run(&mut shell, "[[ b > a ]]; echo $?; [[ b < a ]]; echo $?");
+    assert_eq!(stdout, "0\n1\n");
+
+    let (status, stdout, stderr) = run(&mut shell, "[[ 1 -eq one ]]\n[[ a == ]]");
+    assert_eq!(status, 2);
+    assert_eq!(stdout, "");
+    assert!(
+        stderr.contains("one: integer expression expected"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_regex_matches() {
+    let mut shell = Shell::builder().capture_output().build();
+
+    let (status, _, _) = run(
+        &mut shell,
+        "[[ key=value2 =~ ^([a-z]+)=(x|value)([0-9])?$ ]]",
+    );
+    assert_eq!(status, 0);
+    assert_eq!(
+        shell.get_array("BASH_REMATCH"),
+        Some(vec!["key=value2", "key", "value", "2"])
+    );
+    assert_eq!(shell.get_var("BASH_REMATCH"), Some("key=value2"));
+
+    // Quoted parts match literally
+    let (status, _, _) = run(&mut shell, "[[ a.c =~ ^a\".\"c$ ]] && [[ abc =~ ^a'.'c$ ]]");
+    assert_eq!(status, 1);
+    assert_eq!(shell.get_array("BASH_REMATCH"), Some(vec![]));
+
+    let (status, _, _) = run(&mut shell, "[[ x =~ '(' ]] || [[ x =~ ( ]]");
+    assert_eq!(status, 2);
+}
@@ -0,0 +1,236 @@
+// Tests for the directory stack: pushd, popd and dirs
+mod common;
+
+use bettershell::commands::{handle_dirs, handle_popd, handle_pushd};
+use bettershell::dir_stack::{self, DirStack};
+use bettershell::shell::Shell;
+use common::run_builtin;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A temporary directory with the subdirectories `a`, `b` and `c`
+fn temp_dirs(name: &str) -> PathBuf {
+    let dir = env::temp_dir().canonicalize().unwrap().join(format!(
+        "bettershell_dirs_{}_{}",
+        name,
+        std::process::id()
+    ));
+    for sub in ["a", "b", "c"] {
+        fs::create_dir_all(dir.join(sub)).unwrap();
+    }
+    dir
+}
+
+/// A shell in `base`, with `$HOME` there too so `dirs` shows it as `~`
+fn shell_in(base: &Path) -> Shell {
+    let mut shell = Shell::new();
+    shell.cwd = base.to_path_buf();
+    shell.set_var("HOME", &base.display().to_string());
+    shell
+}
+
+#[test]
+fn test_pushd_and_popd() {
+    let base = temp_dirs("push_pop");
+    let mut shell = shell_in(&base);
+
+    assert_eq!(
+        run_builtin(handle_pushd, vec!["a"], &mut shell),
+        (0, "~/a ~\n".to_string(), String::new())
+    );
+    assert_eq!(shell.cwd, base.join("a"));
+    assert_eq!(shell.get_var("OLDPWD"), Some(base.to_str().unwrap()));
+    assert_eq!(
+        run_builtin(handle_pushd, vec!["../b"], &mut shell).1,
+        "~/b ~/a ~\n"
+    );
+
+    // Without arguments, the top two directories are swapped
+    assert_eq!(
+        run_builtin(handle_pushd, vec![], &mut shell).1,
+        "~/a ~/b ~\n"
+    );
+    assert_eq!(shell.cwd, base.join("a"));
+
+    assert_eq!(run_builtin(handle_popd, vec![], &mut shell).1, "~/b ~\n");
+    assert_eq!(shell.cwd, base.join("b"));
+    assert_eq!(run_builtin(handle_popd, vec![], &mut shell).1, "~\n");
+    assert_eq!(shell.cwd, base);
+    assert_eq!(
+        run_builtin(handle_popd, vec![], &mut shell),
+        (
+            1,
+            String::new(),
+            "popd: directory stack empty\n".to_string()
+        )
+    );
+    assert_eq!(
+        run_builtin(handle_pushd, vec![], &mut shell),
+        (1, String::new(), "pushd: no other directory\n".to_string())
+    );
+
+    // A failed pushd leaves everything as it was
+    assert_eq!(
+        run_builtin(handle_pushd, vec!["missing"], &mut shell),
+        (
+            1,
+            String::new(),
+            "pushd: missing: No such file or directory\n".to_string()
+        )
+    );
+    assert_eq!(shell.cwd, base);
+    assert!(shell.dir_stack.entries().is_empty());
+}
+
+#[test]
+fn test_rotation_and_removal() {
+    let base = temp_dirs("rotate");
+    let mut shell = shell_in(&base);
+    for dir in ["a", "b", "c"] {
+        run_builtin(
+            handle_pushd,
+            vec![&format!("{}/{}", base.display(), dir)],
+            &mut shell,
+        );
+    }
+    assert_eq!(
+        run_builtin(handle_dirs, vec![], &mut shell).1,
+        "~/c ~/b ~/a ~\n"
+    );
+
+    assert_eq!(
+        run_builtin(handle_pushd, vec!["+2"], &mut shell).1,
+        "~/a ~ ~/c ~/b\n"
+    );
+    assert_eq!(shell.cwd, base.join("a"));
+    assert_eq!(
+        run_builtin(handle_pushd, vec!["-0"], &mut shell).1,
+        "~/b ~/a ~ ~/c\n"
+    );
+    assert_eq!(shell.cwd, base.join("b"));
+
+    // -n only rotates the directories below the working directory
+    assert_eq!(
+        run_builtin(handle_pushd, vec!["-n", "+2"], &mut shell).1,
+        "~/b ~ ~/c ~/a\n"
+    );
+    assert_eq!(shell.cwd, base.join("b"));
+
+    assert_eq!(
+        run_builtin(handle_popd, vec!["+1"], &mut shell).1,
+        "~/b ~/c ~/a\n"
+    );
+    assert_eq!(
+        run_builtin(handle_popd, vec!["-0"], &mut shell).1,
+        "~/b ~/c\n"
+    );
+    assert_eq!(run_builtin(handle_popd, vec!["-n"], &mut shell).1, "~/b\n");
+    assert_eq!(shell.cwd, base.join("b"));
+
+    assert_eq!(
+        run_builtin(handle_pushd, vec!["+3"], &mut shell),
+        (
+            1,
+            String::new(),
+            "pushd: +3: directory stack index out of range\n".to_string()
+        )
+    );
+    assert_eq!(
+        run_builtin(handle_popd, vec!["-x"], &mut shell),
+        (2, String::new(), "popd: -x: invalid option\n".to_string())
+    );
+}
+
+#[test]
+fn test_pushd_without_changing_directory() {
+    let base = temp_dirs("no_cd");
+    let mut shell = shell_in(&base);
+
+    assert_eq!(
+        run_builtin(handle_pushd, vec!["-n", "a"], &mut shell).1,
+        "~ ~/a\n"
+    );
+    assert_eq!(
+        run_builtin(handle_pushd, vec!["-n", "b"], &mut shell).1,
+        "~ ~/b ~/a\n"
+    );
+    assert_eq!(shell.cwd, base);
+}
+
+#[test]
+fn test_dirs_formats() {
+    let base = temp_dirs("formats");
+    let mut shell = shell_in(&base);
+    run_builtin(handle_pushd, vec!["a"], &mut shell);
+    run_builtin(handle_pushd, vec!["/"], &mut shell);
+
+    assert_eq!(
+        run_builtin(handle_dirs, vec!["-p"], &mut shell).1,
+        "/\n~/a\n~\n"
+    );
+    assert_eq!(
+        run_builtin(handle_dirs, vec!["-v"], &mut shell).1,
+        " 0  /\n 1  ~/a\n 2  ~\n"
+    );
+    assert_eq!(
+        run_builtin(handle_dirs, vec!["-l"], &mut shell).1,
+        format!("/ {}/a {}\n", base.display(), base.display())
+    );
+    assert_eq!(run_builtin(handle_dirs, vec!["+1"], &mut shell).1, "~/a\n");
+    assert_eq!(run_builtin(handle_dirs, vec!["-0"], &mut shell).1, "~\n");
+    assert_eq!(
+        run_builtin(handle_dirs, vec!["-lv", "-1"], &mut shell).1,
+        format!(" 1  {}/a\n", base.display())
+    );
+
+    assert_eq!(
+        run_builtin(handle_dirs, vec!["-c"], &mut shell),
+        (0, String::new(), String::new())
+    );
+    assert_eq!(run_builtin(handle_dirs, vec![], &mut shell).1, "/\n");
+}
+
+#[test]
+fn test_abbreviate_home() {
+    assert_eq!(
+        dir_stack::abbreviate_home(Path::new("/home/me"), Some("/home/me")),
+        "~"
+    );
+    assert_eq!(
+        dir_stack::abbreviate_home(Path::new("/home/me/src"), Some("/home/me/")),
+        "~/src"
+    );
+    assert_eq!(
+        dir_stack::abbreviate_home(Path::new("/home/meow"), Some("/home/me")),
+        "/home/meow"
+    );
+    assert_eq!(
+        dir_stack::abbreviate_home(Path::new("/home/me"), None),
+        "/home/me"
+    );
+}
+
+#[test]
+fn test_persisted_stack() {
+    let base = temp_dirs("persist");
+    let file = base.join("state/dirs");
+    let _ = fs::remove_file(&file);
+    let mut shell = shell_in(&base);
+    shell.set_var("BSH_DIRSTACK_FILE", &file.display().to_string());
+
+    run_builtin(handle_pushd, vec!["a"], &mut shell);
+    run_builtin(handle_pushd, vec!["../b"], &mut shell);
+    assert_eq!(
+        fs::read_to_string(&file).unwrap(),
+        format!("{0}/b\n{0}/a\n{0}\n", base.display())
+    );
+
+    // A new session starts with the whole stack below its working directory
+    fs::remove_dir(base.join("a")).unwrap();
+    let mut stack = DirStack::default();
+    let file = file.display().to_string();
+    stack.configure(|name| (name == "BSH_DIRSTACK_FILE").then_some(file.as_str()));
+    stack.load().unwrap();
+    assert_eq!(stack.entries(), [base.join("b"), base.clone()]);
+}
@@ -0,0 +1,92 @@
+// Tests for shell errors: their messages, exit statuses and where diagnostics are printed
+mod common;
+
+use bettershell::error::{self, Location, ShellError};
+use common::{bettershell, stderr, stdout};
+use std::fs;
+use std::io;
+
+#[test]
+fn test_messages_and_statuses() {
+    let error = ShellError::CommandNotFound("nope".to_string());
+    assert_eq!(error.to_string(), "nope: command not found");
+    assert_eq!(error.status(), 127);
+
+    let error = ShellError::builtin("cd", "/nope: No such file or directory");
+    assert_eq!(error.to_string(), "cd: /nope: No such file or directory");
+    assert_eq!(error.status(), 1);
+
+    assert_eq!(ShellError::usage("set", "-q: invalid option").status(), 2);
+    assert_eq!(ShellError::Parse("syntax error".to_string()).status(), 2);
+
+    let not_found = io::Error::from_raw_os_error(libc::ENOENT);
+    let error = ShellError::redirection("/nope/file", &not_found);
+    assert_eq!(error.to_string(), "/nope/file: No such file or directory");
+    assert_eq!(error.status(), 1);
+    assert_eq!(ShellError::spawn("prog", &not_found).status(), 126);
+}
+
+#[test]
+fn test_io_message_drops_os_error_code() {
+    let error = io::Error::from_raw_os_error(libc::EACCES);
+    assert_eq!(error::io_message(&error), "Permission denied");
+}
+
+#[test]
+fn test_report_names_the_location() {
+    let error = ShellError::CommandNotFound("nope".to_string());
+    let mut stderr: Vec<u8> = Vec::new();
+    error::report_to(&mut stderr, &error, None);
+    let location = Location {
+        script: "script.sh".to_string(),
+        line: 3,
+    };
+    error::report_to(&mut stderr, &error, Some(&location));
+    assert_eq!(
+        String::from_utf8(stderr).unwrap(),
+        "bettershell: nope: command not found\n\
+         bettershell: script.sh: line 3: nope: command not found\n"
+    );
+}
+
+#[test]
+fn test_diagnostics_go_to_stderr() {
+    let output = bettershell(&["-c", "cd /bsh_nope; bsh_no_such_command; echo done"]);
+    assert_eq!(stdout(&output), "done\n");
+    assert_eq!(
+        stderr(&output),
+        "bettershell: cd: /bsh_nope: No such file or directory\n\
+         bettershell: bsh_no_such_command: command not found\n"
+    );
+
+    let output = bettershell(&["-c", "bsh_no_such_command"]);
+    assert_eq!(output.status.code(), Some(127));
+
+    let output = bettershell(&["-c", "echo x > /bsh_nope/file"]);
+    assert_eq!(
+        stderr(&output),
+        "bettershell: /bsh_nope/file: No such file or directory\n"
+    );
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_script_errors_name_the_line() {
+    let path = std::env::temp_dir().join(format!("bsh_error_test_{}.sh", std::process::id()));
+    fs::write(&path, "echo one\nbsh_no_such_command\necho 'unterminated\n").unwrap();
+    let script = path.to_str().unwrap();
+
+    let output = bettershell(&[script]);
+    assert_eq!(stdout(&output), "one\n");
+    assert_eq!(
+        stderr(&output),
+        format!(
+            "bettershell: {0}: line 2: bsh_no_such_command: command not found\n\
+             bettershell: {0}: line 3: unexpected EOF while looking for matching `''\n",
+            script
+        )
+    );
+    assert_eq!(output.status.code(), Some(2));
+
+    fs::remove_file(&path).ok();
+}
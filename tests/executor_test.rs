@@ -0,0 +1,173 @@
+// Tests for running command lines: lists, pipelines, redirections and background jobs
+mod common;
+
+use bettershell::executor::run_line;
+use bettershell::shell::Shell;
+use common::temp_path;
+use std::fs;
+
+fn run(line: &str) -> i32 {
+    let mut shell = Shell::new();
+    run_line(line, &mut shell)
+}
+
+#[test]
+fn test_exit_status_of_external_commands() {
+    assert_eq!(run("true"), 0);
+    assert_eq!(run("false"), 1);
+    assert_eq!(run("sh -c 'exit 7'"), 7);
+}
+
+#[test]
+fn test_command_not_found_status() {
+    assert_eq!(run("bettershell_no_such_command_xyz"), 127);
+}
+
+#[test]
+fn test_and_or_lists() {
+    let path = temp_path("and_or");
+    let line = format!(
+        "false && sh -c 'echo no >> {0}' || sh -c 'echo yes >> {0}'; true || sh -c 'echo no >> {0}'",
+        path.display()
+    );
+    assert_eq!(run(&line), 0);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "yes\n");
+}
+
+#[test]
+fn test_output_redirection_and_append() {
+    let path = temp_path("redirect");
+    run(&format!("printf 'one\\n' > {}", path.display()));
+    run(&format!("printf 'two\\n' >> {}", path.display()));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+}
+
+#[test]
+fn test_stderr_redirection() {
+    let path = temp_path("stderr");
+    run(&format!(
+        "sh -c 'echo out; echo err >&2' &> {}",
+        path.display()
+    ));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "out\nerr\n");
+
+    run(&format!("sh -c 'echo err >&2' 2> {}", path.display()));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "err\n");
+}
+
+#[test]
+fn test_input_redirection_and_pipeline() {
+    let input = temp_path("pipeline_in");
+    let output = temp_path("pipeline_out");
+    fs::write(&input, "b\na\nc\na\n").unwrap();
+    let status = run(&format!(
+        "sort < {} | uniq | wc -l > {}",
+        input.display(),
+        output.display()
+    ));
+    assert_eq!(status, 0);
+    assert_eq!(fs::read_to_string(&output).unwrap().trim(), "3");
+}
+
+#[test]
+fn test_pipeline_status_is_last_command() {
+    assert_eq!(run("false | true"), 0);
+    assert_eq!(run("true | false"), 1);
+}
+
+#[test]
+fn test_missing_input_file() {
+    assert_eq!(run("cat < /nonexistent/bettershell/file"), 1);
+}
+
+#[test]
+fn test_quoted_executable_and_status_variable() {
+    let path = temp_path("quoted");
+    run(&format!(
+        "'sh' -c 'exit 3'; sh -c \"echo $? > {}\"",
+        path.display()
+    ));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "3\n");
+}
+
+#[test]
+fn test_background_job_and_wait() {
+    let mut shell = Shell::new();
+    let path = temp_path("background");
+    let status = run_line(
+        &format!("sh -c 'sleep 0.1; echo bg > {}' &", path.display()),
+        &mut shell,
+    );
+    assert_eq!(status, 0);
+
+    let pid = shell.jobs.last_background_pid().expect("$! should be set");
+    assert!(shell.jobs.job_of_pid(pid).is_some());
+
+    assert_eq!(run_line(&format!("wait {}", pid), &mut shell), 0);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "bg\n");
+    assert!(shell.jobs.job_of_pid(pid).is_none());
+}
+
+#[test]
+fn test_wait_returns_job_status() {
+    let mut shell = Shell::new();
+    run_line("sh -c 'exit 5' &", &mut shell);
+    let pid = shell.jobs.last_background_pid().unwrap();
+    assert_eq!(run_line(&format!("wait {}", pid), &mut shell), 5);
+    assert_eq!(shell.last_status, 5);
+}
+
+#[test]
+fn test_wait_for_unknown_pid() {
+    assert_eq!(run("wait 999999"), 127);
+}
+
+#[test]
+fn test_syntax_error_status() {
+    assert_eq!(run("echo |"), 2);
+}
+
+#[test]
+fn test_process_substitution() {
+    let path = temp_path("process_substitution");
+    let line = format!(
+        "diff <(printf 'a\\nb\\n') <(printf 'a\\nc\\n') > {0}; cat <(echo in) >> {0}",
+        path.display()
+    );
+    assert_eq!(run(&line), 0);
+    let output = fs::read_to_string(&path).unwrap();
+    assert!(output.contains("< b\n---\n> c\n"), "{}", output);
+    assert!(output.ends_with("in\n"));
+
+    // `>(cmd)` reads what the program writes; the helper is done once the line is
+    run(&format!(
+        "echo out | tee >(tr a-z A-Z > {}) > /dev/null",
+        path.display()
+    ));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "OUT\n");
+
+    // As a redirection target, for a builtin in the shell
+    let mut shell = Shell::new();
+    run_line("read line < <(echo from helper)", &mut shell);
+    assert_eq!(shell.get_var("line"), Some("from helper"));
+}
+
+#[test]
+fn test_prefix_assignments() {
+    let mut shell = Shell::new();
+    shell.set_var("IFS", " ");
+    assert_eq!(run_line("IFS=: read a b <<< 'x:y z'", &mut shell), 0);
+    assert_eq!(shell.get_var("a"), Some("x"));
+    assert_eq!(shell.get_var("b"), Some("y z"));
+    assert_eq!(shell.get_var("IFS"), Some(" "));
+
+    // Programs get them in their environment, and they are gone afterwards
+    let path = temp_path("prefix_assignments");
+    let line = format!(
+        "X=1 Y=$X sh -c 'echo $X $Y' > {0}; X=2 echo $X | cat >> {0}",
+        path.display()
+    );
+    assert_eq!(run_line(&line, &mut shell), 0);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "1 1\n\n");
+    assert_eq!(shell.get_var("X"), None);
+}
@@ -0,0 +1,144 @@
+// Tests for word expansion (tilde, parameters, field splitting and quote removal)
+use bettershell::expand::{expand_single, expand_word, expand_words};
+use bettershell::options::ShellOption;
+use bettershell::shell::Shell;
+use std::fs;
+
+fn expand(word: &str) -> Vec<String> {
+    expand_word(word, &Shell::new()).unwrap()
+}
+
+#[test]
+fn test_quote_removal() {
+    assert_eq!(expand("'hello world'"), ["hello world"]);
+    assert_eq!(expand("\"a\"'b'c"), ["abc"]);
+    assert_eq!(expand("\"\""), [""]);
+}
+
+#[test]
+fn test_backslashes() {
+    assert_eq!(expand("a\\ b"), ["a b"]);
+    assert_eq!(expand("'a\\nb'"), ["a\\nb"]);
+    assert_eq!(expand("\"a\\\"b\\n\""), ["a\"b\\n"]);
+}
+
+#[test]
+fn test_variable_expansion() {
+    let mut shell = Shell::new();
+    shell.set_var("BSH_TEST_EXPAND", "one  two");
+    let expand = |word: &str| expand_word(word, &shell).unwrap();
+    assert_eq!(expand("$BSH_TEST_EXPAND"), ["one", "two"]);
+    assert_eq!(expand("\"$BSH_TEST_EXPAND\""), ["one  two"]);
+    assert_eq!(expand("${BSH_TEST_EXPAND}x"), ["one", "twox"]);
+    assert_eq!(expand("'$BSH_TEST_EXPAND'"), ["$BSH_TEST_EXPAND"]);
+    assert_eq!(
+        expand_single("$BSH_TEST_EXPAND", &shell).unwrap(),
+        "one  two"
+    );
+}
+
+#[test]
+fn test_unset_variable_disappears() {
+    assert!(expand("$BSH_TEST_UNSET_VARIABLE").is_empty());
+    assert_eq!(
+        expand_words(
+            &["echo".to_string(), "$BSH_TEST_UNSET_VARIABLE".to_string()],
+            &Shell::new()
+        )
+        .unwrap(),
+        ["echo"]
+    );
+}
+
+#[test]
+fn test_default_values() {
+    let mut shell = Shell::builder()
+        .var("BSH_EMPTY", "")
+        .var("BSH_SET", "v")
+        .option(ShellOption::Nounset, true)
+        .build();
+    let expand = |word: &str| expand_word(word, &shell);
+    assert_eq!(expand("${BSH_NOPE:-a b}").unwrap(), ["a", "b"]);
+    assert_eq!(expand("\"${BSH_NOPE-a b}\"").unwrap(), ["a b"]);
+    assert_eq!(
+        expand("${BSH_EMPTY-unset}${BSH_EMPTY:-null}").unwrap(),
+        ["null"]
+    );
+    assert_eq!(expand("${BSH_SET:-$BSH_NOPE}").unwrap(), ["v"]);
+    assert_eq!(expand("${BSH_NOPE:-${BSH_SET}x}").unwrap(), ["vx"]);
+    assert_eq!(
+        expand("${BSH_SET:+alt}${BSH_EMPTY+s}${BSH_EMPTY:+t}").unwrap(),
+        ["alts"]
+    );
+    assert_eq!(expand("${BSH_SET:?}").unwrap(), ["v"]);
+    assert_eq!(
+        expand("${BSH_EMPTY:?}").unwrap_err(),
+        "BSH_EMPTY: parameter null or not set"
+    );
+    assert_eq!(
+        expand("${BSH_NOPE?not $BSH_SET}").unwrap_err(),
+        "BSH_NOPE: not v"
+    );
+
+    // Assigning defaults and other operators aren't supported, which is an error
+    assert_eq!(
+        expand("${BSH_NOPE:=x}").unwrap_err(),
+        "${BSH_NOPE:=x}: bad substitution"
+    );
+    assert_eq!(
+        expand("${BSH_SET%v}").unwrap_err(),
+        "${BSH_SET%v}: bad substitution"
+    );
+    shell.set_var("BSH_SET", "w");
+    assert_eq!(expand_word("${BSH_SET:-x}", &shell).unwrap(), ["w"]);
+}
+
+#[test]
+fn test_lone_dollar_is_literal() {
+    assert_eq!(expand("$"), ["$"]);
+    assert_eq!(expand("a$ b"), ["a$ b"]);
+}
+
+#[test]
+fn test_tilde_expansion() {
+    let home = std::env::var("HOME").unwrap_or_default();
+    assert_eq!(expand("~/x"), [format!("{}/x", home)]);
+    assert_eq!(expand("'~'"), ["~"]);
+    assert_eq!(expand("a~"), ["a~"]);
+}
+
+#[test]
+fn test_pathname_expansion() {
+    let dir = std::env::temp_dir().join(format!("bettershell_glob_{}", std::process::id()));
+    fs::create_dir_all(dir.join("sub")).unwrap();
+    for name in ["a.txt", "b.txt", "c.rs", ".hidden.txt", "sub/d.txt"] {
+        fs::write(dir.join(name), "").unwrap();
+    }
+    let base = dir.display().to_string();
+
+    assert_eq!(
+        expand(&format!("{}/*.txt", base)),
+        [format!("{}/a.txt", base), format!("{}/b.txt", base)]
+    );
+    assert_eq!(
+        expand(&format!("{}/[ab].t?t", base)),
+        [format!("{}/a.txt", base), format!("{}/b.txt", base)]
+    );
+    assert_eq!(
+        expand(&format!("{}/*/*.txt", base)),
+        [format!("{}/sub/d.txt", base)]
+    );
+    assert_eq!(
+        expand(&format!("{}/.h*", base)),
+        [format!("{}/.hidden.txt", base)]
+    );
+    // Quoted wildcards and patterns without matches stay as they are
+    assert_eq!(
+        expand(&format!("'{}/*.txt'", base)),
+        [format!("{}/*.txt", base)]
+    );
+    assert_eq!(
+        expand(&format!("{}/*.none", base)),
+        [format!("{}/*.none", base)]
+    );
+}
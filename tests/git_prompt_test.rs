@@ -0,0 +1,182 @@
+// Tests for the git prompt segment
+// Repositories are faked by writing the files inside .git by hand
+
+use bettershell::git_prompt::*;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+fn fake_repo(name: &str, head: &str) -> PathBuf {
+    let root = env::temp_dir().join(format!("bsh_git_prompt_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join(".git/refs/heads")).unwrap();
+    fs::write(root.join(".git/HEAD"), head).unwrap();
+    root
+}
+
+fn clean_status(head: GitHead) -> GitStatus {
+    GitStatus {
+        head,
+        ahead: 0,
+        behind: 0,
+        staged: false,
+        dirty: false,
+        untracked: false,
+    }
+}
+
+#[test]
+fn test_discover_from_subdirectory() {
+    let root = fake_repo("discover", "ref: refs/heads/main\n");
+    fs::create_dir_all(root.join("src/nested")).unwrap();
+
+    let repo = GitRepo::discover(&root.join("src/nested")).unwrap();
+    assert_eq!(repo.worktree, root);
+    assert_eq!(repo.git_dir, root.join(".git"));
+
+    fs::remove_dir_all(&root).ok();
+}
+
+#[test]
+fn test_discover_outside_repo() {
+    assert_eq!(GitRepo::discover(std::path::Path::new("/")), None);
+}
+
+#[test]
+fn test_head_branch() {
+    let root = fake_repo("branch", "ref: refs/heads/feature/prompt\n");
+
+    let repo = GitRepo::discover(&root).unwrap();
+    assert_eq!(
+        repo.head(),
+        Some(GitHead::Branch("feature/prompt".to_string()))
+    );
+
+    fs::remove_dir_all(&root).ok();
+}
+
+#[test]
+fn test_head_detached() {
+    let root = fake_repo("detached", "8b9a893aa0c1d6e0f2b6f1c0c4c43fe1a1f0e2d1\n");
+
+    let repo = GitRepo::discover(&root).unwrap();
+    assert_eq!(repo.head(), Some(GitHead::Detached("8b9a893".to_string())));
+
+    fs::remove_dir_all(&root).ok();
+}
+
+#[test]
+fn test_resolve_loose_and_packed_refs() {
+    let root = fake_repo("refs", "ref: refs/heads/main\n");
+    fs::write(
+        root.join(".git/refs/heads/main"),
+        "1111111111111111111111111111111111111111\n",
+    )
+    .unwrap();
+    fs::write(
+        root.join(".git/packed-refs"),
+        "# pack-refs with: peeled fully-peeled sorted\n\
+         2222222222222222222222222222222222222222 refs/remotes/origin/main\n\
+         ^3333333333333333333333333333333333333333\n",
+    )
+    .unwrap();
+
+    let repo = GitRepo::discover(&root).unwrap();
+    assert_eq!(
+        repo.resolve_ref("refs/heads/main").as_deref(),
+        Some("1111111111111111111111111111111111111111")
+    );
+    assert_eq!(
+        repo.resolve_ref("refs/remotes/origin/main").as_deref(),
+        Some("2222222222222222222222222222222222222222")
+    );
+    assert_eq!(repo.resolve_ref("refs/heads/missing"), None);
+
+    fs::remove_dir_all(&root).ok();
+}
+
+#[test]
+fn test_upstream_from_config() {
+    let root = fake_repo("upstream", "ref: refs/heads/main\n");
+    fs::write(
+        root.join(".git/config"),
+        "[core]\n\tbare = false\n[branch \"main\"]\n\tremote = origin\n\tmerge = refs/heads/main\n[branch \"local\"]\n\tremote = .\n\tmerge = refs/heads/main\n",
+    )
+    .unwrap();
+
+    let repo = GitRepo::discover(&root).unwrap();
+    assert_eq!(
+        repo.upstream_ref("main").as_deref(),
+        Some("refs/remotes/origin/main")
+    );
+    assert_eq!(
+        repo.upstream_ref("local").as_deref(),
+        Some("refs/heads/main")
+    );
+    assert_eq!(repo.upstream_ref("other"), None);
+
+    fs::remove_dir_all(&root).ok();
+}
+
+#[test]
+fn test_read_status_without_git_output() {
+    // The fake repo has no objects, so git fails and only the HEAD info is left
+    let root = fake_repo("status", "ref: refs/heads/main\n");
+
+    let status = read_status(&root, Duration::from_millis(500)).unwrap();
+    assert_eq!(status, clean_status(GitHead::Branch("main".to_string())));
+
+    fs::remove_dir_all(&root).ok();
+}
+
+#[test]
+fn test_parse_porcelain_v2() {
+    let output = "# branch.oid 1111111111111111111111111111111111111111\n\
+                  # branch.head main\n\
+                  # branch.upstream origin/main\n\
+                  # branch.ab +2 -1\n\
+                  1 M. N... 100644 100644 100644 aaaa bbbb src/main.rs\n\
+                  1 .M N... 100644 100644 100644 aaaa bbbb src/lib.rs\n\
+                  ? notes.txt\n";
+
+    let mut status = clean_status(GitHead::Detached("1111111".to_string()));
+    parse_porcelain_v2(output, &mut status);
+
+    assert_eq!(status.head, GitHead::Branch("main".to_string()));
+    assert_eq!((status.ahead, status.behind), (2, 1));
+    assert!(status.staged);
+    assert!(status.dirty);
+    assert!(status.untracked);
+}
+
+#[test]
+fn test_format_segment() {
+    let mut status = clean_status(GitHead::Branch("main".to_string()));
+    assert_eq!(status.format_segment(), "(main)");
+
+    status.ahead = 1;
+    status.behind = 2;
+    status.staged = true;
+    status.dirty = true;
+    status.untracked = true;
+    assert_eq!(status.format_segment(), "(main ↑1↓2 +*?)");
+
+    let detached = clean_status(GitHead::Detached("abc1234".to_string()));
+    assert_eq!(detached.format_segment(), "(detached abc1234)");
+}
+
+#[test]
+fn test_settings() {
+    assert!(!is_enabled(None));
+    assert!(is_enabled(Some("1")));
+    assert!(is_enabled(Some("on")));
+    assert!(!is_enabled(Some("0")));
+
+    assert_eq!(timeout(None), Duration::from_millis(DEFAULT_TIMEOUT_MS));
+    assert_eq!(timeout(Some("50")), Duration::from_millis(50));
+    assert_eq!(
+        timeout(Some("soon")),
+        Duration::from_millis(DEFAULT_TIMEOUT_MS)
+    );
+}
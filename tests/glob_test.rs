@@ -0,0 +1,37 @@
+// Tests for shell pattern matching
+use bettershell::glob::{escape, has_wildcards, matches};
+
+#[test]
+fn test_star_and_question_mark() {
+    assert!(matches("*", "anything"));
+    assert!(matches("*", ""));
+    assert!(matches("a*c", "abbbc"));
+    assert!(matches("a*b*c", "axxbyyc"));
+    assert!(!matches("a*c", "abcd"));
+    assert!(matches("?.rs", "a.rs"));
+    assert!(!matches("?.rs", "ab.rs"));
+}
+
+#[test]
+fn test_bracket_expressions() {
+    assert!(matches("[abc]x", "bx"));
+    assert!(!matches("[abc]x", "dx"));
+    assert!(matches("[a-z]1", "q1"));
+    assert!(matches("[!a-z]", "Q"));
+    assert!(matches("[^a-z]", "1"));
+    assert!(!matches("[!a-z]", "q"));
+    assert!(matches("[]]", "]"));
+    // An unclosed bracket is literal
+    assert!(matches("[abc", "[abc"));
+}
+
+#[test]
+fn test_escaping() {
+    assert!(matches("\\*", "*"));
+    assert!(!matches("\\*", "a"));
+    assert_eq!(escape("a*b?[c]"), "a\\*b\\?\\[c\\]");
+    assert!(matches(&escape("a*b"), "a*b"));
+    assert!(has_wildcards("*.rs"));
+    assert!(!has_wildcards("\\*.rs"));
+    assert!(!has_wildcards("plain"));
+}
@@ -0,0 +1,161 @@
+// Tests for the table of commands found in PATH and the hash builtin
+mod common;
+
+use bettershell::commands::{handle_hash, handle_type};
+use bettershell::executor::run_line;
+use bettershell::hash::CommandHash;
+use bettershell::shell::Shell;
+use common::run_builtin;
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("bettershell_hash_{}_{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes a script printing `text` into `dir`
+fn write_program(dir: &Path, name: &str, text: &str) -> PathBuf {
+    let path = dir.join(name);
+    fs::write(
+        &path,
+        format!("#!/bin/sh\necho {} >> {}/out\n", text, dir.display()),
+    )
+    .unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+#[test]
+fn test_resolve_remembers_commands() {
+    let dir = temp_dir("resolve");
+    let program = write_program(&dir, "bsh_prog", "x");
+    let search_path = format!("/nonexistent:{}", dir.display());
+    let mut hash = CommandHash::default();
+
+    assert_eq!(
+        hash.resolve("bsh_prog", &search_path, &dir),
+        Some((program.clone(), false))
+    );
+    assert_eq!(
+        hash.resolve("bsh_prog", &search_path, &dir),
+        Some((program.clone(), true))
+    );
+    assert_eq!(hash.get("bsh_prog", &search_path).unwrap().hits, 2);
+    assert_eq!(hash.resolve("bsh_nope", &search_path, &dir), None);
+
+    // A different PATH starts over
+    assert_eq!(hash.get("bsh_prog", "/nonexistent"), None);
+    assert_eq!(hash.resolve("bsh_prog", "/nonexistent", &dir), None);
+}
+
+#[test]
+fn test_relative_directories_are_not_remembered() {
+    let dir = temp_dir("relative");
+    write_program(&dir, "bsh_prog", "x");
+    let mut hash = CommandHash::default();
+
+    assert_eq!(
+        hash.resolve("bsh_prog", ".", &dir),
+        Some((PathBuf::from("./bsh_prog"), false))
+    );
+    assert_eq!(hash.get("bsh_prog", "."), None);
+}
+
+#[test]
+fn test_moved_program_is_searched_again() {
+    let first = temp_dir("moved_first");
+    let second = temp_dir("moved_second");
+    let _ = fs::remove_file(second.join("out"));
+    let program = write_program(&first, "bsh_moving", "first");
+    let mut shell = Shell::new();
+    shell.set_var(
+        "PATH",
+        &format!("{}:{}:/usr/bin:/bin", first.display(), second.display()),
+    );
+
+    assert_eq!(run_line("bsh_moving", &mut shell), 0);
+    fs::remove_file(&program).unwrap();
+    write_program(&second, "bsh_moving", "second");
+
+    assert_eq!(run_line("bsh_moving", &mut shell), 0);
+    assert_eq!(fs::read_to_string(second.join("out")).unwrap(), "second\n");
+    let search_path = shell.get_var("PATH").unwrap().to_string();
+    assert_eq!(
+        shell.hash.get("bsh_moving", &search_path).unwrap().path,
+        second.join("bsh_moving")
+    );
+}
+
+#[test]
+fn test_hash_builtin() {
+    let dir = temp_dir("builtin");
+    let program = write_program(&dir, "bsh_hashed", "x");
+    let mut shell = Shell::new();
+    shell.set_var("PATH", &dir.display().to_string());
+
+    assert_eq!(
+        run_builtin(handle_hash, vec![], &mut shell),
+        (0, "hash: hash table empty\n".to_string(), String::new())
+    );
+
+    assert_eq!(
+        run_builtin(handle_hash, vec!["bsh_hashed"], &mut shell).0,
+        0
+    );
+    assert_eq!(
+        run_builtin(handle_hash, vec![], &mut shell).1,
+        format!("hits\tcommand\n   0\t{}\n", program.display())
+    );
+    assert_eq!(
+        run_builtin(handle_type, vec!["bsh_hashed"], &mut shell).1,
+        format!("bsh_hashed is hashed ({})\n", program.display())
+    );
+
+    assert_eq!(
+        run_builtin(handle_hash, vec!["-p", "/bin/echo", "bsh_echo"], &mut shell).0,
+        0
+    );
+    assert_eq!(
+        shell
+            .hash
+            .get("bsh_echo", &dir.display().to_string())
+            .unwrap()
+            .path,
+        Path::new("/bin/echo")
+    );
+
+    assert_eq!(
+        run_builtin(handle_hash, vec!["-d", "bsh_echo"], &mut shell).0,
+        0
+    );
+    assert_eq!(
+        run_builtin(handle_hash, vec!["-d", "bsh_echo"], &mut shell),
+        (
+            1,
+            String::new(),
+            "bettershell: hash: bsh_echo: not found\n".to_string()
+        )
+    );
+    assert_eq!(
+        run_builtin(handle_hash, vec!["bsh_nope"], &mut shell),
+        (
+            1,
+            String::new(),
+            "bettershell: hash: bsh_nope: not found\n".to_string()
+        )
+    );
+
+    assert_eq!(run_builtin(handle_hash, vec!["-r"], &mut shell).0, 0);
+    assert_eq!(
+        run_builtin(handle_hash, vec![], &mut shell).1,
+        "hash: hash table empty\n"
+    );
+    assert_eq!(
+        run_builtin(handle_hash, vec!["-x"], &mut shell),
+        (2, String::new(), "hash: -x: invalid option\n".to_string())
+    );
+}
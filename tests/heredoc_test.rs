@@ -0,0 +1,84 @@
+// Tests for here-documents and here-strings
+use bettershell::expand::expand_here_document;
+use bettershell::parser;
+use bettershell::shell::{CapturedOutput, Shell};
+
+/// Runs commands in a shell capturing their output; returns the status and stdout
+fn run(commands: &str) -> (i32, String) {
+    let mut shell = Shell::builder()
+        .var("name", "world")
+        .capture_output()
+        .build();
+    let status = shell.run_str(commands).unwrap();
+    let CapturedOutput { stdout, .. } = shell.take_output();
+    (status, String::from_utf8(stdout).unwrap())
+}
+
+#[test]
+fn test_here_document_is_expanded() {
+    let (status, stdout) =
+        run("cat <<EOF\nhello $name\n\"${name}\" \\$name \\\\ \\a\nEOF\necho done");
+    assert_eq!(status, 0);
+    assert_eq!(stdout, "hello world\n\"world\" $name \\ \\a\ndone\n");
+}
+
+#[test]
+fn test_quoted_delimiter_disables_expansion() {
+    let (_, stdout) = run("cat <<'EOF'\n$name \\$x\nEOF\ncat <<\"E\"\\N\n$name\nEN\n");
+    assert_eq!(stdout, "$name \\$x\n$name\n");
+}
+
+#[test]
+fn test_here_document_strips_tabs() {
+    let (_, stdout) = run("cat <<-EOF\n\t\tindented\n  spaces\n\tEOF\n");
+    assert_eq!(stdout, "indented\n  spaces\n");
+}
+
+#[test]
+fn test_several_here_documents_on_one_line() {
+    let (_, stdout) = run("cat <<A; cat <<B | tr a-z A-Z\nfirst\nA\nsecond\nB\necho end");
+    assert_eq!(stdout, "first\nSECOND\nend\n");
+}
+
+#[test]
+fn test_here_string() {
+    let (_, stdout) = run("cat <<< \"hi $name\"; read -r a b <<< 'x y z'; echo \"$b\"");
+    assert_eq!(stdout, "hi world\ny z\n");
+}
+
+#[test]
+fn test_here_documents_feed_builtins_one_at_a_time() {
+    let (_, stdout) = run("read a <<EOF\none\ntwo\nEOF\nread b <<< three; echo $a $b");
+    assert_eq!(stdout, "one three\n");
+}
+
+#[test]
+fn test_expand_here_document() {
+    let shell = Shell::builder().var("x", "1").build();
+    assert_eq!(
+        expand_here_document("$x '$x' \"a\"\\\nb\n", &shell).unwrap(),
+        "1 '1' \"a\"b\n"
+    );
+}
+
+#[test]
+fn test_incomplete_here_document() {
+    assert!(parser::is_incomplete("cat <<EOF"));
+    assert!(parser::is_incomplete("cat <<EOF\nline"));
+    assert!(!parser::is_incomplete("cat <<EOF\nline\nEOF"));
+    assert!(!parser::is_incomplete("cat <<< word"));
+    assert_eq!(
+        parser::parse("cat <<EOF\nline").unwrap_err(),
+        "here-document delimited by end-of-file (wanted `EOF')"
+    );
+
+    let lines = parser::command_lines("echo a\ncat <<EOF\nb\nEOF\necho c\n");
+    assert_eq!(
+        lines,
+        [
+            (1, "echo a".to_string()),
+            (2, "cat <<EOF\nb\nEOF".to_string()),
+            (5, "echo c".to_string()),
+        ]
+    );
+}
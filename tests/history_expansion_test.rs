@@ -0,0 +1,155 @@
+// Tests for csh-style history expansion
+
+use bettershell::history::History;
+use bettershell::history_expansion::*;
+
+fn sample_history() -> History {
+    let mut history = History::new();
+    for command in [
+        "cd /usr/local/lib",
+        "vim src/main.rs",
+        "grep -r needle src/history.rs",
+        "echo one two three four",
+    ] {
+        history.add(command);
+    }
+    history
+}
+
+fn expanded(line: &str) -> String {
+    expand(line, &sample_history()).unwrap().unwrap().line
+}
+
+#[test]
+fn test_no_expansion() {
+    let history = sample_history();
+    assert_eq!(expand("echo hello", &history), Ok(None));
+    // A lone `!` or one followed by a blank or `=` stays as it is
+    assert_eq!(expand("echo hi !", &history), Ok(None));
+    assert_eq!(expand("test ! -f file", &history), Ok(None));
+    assert_eq!(expand("a != b", &history), Ok(None));
+    // A `!` before a metacharacter or a closing quote isn't an event either
+    assert_eq!(expand("echo wow!; echo two", &history), Ok(None));
+    assert_eq!(expand("echo \"hey!\"", &history), Ok(None));
+    assert_eq!(expand("true!&& echo a!|cat", &history), Ok(None));
+}
+
+#[test]
+fn test_quoted_and_escaped_bang() {
+    let history = sample_history();
+    assert_eq!(expand("echo '!!'", &history), Ok(None));
+    assert_eq!(expand("echo \\!!", &history), Ok(None));
+    // Double quotes don't prevent expansion
+    assert_eq!(expanded("echo \"!!\""), "echo \"echo one two three four\"");
+}
+
+#[test]
+fn test_event_designators() {
+    assert_eq!(expanded("!!"), "echo one two three four");
+    assert_eq!(expanded("sudo !!"), "sudo echo one two three four");
+    assert_eq!(expanded("!1"), "cd /usr/local/lib");
+    assert_eq!(expanded("!-2"), "grep -r needle src/history.rs");
+    assert_eq!(expanded("!vim"), "vim src/main.rs");
+    assert_eq!(expanded("!?needle?"), "grep -r needle src/history.rs");
+    assert_eq!(expanded("!?local"), "cd /usr/local/lib");
+    assert_eq!(expanded("echo a !#"), "echo a echo a ");
+}
+
+#[test]
+fn test_event_not_found() {
+    let history = sample_history();
+    assert_eq!(
+        expand("!nothing", &history),
+        Err("!nothing: event not found".to_string())
+    );
+    assert_eq!(
+        expand("!42", &history),
+        Err("!42: event not found".to_string())
+    );
+    assert_eq!(
+        expand("!!", &History::new()),
+        Err("!!: event not found".to_string())
+    );
+}
+
+#[test]
+fn test_word_designators() {
+    assert_eq!(expanded("!!:0"), "echo");
+    assert_eq!(expanded("!!:2"), "two");
+    assert_eq!(expanded("!!:$"), "four");
+    assert_eq!(expanded("!!:^"), "one");
+    assert_eq!(expanded("!!:*"), "one two three four");
+    assert_eq!(expanded("!!:1-3"), "one two three");
+    assert_eq!(expanded("!!:-2"), "echo one two");
+    assert_eq!(expanded("!!:2*"), "two three four");
+    assert_eq!(expanded("!!:2-"), "two three");
+    assert_eq!(expanded("!vim:1"), "src/main.rs");
+    assert_eq!(expanded("!?needle?:%"), "needle");
+}
+
+#[test]
+fn test_word_designator_shorthands() {
+    assert_eq!(expanded("ls !$"), "ls four");
+    assert_eq!(expanded("ls !^"), "ls one");
+    assert_eq!(expanded("ls !*"), "ls one two three four");
+    assert_eq!(expanded("ls !:3"), "ls three");
+}
+
+#[test]
+fn test_bad_word_specifier() {
+    let history = sample_history();
+    assert_eq!(
+        expand("!!:9", &history),
+        Err(":9: bad word specifier".to_string())
+    );
+}
+
+#[test]
+fn test_modifiers() {
+    assert_eq!(expanded("!vim:1:h"), "src");
+    assert_eq!(expanded("!vim:1:t"), "main.rs");
+    assert_eq!(expanded("!vim:1:r"), "src/main");
+    assert_eq!(expanded("!vim:1:e"), ".rs");
+    assert_eq!(expanded("!cd:$:h:h"), "/usr");
+    assert_eq!(expanded("!vim:s/main/lib/"), "vim src/lib.rs");
+    assert_eq!(expanded("!!:s/o/0"), "ech0 one two three four");
+    assert_eq!(expanded("!!:gs/o/0/"), "ech0 0ne tw0 three f0ur");
+    assert_eq!(expanded("!vim:s|src|&/bin|"), "vim src/bin/main.rs");
+}
+
+#[test]
+fn test_substitution_failed() {
+    let history = sample_history();
+    assert_eq!(
+        expand("!!:s/xyz/abc/", &history),
+        Err(":s/xyz/abc/: substitution failed".to_string())
+    );
+}
+
+#[test]
+fn test_print_only_modifier() {
+    let expansion = expand("!vim:p", &sample_history()).unwrap().unwrap();
+    assert_eq!(expansion.line, "vim src/main.rs");
+    assert!(expansion.print_only);
+}
+
+#[test]
+fn test_quick_substitution() {
+    assert_eq!(expanded("^four^five^"), "echo one two three five");
+    assert_eq!(expanded("^two^2"), "echo one 2 three four");
+    assert_eq!(expanded("^four^five^ six"), "echo one two three five six");
+
+    let history = sample_history();
+    assert_eq!(
+        expand("^xyz^abc^", &history),
+        Err("^xyz^abc: substitution failed".to_string())
+    );
+}
+
+#[test]
+fn test_split_words_keeps_quotes() {
+    assert_eq!(
+        split_words("echo \"a b\" 'c d' e\\ f"),
+        vec!["echo", "\"a b\"", "'c d'", "e\\ f"]
+    );
+}
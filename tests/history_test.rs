@@ -0,0 +1,269 @@
+// Tests for the persistent command history
+
+use bettershell::builtins::Io;
+use bettershell::history::*;
+use bettershell::shell::Shell;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+fn temp_file(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("bsh_history_{}_{}", name, std::process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+fn commands(history: &History) -> Vec<&str> {
+    history
+        .entries()
+        .iter()
+        .map(|entry| entry.command.as_str())
+        .collect()
+}
+
+#[test]
+fn test_add_and_get() {
+    let mut history = History::new();
+    assert!(history.add("echo hello\n"));
+    assert!(history.add("pwd"));
+    assert!(!history.add("   "));
+
+    assert_eq!(history.len(), 2);
+    assert_eq!(history.get(1).unwrap().command, "echo hello");
+    assert_eq!(history.get(2).unwrap().command, "pwd");
+    assert!(history.get(0).is_none());
+    assert!(history.get(1).unwrap().timestamp > 0);
+}
+
+#[test]
+fn test_histcontrol_parse() {
+    let control = HistoryControl::parse("ignoreboth:erasedups");
+    assert!(control.ignore_space);
+    assert!(control.ignore_dups);
+    assert!(control.erase_dups);
+
+    assert_eq!(HistoryControl::parse(""), HistoryControl::default());
+}
+
+#[test]
+fn test_ignorespace_and_ignoredups() {
+    let mut history = History::new();
+    history.control = HistoryControl::parse("ignoreboth");
+
+    history.add(" secret command");
+    history.add("ls");
+    history.add("ls");
+    history.add("pwd");
+    history.add("ls");
+
+    assert_eq!(commands(&history), vec!["ls", "pwd", "ls"]);
+}
+
+#[test]
+fn test_erasedups() {
+    let mut history = History::new();
+    history.control = HistoryControl::parse("erasedups");
+
+    history.add("ls");
+    history.add("pwd");
+    history.add("ls");
+
+    assert_eq!(commands(&history), vec!["pwd", "ls"]);
+}
+
+#[test]
+fn test_histsize_limit() {
+    let mut history = History::new();
+    history.size = Some(2);
+
+    history.add("one");
+    history.add("two");
+    history.add("three");
+
+    assert_eq!(commands(&history), vec!["two", "three"]);
+}
+
+#[test]
+fn test_configure_from_shell_variables() {
+    let mut shell = Shell::builder().clear_env().build();
+    shell.set_var("HOME", "/home/me");
+    shell.configure_history();
+    assert_eq!(
+        shell.history.file,
+        Some(PathBuf::from("/home/me/.local/state/bettershell/history"))
+    );
+    assert_eq!(shell.history.size, Some(DEFAULT_HISTSIZE));
+    assert_eq!(shell.history.control, HistoryControl::default());
+
+    // Assignments in the shell apply once the history is configured again
+    shell.history.add("kept");
+    shell.set_var("HISTCONTROL", "ignoredups");
+    shell.set_var("HISTSIZE", "10");
+    shell.set_var("HISTFILESIZE", "20");
+    shell.set_var("HISTFILE", "");
+    shell.configure_history();
+    assert_eq!(commands(&shell.history), vec!["kept"]);
+    assert!(shell.history.control.ignore_dups);
+    assert_eq!(shell.history.size, Some(10));
+    assert_eq!(shell.history.file_size, Some(20));
+    assert_eq!(shell.history.file, None);
+}
+
+#[test]
+fn test_delete_entries() {
+    let mut history = History::new();
+    for command in ["one", "two", "three", "four", "five"] {
+        history.add(command);
+    }
+
+    history.delete(2).unwrap();
+    assert_eq!(commands(&history), vec!["one", "three", "four", "five"]);
+
+    history.delete(-1).unwrap();
+    assert_eq!(commands(&history), vec!["one", "three", "four"]);
+
+    history.delete_range(1, 2).unwrap();
+    assert_eq!(commands(&history), vec!["four"]);
+
+    assert!(history.delete(5).is_err());
+    assert!(history.delete(0).is_err());
+}
+
+#[test]
+fn test_file_format_roundtrip() {
+    let entries = vec![
+        HistoryEntry {
+            timestamp: 1700000000,
+            command: "echo one".to_string(),
+        },
+        HistoryEntry {
+            timestamp: 1700000001,
+            command: "echo two".to_string(),
+        },
+    ];
+
+    let content = format_file(&entries);
+    assert_eq!(content, "#1700000000\necho one\n#1700000001\necho two\n");
+    assert_eq!(parse_file(&content), entries);
+
+    // Files written without timestamps are still readable
+    let plain = parse_file("ls\npwd\n");
+    assert_eq!(plain.len(), 2);
+    assert_eq!(plain[1].timestamp, 0);
+
+    // Commands of several lines (here-documents) stay together, even with `#123` lines
+    let entries = vec![
+        HistoryEntry {
+            timestamp: 1700000002,
+            command: "cat <<EOF\n#123\n\n\\x\n  \\y\nEOF".to_string(),
+        },
+        HistoryEntry {
+            timestamp: 1700000003,
+            command: "\\ls".to_string(),
+        },
+    ];
+    let content = format_file(&entries);
+    assert_eq!(
+        content,
+        "#1700000002\ncat <<EOF\n\\#123\n\n\\\\x\n  \\y\nEOF\n#1700000003\n\\ls\n"
+    );
+    assert_eq!(parse_file(&content), entries);
+}
+
+#[test]
+fn test_add_appends_to_file() {
+    let path = temp_file("append");
+
+    let mut first = History::new();
+    first.file = Some(path.clone());
+    first.add("echo from first");
+
+    // A second shell appends to the same file instead of overwriting it
+    let mut second = History::new();
+    second.file = Some(path.clone());
+    second.add("echo from second");
+
+    let mut reloaded = History::new();
+    reloaded.file = Some(path.clone());
+    reloaded.load().unwrap();
+    assert_eq!(
+        commands(&reloaded),
+        vec!["echo from first", "echo from second"]
+    );
+
+    fs::remove_file(&path).ok();
+}
+
+#[test]
+fn test_histfilesize_truncates_file() {
+    let path = temp_file("truncate");
+
+    let mut history = History::new();
+    history.file = Some(path.clone());
+    history.file_size = Some(2);
+    history.add("one");
+    history.add("two");
+    history.add("three");
+
+    let content = fs::read_to_string(&path).unwrap();
+    let stored: Vec<String> = parse_file(&content)
+        .into_iter()
+        .map(|entry| entry.command)
+        .collect();
+    assert_eq!(stored, vec!["two", "three"]);
+
+    fs::remove_file(&path).ok();
+}
+
+#[test]
+fn test_write_and_read_file() {
+    let path = temp_file("write");
+
+    let mut history = History::new();
+    history.add("ls");
+    history.add("pwd");
+    history.write_file(&path).unwrap();
+
+    let mut other = History::new();
+    other.read_file(&path).unwrap();
+    assert_eq!(commands(&other), vec!["ls", "pwd"]);
+
+    fs::remove_file(&path).ok();
+}
+
+#[test]
+fn test_load_missing_file() {
+    let mut history = History::new();
+    history.file = Some(temp_file("missing"));
+    assert!(history.load().is_ok());
+    assert!(history.is_empty());
+}
+
+#[test]
+fn test_handle_history_clear_and_delete() {
+    use bettershell::commands::handle_history;
+
+    let mut shell = Shell::new();
+    shell.history.add("one");
+    shell.history.add("two");
+    shell.history.add("three");
+
+    let mut stdout: Vec<u8> = Vec::new();
+    let mut io = Io::new(io::empty(), &mut stdout, io::sink());
+
+    assert_eq!(
+        handle_history(vec!["-d", "1"], &mut shell, &mut io).unwrap(),
+        0
+    );
+    assert_eq!(commands(&shell.history), vec!["two", "three"]);
+
+    assert_eq!(handle_history(vec![], &mut shell, &mut io).unwrap(), 0);
+    assert_eq!(handle_history(vec!["-c"], &mut shell, &mut io).unwrap(), 0);
+    drop(io);
+    assert_eq!(
+        String::from_utf8(stdout).unwrap(),
+        "    1  two\n    2  three\n"
+    );
+    assert!(shell.history.is_empty());
+}
@@ -0,0 +1,48 @@
+// Tests for the job table and job specifications
+use bettershell::jobs::{JobTable, ProcessState};
+
+#[test]
+fn test_job_specs() {
+    let mut jobs = JobTable::default();
+    let first = jobs.add_job(None, vec![999_001], "sleep 100".to_string());
+    let second = jobs.add_job(None, vec![999_002], "vim notes.txt".to_string());
+
+    assert_eq!(jobs.resolve_job_spec(&format!("%{}", first)), Ok(first));
+    assert_eq!(jobs.resolve_job_spec("%%"), Ok(second));
+    assert_eq!(jobs.resolve_job_spec("%+"), Ok(second));
+    assert_eq!(jobs.resolve_job_spec("%-"), Ok(first));
+    assert_eq!(jobs.resolve_job_spec("%vim"), Ok(second));
+    assert_eq!(jobs.resolve_job_spec("%?notes"), Ok(second));
+    assert_eq!(
+        jobs.resolve_job_spec("%nothing"),
+        Err("%nothing: no such job".to_string())
+    );
+    assert_eq!(jobs.job_of_pid(999_001), Some(first));
+
+    jobs.remove_job(first);
+    jobs.remove_job(second);
+    assert_eq!(jobs.job_of_pid(999_001), None);
+}
+
+#[test]
+fn test_job_state_labels() {
+    let mut jobs = JobTable::default();
+    let id = jobs.add_job(None, vec![999_101, 999_102], "a | b".to_string());
+    let mut job = jobs.get_job(id).unwrap();
+    jobs.remove_job(id);
+
+    assert_eq!(job.state_label(), "Running");
+    job.processes[0].state = ProcessState::Stopped(20);
+    assert_eq!(job.state_label(), "Stopped");
+    assert!(job.is_stopped());
+
+    job.processes[0].state = ProcessState::Exited(1);
+    job.processes[1].state = ProcessState::Exited(0);
+    assert!(job.is_completed());
+    assert_eq!(job.state_label(), "Done");
+    assert_eq!(job.status(false), 0);
+
+    job.processes[1].state = ProcessState::Exited(2);
+    assert_eq!(job.state_label(), "Exit 2");
+    assert_eq!(job.status(false), 2);
+}
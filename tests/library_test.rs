@@ -0,0 +1,181 @@
+// Tests for embedding the shell: run_str, run_file, captured output and the builder
+mod common;
+
+use bettershell::error::ShellError;
+use bettershell::options::ShellOption;
+use bettershell::shell::{CapturedOutput, Shell};
+use common::temp_path;
+use std::env;
+use std::fs;
+
+fn output(shell: &mut Shell) -> (String, String) {
+    let CapturedOutput { stdout, stderr } = shell.take_output();
+    (
+        String::from_utf8(stdout).unwrap(),
+        String::from_utf8(stderr).unwrap(),
+    )
+}
+
+#[test]
+fn test_run_str_captures_output() {
+    let mut shell = Shell::builder().capture_output().build();
+
+    let status = shell.run_str("echo one\nprintf 'two\\n'\nbsh_no_such_command");
+    assert_eq!(status.unwrap(), 127);
+    assert_eq!(
+        output(&mut shell),
+        (
+            "one\ntwo\n".to_string(),
+            "bettershell: bsh_no_such_command: command not found\n".to_string()
+        )
+    );
+
+    // The buffers start over after taking the output
+    assert_eq!(shell.run_str("echo three").unwrap(), 0);
+    assert_eq!(output(&mut shell).0, "three\n");
+}
+
+#[test]
+fn test_captured_output_with_redirections() {
+    let path = temp_path("redirected");
+    let mut shell = Shell::builder().capture_output().build();
+
+    let commands = format!(
+        "echo out; echo err >&2; ls /bsh_nope 2>&1\n\
+         echo file > {0}; cat {0} | tr a-z A-Z; printf 'x\\n' >&2 2>/dev/null",
+        path.display()
+    );
+    assert_eq!(shell.run_str(&commands).unwrap(), 0);
+    let (stdout, stderr) = output(&mut shell);
+    assert!(stdout.starts_with("out\nls: "), "{}", stdout);
+    assert!(stdout.ends_with("FILE\n"), "{}", stdout);
+    assert_eq!(stderr, "err\nx\n");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "file\n");
+}
+
+#[test]
+fn test_shells_capture_separately() {
+    // Capturing doesn't touch the fds of the process, so shells can run side by side
+    let threads: Vec<_> = (0..4)
+        .map(|index| {
+            std::thread::spawn(move || {
+                let mut shell = Shell::builder().capture_output().build();
+                let commands = format!("echo {0}; sh -c 'echo {0}'", index);
+                for _ in 0..20 {
+                    shell.run_str(&commands).unwrap();
+                    assert_eq!(output(&mut shell).0, format!("{0}\n{0}\n", index));
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}
+
+#[test]
+fn test_variables() {
+    let mut shell = Shell::builder()
+        .clear_env()
+        .env("PATH", &env::var("PATH").unwrap())
+        .env("BSH_EXPORTED", "exported")
+        .var("BSH_LOCAL", "local")
+        .capture_output()
+        .build();
+    shell.set_var("BSH_LATER", "later");
+    assert_eq!(shell.get_var("BSH_LOCAL"), Some("local"));
+    assert_eq!(shell.get_var("HOME"), None);
+
+    let status =
+        shell.run_str("echo $BSH_LOCAL $BSH_LATER\nsh -c 'echo ${BSH_EXPORTED}${BSH_LOCAL}'");
+    assert_eq!(status.unwrap(), 0);
+    assert_eq!(output(&mut shell).0, "local later\nexported\n");
+}
+
+#[test]
+fn test_exit_stops_running_commands() {
+    let mut shell = Shell::builder().capture_output().build();
+
+    let status = shell.run_str("trap 'echo bye' EXIT\necho before; exit; echo after\necho later");
+    assert_eq!(status.unwrap(), 0);
+    assert_eq!(output(&mut shell).0, "before\nbye\n");
+
+    // The shell can still be used afterwards
+    assert_eq!(shell.run_str("echo again").unwrap(), 0);
+    assert_eq!(output(&mut shell).0, "again\n");
+}
+
+#[test]
+fn test_exit_status() {
+    let mut shell = Shell::builder().capture_output().build();
+
+    assert_eq!(shell.run_str("exit 3").unwrap(), 3);
+    assert_eq!(shell.run_str("false; exit").unwrap(), 1);
+    assert_eq!(shell.run_str("exit 257").unwrap(), 1);
+    assert_eq!(shell.run_str("exit -1").unwrap(), 255);
+    assert_eq!(shell.run_str("exit nope; echo after").unwrap(), 2);
+    assert_eq!(
+        output(&mut shell),
+        (
+            String::new(),
+            "bettershell: exit: nope: numeric argument required\n".to_string()
+        )
+    );
+}
+
+#[test]
+fn test_builder_options() {
+    let mut shell = Shell::builder()
+        .option(ShellOption::Errexit, true)
+        .capture_output()
+        .build();
+    assert!(shell.options.is_set(ShellOption::Errexit));
+
+    assert_eq!(shell.run_str("echo one; false; echo two").unwrap(), 1);
+    assert_eq!(output(&mut shell).0, "one\n");
+}
+
+#[test]
+fn test_syntax_errors_run_nothing() {
+    let mut shell = Shell::builder().capture_output().build();
+
+    let error = shell.run_str("echo fine\necho 'unterminated").unwrap_err();
+    assert!(matches!(error, ShellError::Parse(_)));
+    assert_eq!(
+        error.to_string(),
+        "line 2: unexpected EOF while looking for matching `''"
+    );
+    assert_eq!(output(&mut shell), (String::new(), String::new()));
+}
+
+#[test]
+fn test_run_file() {
+    let script = temp_path("script.sh");
+    fs::write(&script, "echo from script\nbsh_no_such_command\n").unwrap();
+    let mut shell = Shell::builder()
+        .cwd(script.parent().unwrap())
+        .capture_output()
+        .build();
+
+    assert_eq!(shell.run_file("script.sh").unwrap(), 127);
+    assert_eq!(
+        output(&mut shell),
+        (
+            "from script\n".to_string(),
+            format!(
+                "bettershell: {}: line 2: bsh_no_such_command: command not found\n",
+                script.display()
+            )
+        )
+    );
+
+    let error = shell.run_file("missing.sh").unwrap_err();
+    assert_eq!(error.status(), 127);
+    assert_eq!(
+        error.to_string(),
+        format!(
+            "{}: No such file or directory",
+            temp_path("missing.sh").display()
+        )
+    );
+}
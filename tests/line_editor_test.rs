@@ -0,0 +1,156 @@
+// Tests for the line editor, driven by raw key bytes instead of a terminal
+
+use bettershell::history::History;
+use bettershell::line_editor::*;
+
+fn history_of(commands: &[&str]) -> History {
+    let mut history = History::new();
+    for command in commands {
+        history.add(command);
+    }
+    history
+}
+
+fn read(input: &str, history: &History) -> (ReadResult, String) {
+    let mut output: Vec<u8> = Vec::new();
+    let result = read_line_from("$ ", history, input.as_bytes(), &mut output).unwrap();
+    (result, String::from_utf8(output).unwrap())
+}
+
+fn line(text: &str) -> ReadResult {
+    ReadResult::Line(text.to_string())
+}
+
+#[test]
+fn test_key_decoding() {
+    let mut keys = KeyReader::new("a\x1b[A\x1b[3~\x1bOH\x12\x7fä\r".as_bytes());
+    let mut decoded = Vec::new();
+    while let Some(key) = keys.read_key().unwrap() {
+        decoded.push(key);
+    }
+
+    assert_eq!(
+        decoded,
+        vec![
+            Key::Char('a'),
+            Key::Up,
+            Key::Delete,
+            Key::Home,
+            Key::Ctrl('r'),
+            Key::Backspace,
+            Key::Char('ä'),
+            Key::Enter,
+        ]
+    );
+}
+
+#[test]
+fn test_lone_escape_followed_by_key() {
+    let mut keys = KeyReader::new("\x1bx".as_bytes());
+    assert_eq!(keys.read_key().unwrap(), Some(Key::Escape));
+    assert_eq!(keys.read_key().unwrap(), Some(Key::Char('x')));
+    assert_eq!(keys.read_key().unwrap(), None);
+}
+
+#[test]
+fn test_plain_line() {
+    let history = History::new();
+    let (result, output) = read("echo hello\r", &history);
+    assert_eq!(result, line("echo hello"));
+    assert!(output.starts_with("\r$ "));
+}
+
+#[test]
+fn test_cursor_editing() {
+    let history = History::new();
+
+    // Insert in the middle, delete with backspace and delete, jump to start and end
+    let (result, _) = read("eho\x1b[D\x1b[Dc\x1b[F!\x7f\x01\x1b[3~e\r", &history);
+    assert_eq!(result, line("echo"));
+
+    // Ctrl-W deletes the previous word, Ctrl-U everything before the cursor
+    let (result, _) = read("ls foo bar\x17baz\r", &history);
+    assert_eq!(result, line("ls foo baz"));
+    let (result, _) = read("garbage\x15pwd\r", &history);
+    assert_eq!(result, line("pwd"));
+}
+
+#[test]
+fn test_eof() {
+    let history = History::new();
+    assert_eq!(read("\x04", &history).0, ReadResult::Eof);
+    assert_eq!(read("", &history).0, ReadResult::Eof);
+    // Ctrl-D only ends the input on an empty line
+    assert_eq!(read("ab\x02\x04\r", &history).0, line("a"));
+}
+
+#[test]
+fn test_ctrl_c_discards_line() {
+    let history = history_of(&["ls"]);
+    let (result, output) = read("echo hi\x03", &history);
+    assert_eq!(result, ReadResult::Interrupted);
+    assert!(output.ends_with("^C\r\n"));
+    // Also during a history search
+    assert_eq!(read("\x12l\x03", &history).0, ReadResult::Interrupted);
+}
+
+#[test]
+fn test_history_navigation() {
+    let history = history_of(&["first", "second"]);
+
+    assert_eq!(read("\x1b[A\r", &history).0, line("second"));
+    assert_eq!(read("\x1b[A\x1b[A\r", &history).0, line("first"));
+    // Going back down restores what was typed before
+    assert_eq!(read("draft\x1b[A\x1b[B\r", &history).0, line("draft"));
+}
+
+#[test]
+fn test_reverse_search() {
+    let history = history_of(&["git status", "cargo build", "git commit", "ls"]);
+
+    let (result, output) = read("\x12git\r", &history);
+    assert_eq!(result, line("git commit"));
+    assert!(output.contains("(reverse-i-search)`git': \x1b[7mgit\x1b[0m commit"));
+
+    // Repeated Ctrl-R walks to older matches
+    assert_eq!(read("\x12git\x12\r", &history).0, line("git status"));
+}
+
+#[test]
+fn test_forward_search() {
+    let history = history_of(&["git status", "cargo build", "git commit", "ls"]);
+
+    // Go back two matches, then one forward again
+    assert_eq!(read("\x12git\x12\x13\r", &history).0, line("git commit"));
+}
+
+#[test]
+fn test_failed_search() {
+    let history = history_of(&["ls"]);
+
+    let (result, output) = read("\x12xyz\r", &history);
+    assert!(output.contains("(failed reverse-i-search)`xyz'"));
+    assert_eq!(result, line(""));
+}
+
+#[test]
+fn test_escape_keeps_match_for_editing() {
+    let history = history_of(&["cargo build", "cargo test"]);
+
+    // Esc leaves the match in the buffer with the cursor on the match, so editing continues
+    let (result, _) = read("\x12build\x1b\x05 --release\r", &history);
+    assert_eq!(result, line("cargo build --release"));
+
+    // Ctrl-G gives up and restores the original line
+    let (result, _) = read("echo\x12build\x07\r", &history);
+    assert_eq!(result, line("echo"));
+}
+
+#[test]
+fn test_search_reuses_last_query() {
+    let history = history_of(&["make a", "make b", "ls"]);
+
+    // Esc ends the first search; a second Ctrl-R Ctrl-R searches for "make" again
+    let (result, _) = read("\x12make\x1b\x15\x12\x12\r", &history);
+    assert_eq!(result, line("make b"));
+}
@@ -0,0 +1,135 @@
+// Tests for shell options, set from the command line and with the set builtin
+mod common;
+
+use bettershell::options::{Options, ShellOption};
+use common::{bettershell, stderr, stdout};
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn test_option_names_and_letters() {
+    assert_eq!(ShellOption::from_letter('e'), Some(ShellOption::Errexit));
+    assert_eq!(
+        ShellOption::from_name("pipefail"),
+        Some(ShellOption::Pipefail)
+    );
+    assert_eq!(ShellOption::Pipefail.letter(), None);
+    assert_eq!(ShellOption::Nounset.name(), "nounset");
+
+    let mut options = Options::default();
+    assert_eq!(options.apply_letters("-eu"), Ok(()));
+    assert!(options.is_set(ShellOption::Errexit) && options.is_set(ShellOption::Nounset));
+    assert_eq!(options.flags(), "eu");
+    assert_eq!(options.apply_letters("+e"), Ok(()));
+    assert!(!options.is_set(ShellOption::Errexit));
+    assert_eq!(
+        options.apply_letters("-q"),
+        Err("-q: invalid option".to_string())
+    );
+    assert_eq!(
+        options.apply_letters("+q"),
+        Err("+q: invalid option".to_string())
+    );
+}
+
+#[test]
+fn test_errexit() {
+    let output = bettershell(&["-e", "-c", "echo before; false; echo after"]);
+    assert_eq!(stdout(&output), "before\n");
+    assert_eq!(output.status.code(), Some(1));
+
+    // Failures tested by `&&` and `||` don't count
+    let output = bettershell(&["-e", "-c", "false || true; false && true; echo ok"]);
+    assert_eq!(stdout(&output), "ok\n");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_errexit_in_script() {
+    let path = std::env::temp_dir().join(format!("bettershell_errexit_{}.sh", std::process::id()));
+    fs::write(
+        &path,
+        "#!/usr/bin/env bettershell\necho one\nsh -c 'exit 4'\necho two\n",
+    )
+    .unwrap();
+
+    let output = bettershell(&["-eux", path.to_str().unwrap()]);
+    assert_eq!(stdout(&output), "one\n");
+    assert_eq!(stderr(&output), "+ echo one\n+ sh -c 'exit 4'\n");
+    assert_eq!(output.status.code(), Some(4));
+
+    let output = bettershell(&[path.to_str().unwrap()]);
+    assert_eq!(stdout(&output), "one\ntwo\n");
+}
+
+#[test]
+fn test_nounset() {
+    let output = bettershell(&["-u", "-c", "echo $BSH_SURELY_UNSET; echo after"]);
+    assert_eq!(stdout(&output), "");
+    assert_eq!(
+        stderr(&output),
+        "bettershell: BSH_SURELY_UNSET: unbound variable\n"
+    );
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = bettershell(&["-c", "echo x$BSH_SURELY_UNSET"]);
+    assert_eq!(stdout(&output), "x\n");
+}
+
+#[test]
+fn test_xtrace_uses_ps4() {
+    let output = Command::new(env!("CARGO_BIN_EXE_bettershell"))
+        .args(["-x", "-c", "echo 'a b' | cat"])
+        .env("PS4", "trace> ")
+        .output()
+        .unwrap();
+    assert_eq!(stdout(&output), "a b\n");
+    assert_eq!(stderr(&output), "trace> echo 'a b'\ntrace> cat\n");
+}
+
+#[test]
+fn test_pipefail() {
+    assert_eq!(bettershell(&["-c", "false | true"]).status.code(), Some(0));
+    let output = bettershell(&["-o", "pipefail", "-c", "sh -c 'exit 3' | false | true"]);
+    assert_eq!(output.status.code(), Some(1));
+    let output = bettershell(&["-o", "pipefail", "-c", "sh -c 'exit 3' | true"]);
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn test_noexec_and_noglob() {
+    let output = bettershell(&["-n", "-c", "echo should not run"]);
+    assert_eq!(stdout(&output), "");
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = bettershell(&["-f", "-c", "echo /*"]);
+    assert_eq!(stdout(&output), "/*\n");
+}
+
+#[test]
+fn test_set_builtin() {
+    let output = bettershell(&[
+        "-c",
+        "set -eu; echo $-; set +u -o pipefail; echo $-; false; echo no",
+    ]);
+    assert_eq!(stdout(&output), "eu\ne\n");
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = bettershell(&["-c", "set -o pipefail; set -o"]);
+    assert!(stdout(&output).contains("pipefail       \ton\n"));
+    assert!(stdout(&output).contains("errexit        \toff\n"));
+
+    let output = bettershell(&["-c", "set -x; set +o"]);
+    assert!(stdout(&output).contains("set -o xtrace\n"));
+    assert!(stdout(&output).contains("set +o errexit\n"));
+
+    assert_eq!(bettershell(&["-c", "set -q"]).status.code(), Some(2));
+    assert_eq!(bettershell(&["-c", "set -o nope"]).status.code(), Some(1));
+}
+
+#[test]
+fn test_invalid_command_line_option() {
+    let output = bettershell(&["-q"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).starts_with("bettershell: -q: invalid option"));
+}
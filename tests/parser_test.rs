@@ -0,0 +1,217 @@
+// Tests for parsing command lines into lists, pipelines and redirections
+use bettershell::parser::{
+    Connector, RedirectKind, find_process_substitution, parse, parse_with_aliases,
+};
+use std::collections::BTreeMap;
+
+#[test]
+fn test_parse_simple_command_keeps_quotes() {
+    let items = parse("echo 'hello world' \"x\"").unwrap();
+    assert_eq!(items.len(), 1);
+    let command = &items[0].commands.first.commands[0];
+    assert_eq!(command.words, ["echo", "'hello world'", "\"x\""]);
+    assert!(!items[0].background);
+}
+
+#[test]
+fn test_parse_pipeline() {
+    let items = parse("cat file | grep x | wc -l").unwrap();
+    let pipeline = &items[0].commands.first;
+    assert_eq!(pipeline.commands.len(), 3);
+    assert_eq!(pipeline.commands[2].words, ["wc", "-l"]);
+}
+
+#[test]
+fn test_parse_and_or_list() {
+    let items = parse("true && echo yes || echo no").unwrap();
+    let list = &items[0].commands;
+    assert_eq!(list.rest.len(), 2);
+    assert_eq!(list.rest[0].0, Connector::And);
+    assert_eq!(list.rest[1].0, Connector::Or);
+}
+
+#[test]
+fn test_parse_background_and_sequence() {
+    let items = parse("sleep 1 & echo done; echo again").unwrap();
+    assert_eq!(items.len(), 3);
+    assert!(items[0].background);
+    assert!(!items[1].background);
+    assert_eq!(items[0].commands.to_string(), "sleep 1");
+}
+
+#[test]
+fn test_parse_redirections() {
+    let items = parse("cmd <in >out 2>>err 2>&1 &>all").unwrap();
+    let redirects = &items[0].commands.first.commands[0].redirects;
+    let kinds: Vec<(i32, RedirectKind, &str)> = redirects
+        .iter()
+        .map(|r| (r.fd, r.kind, r.target.as_str()))
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            (0, RedirectKind::Input, "in"),
+            (1, RedirectKind::Output, "out"),
+            (2, RedirectKind::Append, "err"),
+            (2, RedirectKind::DuplicateOutput, "1"),
+            (1, RedirectKind::OutputAll, "all"),
+        ]
+    );
+}
+
+#[test]
+fn test_parse_operators_inside_quotes_are_words() {
+    let items = parse("echo 'a | b' \"c && d\" e\\;f").unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(
+        items[0].commands.first.commands[0].words,
+        ["echo", "'a | b'", "\"c && d\"", "e\\;f"]
+    );
+}
+
+#[test]
+fn test_parse_comment() {
+    let items = parse("echo hi # a comment").unwrap();
+    assert_eq!(items[0].commands.first.commands[0].words, ["echo", "hi"]);
+}
+
+#[test]
+fn test_parse_syntax_errors() {
+    assert_eq!(
+        parse("| ls").unwrap_err(),
+        "syntax error near unexpected token `|'"
+    );
+    assert_eq!(
+        parse("ls >").unwrap_err(),
+        "syntax error near unexpected token `newline'"
+    );
+    assert!(parse("echo 'unterminated").is_err());
+    assert!(parse("ls &&").is_err());
+}
+
+#[test]
+fn test_parse_expands_aliases() {
+    let aliases: BTreeMap<String, String> = [
+        ("ll", "ls -l"),
+        ("ls", "ls --color"),
+        ("loop", "loop again"),
+        ("sudo", "sudo "),
+        ("both", "echo one; echo two |"),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), value.to_string()))
+    .collect();
+    let words = |line: &str| -> Vec<String> {
+        let items = parse_with_aliases(line, &aliases).unwrap();
+        items[0].commands.first.commands[0].words.clone()
+    };
+
+    // Aliases are expanded again, but never inside their own value
+    assert_eq!(words("ll /tmp"), ["ls", "--color", "-l", "/tmp"]);
+    assert_eq!(words("loop"), ["loop", "again"]);
+    // Only the first word, unless the value ends with a blank
+    assert_eq!(words("echo ll"), ["echo", "ll"]);
+    assert_eq!(words("sudo ll"), ["sudo", "ls", "--color", "-l"]);
+    // Quoting the name bypasses the alias
+    assert_eq!(words("\\ll"), ["\\ll"]);
+    assert_eq!(words("'ll'"), ["'ll'"]);
+    // Redirection targets aren't commands, but the word after them is
+    assert_eq!(words(">ll ll"), ["ls", "--color", "-l"]);
+
+    // The value may contain operators
+    let items = parse_with_aliases("both cat; ll", &aliases).unwrap();
+    assert_eq!(items.len(), 3);
+    assert_eq!(items[0].commands.to_string(), "echo one");
+    assert_eq!(items[1].commands.first.commands.len(), 2);
+    assert_eq!(items[1].commands.to_string(), "echo two | cat");
+    assert_eq!(items[2].commands.to_string(), "ls --color -l");
+}
+
+#[test]
+fn test_parse_conditional_expression() {
+    let items = parse("[[ a < b && $x =~ ^(y|z)$ ]] || echo no; [[ -n x ]]").unwrap();
+    assert_eq!(items.len(), 2);
+    let list = &items[0].commands;
+    let command = &list.first.commands[0];
+    assert_eq!(
+        command.words,
+        ["[[", "a", "<", "b", "&&", "$x", "=~", "^(y|z)$", "]]"]
+    );
+    assert!(command.redirects.is_empty());
+    assert_eq!(list.rest[0].0, Connector::Or);
+
+    // Only in command position
+    let items = parse("echo [[ a > b").unwrap();
+    assert_eq!(items[0].commands.first.commands[0].redirects.len(), 1);
+    assert_eq!(
+        parse("[[ a == b").unwrap_err(),
+        "unexpected EOF while looking for `]]'"
+    );
+    assert!(parse("[[ a ; b ]]").is_err());
+}
+
+#[test]
+fn test_parse_array_assignment() {
+    let items = parse("arr=(a 'b )' (c)) x+=(d); echo (e)").unwrap();
+    let command = &items[0].commands.first.commands[0];
+    assert_eq!(command.words, ["arr=(a 'b )' (c))", "x+=(d)"]);
+    let command = &items[1].commands.first.commands[0];
+    assert_eq!(command.words, ["echo", "(e)"]);
+    assert_eq!(
+        parse("arr=(a b").unwrap_err(),
+        "unexpected EOF while looking for matching `)'"
+    );
+}
+
+#[test]
+fn test_parse_here_documents() {
+    let items = parse("cat <<EOF 0<<-'E' <<<word\n$x\nEOF\n\tbody\n\tE\necho next").unwrap();
+    assert_eq!(items.len(), 2);
+    let redirects = &items[0].commands.first.commands[0].redirects;
+    let documents: Vec<(RedirectKind, &str, Option<&str>, bool)> = redirects
+        .iter()
+        .map(|r| {
+            let document = r.here_document.as_ref();
+            (
+                r.kind,
+                r.target.as_str(),
+                document.map(|d| d.body.as_str()),
+                document.is_some_and(|d| d.quoted),
+            )
+        })
+        .collect();
+    assert_eq!(
+        documents,
+        [
+            (
+                RedirectKind::HereDocument { strip_tabs: false },
+                "EOF",
+                Some("$x\n"),
+                false
+            ),
+            (
+                RedirectKind::HereDocument { strip_tabs: true },
+                "'E'",
+                Some("body\n"),
+                true
+            ),
+            (RedirectKind::HereString, "word", None, false),
+        ]
+    );
+    assert_eq!(redirects[1].to_string(), "<<-'E'");
+    assert_eq!(items[1].commands.first.commands[0].words, ["echo", "next"]);
+}
+
+#[test]
+fn test_parse_process_substitution() {
+    let items = parse("diff <(sort a) <(ls | head) >(cat) < <(echo x)").unwrap();
+    let command = &items[0].commands.first.commands[0];
+    assert_eq!(
+        command.words,
+        ["diff", "<(sort a)", "<(ls | head)", ">(cat)"]
+    );
+    assert_eq!(command.redirects[0].target, "<(echo x)");
+
+    assert_eq!(find_process_substitution("--file=<(ls)x"), Some(7..12));
+    assert_eq!(find_process_substitution("'<(ls)' \"<(ls)\""), None);
+}
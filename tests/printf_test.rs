@@ -0,0 +1,182 @@
+// Tests for the echo and printf builtins and the formatting behind them
+use bettershell::builtins::Io;
+use bettershell::commands::{handle_echo, handle_printf};
+use bettershell::printf::{self, EscapeStyle};
+use bettershell::shell::Shell;
+use std::io;
+
+fn echo(args: Vec<&str>) -> String {
+    let mut stdout: Vec<u8> = Vec::new();
+    let result = handle_echo(args, &mut Io::new(io::empty(), &mut stdout, io::sink()));
+    assert_eq!(result.unwrap(), 0);
+    String::from_utf8(stdout).unwrap()
+}
+
+/// The output of `printf format args...`
+fn format(format: &str, args: &[&str]) -> String {
+    let formatted = printf::format(format, args);
+    assert_eq!(formatted.errors, Vec::<String>::new());
+    String::from_utf8(formatted.output).unwrap()
+}
+
+#[test]
+fn test_echo_options() {
+    assert_eq!(echo(vec!["a", "b"]), "a b\n");
+    assert_eq!(echo(vec!["-n", "a"]), "a");
+    assert_eq!(echo(vec!["a\\tb"]), "a\\tb\n");
+    assert_eq!(echo(vec!["-e", "a\\tb\\n"]), "a\tb\n\n");
+    assert_eq!(echo(vec!["-ne", "x\\x41\\0101"]), "xAA");
+    assert_eq!(echo(vec!["-e", "one\\ctwo"]), "one");
+    // -E turns escapes off again, other options are just words
+    assert_eq!(echo(vec!["-eE", "a\\n"]), "a\\n\n");
+    assert_eq!(echo(vec!["-x", "-n"]), "-x -n\n");
+    assert_eq!(echo(vec!["-"]), "-\n");
+}
+
+#[test]
+fn test_unescape() {
+    assert_eq!(
+        printf::unescape("\\a\\e\\\\\\q\\u00e9", EscapeStyle::Echo),
+        (b"\x07\x1b\\\\q\xc3\xa9".to_vec(), false)
+    );
+    // Octal numbers start with 0 for echo only
+    assert_eq!(
+        printf::unescape("\\101\\0101", EscapeStyle::Echo),
+        (b"\\101A".to_vec(), false)
+    );
+    assert_eq!(
+        printf::unescape("\\101\\0101", EscapeStyle::Printf),
+        (b"A\x081".to_vec(), false)
+    );
+}
+
+#[test]
+fn test_conversions() {
+    assert_eq!(format("%s-%d\\n", &["a", "42"]), "a-42\n");
+    assert_eq!(
+        format("%5s|%-5s|%.2s", &["ab", "cd", "xyz"]),
+        "   ab|cd   |xy"
+    );
+    assert_eq!(
+        format("%05d|%+d|% d|%.3d", &["-42", "7", "7", "5"]),
+        "-0042|+7| 7|005"
+    );
+    assert_eq!(
+        format("%x %X %#x %o %#o", &["255", "255", "255", "8", "8"]),
+        "ff FF 0xff 10 010"
+    );
+    assert_eq!(format("%d %d %d", &["0x10", "010", "'A"]), "16 8 65");
+    assert_eq!(format("%u", &["-1"]), "18446744073709551615");
+    assert_eq!(format("%c%c", &["hello", "x"]), "hx");
+    assert_eq!(format("%%", &[]), "%");
+}
+
+#[test]
+fn test_floats() {
+    assert_eq!(format("%f", &["3.14159"]), "3.141590");
+    assert_eq!(
+        format("%.2f|%8.3f|%-8.1f|", &["2.345", "1", "-1.25"]),
+        "2.35|   1.000|-1.2    |"
+    );
+    assert_eq!(format("%e", &["12345.678"]), "1.234568e+04");
+    assert_eq!(format("%E", &["0.00012"]), "1.200000E-04");
+    assert_eq!(
+        format("%g %g %g", &["100000", "1000000", "0.0001"]),
+        "100000 1e+06 0.0001"
+    );
+    assert_eq!(format("%g %G", &["0.00001234", "1.5"]), "1.234e-05 1.5");
+    assert_eq!(format("%08.2f", &["-3.5"]), "-0003.50");
+}
+
+#[test]
+fn test_widths_from_arguments() {
+    assert_eq!(format("[%*s]", &["5", "ab"]), "[   ab]");
+    assert_eq!(format("[%-*s]", &["4", "ab"]), "[ab  ]");
+    assert_eq!(format("[%*s]", &["-4", "ab"]), "[ab  ]");
+    assert_eq!(format("[%.*f]", &["1", "2.25"]), "[2.2]");
+    assert_eq!(format("[%*.*s]", &["6", "3", "abcdef"]), "[   abc]");
+}
+
+#[test]
+fn test_format_reuse() {
+    assert_eq!(
+        format("%s=%s\\n", &["a", "1", "b", "2", "c"]),
+        "a=1\nb=2\nc=\n"
+    );
+    assert_eq!(format("<%d>", &["1", "2", "3"]), "<1><2><3>");
+    // A format without conversions is printed once
+    assert_eq!(format("plain\\n", &["x", "y"]), "plain\n");
+    assert_eq!(format("%s %d|", &[]), " 0|");
+}
+
+#[test]
+fn test_b_and_q() {
+    assert_eq!(format("%b|%s", &["a\\tb", "a\\tb"]), "a\tb|a\\tb");
+    assert_eq!(format("%b", &["\\0101\\x42"]), "AB");
+    // \c in %b stops all output
+    assert_eq!(format("%b-%s\\n", &["one\\ctwo", "x"]), "one");
+
+    assert_eq!(
+        format("%q ", &["simple", "a b", "it's", "", "$HOME", "~x"]),
+        "simple a\\ b it\\'s '' \\$HOME \\~x "
+    );
+    assert_eq!(format("%q", &["tab\there\n"]), "$'tab\\there\\n'");
+}
+
+#[test]
+fn test_errors() {
+    let formatted = printf::format("%d|%d", &["12abc", "3"]);
+    assert_eq!(formatted.output, b"0|3");
+    assert_eq!(formatted.errors, ["12abc: invalid number"]);
+
+    let formatted = printf::format("a%zb", &["x"]);
+    assert_eq!(formatted.output, b"a");
+    assert_eq!(formatted.errors, ["`z': invalid format character"]);
+
+    // Huge widths and precisions are refused instead of exhausting memory
+    let formatted = printf::format("a%.99999999999f", &["1"]);
+    assert_eq!(formatted.output, b"a");
+    assert_eq!(formatted.errors, ["99999999999: precision out of range"]);
+    let formatted = printf::format("%*d", &["99999999999", "1"]);
+    assert_eq!(formatted.errors, ["99999999999: field width out of range"]);
+    let formatted = printf::format("%*d", &["-99999999999", "1"]);
+    assert_eq!(formatted.errors, ["99999999999: field width out of range"]);
+    assert_eq!(printf::format("%.10000d", &["1"]).output.len(), 10000);
+    assert_eq!(
+        printf::format("%.10000g", &["0.0001"]).errors,
+        Vec::<String>::new()
+    );
+    assert_eq!(printf::format("%.10001e", &["1"]).errors.len(), 1);
+}
+
+#[test]
+fn test_printf_builtin() {
+    let mut shell = Shell::new();
+    let mut stdout: Vec<u8> = Vec::new();
+    let mut stderr: Vec<u8> = Vec::new();
+
+    let status = handle_printf(
+        vec!["-v", "bsh_out", "%s-%03d", "x", "7"],
+        &mut shell,
+        &mut Io::new(io::empty(), &mut stdout, &mut stderr),
+    );
+    assert_eq!(status.unwrap(), 0);
+    assert_eq!(shell.get_var("bsh_out"), Some("x-007"));
+
+    let status = handle_printf(
+        vec!["%d\\n", "nope"],
+        &mut shell,
+        &mut Io::new(io::empty(), &mut stdout, &mut stderr),
+    );
+    assert_eq!(status.unwrap(), 1);
+    assert_eq!(stdout, b"0\n");
+    assert_eq!(stderr, b"bettershell: printf: nope: invalid number\n");
+
+    let error = handle_printf(
+        vec!["-v", "1x", "%s"],
+        &mut shell,
+        &mut Io::new(io::empty(), io::sink(), io::sink()),
+    )
+    .unwrap_err();
+    assert_eq!(error.to_string(), "printf: `1x': not a valid identifier");
+}
@@ -0,0 +1,163 @@
+// Tests for the read builtin and the input splitting behind it
+use bettershell::builtins::Io;
+use bettershell::commands::handle_read;
+use bettershell::read::{self, ReadEnd, ReadOptions};
+use bettershell::shell::Shell;
+use std::io::{self, Write};
+use std::os::fd::AsRawFd;
+
+/// Runs `read args...` with `input` on stdin; returns its status and stderr
+fn read(args: Vec<&str>, input: &str, shell: &mut Shell) -> (i32, String) {
+    let mut stderr: Vec<u8> = Vec::new();
+    let result = handle_read(
+        args,
+        shell,
+        &mut Io::new(input.as_bytes(), io::sink(), &mut stderr),
+    );
+    let status = result.unwrap_or_else(|error| {
+        stderr.extend(format!("{}\n", error).bytes());
+        error.status()
+    });
+    (status, String::from_utf8(stderr).unwrap())
+}
+
+fn unescaped(text: &str) -> Vec<(char, bool)> {
+    text.chars().map(|ch| (ch, false)).collect()
+}
+
+#[test]
+fn test_split_fields() {
+    let split =
+        |text: &str, ifs: &str, max: Option<usize>| read::split_fields(&unescaped(text), ifs, max);
+    assert_eq!(split("  a  b c ", " \t\n", None), ["a", "b", "c"]);
+    assert_eq!(split("a  b c  ", " \t\n", Some(2)), ["a", "b c"]);
+    assert_eq!(split("a,,b, c", ",", None), ["a", "", "b", " c"]);
+    assert_eq!(split("a , b", " ,", None), ["a", "b"]);
+    assert_eq!(split("", " ", None), Vec::<String>::new());
+
+    // Escaped characters don't separate
+    let mut chars = unescaped("a b");
+    chars[1].1 = true;
+    assert_eq!(read::split_fields(&chars, " ", None), ["a b"]);
+}
+
+#[test]
+fn test_read_input() {
+    let options = ReadOptions::default();
+    let input = read::read_input(&mut "a\\ b\\\nc\nrest".as_bytes(), &options).unwrap();
+    assert_eq!(input.text(), "a bc");
+    assert_eq!(input.chars[1], (' ', true));
+    assert_eq!(input.end, ReadEnd::Delimiter);
+
+    let options = ReadOptions {
+        raw: true,
+        delimiter: b':',
+        ..ReadOptions::default()
+    };
+    let input = read::read_input(&mut "x\\y:z".as_bytes(), &options).unwrap();
+    assert_eq!(
+        (input.text().as_str(), input.end),
+        ("x\\y", ReadEnd::Delimiter)
+    );
+
+    let options = ReadOptions {
+        count: Some(2),
+        ..ReadOptions::default()
+    };
+    let input = read::read_input(&mut "éèx".as_bytes(), &options).unwrap();
+    assert_eq!((input.text().as_str(), input.end), ("éè", ReadEnd::Count));
+}
+
+#[test]
+fn test_read_builtin() {
+    let mut shell = Shell::new();
+
+    assert_eq!(read(vec!["a", "b"], "one two three\n", &mut shell).0, 0);
+    assert_eq!(shell.get_var("a"), Some("one"));
+    assert_eq!(shell.get_var("b"), Some("two three"));
+
+    // Without names the line goes to REPLY as it is
+    assert_eq!(read(vec![], "  spaced  \n", &mut shell).0, 0);
+    assert_eq!(shell.get_var("REPLY"), Some("  spaced  "));
+
+    // At the end of the input, what was read is still assigned
+    assert_eq!(read(vec!["-r", "x", "y"], "a\\b", &mut shell).0, 1);
+    assert_eq!(shell.get_var("x"), Some("a\\b"));
+    assert_eq!(shell.get_var("y"), Some(""));
+
+    shell.set_var("IFS", ":");
+    assert_eq!(read(vec!["-a", "parts"], "x:y::z\n", &mut shell).0, 0);
+    assert_eq!(shell.get_array("parts"), Some(vec!["x", "y", "", "z"]));
+    shell.unset_var("IFS");
+
+    assert_eq!(read(vec!["-n2", "x"], "abc", &mut shell).0, 0);
+    assert_eq!(shell.get_var("x"), Some("ab"));
+    assert_eq!(read(vec!["-N", "3", "x", "y"], "a\nb c", &mut shell).0, 0);
+    assert_eq!(shell.get_var("x"), Some("a\nb"));
+    assert_eq!(
+        read(vec!["-d", ";", "-p", "? ", "x"], "a;b", &mut shell),
+        (0, String::new())
+    );
+    assert_eq!(shell.get_var("x"), Some("a"));
+}
+
+#[test]
+fn test_read_timeout_on_given_input() {
+    let mut shell = Shell::new();
+
+    // The timeout applies to the input given to the builtin, not to the shell's stdin
+    assert_eq!(read(vec!["-t", "0"], "ready\n", &mut shell).0, 0);
+    assert_eq!(read(vec!["-t", "0.1", "x"], "ready\n", &mut shell).0, 0);
+    assert_eq!(shell.get_var("x"), Some("ready"));
+}
+
+#[test]
+fn test_read_errors() {
+    let mut shell = Shell::new();
+
+    assert_eq!(
+        read(vec!["-z"], "", &mut shell),
+        (2, "read: -z: invalid option\n".to_string())
+    );
+    assert_eq!(
+        read(vec!["-p"], "", &mut shell),
+        (2, "read: -p: option requires an argument\n".to_string())
+    );
+    assert_eq!(
+        read(vec!["-t", "soon"], "", &mut shell),
+        (1, "read: soon: invalid timeout specification\n".to_string())
+    );
+    assert_eq!(
+        read(vec!["a-b"], "", &mut shell),
+        (1, "read: `a-b': not a valid identifier\n".to_string())
+    );
+}
+
+#[test]
+fn test_read_from_fd_with_timeout() {
+    let (reader, mut writer) = io::pipe().unwrap();
+    let fd = reader.as_raw_fd().to_string();
+    let mut shell = Shell::new();
+
+    // Nothing to read yet
+    assert_eq!(read(vec!["-t", "0", "-u", &fd], "", &mut shell).0, 1);
+    assert_eq!(
+        read(vec!["-t", "0.1", "-u", &fd, "x"], "", &mut shell).0,
+        142
+    );
+
+    writer.write_all(b"partial").unwrap();
+    assert_eq!(read(vec!["-t", "0", "-u", &fd], "", &mut shell).0, 0);
+    assert_eq!(
+        read(vec!["-t", "0.1", "-u", &fd, "x"], "", &mut shell).0,
+        142
+    );
+    assert_eq!(shell.get_var("x"), Some("partial"));
+
+    writer.write_all(b"line\nnext").unwrap();
+    drop(writer);
+    assert_eq!(read(vec!["-u", &fd, "x"], "", &mut shell).0, 0);
+    assert_eq!(shell.get_var("x"), Some("line"));
+    assert_eq!(read(vec!["-u", &fd, "x"], "", &mut shell).0, 1);
+    assert_eq!(shell.get_var("x"), Some("next"));
+}
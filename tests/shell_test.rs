@@ -0,0 +1,66 @@
+// Tests for the state of a shell: separate shells don't share directories, variables or jobs
+mod common;
+
+use bettershell::executor::run_line;
+use bettershell::shell::{Shell, Value, Variable};
+use common::temp_path;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn test_shells_have_their_own_directory() {
+    let original_dir = env::current_dir().unwrap();
+    let mut first = Shell::new();
+    let mut second = Shell::new();
+
+    assert_eq!(run_line("cd /", &mut first), 0);
+    assert_eq!(run_line("cd /tmp", &mut second), 0);
+    assert_eq!(first.cwd, Path::new("/"));
+    assert_eq!(second.cwd, Path::new("/tmp"));
+    assert_eq!(env::current_dir().unwrap(), original_dir);
+}
+
+#[test]
+fn test_commands_run_in_the_shell_directory() {
+    let path = temp_path("cwd");
+    let mut shell = Shell::new();
+    shell.cwd = "/".into();
+    run_line(&format!("pwd > {}", path.display()), &mut shell);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "/\n");
+
+    run_line(&format!("sh -c pwd > {}", path.display()), &mut shell);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "/\n");
+}
+
+#[test]
+fn test_exported_variables_reach_commands() {
+    let path = temp_path("env");
+    let mut shell = Shell::new();
+    shell.set_var("BSH_TEST_LOCAL", "local");
+    shell.vars.insert(
+        "BSH_TEST_EXPORTED".to_string(),
+        Variable {
+            value: Value::Scalar("exported".to_string()),
+            exported: true,
+        },
+    );
+    run_line(
+        &format!(
+            "sh -c 'echo \"$BSH_TEST_LOCAL,$BSH_TEST_EXPORTED\"' > {}",
+            path.display()
+        ),
+        &mut shell,
+    );
+    assert_eq!(fs::read_to_string(&path).unwrap(), ",exported\n");
+    assert_eq!(env::var("BSH_TEST_EXPORTED").ok(), None);
+}
+
+#[test]
+fn test_status_belongs_to_the_shell() {
+    let mut first = Shell::new();
+    let second = Shell::new();
+    run_line("sh -c 'exit 4'", &mut first);
+    assert_eq!(first.last_status, 4);
+    assert_eq!(second.last_status, 0);
+}
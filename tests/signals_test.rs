@@ -0,0 +1,41 @@
+// Tests for signal names, termination messages and how killed jobs are reported
+use bettershell::executor::run_line;
+use bettershell::shell::Shell;
+use bettershell::signals::{signal_description, signal_name, termination_message};
+
+#[test]
+fn test_signal_names() {
+    assert_eq!(signal_name(libc::SIGINT), Some("INT"));
+    assert_eq!(signal_name(libc::SIGTERM), Some("TERM"));
+    assert_eq!(signal_name(9999), None);
+    assert_eq!(signal_description(libc::SIGKILL), "Killed");
+    assert_eq!(signal_description(9999), "Signal 9999");
+}
+
+#[test]
+fn test_termination_messages() {
+    assert_eq!(
+        termination_message(libc::SIGSEGV, true),
+        Some("Segmentation fault (core dumped)".to_string())
+    );
+    assert_eq!(
+        termination_message(libc::SIGTERM, false),
+        Some("Terminated".to_string())
+    );
+    // Ctrl-C and broken pipes are not reported
+    assert_eq!(termination_message(libc::SIGINT, false), None);
+    assert_eq!(termination_message(libc::SIGPIPE, false), None);
+}
+
+#[test]
+fn test_status_of_killed_child() {
+    let mut shell = Shell::new();
+    assert_eq!(
+        run_line("sh -c 'kill -TERM $$'", &mut shell),
+        128 + libc::SIGTERM
+    );
+    assert_eq!(
+        run_line("sh -c 'kill -KILL $$'", &mut shell),
+        128 + libc::SIGKILL
+    );
+}
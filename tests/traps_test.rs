@@ -0,0 +1,186 @@
+// Tests for the trap builtin: signal traps and the EXIT, ERR and DEBUG pseudo-signals
+mod common;
+
+use bettershell::builtins::Io;
+use bettershell::commands;
+use bettershell::executor::run_line;
+use bettershell::shell::Shell;
+use bettershell::traps::{self, Condition};
+use common::temp_path;
+use std::fs;
+use std::io;
+use std::sync::Mutex;
+
+/// Runs the trap builtin with captured output; returns its status, stdout and stderr
+fn handle_trap(args: Vec<&str>, shell: &mut Shell) -> (i32, String, String) {
+    let mut stdout: Vec<u8> = Vec::new();
+    let mut stderr: Vec<u8> = Vec::new();
+    let status = commands::handle_trap(
+        args,
+        shell,
+        &mut Io::new(io::empty(), &mut stdout, &mut stderr),
+    );
+    (
+        status.unwrap(),
+        String::from_utf8(stdout).unwrap(),
+        String::from_utf8(stderr).unwrap(),
+    )
+}
+
+/// ERR and DEBUG traps fire for every command, so tests running commands take turns
+static COMMANDS: Mutex<()> = Mutex::new(());
+
+#[test]
+fn test_condition_parsing() {
+    assert_eq!(Condition::parse("EXIT"), Some(Condition::Exit));
+    assert_eq!(Condition::parse("0"), Some(Condition::Exit));
+    assert_eq!(Condition::parse("err"), Some(Condition::Err));
+    assert_eq!(
+        Condition::parse("INT"),
+        Some(Condition::Signal(libc::SIGINT))
+    );
+    assert_eq!(
+        Condition::parse("SIGTERM"),
+        Some(Condition::Signal(libc::SIGTERM))
+    );
+    assert_eq!(Condition::parse("15"), Some(Condition::Signal(15)));
+    assert_eq!(Condition::parse("NOPE"), None);
+    assert_eq!(Condition::Signal(libc::SIGHUP).name(), "SIGHUP");
+    assert_eq!(Condition::parse("RETURN"), None);
+}
+
+#[test]
+fn test_quote_for_trap_p() {
+    assert_eq!(traps::quote("echo hi"), "'echo hi'");
+    assert_eq!(traps::quote("echo 'x'"), "'echo '\\''x'\\'''");
+}
+
+#[test]
+fn test_set_ignore_and_reset() {
+    let mut shell = Shell::new();
+    assert_eq!(
+        handle_trap(vec!["echo hup", "HUP", "SIGUSR2"], &mut shell).0,
+        0
+    );
+    assert_eq!(
+        shell.traps.get(Condition::Signal(libc::SIGHUP)),
+        Some("echo hup".to_string())
+    );
+    assert_eq!(
+        shell.traps.get(Condition::Signal(libc::SIGUSR2)),
+        Some("echo hup".to_string())
+    );
+
+    assert_eq!(handle_trap(vec!["", "HUP"], &mut shell).0, 0);
+    assert_eq!(
+        shell.traps.get(Condition::Signal(libc::SIGHUP)),
+        Some(String::new())
+    );
+
+    assert_eq!(handle_trap(vec!["-", "HUP"], &mut shell).0, 0);
+    assert_eq!(handle_trap(vec!["USR2"], &mut shell).0, 0);
+    assert_eq!(shell.traps.get(Condition::Signal(libc::SIGHUP)), None);
+    assert_eq!(shell.traps.get(Condition::Signal(libc::SIGUSR2)), None);
+
+    assert_eq!(
+        handle_trap(vec!["echo x", "BOGUS"], &mut shell),
+        (
+            1,
+            String::new(),
+            "bettershell: trap: BOGUS: invalid signal specification\n".to_string()
+        )
+    );
+    assert_eq!(
+        handle_trap(vec!["echo x", "RETURN"], &mut shell),
+        (
+            1,
+            String::new(),
+            "bettershell: trap: RETURN: unsupported pseudo-signal\n".to_string()
+        )
+    );
+
+    let (status, stdout, _) = handle_trap(vec!["-l"], &mut shell);
+    assert_eq!(status, 0);
+    assert!(stdout.starts_with(" 1) SIGHUP       2) SIGINT"));
+
+    handle_trap(vec!["echo bye", "USR1"], &mut shell);
+    let (_, stdout, _) = handle_trap(vec!["-p", "USR1"], &mut shell);
+    assert_eq!(stdout, "trap -- 'echo bye' SIGUSR1\n");
+    handle_trap(vec!["-", "USR1"], &mut shell);
+}
+
+#[test]
+fn test_traps_belong_to_their_shell() {
+    let mut shell = Shell::new();
+    handle_trap(vec!["echo hup", "HUP"], &mut shell);
+    assert_eq!(
+        Shell::new().traps.get(Condition::Signal(libc::SIGHUP)),
+        None
+    );
+    handle_trap(vec!["-", "HUP"], &mut shell);
+}
+
+#[test]
+fn test_signal_trap_runs_at_next_command() {
+    let _guard = COMMANDS.lock().unwrap();
+    let path = temp_path("usr1");
+    let mut shell = Shell::new();
+    run_line(
+        &format!("trap 'printf \"caught\\n\" >> {}' USR1", path.display()),
+        &mut shell,
+    );
+    run_line("kill -USR1 $$; true", &mut shell);
+    run_line("trap - USR1", &mut shell);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "caught\n");
+}
+
+#[test]
+fn test_exit_trap() {
+    let _guard = COMMANDS.lock().unwrap();
+    let path = temp_path("exit");
+    let mut shell = Shell::new();
+    run_line(
+        &format!("trap 'printf \"bye\\n\" > {}' EXIT", path.display()),
+        &mut shell,
+    );
+    traps::run_exit_trap(&mut shell);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "bye\n");
+    assert_eq!(shell.traps.get(Condition::Exit), None);
+}
+
+// Trap actions use printf: the output of builtins isn't redirected inside the test harness
+#[test]
+fn test_err_and_debug_traps() {
+    let _guard = COMMANDS.lock().unwrap();
+    let err_path = temp_path("err");
+    let debug_path = temp_path("debug");
+    let mut shell = Shell::new();
+
+    run_line(
+        &format!("trap 'printf \"%s\\n\" $? >> {}' ERR", err_path.display()),
+        &mut shell,
+    );
+    let status = run_line(
+        "false; sh -c 'exit 3'; true && false; false || true; false && true",
+        &mut shell,
+    );
+    run_line("trap - ERR", &mut shell);
+    assert_eq!(status, 1);
+    // `false && true` doesn't fire: the failure was tested by `&&`
+    assert_eq!(fs::read_to_string(&err_path).unwrap(), "1\n3\n1\n");
+
+    run_line(
+        &format!(
+            "trap 'printf \"debug\\n\" >> {}' DEBUG",
+            debug_path.display()
+        ),
+        &mut shell,
+    );
+    run_line("true; true | true", &mut shell);
+    run_line("trap - DEBUG", &mut shell);
+    // One for each command line plus one for `trap - DEBUG` itself
+    assert_eq!(
+        fs::read_to_string(&debug_path).unwrap(),
+        "debug\ndebug\ndebug\n"
+    );
+}